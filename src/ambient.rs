@@ -0,0 +1,153 @@
+//! Darkening and cooling sprites a little at night, or when the desktop is dark themed,
+//! and keeping the manager told of the local time, for behaviors that only happen at some
+//! times or on some dates, see [`crate::formula::Predicate`].
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{rgba::Rgba, ManagerEvent};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Night starts at this hour of the day...
+const DUSK: u32 = 20;
+/// ...and ends at this one.
+const DAWN: u32 = 6;
+
+/// How much of each color channel is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tint {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+}
+
+impl Tint {
+    /// A bit darker, and a bit bluer than that.
+    pub const NIGHT: Tint = Tint {
+        red: 0.72,
+        green: 0.78,
+        blue: 0.9,
+    };
+
+    /// `color` in this tint, as see-through as it was.
+    pub fn apply(self, color: Rgba) -> Rgba {
+        let channel = |value: u8, keep: f64| (value as f64 * keep).round() as u8;
+        Rgba::new(
+            channel(color.red, self.red),
+            channel(color.green, self.green),
+            channel(color.blue, self.blue),
+            color.alpha,
+        )
+    }
+}
+
+/// The local date and time, down to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    /// From 1 for January to 12.
+    pub month: u32,
+    /// Of the month, from 1.
+    pub day: u32,
+    /// From 1 for Monday to 7 for Sunday.
+    pub weekday: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl LocalTime {
+    /// How many seconds it's been since midnight.
+    pub fn seconds(self) -> u32 {
+        self.hour * 3600 + self.minute * 60 + self.second
+    }
+}
+
+/// Whether `hour`, from 0 to 23, is at night.
+pub fn is_night(hour: u32) -> bool {
+    !(DAWN..DUSK).contains(&hour)
+}
+
+/// Read the local time every [`POLL_INTERVAL`] on its own thread,
+/// sending it as a [`ManagerEvent::Clock`], until `should_exit` is set.
+pub fn spawn(
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Ambient clock thread"))
+        .spawn(move || poll_clock(proxy, should_exit))
+}
+
+fn poll_clock(proxy: EventLoopProxy<ManagerEvent>, should_exit: Arc<AtomicBool>) {
+    while !should_exit.load(Ordering::Relaxed) {
+        let Some(time) = local_time() else {
+            log::warn!("Could not read the local time, shimejis won't get darker at night");
+            return;
+        };
+        if proxy.send_event(ManagerEvent::Clock(time)).is_err() {
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+cfg_if! {
+    if #[cfg(unix)] {
+        /// The time in the local time zone.
+        pub fn local_time() -> Option<LocalTime> {
+            // SAFETY: `time` and `tm` are locals, and `localtime_r` only writes to `tm`.
+            let tm = unsafe {
+                let time = libc::time(std::ptr::null_mut());
+                let mut tm: libc::tm = std::mem::zeroed();
+                if libc::localtime_r(&time, &mut tm).is_null() {
+                    return None;
+                }
+                tm
+            };
+            let field = |value: libc::c_int| u32::try_from(value).ok();
+            Some(LocalTime {
+                month: field(tm.tm_mon)? + 1,
+                day: field(tm.tm_mday)?,
+                // `tm_wday` starts the week on Sunday, at 0
+                weekday: (field(tm.tm_wday)? + 6) % 7 + 1,
+                hour: field(tm.tm_hour)?,
+                minute: field(tm.tm_min)?,
+                second: field(tm.tm_sec)?.min(59),
+            })
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use windows_sys::Win32::{Foundation::SYSTEMTIME, System::SystemInformation::GetLocalTime};
+
+        /// The time in the local time zone.
+        pub fn local_time() -> Option<LocalTime> {
+            // SAFETY: `time` is a local that `GetLocalTime` fills in.
+            let time = unsafe {
+                let mut time: SYSTEMTIME = std::mem::zeroed();
+                GetLocalTime(&mut time);
+                time
+            };
+            Some(LocalTime {
+                month: time.wMonth as u32,
+                day: time.wDay as u32,
+                // `wDayOfWeek` starts the week on Sunday, at 0
+                weekday: (time.wDayOfWeek as u32 + 6) % 7 + 1,
+                hour: time.wHour as u32,
+                minute: time.wMinute as u32,
+                second: time.wSecond as u32,
+            })
+        }
+    } else {
+        pub fn local_time() -> Option<LocalTime> {
+            None
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Packs bundled into one `.zip` archive, or `.shimeji`, which is the same thing renamed,
+//! so a pack can be downloaded and dropped in as a single file instead of a folder tree.
+//!
+//! An archive holds a pack's XML and everything it names, which are found relative to
+//! the XML instead of the working directory. It's unpacked into the [`cache_dir`], in
+//! a directory named after a hash of the archive's path, size and modification time,
+//! so it's only unpacked again once it changes.
+//!
+//! Only entries that are stored or deflated are read, which is what zip tools write
+//! unless told otherwise. No entry may unpack to more than [`MAX_ENTRY_SIZE`], nor all of
+//! them to more than [`MAX_UNPACKED_SIZE`], so a small archive can't fill the disk.
+
+use std::{
+    fs,
+    io::{Cursor, Read as _},
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{ensure, Context as _};
+use sha2::{Digest as _, Sha256};
+use zip::ZipArchive;
+
+use crate::frame_store::cache_dir;
+
+/// The most one file in an archive may unpack to.
+pub const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+/// The most everything in an archive may unpack to.
+pub const MAX_UNPACKED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Whether `path` is a pack archive, going by its extension.
+pub fn is_archive(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("zip") || extension.eq_ignore_ascii_case("shimeji")
+    })
+}
+
+/// A file in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Where it goes, relative to where the archive is unpacked.
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+/// Every file in the zip archive `archive`, leaving out directories.
+pub fn entries(archive: &[u8]) -> anyhow::Result<Vec<Entry>> {
+    entries_capped(archive, MAX_ENTRY_SIZE, MAX_UNPACKED_SIZE)
+}
+
+/// [`entries`], with `max_entry` and `max_total` in place of [`MAX_ENTRY_SIZE`]
+/// and [`MAX_UNPACKED_SIZE`].
+pub(crate) fn entries_capped(
+    archive: &[u8],
+    max_entry: u64,
+    max_total: u64,
+) -> anyhow::Result<Vec<Entry>> {
+    let mut archive = ZipArchive::new(Cursor::new(archive)).context("not a zip archive")?;
+    let mut entries = vec![];
+    let mut total = 0;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_owned();
+        let path = entry_path(&name)?;
+        // the sizes are only what the archive claims, so they're checked again while reading
+        let cap = max_entry.min(max_total - total);
+        ensure!(
+            file.size() <= cap,
+            "{name} would unpack to {} bytes, more than the {cap} allowed",
+            file.size()
+        );
+        let mut contents = vec![];
+        file.by_ref()
+            .take(cap + 1)
+            .read_to_end(&mut contents)
+            .with_context(|| format!("could not unpack {name}"))?;
+        let size = contents.len() as u64;
+        ensure!(
+            size <= cap && size == file.size(),
+            "{name} is bigger than it says"
+        );
+        total += size;
+        entries.push(Entry { path, contents });
+    }
+    Ok(entries)
+}
+
+/// The entry `name` as a relative path, refusing any that would land outside of
+/// where the archive is unpacked.
+fn entry_path(name: &str) -> anyhow::Result<PathBuf> {
+    let path = PathBuf::from(name.replace('\\', "/"));
+    let inside = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    ensure!(inside, "{name} would be unpacked outside of the pack");
+    Ok(path)
+}
+
+/// Unpack the archive at `path`, unless it already was,
+/// returning the XML of the pack inside: the one nearest the top.
+pub fn unpack(path: &Path) -> anyhow::Result<PathBuf> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("could not read {}", path.display()))?;
+    // hashed the same by every build, so an update doesn't unpack everything again
+    let mut hasher = Sha256::new();
+    hasher.update(fs::canonicalize(path)?.as_os_str().as_encoded_bytes());
+    hasher.update(metadata.len().to_le_bytes());
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_nanos());
+    hasher.update(modified.to_le_bytes());
+    let directory = cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("archives")
+        .join(format!(
+            "{:016x}",
+            u64::from_be_bytes(hasher.finalize()[..8].try_into()?)
+        ));
+    let xml = |directory: &Path| {
+        pack_xml(directory).with_context(|| format!("{} has no pack XML in it", path.display()))
+    };
+    if directory.is_dir() {
+        return xml(&directory);
+    }
+    let archive = fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+    let entries =
+        entries(&archive).with_context(|| format!("could not unpack {}", path.display()))?;
+    // unpacked beside where it goes, so a half unpacked archive is never taken for a whole one
+    let partial = directory.with_extension("partial");
+    fs::remove_dir_all(&partial).ok();
+    for entry in entries {
+        let file = partial.join(&entry.path);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file, entry.contents)
+            .with_context(|| format!("could not unpack {}", file.display()))?;
+    }
+    fs::rename(&partial, &directory)
+        .with_context(|| format!("could not unpack {}", path.display()))?;
+    log::info!("Unpacked {} into {}", path.display(), directory.display());
+    xml(&directory)
+}
+
+/// The XML file least deep in `directory`, the first by name of those as deep.
+fn pack_xml(directory: &Path) -> Option<PathBuf> {
+    let mut level = vec![directory.to_owned()];
+    while !level.is_empty() {
+        let mut files = vec![];
+        let mut directories = vec![];
+        for directory in level {
+            for entry in fs::read_dir(directory).ok()?.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    directories.push(path);
+                } else if path.extension().is_some_and(|extension| extension == "xml") {
+                    files.push(path);
+                }
+            }
+        }
+        if let Some(file) = files.into_iter().min() {
+            return Some(file);
+        }
+        level = directories;
+    }
+    None
+}
@@ -0,0 +1,82 @@
+//! Playing the sounds packs tie to frames, e.g. `<frame number="2" file="walk2.png" sound="step.ogg"/>`.
+//!
+//! Sounds are decoded and mixed by rodio on a thread of their own, which holds the one
+//! output stream the process opens, so a bucket thread never waits on one.
+//! `.ogg`, `.wav`, `.flac` and `.mp3` files play on every platform.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{
+        mpsc::{self, Sender},
+        OnceLock,
+    },
+    thread,
+};
+
+use anyhow::Context as _;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Source as _};
+
+use crate::log_throttle::once;
+
+/// Where sounds are sent to be played, `None` if the sound thread couldn't be started.
+static PLAYER: OnceLock<Option<Sender<String>>> = OnceLock::new();
+
+/// Start playing the sound at `path`, logging once if it can't be.
+pub fn play(path: &str) {
+    if !Path::new(path).exists() {
+        once!(path, log::Level::Warn, "Sound {path} does not exist");
+        return;
+    }
+    if let Err(why) = start(path) {
+        once!(path, log::Level::Warn, "Could not play {path}: {why:#}");
+    }
+}
+
+fn start(path: &str) -> anyhow::Result<()> {
+    PLAYER
+        .get_or_init(spawn_player)
+        .as_ref()
+        .context("the sound thread couldn't be started")?
+        .send(path.to_owned())
+        .ok()
+        .context("there's no sound output to play it on")
+}
+
+/// Start the thread that plays every sound sent to the returned sender.
+/// It stops, dropping the receiver, if there's no output to play them on.
+fn spawn_player() -> Option<Sender<String>> {
+    let (sender, receiver) = mpsc::channel::<String>();
+    let spawned = thread::Builder::new()
+        .name(String::from("Sound thread"))
+        .spawn(move || {
+            // sounds stop once it's dropped, and it can't be moved to another thread
+            let (_stream, output) = match OutputStream::try_default() {
+                Ok(opened) => opened,
+                Err(why) => {
+                    log::warn!("No sounds will play: {why}");
+                    return;
+                }
+            };
+            for path in receiver {
+                if let Err(why) = play_on(&output, &path) {
+                    once!(&path, log::Level::Warn, "Could not play {path}: {why:#}");
+                }
+            }
+        });
+    match spawned {
+        Ok(_) => Some(sender),
+        Err(why) => {
+            log::warn!("No sounds will play: {why}");
+            None
+        }
+    }
+}
+
+fn play_on(output: &OutputStreamHandle, path: &str) -> anyhow::Result<()> {
+    let file = BufReader::new(File::open(path)?);
+    let sound = Decoder::new(file)?;
+    output.play_raw(sound.convert_samples())?;
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Choosing between X11 and Wayland on Linux.
+//!
+//! Wayland windows are plain winit toplevels: winit has no layer-shell support,
+//! and Wayland doesn't let clients place their own windows, so shimejis stay
+//! wherever the compositor puts them. X11 (or XWayland) is preferred whenever
+//! it is available for that reason.
+
+use std::{env, ffi::OsString, str::FromStr};
+
+use derive_more::derive::{Display, Error};
+use winit::{
+    error::EventLoopError,
+    event_loop::EventLoop,
+    platform::{wayland::EventLoopBuilderExtWayland, x11::EventLoopBuilderExtX11},
+};
+
+/// The Wayland app id and X11 WM_CLASS of every window,
+/// for users who want window manager rules to match shimejis.
+pub const APP_ID: &str = "new-shimeji";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    X11,
+    Wayland,
+}
+
+#[derive(Debug, Display, Error)]
+#[display("unknown backend {name:?}, expected x11 or wayland")]
+pub struct UnknownBackend {
+    name: String,
+}
+
+/// The requested backend has no display server to talk to.
+#[derive(Debug, Display, Error)]
+#[display("the {backend:?} backend needs {variable} to be set, is a display server running?")]
+pub struct BackendUnavailable {
+    #[error(not(source))]
+    backend: Backend,
+    variable: &'static str,
+}
+
+impl FromStr for Backend {
+    type Err = UnknownBackend;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "x11" => Ok(Self::X11),
+            "wayland" => Ok(Self::Wayland),
+            _ => Err(UnknownBackend {
+                name: String::from(s),
+            }),
+        }
+    }
+}
+
+impl Backend {
+    /// X11 if there is an X server (including XWayland) to talk to, otherwise Wayland.
+    pub fn detect() -> Self {
+        Self::detect_from(env::var_os("DISPLAY"), env::var_os("WAYLAND_DISPLAY"))
+    }
+    pub fn detect_from(display: Option<OsString>, wayland_display: Option<OsString>) -> Self {
+        let set = |var: &Option<OsString>| var.as_ref().is_some_and(|var| !var.is_empty());
+        if !set(&display) && set(&wayland_display) {
+            Self::Wayland
+        } else {
+            Self::X11
+        }
+    }
+    /// The environment variable pointing at this backend's display server.
+    pub fn display_variable(self) -> &'static str {
+        match self {
+            Self::X11 => "DISPLAY",
+            Self::Wayland => "WAYLAND_DISPLAY",
+        }
+    }
+    pub fn ensure_available(self) -> Result<(), BackendUnavailable> {
+        self.ensure_available_from(env::var_os(self.display_variable()))
+    }
+    /// Like [`Backend::ensure_available`], with `display` as the value of its variable.
+    pub fn ensure_available_from(
+        self,
+        display: Option<OsString>,
+    ) -> Result<(), BackendUnavailable> {
+        let variable = self.display_variable();
+        match display {
+            Some(value) if !value.is_empty() => Ok(()),
+            _ => Err(BackendUnavailable {
+                backend: self,
+                variable,
+            }),
+        }
+    }
+    /// The backend passed with `--backend <name>` or `--backend=<name>`, if any.
+    pub fn from_args(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<Option<Self>, UnknownBackend> {
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--backend=") {
+                return name.parse().map(Some);
+            }
+            if arg == "--backend" {
+                let name = args.next().unwrap_or_default();
+                return name.parse().map(Some);
+            }
+        }
+        Ok(None)
+    }
+    pub fn build_event_loop<T>(self) -> Result<EventLoop<T>, EventLoopError> {
+        let mut builder = EventLoop::with_user_event();
+        match self {
+            Self::X11 => builder.with_x11(),
+            Self::Wayland => builder.with_wayland(),
+        };
+        builder.build()
+    }
+}
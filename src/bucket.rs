@@ -2,7 +2,11 @@
 pub enum BucketError {
     DoubleInit,
     NotRunning,
+    /// Its thread stopped listening, e.g. because it crashed.
+    ThreadGone,
     Io(std::io::Error),
+    /// No renderer could draw to a window it was given.
+    Render(RenderError),
 }
 
 /// A bucket of Shimejis, for one thread.
@@ -18,7 +22,13 @@ pub struct ShimejiBucket {
     thread: Option<JoinHandle<()>>,
     should_exit: Arc<AtomicBool>,
     currently_responsible_shimejis: usize,
+    /// Whether its shimejis are drawn on an overlay, see [`ShimejiBucket::set_overlay`].
+    overlaid: bool,
     sender: Option<Sender<BucketThreadMessage<'static>>>,
+    /// Shared with every other bucket's thread.
+    neighborhood: Neighborhood,
+    /// How many times its thread crashed and was started again, see [`ShimejiBucket::restart`].
+    respawns: u32,
 }
 
 impl PartialEq for ShimejiBucket {
@@ -30,12 +40,150 @@ impl Eq for ShimejiBucket {}
 
 #[derive(Debug)]
 pub enum BucketThreadMessage<'a> {
-    Add(Arc<Window>, Pixels<'a>, Arc<ShimejiData>),
+    /// A shimeji and the surface of its window, or `None` to draw it on the [`Overlay`](BucketThreadMessage::Overlay).
+    Add(
+        Arc<Window>,
+        Option<Box<dyn Renderer + 'a>>,
+        Arc<ShimejiData>,
+    ),
+    /// A window over the whole desktop to draw shimejis added without a surface on,
+    /// see [`crate::overlay`].
+    Overlay(Arc<Window>),
+    AddProp(Arc<Window>, Box<dyn Renderer + 'a>, Arc<PropData>),
     Resized {
         id: WindowId,
         size: PhysicalSize<u32>,
     },
+    /// Something happened to a shimeji's window, see [`ShimejiInputEvent`].
+    WindowEvent(WindowId, ShimejiInputEvent),
     Remove(WindowId),
+    /// Pick up a shimeji, holding it at `offset` relative to its window.
+    Grab {
+        id: WindowId,
+        offset: PhysicalPosition<f64>,
+    },
+    /// Move a held shimeji so its grab offset lines up with `position`.
+    Drag {
+        id: WindowId,
+        position: PhysicalPosition<f64>,
+    },
+    Release {
+        id: WindowId,
+        velocity: (f64, f64),
+    },
+    Pet(WindowId),
+    /// Make a shimeji hyperactive, see [`ManagerEvent::StartParty`](crate::ManagerEvent).
+    Party(WindowId),
+    /// Have every shimeji switch to `behavior` and turn toward the `corner` of its monitor
+    /// a notification popped up in, see [`crate::notifications`].
+    Alert {
+        behavior: String,
+        corner: Corner,
+    },
+    /// Boxed, it's much bigger than every other message.
+    World(Box<WorldSnapshot>),
+    /// Where to report back to the manager, e.g. once a window is removed.
+    Manager(EventLoopProxy<ManagerEvent>),
+    Steer {
+        id: WindowId,
+        command: MovementCommand,
+    },
+    /// Step through a shimeji for debugging.
+    Step {
+        id: WindowId,
+        command: StepCommand,
+    },
+    /// The shimeji's pack was loaded again, see [`crate::hot_reload`].
+    Reload {
+        id: WindowId,
+        data: Arc<ShimejiData>,
+    },
+    /// More of the shimeji's frames were decoded in the background,
+    /// see [`Undecoded::decode`](crate::loader::Undecoded::decode).
+    SwapFrames {
+        id: WindowId,
+        data: Arc<ShimejiData>,
+    },
+    /// Sent through a [`ShimejiHandle`](crate::handle::ShimejiHandle).
+    Control {
+        id: WindowId,
+        control: Control,
+    },
+    /// Freeze every shimeji and prop of the bucket where they are, drawing nothing
+    /// until [`BucketThreadMessage::Resume`]. Their windows stay open, showing their last frame.
+    Pause,
+    Resume,
+}
+
+/// The windows a bucket thread had when it panicked, handed back to the manager through
+/// [`ManagerEvent::BucketCrashed`] so [`ShimejiBucket::restart`] can give them to a new thread.
+/// Their surfaces are dropped, as a lost device is a likely reason for the panic.
+#[derive(Debug, Clone, Default)]
+pub struct Orphans {
+    pub shimejis: Vec<(Arc<Window>, Arc<ShimejiData>)>,
+    pub props: Vec<(Arc<Window>, Arc<PropData>)>,
+    pub overlay: Option<Arc<Window>>,
+}
+
+/// What a bucket thread hears about its windows from winit, as it can't listen itself.
+///
+/// Positions are relative to the top left of the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShimejiInputEvent {
+    CursorEntered,
+    CursorLeft,
+    CursorMoved(PhysicalPosition<f64>),
+    MouseInput {
+        button: MouseButton,
+        pressed: bool,
+    },
+    Focused(bool),
+    ScaleFactorChanged(f64),
+    /// The window can't be seen at all, e.g. behind a fullscreen window, or can again.
+    Occluded(bool),
+}
+
+impl ShimejiInputEvent {
+    /// The part of `event` a bucket thread cares about, if any.
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match *event {
+            WindowEvent::CursorEntered { .. } => Self::CursorEntered,
+            WindowEvent::CursorLeft { .. } => Self::CursorLeft,
+            WindowEvent::CursorMoved { position, .. } => Self::CursorMoved(position),
+            WindowEvent::MouseInput { button, state, .. } => Self::MouseInput {
+                button,
+                pressed: state.is_pressed(),
+            },
+            WindowEvent::Focused(focused) => Self::Focused(focused),
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                Self::ScaleFactorChanged(scale_factor)
+            }
+            WindowEvent::Occluded(occluded) => Self::Occluded(occluded),
+            _ => return None,
+        })
+    }
+}
+
+/// Steers a single shimeji from any thread, without going through its bucket.
+#[derive(Debug, Clone)]
+pub struct SteeringHandle {
+    id: WindowId,
+    sender: Sender<BucketThreadMessage<'static>>,
+}
+
+impl SteeringHandle {
+    pub fn id(&self) -> WindowId {
+        self.id
+    }
+    /// Returns `false` if the shimeji's bucket thread has stopped.
+    pub fn steer(&self, command: MovementCommand) -> bool {
+        self.sender
+            .send(BucketThreadMessage::Steer {
+                id: self.id,
+                command,
+            })
+            .is_ok()
+    }
 }
 
 use std::{
@@ -45,18 +193,33 @@ use std::{
         Arc,
     },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use anyhow::Context;
 use derive_more::derive::{Display, Error, From};
-use pixels::{Pixels, PixelsBuilder, SurfaceTexture};
 use winit::{
-    dpi::PhysicalSize,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{MouseButton, WindowEvent},
+    event_loop::EventLoopProxy,
     raw_window_handle::HasWindowHandle,
     window::{Window, WindowId},
 };
 
-use crate::shimeji::ShimejiData;
+use crate::{
+    handle::Control,
+    interaction::InteractionEvent,
+    loader::PropData,
+    movement::MovementCommand,
+    neighbors::Neighborhood,
+    notifications::Corner,
+    renderer::{self, RenderError, Renderer},
+    shimeji::{ShimejiData, StepCommand},
+    world::WorldSnapshot,
+    ManagerEvent,
+};
+
+/// How often [`ShimejiBucket::join_thread_timeout`] checks whether the thread stopped.
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(5);
 
 impl Drop for ShimejiBucket {
     fn drop(&mut self) {
@@ -70,14 +233,17 @@ impl ShimejiBucket {
     pub fn is_running(&self) -> bool {
         self.is_running
     }
-    pub fn new(id: usize, should_exit: Arc<AtomicBool>) -> Self {
+    pub fn new(id: usize, should_exit: Arc<AtomicBool>, neighborhood: Neighborhood) -> Self {
         ShimejiBucket {
             id,
             is_running: false,
             thread: None,
             should_exit,
             currently_responsible_shimejis: 0,
+            overlaid: false,
             sender: None,
+            neighborhood,
+            respawns: 0,
         }
     }
     pub fn init(&mut self) -> Result<(), BucketError> {
@@ -87,11 +253,12 @@ impl ShimejiBucket {
         let should_exit = self.should_exit.clone();
         log::trace!("Initting bucket id: {}", &self.id);
         let (sender, receiver) = mpsc::channel();
-        let id = self.id.clone();
+        let id = self.id;
+        let neighborhood = self.neighborhood.clone();
         let thread = thread::Builder::new()
             .name(format!("Bucket {} thread", &self.id))
             .spawn(move || {
-                crate::shimeji::loop_for_shimeji_execution(receiver, should_exit, id);
+                crate::shimeji::loop_for_shimeji_execution(receiver, should_exit, id, neighborhood);
             })?;
         self.sender = Some(sender.clone());
         self.thread = Some(thread);
@@ -111,31 +278,163 @@ impl ShimejiBucket {
         self.is_running = false;
         Ok(())
     }
+    /// Stop the thread like [`ShimejiBucket::join_thread`], but give up on it after `timeout`,
+    /// leaving it to finish on its own. Returns whether it stopped in time.
+    ///
+    /// The thread only stops once [`ShimejiBucket::new`]'s `should_exit` is set,
+    /// or every sender is gone.
+    pub fn join_thread_timeout(&mut self, timeout: Duration) -> bool {
+        if !self.is_running || self.thread.is_none() {
+            return true;
+        }
+        drop(self.sender.take());
+        let deadline = Instant::now() + timeout;
+        let thread = self.thread.take().unwrap();
+        while !thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+        self.is_running = false;
+        if !thread.is_finished() {
+            log::warn!(
+                "Bucket {} thread didn't stop in {timeout:?}, leaving it",
+                self.id
+            );
+            return false;
+        }
+        match thread.join() {
+            Ok(_) => log::debug!("Thread joined successfully on id {}", self.id),
+            Err(why) => log::error!("THREAD JOIN ERROR on id {}: {why:?}", self.id),
+        };
+        true
+    }
+    /// Start a new thread in place of one that crashed, and give it back the windows
+    /// the old one had, so their shimejis carry on from where their windows are.
+    ///
+    /// # Errors
+    /// Errors if the new thread can't be started, or no renderer can draw to one of the windows.
+    pub fn restart(
+        &mut self,
+        orphans: Orphans,
+        proxy: EventLoopProxy<ManagerEvent>,
+    ) -> Result<(), BucketError> {
+        // it already stopped, having handed its windows back
+        self.join_thread()?;
+        self.respawns += 1;
+        self.currently_responsible_shimejis = 0;
+        self.overlaid = false;
+        self.init()?;
+        self.connect_manager(proxy)?;
+        if let Some(window) = orphans.overlay {
+            self.send(BucketThreadMessage::Overlay(window))?;
+            self.overlaid = true;
+        }
+        for (window, prop) in orphans.props {
+            let pixels = renderer::create(&window, prop.width, prop.height)?;
+            self.send(BucketThreadMessage::AddProp(window, pixels, prop))?;
+        }
+        for (window, shimeji) in orphans.shimejis {
+            self.add_window(shimeji, window)?;
+        }
+        Ok(())
+    }
+    /// How many times its thread crashed and was started again.
+    pub fn respawns(&self) -> u32 {
+        self.respawns
+    }
+    fn send(&self, message: BucketThreadMessage<'static>) -> Result<(), BucketError> {
+        let sender = self.sender.as_ref().ok_or(BucketError::NotRunning)?;
+        sender.send(message).map_err(|_| BucketError::ThreadGone)
+    }
     ///
     /// # Errors
-    /// Errors if `!self.is_running` or if `self.sender` == `None`.
+    /// Errors if `!self.is_running` or if `self.sender` == `None`,
+    /// or if no renderer can draw to `window`.
     pub fn add(&mut self, shimeji: Arc<ShimejiData>, window: Window) -> Result<(), BucketError> {
+        self.add_window(shimeji, Arc::new(window))
+    }
+    fn add_window(
+        &mut self,
+        shimeji: Arc<ShimejiData>,
+        rc: Arc<Window>,
+    ) -> Result<(), BucketError> {
         if !self.is_running {
             return Err(BucketError::NotRunning);
         }
-        self.currently_responsible_shimejis += 1;
-        let sender = self.sender.as_ref().ok_or(BucketError::NotRunning)?;
-
-        let rc = Arc::new(window);
-        let pixels = {
-            let window_size = rc.inner_size();
-            let surface_texture =
-                SurfaceTexture::new(window_size.width, window_size.height, Arc::clone(&rc));
-            PixelsBuilder::new(shimeji.width, shimeji.height, surface_texture)
-                .build()
-                .unwrap()
+        let pixels = match self.overlaid {
+            true => None,
+            false => Some(renderer::create(&rc, shimeji.width, shimeji.height)?),
         };
+        self.currently_responsible_shimejis += 1;
         assert!(rc.window_handle().is_ok());
-        sender
-            .send(BucketThreadMessage::Add(rc, pixels, shimeji))
-            .unwrap();
+        self.send(BucketThreadMessage::Add(rc, pixels, shimeji))
+    }
+    /// Draw every shimeji added from now on into `window`, which should cover the desktop,
+    /// instead of giving each a surface of its own.
+    ///
+    /// # Errors
+    /// Errors if `!self.is_running` or if `self.sender` == `None`.
+    pub fn set_overlay(&mut self, window: Window) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::Overlay(Arc::new(window)))?;
+        self.overlaid = true;
+        Ok(())
+    }
+    /// Close the window of the shimeji `id` and stop managing it.
+    ///
+    /// # Errors
+    /// Errors if `!self.is_running` or if `self.sender` == `None`.
+    pub fn remove(&mut self, id: WindowId) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::Remove(id))?;
+        self.currently_responsible_shimejis = self.currently_responsible_shimejis.saturating_sub(1);
         Ok(())
     }
+    pub fn connect_manager(
+        &mut self,
+        proxy: EventLoopProxy<ManagerEvent>,
+    ) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::Manager(proxy))
+    }
+    pub fn start_partying(&mut self, id: WindowId) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::Party(id))
+    }
+    /// Have this bucket's shimejis react to a notification in `corner`.
+    pub fn alert(&mut self, behavior: String, corner: Corner) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::Alert { behavior, corner })
+    }
+    /// Let this bucket's thread know how the rest of the world looks.
+    pub fn update_world(&mut self, world: WorldSnapshot) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::World(Box::new(world)))
+    }
+    /// Hand a prop's window over to this bucket's thread.
+    ///
+    /// # Errors
+    /// Errors if `!self.is_running` or if `self.sender` == `None`,
+    /// or if no renderer can draw to `window`.
+    pub fn add_prop(&mut self, prop: Arc<PropData>, window: Window) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        let rc = Arc::new(window);
+        let pixels = renderer::create(&rc, prop.width, prop.height)?;
+        self.send(BucketThreadMessage::AddProp(rc, pixels, prop))
+    }
     pub fn was_resized(
         &mut self,
         id: WindowId,
@@ -144,12 +443,79 @@ impl ShimejiBucket {
         if !self.is_running {
             return Err(BucketError::NotRunning);
         }
+        self.send(BucketThreadMessage::Resized { id, size })
+    }
+    /// Forward something that happened to one of this bucket's windows to its thread.
+    pub fn window_event(
+        &mut self,
+        id: WindowId,
+        event: ShimejiInputEvent,
+    ) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::WindowEvent(id, event))
+    }
+    /// Forward pointer input on one of this bucket's shimejis to its thread.
+    pub fn interact(&mut self, event: InteractionEvent) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(match event {
+            InteractionEvent::Grab { id, offset } => BucketThreadMessage::Grab { id, offset },
+            InteractionEvent::Drag { id, position } => BucketThreadMessage::Drag { id, position },
+            InteractionEvent::Release { id, velocity } => {
+                BucketThreadMessage::Release { id, velocity }
+            }
+            InteractionEvent::Pet(id) => BucketThreadMessage::Pet(id),
+        })
+    }
+    pub fn debug_step(&mut self, id: WindowId, command: StepCommand) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::Step { id, command })
+    }
+    /// Apply a [`Control`] from a shimeji's handle.
+    /// [`Control::Despawn`] is up to the manager, see [`ShimejiBucket::remove`].
+    pub fn control(&mut self, id: WindowId, control: Control) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::Control { id, control })
+    }
+    /// Swap the data of the shimeji in window `id` for `data`, loaded again from disk.
+    pub fn reload(&mut self, id: WindowId, data: Arc<ShimejiData>) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::Reload { id, data })
+    }
+    /// Swap the data of the shimeji in window `id` for `data`, the same pack with more of
+    /// its frames decoded, without starting it over.
+    pub fn swap_frames(&mut self, id: WindowId, data: Arc<ShimejiData>) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(BucketThreadMessage::SwapFrames { id, data })
+    }
+    /// Freeze this bucket's shimejis and props, or let them carry on.
+    pub fn set_paused(&mut self, paused: bool) -> Result<(), BucketError> {
+        if !self.is_running {
+            return Err(BucketError::NotRunning);
+        }
+        self.send(match paused {
+            true => BucketThreadMessage::Pause,
+            false => BucketThreadMessage::Resume,
+        })
+    }
+    /// Get a handle that can steer the shimeji in window `id` from another thread.
+    pub fn steering_handle(&self, id: WindowId) -> Result<SteeringHandle, BucketError> {
         let sender = self.sender.as_ref().ok_or(BucketError::NotRunning)?;
-        sender
-            .send(BucketThreadMessage::Resized { id, size })
-            .context("should be able to send resized message")
-            .unwrap();
-        Ok(())
+        Ok(SteeringHandle {
+            id,
+            sender: sender.clone(),
+        })
     }
     pub fn contained_shimejis(&self) -> usize {
         self.currently_responsible_shimejis
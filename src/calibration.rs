@@ -0,0 +1,176 @@
+//! Lets the user drag a line to wherever the floor should be on each monitor,
+//! for when panels and taskbars make shimejis hover or sink.
+
+use std::{collections::HashMap, sync::Arc};
+
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, MouseButton, WindowEvent},
+    event_loop::ActiveEventLoop,
+    window::{Window, WindowAttributes, WindowId},
+};
+
+use crate::{
+    renderer::{self, Renderer},
+    rgba::{PixelFormat, Rgba},
+    world::WorldSnapshot,
+};
+
+/// How tall the floor line is, in pixels.
+const LINE_THICKNESS: u32 = 4;
+/// In [`PixelFormat::Rgba8`].
+const LINE_COLOR: [u8; 4] = [255, 64, 160, 255];
+
+/// A monitor's floor, as the user placed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalibratedFloor {
+    pub monitor: String,
+    /// How far above the bottom of the monitor the floor is.
+    pub offset: i32,
+}
+
+#[derive(derive_more::Debug)]
+struct FloorLine {
+    window: Arc<Window>,
+    #[debug(skip)]
+    pixels: Box<dyn Renderer>,
+    monitor: String,
+    /// The bottom edge of the monitor, in desktop coordinates.
+    monitor_bottom: i32,
+    /// Where the cursor is, relative to the line.
+    cursor: PhysicalPosition<f64>,
+    /// Where the line was grabbed, relative to the line, while it is being dragged.
+    held_at: Option<PhysicalPosition<f64>>,
+}
+
+impl FloorLine {
+    /// How far above the bottom of the monitor the line's bottom edge is.
+    fn offset(&self) -> i32 {
+        let top = self
+            .window
+            .outer_position()
+            .map(|position| position.y)
+            .unwrap_or(self.monitor_bottom);
+        self.monitor_bottom - (top + LINE_THICKNESS as i32)
+    }
+    fn draw(&mut self) {
+        let format = self
+            .pixels
+            .layout()
+            .map_or(PixelFormat::Rgba8, |(_, format)| format);
+        let color = Rgba::read_as(PixelFormat::Rgba8, &LINE_COLOR);
+        for pixel in self.pixels.frame_mut().chunks_exact_mut(4) {
+            color.write_as(format, pixel);
+        }
+        if let Err(why) = self.pixels.render() {
+            log::error!("Could not draw floor line: {why}");
+        }
+    }
+}
+
+/// One draggable floor line per monitor, each gone once the user drops it.
+#[derive(Debug, Default)]
+pub struct Calibration {
+    lines: HashMap<WindowId, FloorLine>,
+}
+
+impl Calibration {
+    /// Put a floor line on every named monitor, where its floor currently is.
+    ///
+    /// Lines are made from `attributes`, so they look like every other window.
+    pub fn start(
+        event_loop: &ActiveEventLoop,
+        attributes: &WindowAttributes,
+        world: &WorldSnapshot,
+    ) -> Self {
+        let mut lines = HashMap::new();
+        for monitor in event_loop.available_monitors() {
+            let Some(name) = monitor.name() else {
+                log::warn!("Cannot calibrate a monitor with no name, skipping it");
+                continue;
+            };
+            let (position, size) = (monitor.position(), monitor.size());
+            let monitor_bottom = position.y + size.height as i32;
+            let top = monitor_bottom - world.floor_offset(Some(&name)) - LINE_THICKNESS as i32;
+            let attributes = attributes
+                .clone()
+                .with_title(format!("shimeji floor: {name}"))
+                .with_inner_size(PhysicalSize::new(size.width, LINE_THICKNESS))
+                .with_position(PhysicalPosition::new(position.x, top));
+            let window = match event_loop.create_window(attributes) {
+                Ok(window) => Arc::new(window),
+                Err(why) => {
+                    log::error!("Could not create floor line for {name}: {why}");
+                    continue;
+                }
+            };
+            let pixels = match renderer::create(&window, size.width, LINE_THICKNESS) {
+                Ok(pixels) => pixels,
+                Err(why) => {
+                    log::error!("Could not draw floor line for {name}: {why}");
+                    continue;
+                }
+            };
+            let mut line = FloorLine {
+                window,
+                pixels,
+                monitor: name,
+                monitor_bottom,
+                cursor: PhysicalPosition::default(),
+                held_at: None,
+            };
+            line.draw();
+            lines.insert(line.window.id(), line);
+        }
+        Self { lines }
+    }
+    pub fn owns(&self, id: WindowId) -> bool {
+        self.lines.contains_key(&id)
+    }
+    /// Whether every line has been placed.
+    pub fn is_done(&self) -> bool {
+        self.lines.is_empty()
+    }
+    /// Drag the line in window `id` around with the left mouse button.
+    ///
+    /// Returns the floor once the line is dropped, closing its window.
+    pub fn handle(&mut self, id: WindowId, event: &WindowEvent) -> Option<CalibratedFloor> {
+        let line = self.lines.get_mut(&id)?;
+        match *event {
+            WindowEvent::RedrawRequested => line.draw(),
+            WindowEvent::Resized(size) => {
+                if let Err(why) = line.pixels.resize_surface(size.width, size.height) {
+                    log::error!("Could not resize floor line: {why}");
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                line.cursor = position;
+                let (Some(held_at), Ok(outer)) = (line.held_at, line.window.outer_position())
+                else {
+                    return None;
+                };
+                // only up and down, the line always spans the monitor
+                let top = outer.y + (position.y - held_at.y).round() as i32;
+                line.window
+                    .set_outer_position(PhysicalPosition::new(outer.x, top));
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => match state {
+                ElementState::Pressed => line.held_at = Some(line.cursor),
+                ElementState::Released if line.held_at.is_some() => {
+                    let line = self.lines.remove(&id)?;
+                    return Some(CalibratedFloor {
+                        offset: line.offset(),
+                        monitor: line.monitor,
+                    });
+                }
+                ElementState::Released => (),
+            },
+            _ => (),
+        }
+        None
+    }
+}
@@ -0,0 +1,170 @@
+//! What this platform and session support, so features that would otherwise silently
+//! degrade can be explained to users (with `--capabilities`) and to embedders.
+
+use std::fmt;
+
+#[cfg(target_os = "linux")]
+use crate::backend::Backend;
+
+/// The command line flag that prints the [`Capabilities`] and exits.
+pub const FLAG: &str = "--capabilities";
+
+/// Whether a feature works here, and why not if it doesn't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Support {
+    Yes,
+    No(&'static str),
+}
+
+impl Support {
+    pub fn is_supported(self) -> bool {
+        self == Support::Yes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// See-through windows, instead of a box around every sprite.
+    pub transparency: Support,
+    /// Letting clicks on a shimeji's transparent pixels through to the window below.
+    pub click_through: Support,
+    /// Wayland's layer-shell, for windows that stay above everything else.
+    pub layer_shell: Support,
+    /// Knowing where the cursor is when it isn't over a shimeji.
+    pub global_cursor: Support,
+    /// Finding other applications' windows for shimejis to stand on.
+    pub window_enumeration: Support,
+    /// The tray icon and its menu.
+    pub tray: Support,
+    /// Telling when the user is away, for shimejis to fall asleep, see [`crate::idle`].
+    pub idle_detection: Support,
+    /// Telling which application is focused, for shimejis to react to, see [`crate::focus`].
+    pub focus_tracking: Support,
+    /// Hearing about other applications' notifications, for shimejis to react to,
+    /// see [`crate::notifications`].
+    pub notifications: Support,
+}
+
+impl Capabilities {
+    /// Every feature with its name, in the order they are printed.
+    pub fn features(&self) -> [(&'static str, Support); 9] {
+        [
+            ("transparency", self.transparency),
+            ("click-through", self.click_through),
+            ("layer-shell", self.layer_shell),
+            ("global cursor tracking", self.global_cursor),
+            ("other-window enumeration", self.window_enumeration),
+            ("tray", self.tray),
+            ("idle detection", self.idle_detection),
+            ("focus tracking", self.focus_tracking),
+            ("notification reactions", self.notifications),
+        ]
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, support) in self.features() {
+            match support {
+                Support::Yes => writeln!(f, "{name}: yes")?,
+                Support::No(why) => writeln!(f, "{name}: no, {why}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `--capabilities` was passed.
+pub fn wants_report(mut args: impl Iterator<Item = String>) -> bool {
+    args.any(|arg| arg == FLAG)
+}
+
+/// What is supported on this platform, using the backend picked by [`Backend::detect`] on Linux.
+pub fn capabilities() -> Capabilities {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            for_backend(Backend::detect())
+        } else {
+            Capabilities {
+                transparency: Support::Yes,
+                click_through: NO_CLICK_THROUGH,
+                layer_shell: Support::No("layer-shell is a Wayland protocol"),
+                global_cursor: NO_GLOBAL_CURSOR,
+                window_enumeration: if cfg!(target_os = "windows") {
+                    Support::Yes
+                } else {
+                    Support::No("finding other windows isn't supported on this platform")
+                },
+                tray: if cfg!(target_os = "windows") {
+                    Support::No("there is no tray icon on Windows yet")
+                } else {
+                    Support::Yes
+                },
+                idle_detection: if cfg!(target_os = "windows") {
+                    Support::Yes
+                } else {
+                    Support::No("idle detection isn't supported on this platform")
+                },
+                focus_tracking: if cfg!(target_os = "windows") {
+                    Support::Yes
+                } else {
+                    Support::No("finding the focused window isn't supported on this platform")
+                },
+                notifications: Support::No(
+                    "other applications' notifications can't be seen on this platform",
+                ),
+            }
+        }
+    }
+}
+
+/// What is supported on Linux when running on `backend`.
+#[cfg(target_os = "linux")]
+pub fn for_backend(backend: Backend) -> Capabilities {
+    let transparency = match backend {
+        Backend::Wayland => Support::Yes,
+        Backend::X11 => match crate::compositor::is_compositing() {
+            Ok(true) => Support::Yes,
+            Ok(false) => Support::No("no compositing manager is running"),
+            Err(why) => {
+                log::debug!("Could not ask the X server about compositing: {why}");
+                Support::No("the X server couldn't be reached")
+            }
+        },
+    };
+    let bus = std::env::var_os("DBUS_SESSION_BUS_ADDRESS").is_some_and(|bus| !bus.is_empty());
+    Capabilities {
+        transparency,
+        click_through: NO_CLICK_THROUGH,
+        layer_shell: match backend {
+            Backend::Wayland => Support::No("winit has no layer-shell support"),
+            Backend::X11 => Support::No("layer-shell is a Wayland protocol"),
+        },
+        global_cursor: NO_GLOBAL_CURSOR,
+        window_enumeration: match backend {
+            Backend::X11 => Support::Yes,
+            Backend::Wayland => Support::No("Wayland doesn't let clients see each other's windows"),
+        },
+        tray: if bus {
+            Support::Yes
+        } else {
+            Support::No("there is no D-Bus session bus for the tray to use")
+        },
+        idle_detection: match backend {
+            Backend::X11 => Support::Yes,
+            Backend::Wayland => Support::No("only input to X11 windows is seen through XWayland"),
+        },
+        focus_tracking: match backend {
+            Backend::X11 => Support::Yes,
+            Backend::Wayland => Support::No("Wayland doesn't say which window is focused"),
+        },
+        notifications: if bus {
+            Support::Yes
+        } else {
+            Support::No("there is no D-Bus session bus to hear notifications on")
+        },
+    }
+}
+
+const NO_CLICK_THROUGH: Support = Support::No("shimeji windows always catch the mouse");
+const NO_GLOBAL_CURSOR: Support = Support::No("the cursor is only seen while it is over a shimeji");
@@ -0,0 +1,116 @@
+//! Asking X11 compositors not to draw shadows or blur behind shimeji windows,
+//! which would otherwise show up as a box around every sprite.
+
+use winit::{
+    raw_window_handle::{HasWindowHandle, RawWindowHandle},
+    window::Window,
+};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{AtomEnum, ConnectionExt as _, PropMode},
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+};
+
+/// A connection to the X server for setting compositor hints on windows.
+#[derive(derive_more::Debug)]
+pub struct CompositorHints {
+    #[debug(skip)]
+    connection: RustConnection,
+    atoms: EffectAtoms,
+}
+
+/// The window properties compositors look at to decide on shadows and blur.
+#[derive(Debug, Clone, Copy)]
+pub struct EffectAtoms {
+    /// picom (and compton before it) skips the shadow of windows with this set to 0.
+    pub compton_shadow: u32,
+    /// KWin draws a shadow for windows with this set.
+    pub kde_shadow: u32,
+    /// KWin blurs behind windows with this set.
+    pub kde_blur: u32,
+}
+
+/// A change to one of a window's properties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyEdit {
+    /// Set the property to this cardinal.
+    Set(u32, u32),
+    Delete(u32),
+}
+
+impl EffectAtoms {
+    /// What to change on a window to turn off its shadow and blur.
+    pub fn edits(self) -> [PropertyEdit; 3] {
+        [
+            PropertyEdit::Set(self.compton_shadow, 0),
+            PropertyEdit::Delete(self.kde_shadow),
+            PropertyEdit::Delete(self.kde_blur),
+        ]
+    }
+}
+
+impl CompositorHints {
+    pub fn connect() -> anyhow::Result<Self> {
+        let (connection, _) = x11rb::connect(None)?;
+        let intern = |name: &str| -> anyhow::Result<u32> {
+            Ok(connection
+                .intern_atom(false, name.as_bytes())?
+                .reply()?
+                .atom)
+        };
+        let atoms = EffectAtoms {
+            compton_shadow: intern("_COMPTON_SHADOW")?,
+            kde_shadow: intern("_KDE_NET_WM_SHADOW")?,
+            kde_blur: intern("_KDE_NET_WM_BLUR_BEHIND_REGION")?,
+        };
+        Ok(Self { connection, atoms })
+    }
+    /// Turn off shadows and blur for `window`. Does nothing for non-X11 windows.
+    pub fn suppress_effects(&self, window: &Window) -> anyhow::Result<()> {
+        let RawWindowHandle::Xlib(handle) = window.window_handle()?.as_raw() else {
+            return Ok(());
+        };
+        let id = handle.window as u32;
+        for edit in self.atoms.edits() {
+            match edit {
+                PropertyEdit::Set(property, value) => {
+                    self.connection.change_property32(
+                        PropMode::REPLACE,
+                        id,
+                        property,
+                        AtomEnum::CARDINAL,
+                        &[value],
+                    )?;
+                }
+                PropertyEdit::Delete(property) => {
+                    self.connection.delete_property(id, property)?;
+                }
+            }
+        }
+        self.connection.flush()?;
+        Ok(())
+    }
+}
+
+/// Turn off shadows and blur for `window` if there are `hints` to do it with.
+pub fn suppress(hints: Option<&CompositorHints>, window: &Window) {
+    let Some(hints) = hints else {
+        return;
+    };
+    if let Err(why) = hints.suppress_effects(window) {
+        log::warn!("Could not set compositor hints: {why}");
+    }
+}
+
+/// Whether a compositing manager is running on the default X screen, without which
+/// transparent windows are drawn over a black box.
+pub fn is_compositing() -> anyhow::Result<bool> {
+    let (connection, screen) = x11rb::connect(None)?;
+    let selection = connection
+        .intern_atom(false, format!("_NET_WM_CM_S{screen}").as_bytes())?
+        .reply()?
+        .atom;
+    let owner = connection.get_selection_owner(selection)?.reply()?.owner;
+    Ok(owner != x11rb::NONE)
+}
@@ -0,0 +1,353 @@
+//! Packs written in TOML or JSON instead of XML, e.g. `shimeji.toml`:
+//!
+//! ```toml
+//! name = "sheet"
+//! width = 32
+//! height = 32
+//!
+//! [[animations]]
+//! name = "idle"
+//! fps = 2
+//! frames = [
+//!   { number = 1, file = "./fuzz/sprite-sheet.png", x = 0, y = 0, w = 32, h = 32 },
+//! ]
+//! ```
+//!
+//! The keys are named like the attributes in `shimeji.xsd`, with a pack's `<Animation>`s
+//! in `animations` and an animation's `<frame>`s in `frames`.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::xml_parser::{
+    every_frame_timed, finish_shimeji, frame_from_attributes, is_known_attribute, parse_loop_mode,
+    parse_value, parse_weight, AnimationXml, PackError, XmlParseError, XmlReturnData,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// The format of the pack at `path`, going by its extension, or `None` for XML.
+    pub fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Format::Toml),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A value from either format, with everything but lists and tables kept as text
+/// so it can be read the same way as an XML attribute.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Scalar(String),
+    List(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+/// Parse a pack written in `format`.
+pub fn parse(text: &str, format: Format) -> Result<XmlReturnData, XmlParseError> {
+    let value = match format {
+        Format::Toml => {
+            let document =
+                text.parse::<toml_edit::DocumentMut>()
+                    .map_err(|why| XmlParseError::Syntax {
+                        message: why.to_string(),
+                    })?;
+            from_toml_table(document.as_table())
+        }
+        Format::Json => Json::new(text).document().ok_or_else(|| {
+            log::error!("Invalid JSON");
+            XmlParseError::MalformedFile
+        })?,
+    };
+    let Value::Table(entries) = value else {
+        return Err(XmlParseError::MalformedFile);
+    };
+    let mut attributes = HashMap::new();
+    let mut animations = vec![];
+    let mut warnings = vec![];
+    for (key, value) in entries {
+        warn_if_unknown("Shimeji", &key, &["animations"], &mut warnings);
+        match (key.as_str(), value) {
+            ("animations", Value::List(list)) => {
+                for animation in list {
+                    animations.push(animation_from(animation, &mut warnings)?);
+                }
+            }
+            ("animations", _) => return Err(XmlParseError::MalformedFile),
+            (_, Value::Scalar(value)) => {
+                attributes.insert(key, value);
+            }
+            _ => {
+                log::debug!("Unrecognized key: {key}");
+            }
+        }
+    }
+    let mut data = finish_shimeji(attributes, animations, vec![], vec![], None)?;
+    data.warnings = warnings;
+    Ok(data)
+}
+
+/// Add a warning to `warnings` if `key` isn't one of the attributes of `element`
+/// in `shimeji.xsd`, or one of `also`, the lists in it.
+fn warn_if_unknown(element: &str, key: &str, also: &[&str], warnings: &mut Vec<PackError>) {
+    if !is_known_attribute(element, key) && !also.contains(&key) {
+        warnings.push(
+            XmlParseError::UnknownKey {
+                key: key.to_owned(),
+            }
+            .into(),
+        );
+    }
+}
+
+/// Parse the pack in the file at `path`, which must be in a [`Format`].
+pub fn parse_file(path: &Path) -> anyhow::Result<XmlReturnData> {
+    let Some(format) = Format::of(path) else {
+        anyhow::bail!("{} isn't a TOML or JSON file", path.display());
+    };
+    let text = fs::read_to_string(path)?;
+    Ok(parse(&text, format).map_err(|why| PackError::from(why).in_file(path))?)
+}
+
+fn animation_from(
+    value: Value,
+    warnings: &mut Vec<PackError>,
+) -> Result<AnimationXml, XmlParseError> {
+    let Value::Table(entries) = value else {
+        return Err(XmlParseError::MalformedFile);
+    };
+    let mut attributes = HashMap::new();
+    let mut frames = vec![];
+    for (key, value) in entries {
+        warn_if_unknown("Animation", &key, &["frames"], warnings);
+        match (key.as_str(), value) {
+            ("frames", Value::List(list)) => {
+                for frame in list {
+                    let Value::Table(entries) = frame else {
+                        return Err(XmlParseError::MalformedFile);
+                    };
+                    let frame = scalars(entries)?;
+                    for key in frame.keys() {
+                        warn_if_unknown("frame", key, &[], warnings);
+                    }
+                    frames.push(frame_from_attributes(frame, Path::new(""))?);
+                }
+            }
+            (_, Value::Scalar(value)) => {
+                attributes.insert(key, value);
+            }
+            _ => return Err(XmlParseError::MalformedFile),
+        }
+    }
+    let name = attributes
+        .remove("name")
+        .ok_or(XmlParseError::MissingAttribute { attribute: "name" })?;
+    let src = attributes.remove("src");
+    if let Some(src) = &src {
+        if !fs::exists(src).unwrap() {
+            return Err(XmlParseError::MissingImageFile {
+                file_path: src.clone(),
+            });
+        }
+    }
+    let fps = attributes
+        .remove("fps")
+        .map(|fps| parse_value("fps", fps))
+        .transpose()?;
+    let weight = attributes.remove("weight").map(parse_weight).transpose()?;
+    // frames come from either `frames` or `src`, not both
+    if frames.is_empty() == src.is_none() {
+        return Err(XmlParseError::FramesOrSrc { animation: name });
+    }
+    if fps.is_none() && src.is_none() && !every_frame_timed(&frames) {
+        return Err(XmlParseError::MissingAttribute { attribute: "fps" });
+    }
+    Ok(AnimationXml {
+        name,
+        fps,
+        weight,
+        next: attributes.remove("next"),
+        loop_mode: attributes
+            .remove("loop")
+            .map(|mode| parse_loop_mode(&mode))
+            .transpose()?,
+        src,
+        frames,
+    })
+}
+
+fn scalars(entries: Vec<(String, Value)>) -> Result<HashMap<String, String>, XmlParseError> {
+    entries
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::Scalar(value) => Ok((key, value)),
+            _ => Err(XmlParseError::MalformedFile),
+        })
+        .collect()
+}
+
+fn from_toml_table<'a>(entries: impl IntoIterator<Item = (&'a str, &'a toml_edit::Item)>) -> Value {
+    Value::Table(
+        entries
+            .into_iter()
+            .filter_map(|(key, item)| Some((key.to_owned(), from_toml_item(item)?)))
+            .collect(),
+    )
+}
+
+fn from_toml_item(item: &toml_edit::Item) -> Option<Value> {
+    match item {
+        toml_edit::Item::None => None,
+        toml_edit::Item::Value(value) => Some(from_toml_value(value)),
+        toml_edit::Item::Table(table) => Some(from_toml_table(table.iter())),
+        toml_edit::Item::ArrayOfTables(tables) => Some(Value::List(
+            tables
+                .iter()
+                .map(|table| from_toml_table(table.iter()))
+                .collect(),
+        )),
+    }
+}
+
+fn from_toml_value(value: &toml_edit::Value) -> Value {
+    use toml_edit::Value as Toml;
+    match value {
+        Toml::String(string) => Value::Scalar(string.value().clone()),
+        Toml::Integer(integer) => Value::Scalar(integer.value().to_string()),
+        Toml::Float(float) => Value::Scalar(float.value().to_string()),
+        Toml::Boolean(boolean) => Value::Scalar(boolean.value().to_string()),
+        Toml::Datetime(datetime) => Value::Scalar(datetime.value().to_string()),
+        Toml::Array(array) => Value::List(array.iter().map(from_toml_value).collect()),
+        Toml::InlineTable(table) => Value::Table(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_owned(), from_toml_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Just enough of a JSON reader for packs. `null`s are left out, as if they weren't there.
+struct Json<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Json<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { rest: text }
+    }
+    /// The one value in the whole text.
+    fn document(mut self) -> Option<Value> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        self.rest.is_empty().then_some(value?)
+    }
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start_matches([' ', '\t', '\n', '\r']);
+    }
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+    /// The next value, `Some(None)` for a `null`.
+    fn value(&mut self) -> Option<Option<Value>> {
+        self.skip_whitespace();
+        if self.eat("null") {
+            return Some(None);
+        }
+        for literal in ["true", "false"] {
+            if self.eat(literal) {
+                return Some(Some(Value::Scalar(literal.to_owned())));
+            }
+        }
+        let value = match self.rest.chars().next()? {
+            '"' => Value::Scalar(self.string()?),
+            '[' => {
+                self.eat("[");
+                let mut list = vec![];
+                if !self.eat("]") {
+                    loop {
+                        list.extend(self.value()?);
+                        if self.eat("]") {
+                            break;
+                        }
+                        self.eat(",").then_some(())?;
+                    }
+                }
+                Value::List(list)
+            }
+            '{' => {
+                self.eat("{");
+                let mut entries = vec![];
+                if !self.eat("}") {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.eat(":").then_some(())?;
+                        if let Some(value) = self.value()? {
+                            entries.push((key, value));
+                        }
+                        if self.eat("}") {
+                            break;
+                        }
+                        self.eat(",").then_some(())?;
+                    }
+                }
+                Value::Table(entries)
+            }
+            _ => Value::Scalar(self.number()?),
+        };
+        Some(Some(value))
+    }
+    fn string(&mut self) -> Option<String> {
+        let mut chars = self.rest.strip_prefix('"')?.char_indices();
+        let mut string = String::new();
+        loop {
+            let (at, char) = chars.next()?;
+            match char {
+                '"' => {
+                    self.rest = &self.rest[at + 2..];
+                    return Some(string);
+                }
+                '\\' => string.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let hex: String = (0..4)
+                            .map(|_| chars.next().map(|(_, c)| c))
+                            .collect::<Option<_>>()?;
+                        char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                    }
+                    escaped @ ('"' | '\\' | '/') => escaped,
+                    _ => return None,
+                }),
+                char => string.push(char),
+            }
+        }
+    }
+    fn number(&mut self) -> Option<String> {
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+            .unwrap_or(self.rest.len());
+        let (number, rest) = self.rest.split_at(end);
+        number.parse::<f64>().ok()?;
+        self.rest = rest;
+        Some(number.to_owned())
+    }
+}
@@ -0,0 +1,230 @@
+//! Pack archives downloaded at startup, so sharing a mascot takes one line:
+//! `--pack-url <url>` on the command line, or `pack_url = <url>` in the settings,
+//! as many times as there are packs. See [`archive`](crate::archive) for what's in one.
+//!
+//! A URL should be followed by ` sha256:<hex>`, the archive's checksum, and the download is
+//! refused unless it matches. One without is refused too, unless [`ALLOW_UNPINNED`] is set,
+//! in which case whatever is downloaded is loaded, and its checksum is logged to pin it with.
+//!
+//! Only HTTPS is allowed, redirects included. Downloads are kept in the [`cache_dir`].
+//! A pack with a checksum is only downloaded once, and one without is still loaded from
+//! the last download if it can't be downloaded again.
+
+use std::{
+    fmt, fs,
+    io::{Read as _, Write as _},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::bail;
+use derive_more::derive::{Display, Error};
+use sha2::{Digest as _, Sha256};
+
+use crate::{archive::MAX_UNPACKED_SIZE, frame_store::cache_dir};
+
+pub const FLAG: &str = "--pack-url";
+/// The environment variable that lets packs without a checksum be downloaded.
+pub const ALLOW_UNPINNED: &str = "SHIMEJI_ALLOW_UNPINNED_PACKS";
+const CHECKSUM_PREFIX: &str = "sha256:";
+/// The longest a download can take.
+const MAX_TIME: Duration = Duration::from_secs(300);
+/// The most that's downloaded, as an archive is never bigger than what it unpacks to.
+const MAX_DOWNLOAD_SIZE: u64 = MAX_UNPACKED_SIZE;
+
+#[derive(Debug, Display, Error)]
+pub enum InvalidPackUrl {
+    #[display("packs can only be downloaded over HTTPS, not from {url:?}")]
+    NotHttps { url: String },
+    #[display("{checksum:?} is not {CHECKSUM_PREFIX} and 64 hex digits")]
+    Checksum { checksum: String },
+    #[display("{value:?} should be a URL, then maybe a checksum")]
+    Malformed { value: String },
+}
+
+/// Where to download a pack from, and what its checksum should be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackUrl {
+    pub url: String,
+    pub sha256: Option<[u8; 32]>,
+}
+
+impl FromStr for PackUrl {
+    type Err = InvalidPackUrl;
+
+    /// `<url>` or `<url> sha256:<hex>`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut words = value.split_whitespace();
+        let (Some(url), checksum, None) = (words.next(), words.next(), words.next()) else {
+            return Err(InvalidPackUrl::Malformed {
+                value: value.to_owned(),
+            });
+        };
+        if !url.starts_with("https://") {
+            return Err(InvalidPackUrl::NotHttps {
+                url: url.to_owned(),
+            });
+        }
+        let sha256 = checksum
+            .map(|checksum| {
+                checksum
+                    .strip_prefix(CHECKSUM_PREFIX)
+                    .and_then(from_hex)
+                    .ok_or_else(|| InvalidPackUrl::Checksum {
+                        checksum: checksum.to_owned(),
+                    })
+            })
+            .transpose()?;
+        Ok(Self {
+            url: url.to_owned(),
+            sha256,
+        })
+    }
+}
+
+impl fmt::Display for PackUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.url)?;
+        if let Some(sha256) = &self.sha256 {
+            write!(f, " {CHECKSUM_PREFIX}{}", to_hex(sha256))?;
+        }
+        Ok(())
+    }
+}
+
+/// Every pack asked for with `--pack-url <url>` or `--pack-url=<url>`.
+pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Vec<PackUrl>, InvalidPackUrl> {
+    let mut urls = vec![];
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix(FLAG) {
+            Some("") => args.next().unwrap_or_default(),
+            Some(value) if value.starts_with('=') => value[1..].to_owned(),
+            _ => continue,
+        };
+        urls.push(value.parse()?);
+    }
+    Ok(urls)
+}
+
+/// Download the archive `pack` points to into the cache directory, unless it already is
+/// and has the right checksum, returning where it is.
+/// One without a checksum is only downloaded if `allow_unpinned`.
+///
+/// # Errors
+/// Errors if it can't be downloaded, doesn't have the checksum it should,
+/// or has none and that isn't allowed.
+pub fn fetch(pack: &PackUrl, allow_unpinned: bool) -> anyhow::Result<PathBuf> {
+    if pack.sha256.is_none() && !allow_unpinned {
+        bail!(
+            "{} has no {CHECKSUM_PREFIX} checksum to check it against, \
+             add one or set {ALLOW_UNPINNED} to download it anyway",
+            pack.url
+        );
+    }
+    let extension = match pack.url.ends_with(".zip") {
+        true => "zip",
+        false => "shimeji",
+    };
+    // named the same by every build, so an update doesn't download everything again
+    let name = to_hex(&sha256(pack.url.as_bytes())[..8]);
+    let path = cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("downloads")
+        .join(format!("{name}.{extension}"));
+    if let Some(expected) = pack.sha256 {
+        if fs::read(&path).is_ok_and(|archive| sha256(&archive) == expected) {
+            log::debug!("Already downloaded {}", pack.url);
+            return Ok(path);
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = path.with_extension("partial");
+    log::info!("Downloading {}", pack.url);
+    let checksum = match download(&pack.url, &partial) {
+        Ok(checksum) => checksum,
+        Err(why) => {
+            fs::remove_file(&partial).ok();
+            if pack.sha256.is_none() && path.is_file() {
+                log::warn!(
+                    "Could not download {}, loading the last download: {why:#}",
+                    pack.url
+                );
+                return Ok(path);
+            }
+            return Err(why.context(format!("could not download {}", pack.url)));
+        }
+    };
+    match pack.sha256 {
+        Some(expected) if expected != checksum => {
+            fs::remove_file(&partial).ok();
+            bail!(
+                "{} has the checksum {CHECKSUM_PREFIX}{}, not {CHECKSUM_PREFIX}{}",
+                pack.url,
+                to_hex(&checksum),
+                to_hex(&expected)
+            );
+        }
+        Some(_) => {}
+        None => log::warn!(
+            "Nothing to check {} against, pin it with {CHECKSUM_PREFIX}{}",
+            pack.url,
+            to_hex(&checksum)
+        ),
+    }
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// Download `url` into the file `to`, returning its checksum.
+fn download(url: &str, to: &std::path::Path) -> anyhow::Result<[u8; 32]> {
+    let agent = ureq::AgentBuilder::new()
+        .https_only(true)
+        .timeout(MAX_TIME)
+        .build();
+    let response = agent.get(url).call()?;
+    let mut body = response.into_reader().take(MAX_DOWNLOAD_SIZE + 1);
+    let mut file = std::io::BufWriter::new(fs::File::create(to)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = body.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        size += read as u64;
+        if size > MAX_DOWNLOAD_SIZE {
+            bail!("it's bigger than the {MAX_DOWNLOAD_SIZE} bytes allowed");
+        }
+        hasher.update(&buffer[..read]);
+        file.write_all(&buffer[..read])?;
+    }
+    file.into_inner()
+        .map_err(|why| why.into_error())?
+        .sync_all()?;
+    Ok(hasher.finalize().into())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The 32 bytes spelled out by `hex`, or `None` if it's anything else.
+fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; 32];
+    for (byte, digits) in bytes.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// The SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
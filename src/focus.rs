@@ -0,0 +1,250 @@
+//! Finding out which application the user is in, so shimejis can react to it,
+//! e.g. dancing while a music player is focused, see [`crate::behavior::Condition::Focused`].
+//!
+//! On Linux this only works on X11, Wayland doesn't tell clients about each other's windows.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use winit::event_loop::EventLoopProxy;
+
+use crate::ManagerEvent;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Another application's window that has the keyboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusedWindow {
+    /// The application, its `WM_CLASS` class on X11 and its executable's name on Windows.
+    pub app: String,
+    pub title: String,
+}
+
+impl FocusedWindow {
+    /// Whether `pattern` is in the window's application or title, ignoring case.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_lowercase();
+        self.app.to_lowercase().contains(&pattern) || self.title.to_lowercase().contains(&pattern)
+    }
+}
+
+/// What has the keyboard.
+enum Focus {
+    Nothing,
+    /// One of our own windows, which doesn't count as leaving the application before it.
+    Ours,
+    Other(FocusedWindow),
+}
+
+/// Check which window is focused every [`POLL_INTERVAL`] on its own thread,
+/// sending [`ManagerEvent::Focused`] whenever that changes, until `should_exit` is set.
+pub fn spawn(
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Focus tracking thread"))
+        .spawn(move || poll_focus(proxy, should_exit))
+}
+
+fn poll_focus(proxy: EventLoopProxy<ManagerEvent>, should_exit: Arc<AtomicBool>) {
+    let provider = match Provider::new() {
+        Ok(provider) => provider,
+        Err(why) => {
+            log::warn!("Shimejis can't tell which application you're in: {why}");
+            return;
+        }
+    };
+    let mut last = None;
+    while !should_exit.load(Ordering::Relaxed) {
+        let focused = match provider.focused() {
+            Ok(Focus::Nothing) => None,
+            Ok(Focus::Ours) => last.clone(),
+            Ok(Focus::Other(window)) => Some(window),
+            Err(why) => {
+                log::debug!("Could not find the focused window: {why}");
+                last.clone()
+            }
+        };
+        if focused != last {
+            if proxy
+                .send_event(ManagerEvent::Focused(focused.clone()))
+                .is_err()
+            {
+                return;
+            }
+            last = focused;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use x11rb::{
+            connection::Connection,
+            properties::WmClass,
+            protocol::xproto::{AtomEnum, ConnectionExt as _, Window},
+            rust_connection::RustConnection,
+        };
+
+        /// Reads the window manager's `_NET_ACTIVE_WINDOW` hint.
+        struct Provider {
+            connection: RustConnection,
+            root: Window,
+            active_window: u32,
+            name: u32,
+            utf8_string: u32,
+        }
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                let (connection, screen) = x11rb::connect(None)?;
+                let root = connection.setup().roots[screen].root;
+                let intern = |name: &str| -> anyhow::Result<u32> {
+                    Ok(connection.intern_atom(false, name.as_bytes())?.reply()?.atom)
+                };
+                Ok(Self {
+                    active_window: intern("_NET_ACTIVE_WINDOW")?,
+                    name: intern("_NET_WM_NAME")?,
+                    utf8_string: intern("UTF8_STRING")?,
+                    connection,
+                    root,
+                })
+            }
+            fn property(&self, window: Window, property: u32, kind: u32) -> anyhow::Result<Vec<u8>> {
+                let reply = self
+                    .connection
+                    .get_property(false, window, property, kind, 0, u32::MAX)?
+                    .reply()?;
+                Ok(reply.value)
+            }
+            /// `window`'s title, falling back to the old `WM_NAME` for windows without a UTF-8 one.
+            fn title_of(&self, window: Window) -> anyhow::Result<String> {
+                let mut title = self.property(window, self.name, self.utf8_string)?;
+                if title.is_empty() {
+                    title = self.property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?;
+                }
+                Ok(String::from_utf8_lossy(&title).into_owned())
+            }
+            fn focused(&self) -> anyhow::Result<Focus> {
+                let reply = self
+                    .connection
+                    .get_property(false, self.root, self.active_window, AtomEnum::WINDOW, 0, 1)?
+                    .reply()?;
+                let Some(window) = reply.value32().and_then(|mut windows| windows.next()) else {
+                    return Ok(Focus::Nothing);
+                };
+                if window == x11rb::NONE {
+                    return Ok(Focus::Nothing);
+                }
+                let app = match WmClass::get(&self.connection, window)?.reply()? {
+                    Some(class) if class.class() == crate::backend::APP_ID.as_bytes() => {
+                        return Ok(Focus::Ours);
+                    }
+                    Some(class) => String::from_utf8_lossy(class.class()).into_owned(),
+                    None => String::new(),
+                };
+                Ok(Focus::Other(FocusedWindow {
+                    app,
+                    title: self.title_of(window)?,
+                }))
+            }
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use std::{ffi::OsString, os::windows::ffi::OsStringExt, path::Path};
+
+        use windows_sys::Win32::{
+            Foundation::{CloseHandle, FALSE},
+            System::Threading::{
+                OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+                PROCESS_QUERY_LIMITED_INFORMATION,
+            },
+            UI::WindowsAndMessaging::{
+                GetForegroundWindow, GetWindowTextLengthW, GetWindowTextW,
+                GetWindowThreadProcessId,
+            },
+        };
+
+        /// Asks `GetForegroundWindow`, and the process behind it for its executable.
+        struct Provider;
+
+        /// The name of process `id`'s executable without `.exe`, if it lets us see it.
+        fn executable_of(id: u32) -> Option<String> {
+            let mut path = [0u16; 1024];
+            let mut length = path.len() as u32;
+            // SAFETY: the handle is closed before returning, and `path` and `length`
+            // are locals that the call writes at most `length` characters to.
+            let found = unsafe {
+                let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, id);
+                if process.is_null() {
+                    return None;
+                }
+                let found = QueryFullProcessImageNameW(
+                    process,
+                    PROCESS_NAME_WIN32,
+                    path.as_mut_ptr(),
+                    &mut length,
+                );
+                CloseHandle(process);
+                found
+            };
+            if found == 0 {
+                return None;
+            }
+            let path = OsString::from_wide(&path[..length as usize]);
+            Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        }
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                Ok(Self)
+            }
+            fn focused(&self) -> anyhow::Result<Focus> {
+                // SAFETY: `GetForegroundWindow` has no preconditions.
+                let window = unsafe { GetForegroundWindow() };
+                if window.is_null() {
+                    return Ok(Focus::Nothing);
+                }
+                let mut process = 0;
+                // SAFETY: `window` is a window handle, even if it has closed since,
+                // and `process` is a local.
+                unsafe { GetWindowThreadProcessId(window, &mut process) };
+                if process == std::process::id() {
+                    return Ok(Focus::Ours);
+                }
+                // SAFETY: as above, and `title` has room for as many characters as it's told.
+                let title = unsafe {
+                    let mut title = vec![0u16; GetWindowTextLengthW(window).max(0) as usize + 1];
+                    let length = GetWindowTextW(window, title.as_mut_ptr(), title.len() as i32);
+                    title.truncate(length.max(0) as usize);
+                    title
+                };
+                Ok(Focus::Other(FocusedWindow {
+                    app: executable_of(process).unwrap_or_default(),
+                    title: String::from_utf16_lossy(&title),
+                }))
+            }
+        }
+    } else {
+        struct Provider;
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                anyhow::bail!("finding the focused window isn't supported on this platform")
+            }
+            fn focused(&self) -> anyhow::Result<Focus> {
+                Ok(Focus::Nothing)
+            }
+        }
+    }
+}
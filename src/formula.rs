@@ -0,0 +1,371 @@
+//! Tiny arithmetic formulas used in pack configs, e.g. `weight="max(1, 10 - population)"`.
+//!
+//! Supports numbers, `+ - * /`, parentheses, `min(a, b)`, `max(a, b)`, `random(a, b)`,
+//! and the variables in [`Variable`]. Times of day like `22:30` are the seconds since
+//! midnight they are, to compare `clock` with.
+//!
+//! A [`Predicate`] compares formulas, e.g. `condition="clock >= 22:00 or clock < 6:00"`.
+
+use derive_more::derive::{Display, Error};
+
+use crate::{rng::Rng, world::WorldSnapshot};
+
+#[derive(Debug, Error, Display, PartialEq)]
+pub enum FormulaError {
+    UnexpectedCharacter { character: char },
+    UnknownName { name: String },
+    UnexpectedEnd,
+    TrailingInput,
+    ExpectedComparison,
+}
+
+/// Values a formula can read from the [`WorldSnapshot`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variable {
+    /// How many shimejis are alive.
+    Population,
+    /// `idle`, 1 if the user is away from the keyboard and mouse, 0 if not.
+    Idle,
+    /// `clock`, how many seconds it's been since midnight, local time.
+    Clock,
+    /// `hour`, from 0 to 23.
+    Hour,
+    /// `day` of the month, from 1.
+    Day,
+    /// `month`, from 1 for January to 12.
+    Month,
+    /// `weekday`, from 1 for Monday to 7 for Sunday.
+    Weekday,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Min,
+    Max,
+    /// A number between the two, picked anew every time.
+    Random,
+}
+
+/// How a [`Predicate`] compares two numbers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    pub fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Self::Less => left < right,
+            Self::LessOrEqual => left <= right,
+            Self::Greater => left > right,
+            Self::GreaterOrEqual => left >= right,
+            Self::Equal => left == right,
+            Self::NotEqual => left != right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    Number(f64),
+    Variable(Variable),
+    Negate(Box<Formula>),
+    Binary(Operator, Box<Formula>, Box<Formula>),
+    Call(Function, Box<Formula>, Box<Formula>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Operator(Operator),
+    OpenParen,
+    CloseParen,
+    Comma,
+    Compare(Comparison),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FormulaError> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(&character) = chars.peek() {
+        match character {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                    number.push(c);
+                    chars.next();
+                }
+                let mut number: f64 = number
+                    .parse()
+                    .map_err(|_| FormulaError::UnexpectedCharacter { character })?;
+                // a time of day, `hours:minutes`
+                if chars.next_if_eq(&':').is_some() {
+                    let minutes: String =
+                        std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_digit())).collect();
+                    match minutes.parse::<f64>() {
+                        Ok(minutes) if minutes < 60.0 && number.fract() == 0.0 => {
+                            number = number * 3600.0 + minutes * 60.0;
+                        }
+                        _ => return Err(FormulaError::UnexpectedCharacter { character: ':' }),
+                    }
+                }
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                {
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Name(name));
+            }
+            '<' | '>' | '=' | '!' => {
+                chars.next();
+                let equals = chars.next_if_eq(&'=').is_some();
+                let comparison = match (character, equals) {
+                    ('<', false) => Comparison::Less,
+                    ('<', true) => Comparison::LessOrEqual,
+                    ('>', false) => Comparison::Greater,
+                    ('>', true) => Comparison::GreaterOrEqual,
+                    ('=', true) => Comparison::Equal,
+                    ('!', true) => Comparison::NotEqual,
+                    _ => return Err(FormulaError::UnexpectedCharacter { character }),
+                };
+                tokens.push(Token::Compare(comparison));
+            }
+            _ => {
+                let token = match character {
+                    '+' => Token::Operator(Operator::Add),
+                    '-' => Token::Operator(Operator::Subtract),
+                    '*' => Token::Operator(Operator::Multiply),
+                    '/' => Token::Operator(Operator::Divide),
+                    '(' => Token::OpenParen,
+                    ')' => Token::CloseParen,
+                    ',' => Token::Comma,
+                    character => return Err(FormulaError::UnexpectedCharacter { character }),
+                };
+                tokens.push(token);
+                chars.next();
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Result<Self, FormulaError> {
+        Ok(Self {
+            tokens: tokenize(input)?,
+            position: 0,
+        })
+    }
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+    fn next(&mut self) -> Result<Token, FormulaError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or(FormulaError::UnexpectedEnd)?;
+        self.position += 1;
+        Ok(token)
+    }
+    fn expect(&mut self, expected: Token) -> Result<(), FormulaError> {
+        if self.next()? == expected {
+            Ok(())
+        } else {
+            Err(FormulaError::TrailingInput)
+        }
+    }
+    /// expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Formula, FormulaError> {
+        let mut left = self.term()?;
+        while let Some(&Token::Operator(operator @ (Operator::Add | Operator::Subtract))) =
+            self.peek()
+        {
+            self.position += 1;
+            left = Formula::Binary(operator, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+    /// term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Formula, FormulaError> {
+        let mut left = self.factor()?;
+        while let Some(&Token::Operator(operator @ (Operator::Multiply | Operator::Divide))) =
+            self.peek()
+        {
+            self.position += 1;
+            left = Formula::Binary(operator, Box::new(left), Box::new(self.factor()?));
+        }
+        Ok(left)
+    }
+    /// factor := number | variable | function '(' expression ',' expression ')'
+    ///         | '(' expression ')' | '-' factor
+    fn factor(&mut self) -> Result<Formula, FormulaError> {
+        match self.next()? {
+            Token::Number(number) => Ok(Formula::Number(number)),
+            Token::Operator(Operator::Subtract) => Ok(Formula::Negate(Box::new(self.factor()?))),
+            Token::OpenParen => {
+                let inner = self.expression()?;
+                self.expect(Token::CloseParen)?;
+                Ok(inner)
+            }
+            Token::Name(name) => match name.as_str() {
+                "population" => Ok(Formula::Variable(Variable::Population)),
+                "idle" => Ok(Formula::Variable(Variable::Idle)),
+                "clock" => Ok(Formula::Variable(Variable::Clock)),
+                "hour" => Ok(Formula::Variable(Variable::Hour)),
+                "day" => Ok(Formula::Variable(Variable::Day)),
+                "month" => Ok(Formula::Variable(Variable::Month)),
+                "weekday" => Ok(Formula::Variable(Variable::Weekday)),
+                "min" | "max" | "random" => {
+                    let function = match name.as_str() {
+                        "min" => Function::Min,
+                        "max" => Function::Max,
+                        _ => Function::Random,
+                    };
+                    self.expect(Token::OpenParen)?;
+                    let first = self.expression()?;
+                    self.expect(Token::Comma)?;
+                    let second = self.expression()?;
+                    self.expect(Token::CloseParen)?;
+                    Ok(Formula::Call(function, Box::new(first), Box::new(second)))
+                }
+                _ => Err(FormulaError::UnknownName { name }),
+            },
+            Token::Operator(_) | Token::CloseParen | Token::Comma | Token::Compare(_) => {
+                Err(FormulaError::TrailingInput)
+            }
+        }
+    }
+}
+
+impl Formula {
+    pub fn parse(input: &str) -> Result<Self, FormulaError> {
+        let mut parser = Parser::new(input)?;
+        let formula = parser.expression()?;
+        if parser.peek().is_some() {
+            return Err(FormulaError::TrailingInput);
+        }
+        Ok(formula)
+    }
+    /// Evaluate the formula in `world`, rolling any `random`s with `rng`, the shimeji's own
+    /// so a recorded run rolls the same.
+    pub fn evaluate(&self, world: &WorldSnapshot, rng: &mut Rng) -> f64 {
+        match self {
+            Self::Number(number) => *number,
+            Self::Variable(variable) => match variable {
+                Variable::Population => world.population as f64,
+                Variable::Idle => f64::from(u8::from(world.user_idle)),
+                Variable::Clock => world.clock.map_or(0.0, |clock| clock.seconds() as f64),
+                Variable::Hour => world.clock.map_or(0.0, |clock| clock.hour as f64),
+                Variable::Day => world.clock.map_or(0.0, |clock| clock.day as f64),
+                Variable::Month => world.clock.map_or(0.0, |clock| clock.month as f64),
+                Variable::Weekday => world.clock.map_or(0.0, |clock| clock.weekday as f64),
+            },
+            Self::Negate(inner) => -inner.evaluate(world, rng),
+            Self::Binary(operator, left, right) => {
+                let left = left.evaluate(world, rng);
+                let right = right.evaluate(world, rng);
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    // a weight that divides by an empty population shouldn't poison everything
+                    Operator::Divide if right == 0.0 => 0.0,
+                    Operator::Divide => left / right,
+                }
+            }
+            Self::Call(function, first, second) => {
+                let first = first.evaluate(world, rng);
+                let second = second.evaluate(world, rng);
+                match function {
+                    Function::Min => first.min(second),
+                    Function::Max => first.max(second),
+                    Function::Random => first + rng.f64() * (second - first),
+                }
+            }
+        }
+    }
+}
+
+/// Formulas compared, joined with `and` and `or`, `and` first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Compare(Formula, Comparison, Formula),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+impl Predicate {
+    pub fn parse(input: &str) -> Result<Self, FormulaError> {
+        let mut parser = Parser::new(input)?;
+        let predicate = Self::any(&mut parser)?;
+        if parser.peek().is_some() {
+            return Err(FormulaError::TrailingInput);
+        }
+        Ok(predicate)
+    }
+    /// any := all ('or' all)*
+    fn any(parser: &mut Parser) -> Result<Self, FormulaError> {
+        let mut left = Self::all(parser)?;
+        while parser.peek() == Some(&Token::Name(String::from("or"))) {
+            parser.next()?;
+            left = Self::Or(Box::new(left), Box::new(Self::all(parser)?));
+        }
+        Ok(left)
+    }
+    /// all := comparison ('and' comparison)*
+    fn all(parser: &mut Parser) -> Result<Self, FormulaError> {
+        let mut left = Self::comparison(parser)?;
+        while parser.peek() == Some(&Token::Name(String::from("and"))) {
+            parser.next()?;
+            left = Self::And(Box::new(left), Box::new(Self::comparison(parser)?));
+        }
+        Ok(left)
+    }
+    /// comparison := expression ('<' | '<=' | '>' | '>=' | '==' | '!=') expression
+    fn comparison(parser: &mut Parser) -> Result<Self, FormulaError> {
+        let left = parser.expression()?;
+        let Some(&Token::Compare(comparison)) = parser.peek() else {
+            return Err(FormulaError::ExpectedComparison);
+        };
+        parser.next()?;
+        Ok(Self::Compare(left, comparison, parser.expression()?))
+    }
+    pub fn holds(&self, world: &WorldSnapshot, rng: &mut Rng) -> bool {
+        match self {
+            Self::Compare(left, comparison, right) => {
+                comparison.holds(left.evaluate(world, rng), right.evaluate(world, rng))
+            }
+            Self::And(left, right) => left.holds(world, rng) && right.holds(world, rng),
+            Self::Or(left, right) => left.holds(world, rng) || right.holds(world, rng),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Decoded frames kept on disk between runs, so big packs don't have to be decoded
+//! again every launch. The store is memory-mapped, and its frames point straight into it.
+//!
+//! Each pack gets one file in the [`cache_dir`], named after a hash of everything its
+//! frames were decoded from: the pack's files, their sizes and modification times,
+//! and how the frames were resized. Anything changing makes a new store,
+//! so stale ones are never read, only left behind.
+//!
+//! A store is [`MAGIC`], then the number of animations as a `u32`, then for each animation
+//! the number of frames as a `u32` and whether it has delays as a `u8`, followed by each
+//! frame's width and height as `u32`s and, with delays, how long it shows for in nanoseconds
+//! as a `u64`. Then the pixels of every frame in order, 4 bytes each. All little endian.
+
+use std::{
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Write as _,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context as _};
+use memmap2::Mmap;
+
+use crate::{loader::Frame, rgba::Rgba, xml_parser::AnimationXml};
+
+const MAGIC: &[u8; 8] = b"SHIMFRM1";
+/// What the cache directory is called inside the platform's own.
+const CACHE_DIRECTORY: &str = "new-shimeji";
+
+/// The frames of one animation as they were decoded, with the delays of an animated png or GIF.
+pub type DecodedFrames = (Vec<Frame>, Option<Vec<Duration>>);
+
+/// The pixels of a [`Frame`], either its own, shared with its copies or in a [`FrameStore`].
+#[derive(Clone)]
+pub enum FramePixels {
+    Owned(Box<[Rgba]>),
+    Shared(Arc<[Rgba]>),
+    Mapped {
+        store: Arc<Mmap>,
+        /// Where the pixels start in `store`, in bytes.
+        offset: usize,
+        /// How many pixels there are.
+        len: usize,
+    },
+}
+
+impl Deref for FramePixels {
+    type Target = [Rgba];
+
+    fn deref(&self) -> &[Rgba] {
+        match self {
+            Self::Owned(pixels) => pixels,
+            Self::Shared(pixels) => pixels,
+            Self::Mapped { store, offset, len } => {
+                let bytes = &store[*offset..*offset + len * size_of::<Rgba>()];
+                // SAFETY: `Rgba` is four `u8`s with no padding and an alignment of 1,
+                // so any 4 bytes are one, and `bytes` holds exactly `len` of them.
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr().cast(), *len) }
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for FramePixels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // every pixel would bury whatever a frame is printed as part of
+        match self {
+            Self::Owned(pixels) => write!(f, "Owned({} pixels)", pixels.len()),
+            Self::Shared(pixels) => write!(f, "Shared({} pixels)", pixels.len()),
+            Self::Mapped { offset, len, .. } => write!(f, "Mapped({len} pixels at {offset})"),
+        }
+    }
+}
+
+impl FramePixels {
+    /// The same pixels, which copies share instead of each having their own.
+    pub fn shared(self) -> Self {
+        match self {
+            Self::Owned(pixels) => Self::Shared(pixels.into()),
+            shared => shared,
+        }
+    }
+}
+
+impl PartialEq for FramePixels {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl From<Box<[Rgba]>> for FramePixels {
+    fn from(pixels: Box<[Rgba]>) -> Self {
+        Self::Owned(pixels)
+    }
+}
+
+impl From<Vec<Rgba>> for FramePixels {
+    fn from(pixels: Vec<Rgba>) -> Self {
+        Self::Owned(pixels.into_boxed_slice())
+    }
+}
+
+impl<const N: usize> From<[Rgba; N]> for FramePixels {
+    fn from(pixels: [Rgba; N]) -> Self {
+        Self::Owned(pixels.into())
+    }
+}
+
+impl FromIterator<Rgba> for FramePixels {
+    fn from_iter<I: IntoIterator<Item = Rgba>>(pixels: I) -> Self {
+        Self::Owned(pixels.into_iter().collect())
+    }
+}
+
+/// Where caches go on this platform: `$SHIMEJI_FRAME_CACHE_DIR` if it's set, otherwise
+/// `$XDG_CACHE_HOME/new-shimeji` (or `~/.cache/new-shimeji`) on Linux,
+/// `~/Library/Caches/new-shimeji` on macOS and `%LOCALAPPDATA%\new-shimeji` on Windows.
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(directory) = std::env::var_os("SHIMEJI_FRAME_CACHE_DIR") {
+        return Some(PathBuf::from(directory));
+    }
+    let home = || {
+        std::env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Caches"))
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .filter(|cache| !cache.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".cache")))
+    };
+    base.map(|base| base.join(CACHE_DIRECTORY))
+}
+
+/// One pack's decoded frames on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameStore {
+    path: PathBuf,
+}
+
+impl FrameStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+    /// The store in the [`cache_dir`] for `animations`, resized by `resize`,
+    /// which is hashed along with them. `None` if there's no cache directory,
+    /// or a file they're decoded from can't be found, so placeholders aren't kept.
+    pub fn for_animations(animations: &[AnimationXml], resize: impl Hash) -> Option<Self> {
+        let mut hasher = DefaultHasher::new();
+        MAGIC.hash(&mut hasher);
+        resize.hash(&mut hasher);
+        for animation in animations {
+            animation.name.hash(&mut hasher);
+            let files =
+                animation
+                    .src
+                    .iter()
+                    .map(|src| (src, None))
+                    .chain(animation.frames.iter().map(|frame| {
+                        let region = frame
+                            .region
+                            .map(|region| (region.x, region.y, region.width, region.height));
+                        (&frame.file_path, Some((frame.number, region)))
+                    }));
+            for (file, frame) in files {
+                frame.hash(&mut hasher);
+                let path = fs::canonicalize(file).ok()?;
+                let metadata = fs::metadata(&path).ok()?;
+                let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+                (path, metadata.len(), modified).hash(&mut hasher);
+            }
+        }
+        let file = format!("{:016x}.bin", hasher.finish());
+        Some(Self::new(cache_dir()?.join(file)))
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// The frames saved in the store, or `None` if it hasn't been saved yet.
+    ///
+    /// # Errors
+    /// Errors if the store can't be read or isn't one.
+    pub fn load(&self) -> anyhow::Result<Option<Vec<DecodedFrames>>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(why) => {
+                return Err(why).with_context(|| format!("could not open {}", self.path.display()))
+            }
+        };
+        // SAFETY: stores are written next to where they go and moved into place,
+        // never changed where they are, so the mapping can't change under us.
+        // Something else truncating the file anyway would make reading it raise SIGBUS,
+        // which we can't guard against, but nothing should be touching our cache.
+        let store = Arc::new(unsafe { Mmap::map(&file) }?);
+        Self::parse(store)
+            .map(Some)
+            .with_context(|| format!("{} is not a frame store", self.path.display()))
+    }
+    fn parse(store: Arc<Mmap>) -> anyhow::Result<Vec<DecodedFrames>> {
+        let mut header = Reader {
+            bytes: &store,
+            position: 0,
+        };
+        if header.take(MAGIC.len())? != MAGIC {
+            bail!("it doesn't start with {MAGIC:?}");
+        }
+        // (width, height) of every frame, and the delays of each animation that has them
+        let mut layout = vec![];
+        for _ in 0..header.u32()? {
+            let frames = header.u32()?;
+            let has_delays = header.take(1)?[0] != 0;
+            let mut sizes = vec![];
+            let mut delays = has_delays.then(Vec::new);
+            for _ in 0..frames {
+                sizes.push((header.u32()?, header.u32()?));
+                if let Some(delays) = delays.as_mut() {
+                    delays.push(Duration::from_nanos(header.u64()?));
+                }
+            }
+            layout.push((sizes, delays));
+        }
+        let mut offset = header.position;
+        let mut animations = vec![];
+        for (sizes, delays) in layout {
+            let mut frames = vec![];
+            for (width, height) in sizes {
+                let len = (width as usize).checked_mul(height as usize);
+                let end = len
+                    .and_then(|len| len.checked_mul(size_of::<Rgba>()))
+                    .and_then(|bytes| offset.checked_add(bytes));
+                let (Some(len), Some(end)) = (len, end) else {
+                    bail!("a {width}x{height} frame is too big");
+                };
+                if end > store.len() {
+                    bail!("its frames run past its end");
+                }
+                frames.push(Frame {
+                    width,
+                    height,
+                    pixels_row_major: FramePixels::Mapped {
+                        store: Arc::clone(&store),
+                        offset,
+                        len,
+                    },
+                });
+                offset = end;
+            }
+            animations.push((frames, delays));
+        }
+        Ok(animations)
+    }
+    /// Replace what's in the store with `animations`.
+    ///
+    /// The store is written next to where it goes and moved there,
+    /// so nothing ever reads one halfway written.
+    ///
+    /// # Errors
+    /// Errors if the store can't be written.
+    pub fn save(&self, animations: &[DecodedFrames]) -> anyhow::Result<()> {
+        let mut header = MAGIC.to_vec();
+        header.extend((animations.len() as u32).to_le_bytes());
+        for (frames, delays) in animations {
+            header.extend((frames.len() as u32).to_le_bytes());
+            header.push(delays.is_some().into());
+            for (index, frame) in frames.iter().enumerate() {
+                header.extend(frame.width.to_le_bytes());
+                header.extend(frame.height.to_le_bytes());
+                if let Some(delays) = delays {
+                    let delay = delays.get(index).copied().unwrap_or_default();
+                    header.extend((delay.as_nanos() as u64).to_le_bytes());
+                }
+            }
+        }
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let write = || -> anyhow::Result<()> {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = std::io::BufWriter::new(fs::File::create(&temporary)?);
+            file.write_all(&header)?;
+            for frame in animations.iter().flat_map(|(frames, _)| frames) {
+                for pixel in frame.pixels_row_major.iter() {
+                    file.write_all(&[pixel.red, pixel.green, pixel.blue, pixel.alpha])?;
+                }
+            }
+            file.into_inner()
+                .map_err(|why| why.into_error())?
+                .sync_all()?;
+            fs::rename(&temporary, &self.path)?;
+            Ok(())
+        };
+        write().with_context(|| format!("could not save frames to {}", self.path.display()))
+    }
+}
+
+/// Reads a store's header from the front.
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'b [u8]> {
+        let Some(taken) = self.bytes.get(self.position..self.position + len) else {
+            bail!("its header is cut off");
+        };
+        self.position += len;
+        Ok(taken)
+    }
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
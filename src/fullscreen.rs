@@ -0,0 +1,203 @@
+//! Finding other applications' fullscreen windows, so shimejis can get out of the way of
+//! games and videos on the monitor they're on, and come back once they're over.
+//!
+//! On Linux this only works on X11, Wayland doesn't let clients see each other's windows.
+//! Windows only says whether something is fullscreen, taken to be the foreground window.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{monitors::WorkArea, ManagerEvent};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Start looking for fullscreen windows every [`POLL_INTERVAL`] on its own thread,
+/// sending [`ManagerEvent::Fullscreen`] whenever they change, until `should_exit` is set.
+pub fn spawn(
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Fullscreen detection thread"))
+        .spawn(move || poll_fullscreen(proxy, should_exit))
+}
+
+fn poll_fullscreen(proxy: EventLoopProxy<ManagerEvent>, should_exit: Arc<AtomicBool>) {
+    let provider = match Provider::new() {
+        Ok(provider) => provider,
+        Err(why) => {
+            log::warn!("Shimejis won't hide from fullscreen applications: {why}");
+            return;
+        }
+    };
+    let mut last = vec![];
+    while !should_exit.load(Ordering::Relaxed) {
+        match provider.fullscreen() {
+            Ok(windows) if windows != last => {
+                if proxy
+                    .send_event(ManagerEvent::Fullscreen(windows.clone()))
+                    .is_err()
+                {
+                    return;
+                }
+                last = windows;
+            }
+            Ok(_) => (),
+            Err(why) => log::warn!("Could not look for fullscreen windows: {why}"),
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use x11rb::{
+            connection::Connection,
+            properties::WmClass,
+            protocol::xproto::{AtomEnum, ConnectionExt as _, Window},
+            rust_connection::RustConnection,
+        };
+
+        /// Reads `_NET_WM_STATE_FULLSCREEN` off every client the window manager lists.
+        struct Provider {
+            connection: RustConnection,
+            root: Window,
+            client_list: u32,
+            state: u32,
+            fullscreen: u32,
+            hidden: u32,
+        }
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                let (connection, screen) = x11rb::connect(None)?;
+                let root = connection.setup().roots[screen].root;
+                let intern = |name: &str| -> anyhow::Result<u32> {
+                    Ok(connection.intern_atom(false, name.as_bytes())?.reply()?.atom)
+                };
+                Ok(Self {
+                    client_list: intern("_NET_CLIENT_LIST")?,
+                    state: intern("_NET_WM_STATE")?,
+                    fullscreen: intern("_NET_WM_STATE_FULLSCREEN")?,
+                    hidden: intern("_NET_WM_STATE_HIDDEN")?,
+                    connection,
+                    root,
+                })
+            }
+            fn property(&self, window: Window, property: u32, kind: AtomEnum) -> anyhow::Result<Vec<u32>> {
+                let reply = self
+                    .connection
+                    .get_property(false, window, property, kind, 0, u32::MAX)?
+                    .reply()?;
+                Ok(reply.value32().map(Iterator::collect).unwrap_or_default())
+            }
+            /// Where `window` is, if it's fullscreen, showing and not one of ours.
+            fn fullscreen_area(&self, window: Window) -> anyhow::Result<Option<WorkArea>> {
+                let state = self.property(window, self.state, AtomEnum::ATOM)?;
+                if !state.contains(&self.fullscreen) || state.contains(&self.hidden) {
+                    return Ok(None);
+                }
+                let class = WmClass::get(&self.connection, window)?.reply()?;
+                if class.is_some_and(|class| class.class() == crate::backend::APP_ID.as_bytes()) {
+                    return Ok(None);
+                }
+                let geometry = self.connection.get_geometry(window)?.reply()?;
+                let position = self
+                    .connection
+                    .translate_coordinates(window, self.root, 0, 0)?
+                    .reply()?;
+                let (left, top) = (position.dst_x as i32, position.dst_y as i32);
+                Ok(Some(WorkArea {
+                    left,
+                    top,
+                    right: left + geometry.width as i32,
+                    bottom: top + geometry.height as i32,
+                }))
+            }
+            fn fullscreen(&self) -> anyhow::Result<Vec<WorkArea>> {
+                let mut areas = vec![];
+                for window in self.property(self.root, self.client_list, AtomEnum::WINDOW)? {
+                    // windows can close while we look at them
+                    if let Ok(Some(area)) = self.fullscreen_area(window) {
+                        areas.push(area);
+                    }
+                }
+                Ok(areas)
+            }
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use windows_sys::Win32::{
+            Foundation::RECT,
+            UI::{
+                Shell::{
+                    SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE,
+                    QUNS_RUNNING_D3D_FULL_SCREEN,
+                },
+                WindowsAndMessaging::{GetForegroundWindow, GetWindowRect, GetWindowThreadProcessId},
+            },
+        };
+
+        /// Asks `SHQueryUserNotificationState`, which is what keeps notifications
+        /// from popping up over fullscreen applications too.
+        struct Provider;
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                Ok(Self)
+            }
+            fn fullscreen(&self) -> anyhow::Result<Vec<WorkArea>> {
+                let mut state = 0;
+                // SAFETY: `state` is a local for the call to write to.
+                let result = unsafe { SHQueryUserNotificationState(&mut state) };
+                if result < 0 {
+                    anyhow::bail!("SHQueryUserNotificationState failed with {result:#x}");
+                }
+                if ![QUNS_BUSY, QUNS_RUNNING_D3D_FULL_SCREEN, QUNS_PRESENTATION_MODE]
+                    .contains(&state)
+                {
+                    return Ok(vec![]);
+                }
+                let mut process = 0;
+                let mut rect = RECT { left: 0, top: 0, right: 0, bottom: 0 };
+                // SAFETY: the window handle comes straight from `GetForegroundWindow`,
+                // the out pointers are to locals.
+                unsafe {
+                    let window = GetForegroundWindow();
+                    if window.is_null() {
+                        return Ok(vec![]);
+                    }
+                    GetWindowThreadProcessId(window, &mut process);
+                    if process == std::process::id() || GetWindowRect(window, &mut rect) == 0 {
+                        return Ok(vec![]);
+                    }
+                }
+                Ok(vec![WorkArea {
+                    left: rect.left,
+                    top: rect.top,
+                    right: rect.right,
+                    bottom: rect.bottom,
+                }])
+            }
+        }
+    } else {
+        struct Provider;
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                anyhow::bail!("finding fullscreen windows isn't supported on this platform")
+            }
+            fn fullscreen(&self) -> anyhow::Result<Vec<WorkArea>> {
+                Ok(vec![])
+            }
+        }
+    }
+}
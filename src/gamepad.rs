@@ -0,0 +1,77 @@
+//! Easter egg: steer a shimeji around with a game controller.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use gilrs::{Axis, Button, EventType, Gilrs};
+
+use crate::{bucket::SteeringHandle, movement::MovementCommand};
+
+/// In pixels per second, at full stick deflection.
+const WALK_SPEED: f64 = 250.0;
+const STICK_DEADZONE: f32 = 0.25;
+const POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Start polling for controller input on its own thread,
+/// steering the shimeji behind `handle` until `should_exit` is set.
+pub fn spawn(
+    handle: SteeringHandle,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Gamepad thread"))
+        .spawn(move || poll_gamepads(handle, should_exit))
+}
+
+fn poll_gamepads(handle: SteeringHandle, should_exit: Arc<AtomicBool>) {
+    let mut gilrs = match Gilrs::new() {
+        Ok(gilrs) => gilrs,
+        Err(why) => {
+            log::warn!("Gamepad support unavailable: {why}");
+            return;
+        }
+    };
+    log::debug!("Gamepad steering shimeji {:?}", handle.id());
+
+    while !should_exit.load(Ordering::Relaxed) {
+        while let Some(event) = gilrs.next_event() {
+            let command = match event.event {
+                EventType::Connected => {
+                    log::info!("Gamepad connected: {}", gilrs.gamepad(event.id).name());
+                    None
+                }
+                EventType::ButtonPressed(Button::South, _) => Some(MovementCommand::Jump),
+                EventType::ButtonPressed(Button::DPadLeft, _) => {
+                    Some(MovementCommand::Walk(-WALK_SPEED))
+                }
+                EventType::ButtonPressed(Button::DPadRight, _) => {
+                    Some(MovementCommand::Walk(WALK_SPEED))
+                }
+                EventType::ButtonReleased(Button::DPadLeft | Button::DPadRight, _) => {
+                    Some(MovementCommand::Stop)
+                }
+                EventType::AxisChanged(Axis::LeftStickX, value, _) => {
+                    if value.abs() < STICK_DEADZONE {
+                        Some(MovementCommand::Stop)
+                    } else {
+                        Some(MovementCommand::Walk(f64::from(value) * WALK_SPEED))
+                    }
+                }
+                _ => None,
+            };
+            if let Some(command) = command {
+                if !handle.steer(command) {
+                    log::debug!("Steered shimeji is gone, stopping gamepad thread");
+                    return;
+                }
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,202 @@
+//! Controlling single shimejis from outside the manager, see [`BucketManager::spawn`](crate::BucketManager::spawn).
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    sync::{mpsc::Receiver, Arc, OnceLock},
+};
+
+use derive_more::derive::{Display, Error};
+use winit::{dpi::PhysicalPosition, event_loop::EventLoopProxy, window::WindowId};
+
+use crate::{variant::Variant, ManagerEvent};
+
+/// Names one spawned shimeji for as long as the manager runs, even before it has a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display)]
+pub struct ShimejiId(pub u64);
+
+/// How to spawn a shimeji with [`BucketManager::spawn`](crate::BucketManager::spawn).
+#[derive(Debug, Clone)]
+pub struct SpawnConfig {
+    /// Which shimeji of the library to spawn.
+    pub name: Arc<str>,
+    /// Where to put it, instead of the top left of the monitor.
+    pub position: Option<PhysicalPosition<i32>>,
+    /// The behavior to start in, instead of the pack's initial one.
+    pub behavior: Option<String>,
+    /// How to recolor it, instead of the pack's own colors.
+    pub variant: Option<Variant>,
+    /// What its behaviors choose by, to choose like a recorded shimeji did,
+    /// see [`crate::recording`].
+    pub seed: Option<u64>,
+}
+
+impl SpawnConfig {
+    pub fn new(name: impl Into<Arc<str>>) -> Self {
+        Self {
+            name: name.into(),
+            position: None,
+            behavior: None,
+            variant: None,
+            seed: None,
+        }
+    }
+}
+
+/// Something done to a shimeji through its [`ShimejiHandle`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Control {
+    SetPosition(PhysicalPosition<i32>),
+    /// Switch to the behavior with this name.
+    SetBehavior(String),
+    /// Switch to the behavior with this name, and report when it is over,
+    /// see [`ShimejiHandle::play`].
+    Play(String),
+    SetVisible(bool),
+    /// Recolor the shimeji from its next frame on.
+    SetVariant(Variant),
+    /// Choose behaviors by this seed from now on.
+    SetSeed(u64),
+    Despawn,
+}
+
+/// What happened to a shimeji, read from its [`ShimejiHandle`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShimejiEvent {
+    /// It got its window.
+    Spawned(WindowId),
+    /// The behavior it was told to [play](ShimejiHandle::play) played through.
+    Finished(String),
+    /// The behavior it was told to [play](ShimejiHandle::play) never started,
+    /// or something else took over before it played through, e.g. it was picked up.
+    Interrupted(String),
+    /// It is gone, or never made it, e.g. because of the population limit.
+    /// Nothing comes after this.
+    Despawned,
+}
+
+/// How a behavior started with [`ShimejiHandle::play_and_wait`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playback {
+    Finished,
+    Interrupted,
+    /// The shimeji is gone.
+    Despawned,
+}
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum HandleError {
+    /// The manager's event loop hasn't started yet.
+    NotRunning,
+    /// The manager's event loop has stopped.
+    ManagerGone,
+}
+
+/// Controls one shimeji without touching its bucket.
+///
+/// Everything goes through the manager's event loop, so nothing happens
+/// until [`BucketManager::run`](crate::BucketManager::run) has started it.
+#[derive(Debug)]
+pub struct ShimejiHandle {
+    id: ShimejiId,
+    proxy: Arc<OnceLock<EventLoopProxy<ManagerEvent>>>,
+    events: Receiver<ShimejiEvent>,
+    /// Events that came while waiting for a behavior, to be read before newer ones.
+    skipped: RefCell<VecDeque<ShimejiEvent>>,
+}
+
+impl ShimejiHandle {
+    pub(crate) fn new(
+        id: ShimejiId,
+        proxy: Arc<OnceLock<EventLoopProxy<ManagerEvent>>>,
+        events: Receiver<ShimejiEvent>,
+    ) -> Self {
+        Self {
+            id,
+            proxy,
+            events,
+            skipped: RefCell::default(),
+        }
+    }
+    pub fn id(&self) -> ShimejiId {
+        self.id
+    }
+    fn send(&self, control: Control) -> Result<(), HandleError> {
+        let proxy = self.proxy.get().ok_or(HandleError::NotRunning)?;
+        proxy
+            .send_event(ManagerEvent::Control(self.id, control))
+            .map_err(|_| HandleError::ManagerGone)
+    }
+    /// Recolor the shimeji, see [`Variant`].
+    pub fn set_variant(&self, variant: Variant) -> Result<(), HandleError> {
+        self.send(Control::SetVariant(variant))
+    }
+    /// Move the shimeji's window so its top left is at `position`.
+    pub fn set_position(&self, position: PhysicalPosition<i32>) -> Result<(), HandleError> {
+        self.send(Control::SetPosition(position))
+    }
+    /// Switch to the behavior called `behavior`, if the shimeji's pack has one.
+    pub fn set_behavior(&self, behavior: impl Into<String>) -> Result<(), HandleError> {
+        self.send(Control::SetBehavior(behavior.into()))
+    }
+    /// Switch to the behavior called `behavior`, then get [`ShimejiEvent::Finished`]
+    /// once its animation has played through, for lining up what comes next.
+    /// [`ShimejiEvent::Interrupted`] comes instead if it couldn't be played to the end.
+    pub fn play(&self, behavior: impl Into<String>) -> Result<(), HandleError> {
+        self.send(Control::Play(behavior.into()))
+    }
+    /// Like [`ShimejiHandle::play`], but block until the behavior is over.
+    /// Other events that come in the meantime are kept for [`ShimejiHandle::try_next_event`].
+    pub fn play_and_wait(&self, behavior: impl Into<String>) -> Result<Playback, HandleError> {
+        let behavior = behavior.into();
+        self.send(Control::Play(behavior.clone()))?;
+        self.wait_for(&behavior)
+    }
+    /// Block until `behavior`, told to [play](ShimejiHandle::play) already, is over.
+    /// Other events that come in the meantime are kept for [`ShimejiHandle::try_next_event`].
+    pub fn wait_for(&self, behavior: &str) -> Result<Playback, HandleError> {
+        let playback = |event: &ShimejiEvent| match event {
+            ShimejiEvent::Finished(name) if name == behavior => Some(Playback::Finished),
+            ShimejiEvent::Interrupted(name) if name == behavior => Some(Playback::Interrupted),
+            _ => None,
+        };
+        // it may have been over while waiting for another behavior
+        let mut skipped = self.skipped.borrow_mut();
+        let over = skipped
+            .iter()
+            .enumerate()
+            .find_map(|(index, event)| Some((index, playback(event)?)));
+        if let Some((index, playback)) = over {
+            skipped.remove(index);
+            return Ok(playback);
+        }
+        for event in self.events.iter() {
+            if let Some(playback) = playback(&event) {
+                return Ok(playback);
+            }
+            if event == ShimejiEvent::Despawned {
+                return Ok(Playback::Despawned);
+            }
+            skipped.push_back(event);
+        }
+        Err(HandleError::ManagerGone)
+    }
+    /// Hide or show the shimeji's window. Hidden shimejis keep moving.
+    pub fn set_visible(&self, visible: bool) -> Result<(), HandleError> {
+        self.send(Control::SetVisible(visible))
+    }
+    /// Remove the shimeji, closing its window.
+    pub fn despawn(self) -> Result<(), HandleError> {
+        self.send(Control::Despawn)
+    }
+    /// The next thing that happened to the shimeji, if anything did.
+    pub fn try_next_event(&self) -> Option<ShimejiEvent> {
+        let skipped = self.skipped.borrow_mut().pop_front();
+        skipped.or_else(|| self.events.try_recv().ok())
+    }
+    /// Every event as it happens, blocking until the next one.
+    pub fn events(&self) -> impl Iterator<Item = ShimejiEvent> + '_ {
+        let skipped = std::mem::take(&mut *self.skipped.borrow_mut());
+        skipped.into_iter().chain(self.events.iter())
+    }
+}
@@ -0,0 +1,421 @@
+//! `--headless <seconds>`: run the shimejis' behaviors, physics and animations on a
+//! pretend monitor, with no windows or display server, for that many simulated seconds,
+//! then print how fast that went, for benchmarking the bucket loop and perf tests in CI.
+//!
+//! The population is `SHIMEJI_HEADLESS_POPULATION` (12 by default), and the monitor
+//! `SHIMEJI_HEADLESS_MONITOR` (`1920x1080` by default). Every shimeji is stepped once per
+//! [`PHYSICS_TICK`] of simulated time, as fast as it can be, and frames are drawn into
+//! plain buffers. Behaviors still time themselves on the wall clock, so ones waiting on
+//! time passing come up sooner in simulated time than they would on a desktop.
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use derive_more::derive::{Display, Error};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{
+    behavior::{Behavior, BehaviorState, Situation},
+    blit::{blit, BufferSize, Effects, Facing},
+    frame_cursor::FrameCursor,
+    loader::ShimejiLibrary,
+    movement::{Edge, Movement},
+    rgba::PixelFormat,
+    rng::Rng,
+    script::{Locals, ScriptEffect},
+    shimeji::{ShimejiData, PHYSICS_TICK},
+    world::WorldSnapshot,
+};
+
+pub const FLAG: &str = "--headless";
+const DEFAULT_POPULATION: usize = 12;
+const DEFAULT_MONITOR: PhysicalSize<u32> = PhysicalSize::new(1920, 1080);
+/// So runs can be compared, every run makes the same choices.
+const SEED: u64 = 0x5eed;
+
+#[derive(Debug, Display, Error)]
+pub enum InvalidHeadless {
+    #[display("{FLAG} needs a number of seconds, not {seconds:?}")]
+    Seconds { seconds: String },
+    #[display("SHIMEJI_HEADLESS_POPULATION should be a whole number, not {value:?}")]
+    Population { value: String },
+    #[display("SHIMEJI_HEADLESS_MONITOR should be <width>x<height>, not {value:?}")]
+    Monitor { value: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeadlessConfig {
+    /// How much time to simulate.
+    pub duration: Duration,
+    pub population: usize,
+    /// The size of the one monitor the shimejis roam.
+    pub monitor: PhysicalSize<u32>,
+}
+
+impl HeadlessConfig {
+    /// The simulation asked for with `--headless <seconds>` or `--headless=<seconds>`,
+    /// if any, configured from the environment.
+    pub fn from_args(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<Option<Self>, InvalidHeadless> {
+        let seconds = loop {
+            let Some(arg) = args.next() else {
+                return Ok(None);
+            };
+            if let Some(seconds) = arg.strip_prefix("--headless=") {
+                break seconds.to_owned();
+            }
+            if arg == FLAG {
+                break args.next().unwrap_or_default();
+            }
+        };
+        let duration = seconds
+            .parse()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or(InvalidHeadless::Seconds { seconds })?;
+        let population = match std::env::var("SHIMEJI_HEADLESS_POPULATION") {
+            Ok(value) => value
+                .parse()
+                .map_err(|_| InvalidHeadless::Population { value })?,
+            Err(_) => DEFAULT_POPULATION,
+        };
+        let monitor = match std::env::var("SHIMEJI_HEADLESS_MONITOR") {
+            Ok(value) => value
+                .split_once('x')
+                .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                .map(|(width, height)| PhysicalSize::new(width, height))
+                .ok_or(InvalidHeadless::Monitor { value })?,
+            Err(_) => DEFAULT_MONITOR,
+        };
+        Ok(Some(Self {
+            duration,
+            population,
+            monitor,
+        }))
+    }
+}
+
+/// What is timed separately in each tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Picking behaviors and running their scripts.
+    Think,
+    Physics,
+    /// Moving through frames.
+    Animate,
+    /// Drawing the frame showing into the shimeji's buffer.
+    Draw,
+}
+
+impl Phase {
+    const ALL: [Self; 4] = [Self::Think, Self::Physics, Self::Animate, Self::Draw];
+}
+
+/// How a headless run went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub simulated: Duration,
+    pub population: usize,
+    pub ticks: u64,
+    /// How long the whole run took.
+    pub elapsed: Duration,
+    /// How long was spent in each of [`Phase::ALL`], in that order.
+    pub phases: [Duration; 4],
+    pub frames_drawn: u64,
+    pub behaviors_entered: u64,
+}
+
+impl Report {
+    pub fn ticks_per_second(&self) -> f64 {
+        self.ticks as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Simulated {:.1} s of {} shimejis in {:.3} s: {} ticks, {:.0} ticks per second, \
+             {:.1}x real time",
+            self.simulated.as_secs_f64(),
+            self.population,
+            self.elapsed.as_secs_f64(),
+            self.ticks,
+            self.ticks_per_second(),
+            self.simulated.as_secs_f64() / self.elapsed.as_secs_f64().max(f64::EPSILON)
+        )?;
+        let steps = (self.ticks * self.population.max(1) as u64).max(1);
+        for (phase, spent) in Phase::ALL.iter().zip(self.phases) {
+            writeln!(
+                f,
+                "  {phase:?}: {:.3} s, {:.2} µs per shimeji per tick",
+                spent.as_secs_f64(),
+                spent.as_secs_f64() * 1e6 / steps as f64
+            )?;
+        }
+        writeln!(f, "  frames drawn: {}", self.frames_drawn)?;
+        write!(f, "  behaviors entered: {}", self.behaviors_entered)
+    }
+}
+
+/// A shimeji without a window, going through what a bucket thread puts one through.
+#[derive(Debug)]
+struct Simulated {
+    data: Arc<ShimejiData>,
+    behavior: BehaviorState,
+    movement: Movement,
+    animation: String,
+    cursor: FrameCursor,
+    animation_finished: bool,
+    /// How much longer the frame showing shows for, in simulated time.
+    frame_left: Duration,
+    /// Whether the frame showing changed since it was last drawn.
+    frame_changed: bool,
+    facing: Facing,
+    buffer: Vec<u8>,
+}
+
+impl Simulated {
+    fn new(
+        data: Arc<ShimejiData>,
+        position: PhysicalPosition<f64>,
+        monitor: PhysicalSize<u32>,
+    ) -> Self {
+        let mut movement = Movement::new(position).with_gravity(data.gravity);
+        movement.set_floor(Some(monitor.height.saturating_sub(data.height) as f64));
+        let right = monitor.width.saturating_sub(data.width);
+        movement.set_walls(Some((0.0, right as f64)));
+        movement.set_ceiling(Some(0.0));
+        let buffer = vec![0; data.width as usize * data.height as usize * 4];
+        let mut simulated = Self {
+            behavior: BehaviorState::new(&data.behaviors),
+            movement,
+            animation: String::new(),
+            cursor: FrameCursor::new(0, Default::default()),
+            animation_finished: false,
+            frame_left: Duration::ZERO,
+            frame_changed: true,
+            facing: Facing::default(),
+            buffer,
+            data,
+        };
+        let data = Arc::clone(&simulated.data);
+        simulated.enter(data.behaviors.initial());
+        simulated
+    }
+    fn enter(&mut self, behavior: &Behavior) {
+        self.behavior.enter(behavior);
+        self.animation_finished = false;
+        self.show(&behavior.animation);
+        self.movement.command(behavior.action.movement());
+    }
+    fn show(&mut self, name: &str) {
+        self.animation = name.to_owned();
+        let animation = self.data.animations.get(name);
+        self.cursor = FrameCursor::new(
+            animation.map_or(0, |animation| animation.frames.len()),
+            animation
+                .map(|animation| animation.loop_mode)
+                .unwrap_or_default(),
+        );
+        self.frame_left = animation.map_or(Duration::ZERO, |animation| animation.duration_of(0));
+        self.frame_changed = true;
+    }
+    /// Returns whether it went on to another behavior.
+    fn think(&mut self, world: &WorldSnapshot, rng: &mut Rng) -> bool {
+        let data = Arc::clone(&self.data);
+        let position = self.movement.position();
+        let velocity = self.movement.velocity();
+        let locals = Locals {
+            x: position.x,
+            y: position.y,
+            velocity_x: velocity.0,
+            velocity_y: velocity.1,
+            time: 0.0,
+        };
+        for effect in self
+            .behavior
+            .run_script(&data.behaviors, world, locals, rng)
+        {
+            match effect {
+                ScriptEffect::Velocity(x, y) => self.movement.push((x, y)),
+                ScriptEffect::Animation(name) => {
+                    if self.animation != name {
+                        self.show(&name);
+                    }
+                }
+                ScriptEffect::Behavior(name) => {
+                    if let Some(next) = data.behaviors.get(&name) {
+                        self.enter(next);
+                        return true;
+                    }
+                }
+            }
+        }
+        let situation = Situation {
+            held: false,
+            airborne: self.movement.is_airborne(),
+            at_wall: matches!(
+                self.movement.touching(),
+                Some(Edge::LeftWall | Edge::RightWall)
+            ),
+            at_ceiling: self.movement.touching() == Some(Edge::Ceiling),
+            animation_finished: self.animation_finished,
+            near_another: false,
+        };
+        match self.behavior.next(&data.behaviors, situation, world, rng) {
+            Some(next) => {
+                self.enter(next);
+                true
+            }
+            None => {
+                self.animation_finished = false;
+                false
+            }
+        }
+    }
+    fn step_physics(&mut self, delta: Duration) {
+        self.movement.step(delta);
+        let facing = match self.movement.touching() {
+            Some(Edge::LeftWall) => Some(Facing::Left),
+            Some(Edge::RightWall) => Some(Facing::Right),
+            _ => Facing::of_speed(self.movement.velocity().0),
+        };
+        if let Some(facing) = facing.filter(|facing| *facing != self.facing) {
+            self.facing = facing;
+            self.frame_changed = true;
+        }
+    }
+    fn animate(&mut self, delta: Duration) {
+        let data = Arc::clone(&self.data);
+        let Some(animation) = data.animations.get(&self.animation) else {
+            return;
+        };
+        let mut left = delta;
+        while left >= self.frame_left && !self.cursor.is_finished() {
+            left -= self.frame_left;
+            let passed = self.cursor.advance();
+            self.frame_changed = true;
+            if passed {
+                match &animation.next {
+                    Some(next) => {
+                        self.show(next);
+                        return;
+                    }
+                    None => self.animation_finished = true,
+                }
+            }
+            let Some(index) = self.cursor.index() else {
+                return;
+            };
+            self.frame_left = animation.duration_of(index).max(Duration::from_millis(1));
+        }
+        self.frame_left = self.frame_left.saturating_sub(left);
+    }
+    /// Returns whether a frame was drawn.
+    fn draw(&mut self, effects: Effects) -> bool {
+        if !self.frame_changed {
+            return false;
+        }
+        self.frame_changed = false;
+        let Some(frame) = self
+            .cursor
+            .index()
+            .and_then(|index| self.data.animations.get(&self.animation)?.frames.get(index))
+        else {
+            return false;
+        };
+        let size = BufferSize {
+            width: self.data.width,
+            height: self.data.height,
+        };
+        let policy = self.data.scaling.policy().unwrap_or_default();
+        blit(
+            frame,
+            &mut self.buffer,
+            size,
+            PixelFormat::Rgba8,
+            policy,
+            self.facing,
+            effects,
+        );
+        true
+    }
+}
+
+/// Simulate `config.population` shimejis from `library`, taking turns at its packs.
+///
+/// # Errors
+/// Errors if the library is empty.
+pub fn run(config: &HeadlessConfig, library: &ShimejiLibrary) -> anyhow::Result<Report> {
+    let packs: Vec<_> = library
+        .names()
+        .iter()
+        .filter_map(|name| library.get(name))
+        .collect();
+    anyhow::ensure!(!packs.is_empty(), "there are no shimejis to simulate");
+    let mut rng = Rng::with_seed(SEED);
+    let mut shimejis: Vec<_> = packs
+        .iter()
+        .cycle()
+        .take(config.population)
+        .map(|data| {
+            let x = rng.f64() * config.monitor.width.saturating_sub(data.width) as f64;
+            Simulated::new(
+                Arc::clone(data),
+                PhysicalPosition::new(x, 0.0),
+                config.monitor,
+            )
+        })
+        .collect();
+    let world = WorldSnapshot {
+        population: shimejis.len(),
+        ..WorldSnapshot::default()
+    };
+    let mut report = Report {
+        simulated: Duration::ZERO,
+        population: shimejis.len(),
+        ticks: 0,
+        elapsed: Duration::ZERO,
+        phases: [Duration::ZERO; 4],
+        frames_drawn: 0,
+        behaviors_entered: 0,
+    };
+    let started = Instant::now();
+    while report.simulated < config.duration {
+        let mut phase_started = Instant::now();
+        let mut lap = |phase: Phase, report: &mut Report| {
+            let now = Instant::now();
+            report.phases[phase as usize] += now - phase_started;
+            phase_started = now;
+        };
+        for shimeji in shimejis.iter_mut() {
+            report.behaviors_entered += u64::from(shimeji.think(&world, &mut rng));
+        }
+        lap(Phase::Think, &mut report);
+        for shimeji in shimejis.iter_mut() {
+            shimeji.step_physics(PHYSICS_TICK);
+        }
+        lap(Phase::Physics, &mut report);
+        for shimeji in shimejis.iter_mut() {
+            shimeji.animate(PHYSICS_TICK);
+        }
+        lap(Phase::Animate, &mut report);
+        for shimeji in shimejis.iter_mut() {
+            let effects = Effects {
+                letterbox: shimeji.data.letterbox,
+                ..Effects::default()
+            };
+            report.frames_drawn += u64::from(shimeji.draw(effects));
+        }
+        lap(Phase::Draw, &mut report);
+        report.ticks += 1;
+        report.simulated += PHYSICS_TICK;
+    }
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
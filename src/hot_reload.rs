@@ -0,0 +1,93 @@
+//! Loading the library again whenever one of its files changes,
+//! so pack authors see their edits without restarting.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use winit::event_loop::EventLoopProxy;
+
+use crate::{loader::ShimejiLibrary, notify, ManagerEvent};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Check the files of `library` every [`POLL_INTERVAL`] on its own thread,
+/// sending [`ManagerEvent::LibraryReloaded`] with the library loaded again
+/// whenever any of them change, until `should_exit` is set.
+pub fn spawn(
+    library: ShimejiLibrary,
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Hot reload thread"))
+        .spawn(move || watch(library, proxy, should_exit))
+}
+
+/// When each file was last modified, `None` for files that are gone.
+fn modified_times(sources: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    sources
+        .iter()
+        .map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}
+
+fn watch(
+    mut library: ShimejiLibrary,
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) {
+    let mut sources = library.sources();
+    let mut last = modified_times(&sources);
+    // the last problem shown to the user
+    let mut reported: Option<String> = None;
+    log::info!("Watching {} files to reload", sources.len());
+    while !should_exit.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL);
+        let modified = modified_times(&sources);
+        if modified == last {
+            continue;
+        }
+        last = modified;
+        let Some(reloaded) = library.reload() else {
+            log::warn!("The library wasn't loaded from anywhere, so it can't be reloaded");
+            return;
+        };
+        // half-saved files fail to load, the next save will try again
+        match reloaded {
+            Ok(reloaded) => {
+                library = reloaded;
+                reported = None;
+                sources = library.sources();
+                last = modified_times(&sources);
+                log::info!("Reloaded {:?}", library.names());
+                if proxy
+                    .send_event(ManagerEvent::LibraryReloaded(library.clone()))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Err(why) => {
+                log::error!("Could not reload the library: {why:#}");
+                // once for each problem, not every time a half-saved file is tried
+                let problem = format!("{why:#}");
+                if reported.as_ref() != Some(&problem) {
+                    notify::problem("Could not reload the shimejis", &problem);
+                    reported = Some(problem);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,426 @@
+//! Keys that do something wherever the focus is, bound in the settings as
+//! `hotkey.ctrl+alt+s = spawn Shimeji`, `hotkey.ctrl+alt+c = summon`,
+//! `hotkey.ctrl+alt+p = pause` or `hotkey.ctrl+alt+y = party`.
+//!
+//! A key is a letter, a digit or `f1` to `f12`, after any of `ctrl`, `alt`, `shift` and
+//! `super`. They're grabbed from the X server on Linux, so only while an X11 window has
+//! the focus under Wayland, and registered with `RegisterHotKey` on Windows.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use derive_more::derive::{Display, Error};
+use winit::event_loop::EventLoopProxy;
+
+use crate::ManagerEvent;
+
+/// How often to look for key presses, and whether it's time to stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Display, Error)]
+pub enum InvalidHotkey {
+    #[display("{hotkey:?} has no key, only modifiers")]
+    NoKey { hotkey: String },
+    #[display("unknown key {key:?}, expected a letter, a digit or f1 to f12")]
+    UnknownKey { key: String },
+    #[display("unknown hotkey action {action:?}, expected spawn <pack>, summon, pause or party")]
+    UnknownAction { action: String },
+}
+
+/// The keys held down with a hotkey's key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// The Windows or Command key.
+    pub logo: bool,
+}
+
+/// The key pressed with a hotkey's modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A lowercase letter or a digit.
+    Char(char),
+    /// `F1` to `F12`.
+    Function(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hotkey {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+impl FromStr for Hotkey {
+    type Err = InvalidHotkey;
+
+    /// `ctrl+alt+s`, in any case, with any modifiers in any order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::default();
+        let mut key = None;
+        for part in s.split('+').map(|part| part.trim().to_ascii_lowercase()) {
+            match part.as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "alt" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                "super" | "win" | "cmd" => modifiers.logo = true,
+                _ => {
+                    let mut chars = part.chars();
+                    key = Some(match (chars.next(), chars.next()) {
+                        (Some(char), None) if char.is_ascii_alphanumeric() => Key::Char(char),
+                        _ => part
+                            .strip_prefix('f')
+                            .and_then(|number| number.parse().ok())
+                            .filter(|number| (1..=12).contains(number))
+                            .map(Key::Function)
+                            .ok_or(InvalidHotkey::UnknownKey { key: part })?,
+                    });
+                }
+            }
+        }
+        let key = key.ok_or_else(|| InvalidHotkey::NoKey {
+            hotkey: s.to_owned(),
+        })?;
+        Ok(Self { modifiers, key })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Modifiers {
+            ctrl,
+            alt,
+            shift,
+            logo,
+        } = self.modifiers;
+        for (held, name) in [
+            (ctrl, "ctrl"),
+            (alt, "alt"),
+            (shift, "shift"),
+            (logo, "super"),
+        ] {
+            if held {
+                write!(f, "{name}+")?;
+            }
+        }
+        match self.key {
+            Key::Char(char) => write!(f, "{char}"),
+            Key::Function(number) => write!(f, "f{number}"),
+        }
+    }
+}
+
+/// What a hotkey does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotkeyAction {
+    /// Spawn a shimeji of this pack.
+    Spawn(Arc<str>),
+    /// Bring every shimeji over to the cursor.
+    Summon,
+    /// Freeze every shimeji, or let them carry on.
+    TogglePause,
+    /// Throw a party, see [`ManagerEvent::StartParty`].
+    Party,
+}
+
+impl HotkeyAction {
+    pub fn event(&self) -> ManagerEvent {
+        match self {
+            Self::Spawn(pack) => ManagerEvent::Spawn(Arc::clone(pack)),
+            Self::Summon => ManagerEvent::SummonToCursor,
+            Self::TogglePause => ManagerEvent::TogglePause,
+            Self::Party => ManagerEvent::StartParty,
+        }
+    }
+}
+
+impl FromStr for HotkeyAction {
+    type Err = InvalidHotkey;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.split_once(char::is_whitespace) {
+            Some(("spawn", pack)) if !pack.trim().is_empty() => Ok(Self::Spawn(pack.trim().into())),
+            None if s == "summon" => Ok(Self::Summon),
+            None if s == "pause" => Ok(Self::TogglePause),
+            None if s == "party" => Ok(Self::Party),
+            _ => Err(InvalidHotkey::UnknownAction {
+                action: s.to_owned(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for HotkeyAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn(pack) => write!(f, "spawn {pack}"),
+            Self::Summon => write!(f, "summon"),
+            Self::TogglePause => write!(f, "pause"),
+            Self::Party => write!(f, "party"),
+        }
+    }
+}
+
+/// Listen for `hotkeys` on their own thread, sending the manager what each one does
+/// when it's pressed, until `should_exit` is set.
+pub fn spawn(
+    hotkeys: Vec<(Hotkey, HotkeyAction)>,
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Hotkey thread"))
+        .spawn(move || listen(hotkeys, proxy, should_exit))
+}
+
+fn listen(
+    hotkeys: Vec<(Hotkey, HotkeyAction)>,
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) {
+    let keys: Vec<Hotkey> = hotkeys.iter().map(|(hotkey, _)| *hotkey).collect();
+    let grabs = match Grabs::new(&keys) {
+        Ok(grabs) => grabs,
+        Err(why) => {
+            log::warn!("Could not listen for hotkeys: {why:#}");
+            return;
+        }
+    };
+    while !should_exit.load(Ordering::Relaxed) {
+        let pressed = match grabs.pressed() {
+            Ok(pressed) => pressed,
+            Err(why) => {
+                log::warn!("Stopped listening for hotkeys: {why:#}");
+                return;
+            }
+        };
+        for index in pressed {
+            let (hotkey, action) = &hotkeys[index];
+            log::debug!("{hotkey} pressed, doing {action}");
+            if proxy.send_event(action.event()).is_err() {
+                return;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use x11rb::{
+            connection::Connection,
+            protocol::{
+                xproto::{ConnectionExt as _, GrabMode, ModMask, Window},
+                Event,
+            },
+            rust_connection::RustConnection,
+        };
+
+        /// Caps Lock and Num Lock, which shouldn't stop a hotkey from working.
+        const LOCKS: [u16; 4] = [0, LOCK, NUM_LOCK, LOCK | NUM_LOCK];
+        const LOCK: u16 = 1 << 1;
+        const NUM_LOCK: u16 = 1 << 4;
+
+        /// The keysym X11 gives `key`.
+        fn keysym(key: Key) -> u32 {
+            match key {
+                Key::Char(char) => char as u32,
+                Key::Function(number) => 0xffbe + number as u32 - 1,
+            }
+        }
+
+        fn mod_mask(modifiers: Modifiers) -> u16 {
+            let mut mask = 0;
+            for (held, modifier) in [
+                (modifiers.ctrl, ModMask::CONTROL),
+                (modifiers.alt, ModMask::M1),
+                (modifiers.shift, ModMask::SHIFT),
+                (modifiers.logo, ModMask::M4),
+            ] {
+                if held {
+                    mask |= u16::from(modifier);
+                }
+            }
+            mask
+        }
+
+        /// Keys grabbed on the root window, by keycode and modifiers.
+        struct Grabs {
+            connection: RustConnection,
+            keys: Vec<(u8, u16)>,
+        }
+
+        impl Grabs {
+            fn new(hotkeys: &[Hotkey]) -> anyhow::Result<Self> {
+                let (connection, screen) = x11rb::connect(None)?;
+                let root: Window = connection.setup().roots[screen].root;
+                let (min, max) = (connection.setup().min_keycode, connection.setup().max_keycode);
+                let mapping = connection
+                    .get_keyboard_mapping(min, max - min + 1)?
+                    .reply()?;
+                let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+                let mut keys = vec![];
+                for hotkey in hotkeys {
+                    let Some(keycode) = mapping
+                        .keysyms
+                        .chunks(per_keycode)
+                        .position(|keysyms| keysyms.first() == Some(&keysym(hotkey.key)))
+                        .map(|index| min + index as u8)
+                    else {
+                        log::warn!("No key on this keyboard for the hotkey {hotkey}");
+                        // keeps the rest lined up with their actions, and never matches
+                        keys.push((0, 0));
+                        continue;
+                    };
+                    let modifiers = mod_mask(hotkey.modifiers);
+                    for lock in LOCKS {
+                        let grabbed = connection
+                            .grab_key(
+                                false,
+                                root,
+                                ModMask::from(modifiers | lock),
+                                keycode,
+                                GrabMode::ASYNC,
+                                GrabMode::ASYNC,
+                            )?
+                            .check();
+                        if let Err(why) = grabbed {
+                            log::warn!(
+                                "Could not grab the hotkey {hotkey}, is something else using it? {why}"
+                            );
+                            break;
+                        }
+                    }
+                    keys.push((keycode, modifiers));
+                }
+                Ok(Self { connection, keys })
+            }
+            /// Which of the hotkeys were pressed since last time.
+            fn pressed(&self) -> anyhow::Result<Vec<usize>> {
+                let mut pressed = vec![];
+                while let Some(event) = self.connection.poll_for_event()? {
+                    let Event::KeyPress(event) = event else {
+                        continue;
+                    };
+                    // the mouse buttons held are in the bits above the modifiers
+                    let held = u16::from(event.state) & 0xff & !(LOCK | NUM_LOCK);
+                    pressed.extend(
+                        self.keys
+                            .iter()
+                            .position(|&(keycode, modifiers)| {
+                                keycode == event.detail && modifiers == held
+                            }),
+                    );
+                }
+                Ok(pressed)
+            }
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use windows_sys::Win32::UI::{
+            Input::KeyboardAndMouse::{
+                RegisterHotKey, UnregisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT,
+                MOD_WIN,
+            },
+            WindowsAndMessaging::{PeekMessageW, MSG, PM_REMOVE, WM_HOTKEY},
+        };
+
+        /// The virtual key code Windows gives `key`.
+        fn virtual_key(key: Key) -> u32 {
+            match key {
+                Key::Char(char) => char.to_ascii_uppercase() as u32,
+                Key::Function(number) => 0x70 + number as u32 - 1,
+            }
+        }
+
+        /// Hotkeys registered to this thread, by their index.
+        struct Grabs {
+            count: usize,
+        }
+
+        impl Grabs {
+            fn new(hotkeys: &[Hotkey]) -> anyhow::Result<Self> {
+                for (index, hotkey) in hotkeys.iter().enumerate() {
+                    let Modifiers { ctrl, alt, shift, logo } = hotkey.modifiers;
+                    let mut modifiers = MOD_NOREPEAT;
+                    for (held, modifier) in [
+                        (ctrl, MOD_CONTROL),
+                        (alt, MOD_ALT),
+                        (shift, MOD_SHIFT),
+                        (logo, MOD_WIN),
+                    ] {
+                        if held {
+                            modifiers |= modifier;
+                        }
+                    }
+                    // SAFETY: no window, so the hotkey is posted to this thread's queue.
+                    let registered = unsafe {
+                        RegisterHotKey(
+                            std::ptr::null_mut(),
+                            index as i32,
+                            modifiers,
+                            virtual_key(hotkey.key),
+                        )
+                    };
+                    if registered == 0 {
+                        log::warn!(
+                            "Could not register the hotkey {hotkey}, is something else using it? {}",
+                            std::io::Error::last_os_error()
+                        );
+                    }
+                }
+                Ok(Self { count: hotkeys.len() })
+            }
+            /// Which of the hotkeys were pressed since last time.
+            fn pressed(&self) -> anyhow::Result<Vec<usize>> {
+                let mut pressed = vec![];
+                // SAFETY: `MSG` is plain data, and is filled in by `PeekMessageW`.
+                let mut message: MSG = unsafe { std::mem::zeroed() };
+                // SAFETY: `message` is a local, and only this thread's hotkeys are asked for.
+                while unsafe {
+                    PeekMessageW(
+                        &mut message,
+                        std::ptr::null_mut(),
+                        WM_HOTKEY,
+                        WM_HOTKEY,
+                        PM_REMOVE,
+                    )
+                } != 0
+                {
+                    pressed.push(message.wParam);
+                }
+                Ok(pressed)
+            }
+        }
+
+        impl Drop for Grabs {
+            fn drop(&mut self) {
+                for index in 0..self.count {
+                    // SAFETY: registered by this thread, which is the one dropping them.
+                    unsafe { UnregisterHotKey(std::ptr::null_mut(), index as i32) };
+                }
+            }
+        }
+    } else {
+        struct Grabs;
+
+        impl Grabs {
+            fn new(_hotkeys: &[Hotkey]) -> anyhow::Result<Self> {
+                anyhow::bail!("hotkeys aren't supported on this platform yet")
+            }
+            fn pressed(&self) -> anyhow::Result<Vec<usize>> {
+                unreachable!("there are no hotkeys on this platform")
+            }
+        }
+    }
+}
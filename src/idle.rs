@@ -0,0 +1,127 @@
+//! Noticing when the user has stepped away, so shimejis can get bored or fall asleep,
+//! and when they're back, so they can wake up again.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use winit::event_loop::EventLoopProxy;
+
+use crate::ManagerEvent;
+
+/// Short enough that shimejis wake up soon after the mouse moves.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Check how long it's been since the last keyboard or mouse input every [`POLL_INTERVAL`]
+/// on its own thread, sending [`ManagerEvent::Idle`] whenever the user goes idle for `after`
+/// or comes back, until `should_exit` is set.
+pub fn spawn(
+    after: Duration,
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Idle detection thread"))
+        .spawn(move || poll_input(after, proxy, should_exit))
+}
+
+fn poll_input(after: Duration, proxy: EventLoopProxy<ManagerEvent>, should_exit: Arc<AtomicBool>) {
+    let clock = match InputClock::new() {
+        Ok(clock) => clock,
+        Err(why) => {
+            log::warn!("Could not watch for user input, shimejis won't notice you're away: {why}");
+            return;
+        }
+    };
+    let mut last = false;
+    while !should_exit.load(Ordering::Relaxed) {
+        let idle = match clock.since_input() {
+            Ok(since) => since >= after,
+            Err(why) => {
+                log::warn!("Could not tell how long since user input: {why}");
+                return;
+            }
+        };
+        if idle != last {
+            if proxy.send_event(ManagerEvent::Idle(idle)).is_err() {
+                return;
+            }
+            last = idle;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use x11rb::{
+            connection::Connection, protocol::screensaver::ConnectionExt as _,
+            rust_connection::RustConnection,
+        };
+
+        /// Asks the X server's screensaver extension, which XWayland has too
+        /// but only sees input to X11 windows through.
+        struct InputClock {
+            connection: RustConnection,
+            root: u32,
+        }
+
+        impl InputClock {
+            fn new() -> anyhow::Result<Self> {
+                let (connection, screen) = x11rb::connect(None)?;
+                let root = connection.setup().roots[screen].root;
+                connection.screensaver_query_version(1, 1)?.reply()?;
+                Ok(Self { connection, root })
+            }
+            fn since_input(&self) -> anyhow::Result<Duration> {
+                let info = self.connection.screensaver_query_info(self.root)?.reply()?;
+                Ok(Duration::from_millis(info.ms_since_user_input.into()))
+            }
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use windows_sys::Win32::{
+            System::SystemInformation::GetTickCount,
+            UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO},
+        };
+
+        /// Asks `GetLastInputInfo`, which covers every application in the session.
+        struct InputClock;
+
+        impl InputClock {
+            fn new() -> anyhow::Result<Self> {
+                Ok(Self)
+            }
+            fn since_input(&self) -> anyhow::Result<Duration> {
+                let mut info = LASTINPUTINFO {
+                    cbSize: size_of::<LASTINPUTINFO>() as u32,
+                    dwTime: 0,
+                };
+                // SAFETY: `info` is a local with its size filled in, as the call expects.
+                if unsafe { GetLastInputInfo(&mut info) } == 0 {
+                    anyhow::bail!("GetLastInputInfo failed: {}", std::io::Error::last_os_error());
+                }
+                // both wrap around after 49.7 days
+                // SAFETY: `GetTickCount` has no preconditions.
+                let now = unsafe { GetTickCount() };
+                Ok(Duration::from_millis(now.wrapping_sub(info.dwTime).into()))
+            }
+        }
+    } else {
+        struct InputClock;
+
+        impl InputClock {
+            fn new() -> anyhow::Result<Self> {
+                anyhow::bail!("idle detection isn't supported on this platform")
+            }
+            fn since_input(&self) -> anyhow::Result<Duration> {
+                unreachable!("there is no input clock on this platform")
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use derive_more::derive::{Display, Error};
+use winit::{dpi::PhysicalPosition, event::TouchPhase, window::WindowId};
+
+/// How far (in physical pixels) a pointer has to travel after being pressed
+/// before the press is treated as a drag instead of a pet.
+const DRAG_THRESHOLD: f64 = 6.0;
+/// How much each new drag movement counts towards the velocity a shimeji is thrown at,
+/// the rest being the velocity so far, which smooths out jittery pointers.
+const VELOCITY_SMOOTHING: f64 = 0.6;
+/// A pointer held still for this long before letting go drops the shimeji instead of throwing it.
+const THROW_WINDOW: Duration = Duration::from_millis(80);
+/// How soon after a click a shimeji has to be picked up for it to count as a double click.
+pub const DOUBLE_CLICK: Duration = Duration::from_millis(500);
+
+/// Something that can point at a shimeji window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PointerId {
+    Mouse,
+    /// A finger on a touchscreen, as identified by winit's `Touch::id`.
+    Touch(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerPhase {
+    Pressed,
+    Moved,
+    Released,
+    Cancelled,
+}
+
+impl From<TouchPhase> for PointerPhase {
+    fn from(value: TouchPhase) -> Self {
+        match value {
+            TouchPhase::Started => Self::Pressed,
+            TouchPhase::Moved => Self::Moved,
+            TouchPhase::Ended => Self::Released,
+            TouchPhase::Cancelled => Self::Cancelled,
+        }
+    }
+}
+
+/// What the bucket thread should do with a shimeji as a result of pointer input.
+///
+/// All positions are relative to the top left of the shimeji's window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InteractionEvent {
+    /// The shimeji was picked up at `offset`.
+    Grab {
+        id: WindowId,
+        offset: PhysicalPosition<f64>,
+    },
+    /// The pointer holding the shimeji moved to `position`.
+    Drag {
+        id: WindowId,
+        position: PhysicalPosition<f64>,
+    },
+    /// The shimeji was let go while moving at `velocity`, in pixels per second.
+    Release { id: WindowId, velocity: (f64, f64) },
+    /// The shimeji was pressed and released without being dragged.
+    Pet(WindowId),
+}
+
+#[derive(Debug)]
+enum PointerState {
+    Pressed {
+        window: WindowId,
+        origin: PhysicalPosition<f64>,
+    },
+    Dragging {
+        window: WindowId,
+        offset: PhysicalPosition<f64>,
+        last_moved: Instant,
+        velocity: (f64, f64),
+    },
+}
+
+impl PointerState {
+    fn window(&self) -> WindowId {
+        match self {
+            Self::Pressed { window, .. } | Self::Dragging { window, .. } => *window,
+        }
+    }
+}
+
+/// Tracks every pointer (the mouse and each touch) currently pressed on a shimeji,
+/// turning raw press/move/release input into [`InteractionEvent`]s.
+///
+/// Every pointer is tracked separately, so two fingers can drag two shimejis at once.
+#[derive(Debug, Default)]
+pub struct InteractionTracker {
+    pointers: HashMap<PointerId, PointerState>,
+}
+
+impl InteractionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if any pointer is currently pressed on `window`.
+    pub fn is_held(&self, window: WindowId) -> bool {
+        self.pointers.values().any(|state| state.window() == window)
+    }
+
+    /// Feed one piece of pointer input on `window` into the state machine.
+    pub fn handle(
+        &mut self,
+        window: WindowId,
+        pointer: PointerId,
+        phase: PointerPhase,
+        position: PhysicalPosition<f64>,
+    ) -> Option<InteractionEvent> {
+        match phase {
+            PointerPhase::Pressed => {
+                // only one pointer may hold a shimeji at a time
+                if self.is_held(window) {
+                    return None;
+                }
+                self.pointers.insert(
+                    pointer,
+                    PointerState::Pressed {
+                        window,
+                        origin: position,
+                    },
+                );
+                None
+            }
+            PointerPhase::Moved => {
+                let state = self.pointers.get_mut(&pointer)?;
+                match state {
+                    PointerState::Pressed { window, origin } => {
+                        let dx = position.x - origin.x;
+                        let dy = position.y - origin.y;
+                        if dx.hypot(dy) < DRAG_THRESHOLD {
+                            return None;
+                        }
+                        let (window, offset) = (*window, *origin);
+                        *state = PointerState::Dragging {
+                            window,
+                            offset,
+                            last_moved: Instant::now(),
+                            velocity: (0.0, 0.0),
+                        };
+                        Some(InteractionEvent::Grab { id: window, offset })
+                    }
+                    PointerState::Dragging {
+                        window,
+                        offset,
+                        last_moved,
+                        velocity,
+                    } => {
+                        // positions are window-relative, and the window follows the pointer,
+                        // so the distance from the grab offset is how far the window will move
+                        let elapsed = last_moved.elapsed().as_secs_f64();
+                        if elapsed > 0.0 {
+                            let sample = (
+                                (position.x - offset.x) / elapsed,
+                                (position.y - offset.y) / elapsed,
+                            );
+                            let blend = |old: f64, new: f64| old + (new - old) * VELOCITY_SMOOTHING;
+                            *velocity = (blend(velocity.0, sample.0), blend(velocity.1, sample.1));
+                        }
+                        *last_moved = Instant::now();
+                        Some(InteractionEvent::Drag {
+                            id: *window,
+                            position,
+                        })
+                    }
+                }
+            }
+            PointerPhase::Released | PointerPhase::Cancelled => {
+                match self.pointers.remove(&pointer)? {
+                    PointerState::Pressed { window, .. } => {
+                        if phase == PointerPhase::Released {
+                            Some(InteractionEvent::Pet(window))
+                        } else {
+                            None
+                        }
+                    }
+                    PointerState::Dragging {
+                        window,
+                        velocity,
+                        last_moved,
+                        ..
+                    } => Some(InteractionEvent::Release {
+                        id: window,
+                        // a cancelled touch wasn't a flick, the shimeji is just dropped
+                        velocity: match phase == PointerPhase::Cancelled
+                            || last_moved.elapsed() > THROW_WINDOW
+                        {
+                            true => (0.0, 0.0),
+                            false => velocity,
+                        },
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Forget every pointer pressed on `window`, e.g. when it is closed.
+    pub fn forget_window(&mut self, window: WindowId) {
+        self.pointers.retain(|_, state| state.window() != window);
+    }
+}
+
+/// The kinds of interaction an [`EventFilter`] can hold back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InteractionKind {
+    /// Pressing and letting go without dragging, which pets the shimeji.
+    Click,
+    /// Picking the shimeji up to drag it around.
+    Grab,
+}
+
+/// What a filter knows about the shimeji an interaction is for.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilterContext<'a> {
+    /// The behavior it's in, if any.
+    pub behavior: Option<&'a str>,
+    /// When it was last clicked, whether or not that click was let through.
+    pub last_click: Option<Instant>,
+}
+
+/// A rule on which interactions reach a shimeji's behavior, written in settings as e.g.
+/// `filter = ignore click while sleeping` or `filter = require double-click to grab`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventFilter {
+    /// Drop every `kind` of interaction, or only while the shimeji is in `behavior`.
+    Ignore {
+        kind: InteractionKind,
+        behavior: Option<String>,
+    },
+    /// Only pick shimejis up within [`DOUBLE_CLICK`] of clicking them.
+    DoubleClickToGrab,
+}
+
+impl EventFilter {
+    pub fn allows(&self, kind: InteractionKind, context: FilterContext) -> bool {
+        match self {
+            Self::Ignore {
+                kind: ignored,
+                behavior,
+            } => {
+                *ignored != kind
+                    || behavior
+                        .as_deref()
+                        .is_some_and(|behavior| context.behavior != Some(behavior))
+            }
+            Self::DoubleClickToGrab => {
+                kind != InteractionKind::Grab
+                    || context
+                        .last_click
+                        .is_some_and(|clicked| clicked.elapsed() <= DOUBLE_CLICK)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Display, Error)]
+#[display("unknown event filter {filter:?}, expected `ignore <click|grab> [while <behavior>]` or `require double-click to grab`")]
+pub struct UnknownEventFilter {
+    filter: String,
+}
+
+impl FromStr for EventFilter {
+    type Err = UnknownEventFilter;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let kind = |word: &str| match word {
+            "click" => Some(InteractionKind::Click),
+            "grab" => Some(InteractionKind::Grab),
+            _ => None,
+        };
+        let filter = match words[..] {
+            ["require", "double-click", "to", "grab"] => Some(Self::DoubleClickToGrab),
+            ["ignore", ignored] => kind(ignored).map(|kind| Self::Ignore {
+                kind,
+                behavior: None,
+            }),
+            ["ignore", ignored, "while", behavior] => kind(ignored).map(|kind| Self::Ignore {
+                kind,
+                behavior: Some(behavior.to_owned()),
+            }),
+            _ => None,
+        };
+        filter.ok_or_else(|| UnknownEventFilter {
+            filter: s.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for EventFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ignore { kind, behavior } => {
+                let kind = match kind {
+                    InteractionKind::Click => "click",
+                    InteractionKind::Grab => "grab",
+                };
+                write!(f, "ignore {kind}")?;
+                match behavior {
+                    Some(behavior) => write!(f, " while {behavior}"),
+                    None => Ok(()),
+                }
+            }
+            Self::DoubleClickToGrab => write!(f, "require double-click to grab"),
+        }
+    }
+}
+
+/// [`EventFilter`]s checked one after another, an interaction has to get past all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterChain {
+    filters: Vec<EventFilter>,
+}
+
+impl FilterChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add `filter` to the end of the chain.
+    pub fn with(mut self, filter: EventFilter) -> Self {
+        self.push(filter);
+        self
+    }
+    pub fn push(&mut self, filter: EventFilter) {
+        self.filters.push(filter);
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &EventFilter> {
+        self.filters.iter()
+    }
+    pub fn allows(&self, kind: InteractionKind, context: FilterContext) -> bool {
+        self.filters
+            .iter()
+            .all(|filter| filter.allows(kind, context))
+    }
+}
@@ -0,0 +1,335 @@
+//! A local control channel, so scripts and other programs like OBS scenes or keybinding
+//! daemons can control the shimejis: a Unix socket at [`socket_path`] on Linux and macOS,
+//! and the named pipe `\\.\pipe\new-shimeji` on Windows.
+//!
+//! Clients write one [`Command`] a line. Each is answered with whatever lines it has to say,
+//! then a line that's either `ok` or `error: ` and what went wrong:
+//!
+//! - `spawn <name>` spawns another shimeji from the library.
+//! - `remove [<id>]` removes the shimeji with that id, or the newest one.
+//! - `list` answers `<id> <name>` for every shimeji, oldest first.
+//! - `pause` and `resume` freeze every shimeji, and let them carry on.
+//! - `reload-config` loads the settings again.
+//! - `set-behavior <id> <behavior>` switches a shimeji to another behavior.
+//! - `play <id> <behavior> [--wait]` does the same, and with `--wait` answers `finished`,
+//!   `interrupted` or `despawned` once the behavior is over. Other clients wait until then.
+//! - `party` throws a party, unless one is already going on.
+//!
+//! For example, `echo list | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/new-shimeji.sock`.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{handle::Playback, ManagerEvent};
+
+/// How long to wait for the manager to answer a command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a client can keep a connection open without saying anything.
+/// Clients are served one at a time, so one that never hangs up would hold up the rest.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something a client asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Spawn(Arc<str>),
+    /// Remove the shimeji with this id, see [`Command::List`], or the newest one.
+    Remove(Option<u64>),
+    List,
+    Pause,
+    Resume,
+    ReloadConfig,
+    SetBehavior {
+        id: u64,
+        behavior: String,
+    },
+    /// Like [`Command::SetBehavior`], but waiting for the behavior to be over if `wait` is set.
+    Play {
+        id: u64,
+        behavior: String,
+        wait: bool,
+    },
+    /// See [`ManagerEvent::StartParty`].
+    Party,
+}
+
+/// The lines a [`Command`] answered with, or why it couldn't be done.
+pub type Reply = Result<Vec<String>, String>;
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (command, rest) = line
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((line.trim(), ""));
+        let rest = rest.trim();
+        let id = |id: &str| {
+            id.parse::<u64>()
+                .map_err(|_| format!("{id:?} is not a shimeji id"))
+        };
+        let no_arguments = |command: Command| match rest {
+            "" => Ok(command),
+            _ => Err(format!("{line:?} takes no arguments")),
+        };
+        match command {
+            "spawn" if !rest.is_empty() => Ok(Self::Spawn(Arc::from(rest))),
+            "spawn" => Err(String::from("spawn needs the name of a shimeji")),
+            "remove" if rest.is_empty() => Ok(Self::Remove(None)),
+            "remove" => Ok(Self::Remove(Some(id(rest)?))),
+            "list" => no_arguments(Self::List),
+            "pause" => no_arguments(Self::Pause),
+            "resume" => no_arguments(Self::Resume),
+            "reload-config" => no_arguments(Self::ReloadConfig),
+            "party" => no_arguments(Self::Party),
+            "set-behavior" => {
+                let Some((shimeji, behavior)) = rest.split_once(char::is_whitespace) else {
+                    return Err(String::from(
+                        "set-behavior needs a shimeji id and a behavior",
+                    ));
+                };
+                Ok(Self::SetBehavior {
+                    id: id(shimeji)?,
+                    behavior: behavior.trim().to_owned(),
+                })
+            }
+            "play" => {
+                let (rest, wait) = match rest.strip_suffix("--wait") {
+                    Some(rest) if rest.ends_with(char::is_whitespace) => (rest.trim_end(), true),
+                    _ => (rest, false),
+                };
+                let Some((shimeji, behavior)) = rest.split_once(char::is_whitespace) else {
+                    return Err(String::from("play needs a shimeji id and a behavior"));
+                };
+                Ok(Self::Play {
+                    id: id(shimeji)?,
+                    behavior: behavior.trim().to_owned(),
+                    wait,
+                })
+            }
+            _ => Err(format!("unknown command {command:?}")),
+        }
+    }
+}
+
+/// Listen for clients on its own thread, sending each of their commands to the manager
+/// as [`ManagerEvent::Ipc`] and answering with its reply, until `should_exit` is set.
+pub fn spawn(
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("IPC thread"))
+        .spawn(move || listen(proxy, should_exit))
+}
+
+/// Answer every command `client` sends until it hangs up.
+fn serve(client: impl Read + Write, proxy: &EventLoopProxy<ManagerEvent>) -> std::io::Result<()> {
+    let mut client = BufReader::new(client);
+    let mut line = String::new();
+    while client.read_line(&mut line)? != 0 {
+        if line.trim().is_empty() {
+            line.clear();
+            continue;
+        }
+        let reply = line.parse::<Command>().and_then(|command| match command {
+            Command::Play {
+                id,
+                behavior,
+                wait: true,
+            } => play_and_wait(proxy, id, behavior),
+            command => ask(proxy, command),
+        });
+        line.clear();
+        let client = client.get_mut();
+        match reply {
+            Ok(lines) => {
+                for line in lines {
+                    writeln!(client, "{line}")?;
+                }
+                writeln!(client, "ok")?;
+            }
+            Err(why) => writeln!(client, "error: {why}")?,
+        }
+        client.flush()?;
+    }
+    Ok(())
+}
+
+fn ask(proxy: &EventLoopProxy<ManagerEvent>, command: Command) -> Reply {
+    let (sender, receiver) = mpsc::channel();
+    proxy
+        .send_event(ManagerEvent::Ipc(command, sender))
+        .map_err(|_| String::from("the shimejis are shutting down"))?;
+    receiver
+        .recv_timeout(REPLY_TIMEOUT)
+        .map_err(|_| String::from("no answer from the shimejis"))?
+}
+
+/// Play `behavior` on the shimeji `id` through a handle on it, and answer how it ended.
+fn play_and_wait(proxy: &EventLoopProxy<ManagerEvent>, id: u64, behavior: String) -> Reply {
+    let (sender, receiver) = mpsc::channel();
+    proxy
+        .send_event(ManagerEvent::IpcHandle(id, behavior.clone(), sender))
+        .map_err(|_| String::from("the shimejis are shutting down"))?;
+    let handle = receiver
+        .recv_timeout(REPLY_TIMEOUT)
+        .map_err(|_| String::from("no answer from the shimejis"))??;
+    let playback = handle
+        .play_and_wait(behavior)
+        .map_err(|_| String::from("the shimejis are shutting down"))?;
+    let playback = match playback {
+        Playback::Finished => "finished",
+        Playback::Interrupted => "interrupted",
+        Playback::Despawned => "despawned",
+    };
+    Ok(vec![String::from(playback)])
+}
+
+cfg_if! {
+    if #[cfg(unix)] {
+        use std::{
+            io::ErrorKind,
+            os::unix::net::{UnixListener, UnixStream},
+            path::PathBuf,
+        };
+
+        /// How often to check for clients, and whether to stop.
+        const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+        /// `$SHIMEJI_IPC_SOCKET` if it's set, otherwise `new-shimeji.sock` in
+        /// `$XDG_RUNTIME_DIR`, or in the temporary directory if there's no runtime directory.
+        pub fn socket_path() -> PathBuf {
+            if let Some(path) = std::env::var_os("SHIMEJI_IPC_SOCKET") {
+                return PathBuf::from(path);
+            }
+            match std::env::var_os("XDG_RUNTIME_DIR").filter(|directory| !directory.is_empty()) {
+                Some(directory) => PathBuf::from(directory).join("new-shimeji.sock"),
+                None => {
+                    // SAFETY: `getuid` has no preconditions and can't fail.
+                    let user = unsafe { libc::getuid() };
+                    std::env::temp_dir().join(format!("new-shimeji-{user}.sock"))
+                }
+            }
+        }
+
+        fn listen(proxy: EventLoopProxy<ManagerEvent>, should_exit: Arc<AtomicBool>) {
+            let path = socket_path();
+            if UnixStream::connect(&path).is_ok() {
+                log::warn!("Another instance is already listening on {}", path.display());
+                return;
+            }
+            // left behind by a run that didn't get to clean up
+            if let Err(why) = std::fs::remove_file(&path) {
+                if why.kind() != ErrorKind::NotFound {
+                    log::warn!("Could not remove old socket {}: {why}", path.display());
+                }
+            }
+            let listener = match UnixListener::bind(&path)
+                .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+            {
+                Ok(listener) => listener,
+                Err(why) => {
+                    log::warn!("Could not listen on {}: {why}", path.display());
+                    return;
+                }
+            };
+            log::info!("Listening for commands on {}", path.display());
+            while !should_exit.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((client, _)) => {
+                        let served = client
+                            .set_nonblocking(false)
+                            .and_then(|()| client.set_read_timeout(Some(CLIENT_TIMEOUT)))
+                            .and_then(|()| serve(&client, &proxy));
+                        if let Err(why) = served {
+                            log::debug!("Lost an IPC client: {why}");
+                        }
+                    }
+                    Err(why) if why.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                    Err(why) => {
+                        log::warn!("Could not accept IPC clients: {why}");
+                        break;
+                    }
+                }
+            }
+            std::fs::remove_file(&path).ok();
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use std::{
+            fs::File,
+            os::windows::io::{AsRawHandle, FromRawHandle},
+        };
+
+        use windows_sys::Win32::{
+            Foundation::{ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE},
+            Storage::FileSystem::PIPE_ACCESS_DUPLEX,
+            System::Pipes::{
+                ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE,
+                PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+            },
+        };
+
+        const PIPE_NAME: &str = r"\\.\pipe\new-shimeji";
+        const PIPE_BUFFER: u32 = 4096;
+
+        /// Clients are waited for without a timeout, so unlike on Unix the thread only
+        /// notices it should exit once another one connects. It's never joined anyway.
+        fn listen(proxy: EventLoopProxy<ManagerEvent>, should_exit: Arc<AtomicBool>) {
+            let name: Vec<u16> = PIPE_NAME.encode_utf16().chain([0]).collect();
+            log::info!("Listening for commands on {PIPE_NAME}");
+            while !should_exit.load(Ordering::Relaxed) {
+                // SAFETY: `name` is nul-terminated, and no security attributes is allowed.
+                let pipe = unsafe {
+                    CreateNamedPipeW(
+                        name.as_ptr(),
+                        PIPE_ACCESS_DUPLEX,
+                        PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+                        PIPE_UNLIMITED_INSTANCES,
+                        PIPE_BUFFER,
+                        PIPE_BUFFER,
+                        0,
+                        std::ptr::null(),
+                    )
+                };
+                if pipe == INVALID_HANDLE_VALUE {
+                    log::warn!(
+                        "Could not create {PIPE_NAME}: {}",
+                        std::io::Error::last_os_error()
+                    );
+                    return;
+                }
+                // SAFETY: the pipe was just created, and nothing else owns it.
+                let pipe = unsafe { File::from_raw_handle(pipe) };
+                // SAFETY: the handle is open for as long as `pipe` is, and isn't overlapped.
+                let connected = unsafe { ConnectNamedPipe(pipe.as_raw_handle(), std::ptr::null_mut()) }
+                    != 0
+                    // the client connected between creating the pipe and waiting for it
+                    || std::io::Error::last_os_error().raw_os_error()
+                        == Some(ERROR_PIPE_CONNECTED as i32);
+                if !connected {
+                    log::debug!("Lost an IPC client: {}", std::io::Error::last_os_error());
+                    continue;
+                }
+                if let Err(why) = serve(&pipe, &proxy) {
+                    log::debug!("Lost an IPC client: {why}");
+                }
+            }
+        }
+    } else {
+        fn listen(_proxy: EventLoopProxy<ManagerEvent>, _should_exit: Arc<AtomicBool>) {
+            log::warn!("There's no control channel on this platform");
+        }
+    }
+}
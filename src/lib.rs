@@ -0,0 +1,5119 @@
+//! Desktop mascots that walk around on top of other windows.
+//!
+//! [`BucketManager`] runs the shimejis in a [`ShimejiLibrary`], loaded with [`loader`],
+//! and [`ShimejiHandle`]s steer them from other threads once spawned, e.g.
+//!
+//! ```no_run
+//! use new_shimeji::{loader::{PackLibrary, ShimejiLibrary}, BucketManager, SpawnConfig};
+//!
+//! let mut manager = BucketManager::new(2);
+//! manager.set_library(ShimejiLibrary::load("./default.xml", &PackLibrary::new(".")).unwrap());
+//! let handle = manager.spawn(&SpawnConfig::new("Gon"));
+//! manager.run().unwrap();
+//! ```
+#![deny(unused_must_use)]
+#![allow(dead_code)]
+
+use anyhow::Context as _;
+use cfg_if::cfg_if;
+use itertools::Itertools;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        mpsc, Arc, LazyLock, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::{PhysicalPosition, PhysicalSize},
+    error::EventLoopError,
+    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
+    event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
+    keyboard::{KeyCode, PhysicalKey},
+    monitor::MonitorHandle,
+    raw_window_handle::HasWindowHandle,
+    window::{Theme, WindowAttributes, WindowId, WindowLevel},
+};
+
+mod ambient;
+mod archive;
+mod audio;
+#[cfg(target_os = "linux")]
+pub mod backend;
+#[path = "./off_thread/behavior.rs"]
+pub mod behavior;
+#[path = "./off_thread/blit.rs"]
+mod blit;
+mod bucket;
+mod calibration;
+pub mod capabilities;
+#[cfg(target_os = "linux")]
+mod compositor;
+mod config;
+pub mod download;
+mod focus;
+mod formula;
+#[path = "./off_thread/frame_cache.rs"]
+mod frame_cache;
+#[path = "./off_thread/frame_cursor.rs"]
+mod frame_cursor;
+mod frame_store;
+mod fullscreen;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+pub mod handle;
+pub mod headless;
+mod hot_reload;
+pub mod hotkeys;
+mod idle;
+mod interaction;
+mod ipc;
+pub mod loader;
+mod log_throttle;
+mod monitors;
+#[path = "./off_thread/movement.rs"]
+mod movement;
+mod neighbors;
+mod notifications;
+pub mod notify;
+#[path = "./off_thread/overlay.rs"]
+mod overlay;
+pub mod picker;
+pub mod population;
+mod power;
+pub mod preview;
+#[path = "./off_thread/prop.rs"]
+mod prop;
+pub mod recording;
+pub mod renderer;
+mod rgba;
+mod rng;
+mod script;
+#[path = "./off_thread/sequencer.rs"]
+mod sequencer;
+pub mod session;
+pub mod settings;
+#[path = "./off_thread/shimeji.rs"]
+pub mod shimeji;
+pub mod soak;
+pub mod supervisor;
+#[cfg(target_os = "windows")]
+mod tool_window;
+mod tray;
+pub mod validate;
+#[path = "./off_thread/variant.rs"]
+pub mod variant;
+#[path = "./off_thread/window_shape.rs"]
+mod window_shape;
+mod window_surfaces;
+mod world;
+mod xml_parser;
+
+use bucket::{BucketError, ShimejiBucket, ShimejiInputEvent};
+use calibration::{CalibratedFloor, Calibration};
+pub use handle::{Control, ShimejiEvent, ShimejiHandle, ShimejiId, SpawnConfig};
+use interaction::{FilterChain, InteractionEvent, InteractionTracker, PointerId, PointerPhase};
+use loader::ShimejiLibrary;
+use picker::Picker;
+use population::SavedPopulation;
+use session::{SavedShimeji, Session};
+use settings::Settings;
+pub use shimeji::ShimejiData;
+use shimeji::StepCommand;
+use world::WorldSnapshot;
+
+use derive_more::{derive::From, Display, Error};
+
+#[derive(Debug)]
+enum Status {
+    Ok,
+    Exiting,
+}
+
+impl Status {
+    /// Returns `true` if the status is [`Ok`].
+    ///
+    /// [`Ok`]: Status::Ok
+    #[must_use]
+    fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok)
+    }
+}
+#[derive(Display, Debug, Error, From)]
+pub enum ManagerError {
+    /// Should never happen.
+    NoBucketsAvailable,
+    BucketError(BucketError),
+    EventLoopError(EventLoopError),
+    #[cfg(target_os = "linux")]
+    BackendUnavailable(backend::BackendUnavailable),
+    SoakFailed(soak::SoakFailed),
+}
+
+/// How many shimejis can be alive at once, unless configured otherwise.
+const DEFAULT_POPULATION_LIMIT: usize = 64;
+/// How many extra shimejis show up when a party starts.
+const PARTY_GUESTS: usize = 8;
+const PARTY_DURATION: Duration = Duration::from_secs(30);
+/// How long to wait for each bucket thread to close its windows and stop when exiting.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+/// How many times a bucket thread is started again after crashing before its shimejis
+/// are given up on, so one that crashes every frame doesn't keep at it forever.
+const MAX_BUCKET_RESPAWNS: u32 = 3;
+
+/// Sent to the manager from other threads through an [`EventLoopProxy`].
+#[derive(Debug, Clone)]
+pub enum ManagerEvent {
+    /// Close every window, stop every thread, and exit.
+    Exit,
+    /// Invite a crowd of hyperactive shimejis over for [`PARTY_DURATION`].
+    StartParty,
+    /// Send every party guest home.
+    EndParty,
+    /// Spawn another shimeji from the library.
+    Spawn(Arc<str>),
+    /// Remove one of the shimejis that aren't party guests.
+    RemoveOne,
+    /// Remove the shimeji in this window.
+    Remove(WindowId),
+    /// Move the shimeji in this window over to the mouse cursor.
+    TeleportToCursor(WindowId),
+    /// Move every shimeji over to the mouse cursor.
+    SummonToCursor,
+    /// Swap the shimeji in this window for one from another pack, where it stands.
+    ChangePack(WindowId, Arc<str>),
+    /// Load the settings again from where they were loaded from, e.g. after editing them.
+    ReloadSettings,
+    /// A client of the control channel asked for something, and waits for the reply.
+    Ipc(ipc::Command, mpsc::Sender<ipc::Reply>),
+    /// A client of the control channel wants to play this behavior on the shimeji
+    /// with this id and wait for it, and waits for a handle on it to do that with.
+    IpcHandle(u64, String, mpsc::Sender<Result<ShimejiHandle, String>>),
+    /// Create windows for shimejis added with [`BucketManager::spawn`] while running.
+    SpawnPending,
+    /// A bucket thread dropped the window, sent by the bucket itself.
+    Removed(WindowId),
+    /// Let the user place the floor on every monitor.
+    CalibrateFloor,
+    /// Sent through a [`ShimejiHandle`].
+    Control(ShimejiId, Control),
+    /// Other applications' windows moved, opened or closed.
+    WindowSurfaces(Vec<window_surfaces::WindowSurface>),
+    /// The local time, every minute or so, see [`ambient`].
+    Clock(ambient::LocalTime),
+    /// The user went away from the keyboard and mouse, or came back.
+    Idle(bool),
+    /// Other applications' windows went fullscreen, or came out of it.
+    Fullscreen(Vec<monitors::WorkArea>),
+    /// The user switched to another application's window, or to none.
+    Focused(Option<focus::FocusedWindow>),
+    /// Another application showed a desktop notification, see [`notifications`].
+    Notification,
+    /// Freeze every shimeji where it is, or let them all carry on.
+    TogglePause,
+    /// The computer was unplugged, or plugged back in.
+    OnBattery(bool),
+    /// Turn saving power on battery on or off, and save that.
+    ToggleLowPower,
+    /// Turn the night and dark theme tint on or off, and save that.
+    ToggleAmbientTint,
+    /// Turn every sound off or back on, and save that.
+    ToggleMute,
+    /// The shimeji in this window multiplied, and another like it should appear here.
+    Multiply(WindowId, PhysicalPosition<i32>),
+    /// Something for the [`ShimejiHandle`] of the shimeji in this window, from its bucket.
+    Report(WindowId, ShimejiEvent),
+    /// One of the library's files changed, and this is the library loaded again.
+    LibraryReloaded(ShimejiLibrary),
+    /// A pack of the library with more of its frames decoded in the background,
+    /// for the library as of the generation it was decoded for,
+    /// see [`loader::PackLibrary::with_lazy_frames`].
+    FramesDecoded(usize, Arc<ShimejiData>),
+    /// Move the soak test along, see [`soak`].
+    Soak(soak::SoakTick),
+    /// The shimeji in this window went into a behavior here, sent while recording.
+    Entered(WindowId, String, PhysicalPosition<i32>),
+    /// The next thing to happen again from a recording, see [`recording`].
+    Replay(recording::Entry),
+    /// The thread of the bucket with this id panicked, handing back its windows.
+    BucketCrashed(usize, bucket::Orphans),
+}
+
+/// A shimeji waiting for a window to be created for it.
+#[derive(Debug)]
+struct PendingShimeji {
+    data: Arc<ShimejiData>,
+    /// Party guests are hyperactive, and leave when the party ends.
+    party_guest: bool,
+    /// Set if it was spawned with [`BucketManager::spawn`].
+    instance: Option<ShimejiId>,
+}
+
+/// A shimeji spawned with [`BucketManager::spawn`], as the manager sees it.
+#[derive(Debug)]
+struct Instance {
+    /// Only `None` until its window is created.
+    window: Option<WindowId>,
+    events: mpsc::Sender<ShimejiEvent>,
+    /// Controls sent before it had a window, applied once it does.
+    queued: Vec<Control>,
+}
+
+#[derive(Debug)]
+pub struct BucketManager {
+    /// Set once shutting down, for the threads to notice. The manager itself is told
+    /// to exit with [`ManagerEvent::Exit`].
+    should_exit: Arc<AtomicBool>,
+    /// Shimejis that are waiting
+    /// for a context / window to be sent to a bucket.
+    pending_shimejis: Vec<PendingShimeji>,
+    buckets: Vec<Rc<RefCell<ShimejiBucket>>>,
+    buckets_windows_map: HashMap<WindowId, Rc<RefCell<ShimejiBucket>>>,
+    /// Every shimeji that currently has a window.
+    live_shimejis: HashMap<WindowId, Arc<ShimejiData>>,
+    /// The monitor each live shimeji is on, by name, as of when it was last let go of.
+    monitor_of: HashMap<WindowId, String>,
+    /// Where each live shimeji's window last moved to, for saving the [`Session`].
+    positions: HashMap<WindowId, PhysicalPosition<i32>>,
+    population_limit: usize,
+    /// Shimejis that will be removed when the current party ends.
+    party_guests: Vec<WindowId>,
+    /// Shared with every [`ShimejiHandle`], set once the event loop is built.
+    proxy: Arc<OnceLock<EventLoopProxy<ManagerEvent>>>,
+    interactions: InteractionTracker,
+    /// The window the mouse cursor was last over, and where in it.
+    cursor: Option<(WindowId, PhysicalPosition<f64>)>,
+    /// Packs whose props have already been spawned.
+    packs_with_props: HashSet<Arc<str>>,
+    /// Steers the first shimeji spawned.
+    #[cfg(feature = "gamepad")]
+    gamepad_thread: Option<thread::JoinHandle<()>>,
+    #[cfg(target_os = "linux")]
+    backend: backend::Backend,
+    /// Every shimeji that can be spawned by name.
+    library: ShimejiLibrary,
+    /// Give every window the same title instead of naming its pack.
+    anonymous_windows: bool,
+    /// Numbers shimejis in their window titles.
+    spawned_count: u64,
+    /// Every live shimeji, oldest first.
+    spawn_order: Vec<WindowId>,
+    /// Whether compositors may draw shadows and blur behind windows.
+    compositor_effects: bool,
+    /// Whether shimeji windows are cut to the shape of their sprites, see [`window_shape`].
+    shaped_windows: bool,
+    /// Whether each bucket draws its shimejis on one window over the desktop,
+    /// see [`overlay`].
+    overlay: bool,
+    /// The windows of the buckets' overlays, which ignore input.
+    overlay_windows: HashSet<WindowId>,
+    #[cfg(target_os = "linux")]
+    compositor_hints: Option<compositor::CompositorHints>,
+    settings: Settings,
+    /// The floor lines being placed, while calibrating.
+    calibration: Option<Calibration>,
+    /// Whether to open the [`picker`] once the event loop starts, instead of spawning.
+    pick_on_start: bool,
+    picker: Option<Picker>,
+    /// Every shimeji with a [`ShimejiHandle`].
+    instances: HashMap<ShimejiId, Instance>,
+    next_instance: u64,
+    window_surfaces: Vec<window_surfaces::WindowSurface>,
+    /// Where other applications' fullscreen windows are, see [`fullscreen`].
+    fullscreen: Vec<monitors::WorkArea>,
+    fullscreen_thread: Option<thread::JoinHandle<()>>,
+    monitors: monitors::Monitors,
+    window_surfaces_thread: Option<thread::JoinHandle<()>>,
+    /// Whether the stepping hotkeys are on, see [`BucketManager::set_debug_stepping`].
+    debug_stepping: bool,
+    /// Whether the desktop has a dark theme, as far as we can tell.
+    dark_theme: bool,
+    /// The local time when [`ambient`] last told us, if it has.
+    clock: Option<ambient::LocalTime>,
+    ambient_thread: Option<thread::JoinHandle<()>>,
+    user_idle: bool,
+    idle_thread: Option<thread::JoinHandle<()>>,
+    /// The other application's window the user is in, if any.
+    focused: Option<focus::FocusedWindow>,
+    focus_thread: Option<thread::JoinHandle<()>>,
+    /// When shimejis last reacted to a notification, see [`notifications::COOLDOWN`].
+    last_alert: Option<Instant>,
+    notifications_thread: Option<thread::JoinHandle<()>>,
+    /// Whether every bucket is frozen, see [`ManagerEvent::TogglePause`].
+    paused: bool,
+    on_battery: bool,
+    power_thread: Option<thread::JoinHandle<()>>,
+    ipc_thread: Option<thread::JoinHandle<()>>,
+    hotkeys_thread: Option<thread::JoinHandle<()>>,
+    /// The tray icon, if it's showing, see [`BucketManager::run_with_tray`].
+    tray: Option<tray::Tray>,
+    /// Whether to reload the library when its files change, see [`BucketManager::set_hot_reload`].
+    hot_reload: bool,
+    hot_reload_thread: Option<thread::JoinHandle<()>>,
+    /// Which generation of the library frames are being decoded for in the background,
+    /// bumped whenever it's swapped so the decoding thread of the last one stops.
+    frames_generation: Arc<AtomicUsize>,
+    /// Where the population is kept saved as it changes, if anywhere.
+    saved_population: Option<SavedPopulation>,
+    /// Where the shimejis and their positions are saved on exit, if anywhere.
+    session: Option<Session>,
+    /// The soak test being run instead of normal use, if any.
+    soak: Option<soak::SoakRun>,
+    soak_thread: Option<thread::JoinHandle<()>>,
+    /// Where what happens is written down, if it's being recorded.
+    recorder: Option<recording::Recorder>,
+    /// The recording to replay once running, see [`BucketManager::set_replay`].
+    replay: Vec<recording::Entry>,
+    replay_thread: Option<thread::JoinHandle<()>>,
+    /// The shimejis spawned for a replay, by their number in the recording.
+    replayed: HashMap<usize, ShimejiId>,
+}
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use winit::platform::{
+            wayland::WindowAttributesExtWayland,
+            x11::{WindowAttributesExtX11, WindowType},
+        };
+        static WINDOW_ATTRIBS: LazyLock<WindowAttributes> = std::sync::LazyLock::new(|| {
+            let attributes = WindowAttributes::default()
+                .with_visible(true)
+                .with_transparent(true)
+                .with_decorations(false)
+                .with_x11_window_type(vec![WindowType::Dock]);
+            // WM_CLASS on X11 and the app id on Wayland, each ignored by the other backend
+            let attributes =
+                WindowAttributesExtX11::with_name(attributes, backend::APP_ID, backend::APP_ID);
+            WindowAttributesExtWayland::with_name(attributes, backend::APP_ID, backend::APP_ID)
+                .with_window_level(WindowLevel::AlwaysOnTop)
+                .with_inner_size(PhysicalSize::new(10, 10))
+        });
+    } else if #[cfg(target_os = "macos")] {
+        use winit::platform::macos::WindowAttributesExtMacOS;
+        static WINDOW_ATTRIBS: LazyLock<WindowAttributes> = std::sync::LazyLock::new(|| {
+            // AppKit's shadow follows the window's bounds rather than the sprite,
+            // so it would draw a box around every shimeji
+            WindowAttributes::default()
+                .with_visible(true)
+                .with_transparent(true)
+                .with_decorations(false)
+                .with_has_shadow(false)
+                .with_accepts_first_mouse(true)
+                .with_window_level(WindowLevel::AlwaysOnTop)
+                .with_inner_size(PhysicalSize::new(10, 10))
+        });
+    } else if #[cfg(target_os = "windows")] {
+        use winit::platform::windows::WindowAttributesExtWindows;
+        static WINDOW_ATTRIBS: LazyLock<WindowAttributes> = std::sync::LazyLock::new(|| {
+            // transparency is left to winit, which blurs behind an empty region so
+            // the sprite's own alpha shows through on Windows 10 and 11
+            WindowAttributes::default()
+                .with_visible(true)
+                .with_transparent(true)
+                .with_decorations(false)
+                .with_active(false)
+                .with_skip_taskbar(true)
+                .with_undecorated_shadow(false)
+                .with_window_level(WindowLevel::AlwaysOnTop)
+                .with_inner_size(PhysicalSize::new(10, 10))
+        });
+    } else {
+        static WINDOW_ATTRIBS: LazyLock<WindowAttributes> = std::sync::LazyLock::new(|| {
+            WindowAttributes::default()
+                .with_visible(true)
+                .with_transparent(true)
+                .with_decorations(false)
+                .with_window_level(WindowLevel::AlwaysOnTop)
+                .with_inner_size(PhysicalSize::new(10, 10))
+        });
+    }
+
+}
+
+impl ApplicationHandler<ManagerEvent> for BucketManager {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        log::debug!("Resumed");
+        self.set_dark_theme(event_loop.system_theme() == Some(Theme::Dark));
+        self.refresh_monitors(event_loop);
+        self.open_overlays(event_loop);
+        self.open_picker(event_loop);
+
+        self.address_pending_shimejis(event_loop);
+    }
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        log::debug!("Exiting");
+    }
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        use WindowEvent::*;
+        log::trace!("WindowEvent: {event:?}");
+        if self.overlay_windows.contains(&window_id) {
+            return;
+        }
+        if let Some(calibration) = self.calibration.as_mut().filter(|c| c.owns(window_id)) {
+            if let Some(floor) = calibration.handle(window_id, &event) {
+                self.save_floor(floor);
+            }
+            return;
+        }
+        if let Some(picker) = self.picker.as_mut().filter(|p| p.owns(window_id)) {
+            if let Some(picked) = picker.handle(&event) {
+                self.picker = None;
+                self.spawn_picked(event_loop, picked);
+            }
+            return;
+        }
+        if let Some(input) = ShimejiInputEvent::from_window_event(&event) {
+            if let Some(bucket) = self.buckets_windows_map.get(&window_id) {
+                unless_thread_gone(bucket.borrow_mut().window_event(window_id, input))
+                    .context("could not forward window event to bucket")
+                    .unwrap();
+            }
+        }
+        match event {
+            RedrawRequested => {
+                log::trace!("WindowEvent: RedrawRequested")
+            }
+            ThemeChanged(theme) => self.set_dark_theme(theme == Theme::Dark),
+            Moved(position) => {
+                if self.live_shimejis.contains_key(&window_id) {
+                    self.positions.insert(window_id, position);
+                }
+                self.shimeji_moved(event_loop, window_id, position)
+            }
+            ScaleFactorChanged {
+                scale_factor,
+                mut inner_size_writer,
+            } => {
+                // moved onto a monitor with another DPI, stay as big to the eye
+                if let Some(data) = self.live_shimejis.get(&window_id) {
+                    let size = data.size_at(scale_factor);
+                    if let Err(why) = inner_size_writer.request_inner_size(size) {
+                        log::warn!("Could not rescale {} to {scale_factor}x: {why}", data.name);
+                    }
+                }
+                // also likely because a monitor was plugged in, unplugged or rearranged
+                self.refresh_monitors(event_loop)
+            }
+            Resized(size) => {
+                log::trace!("WindowEvent: Resized");
+                // the window may already have been removed
+                let Some(bucket) = self.buckets_windows_map.get(&window_id) else {
+                    log::debug!("Resize of a window with no bucket: {window_id:?}");
+                    return;
+                };
+                unless_thread_gone(bucket.borrow_mut().was_resized(window_id, size))
+                    .context("could not resize window on resize event received")
+                    .unwrap();
+            }
+            CursorMoved { position, .. } => {
+                self.cursor = Some((window_id, position));
+                // only does anything while the mouse is pressed on a shimeji
+                let event = self.interactions.handle(
+                    window_id,
+                    PointerId::Mouse,
+                    PointerPhase::Moved,
+                    position,
+                );
+                if let Some(event) = event {
+                    self.forward_interaction(window_id, event);
+                }
+            }
+            MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let position = match self.cursor {
+                    Some((window, position)) if window == window_id => position,
+                    _ => PhysicalPosition::default(),
+                };
+                let phase = match state {
+                    ElementState::Pressed => PointerPhase::Pressed,
+                    ElementState::Released => PointerPhase::Released,
+                };
+                let event = self
+                    .interactions
+                    .handle(window_id, PointerId::Mouse, phase, position);
+                if let Some(event) = event {
+                    self.forward_interaction(window_id, event);
+                }
+            }
+            KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if self.debug_stepping => {
+                let command = match key {
+                    KeyCode::F9 | KeyCode::Pause => StepCommand::TogglePause,
+                    KeyCode::Period => StepCommand::Frame,
+                    KeyCode::Comma => StepCommand::Tick,
+                    _ => return,
+                };
+                // props don't animate
+                if !self.live_shimejis.contains_key(&window_id) {
+                    return;
+                }
+                if let Some(bucket) = self.buckets_windows_map.get(&window_id) {
+                    unless_thread_gone(bucket.borrow_mut().debug_step(window_id, command))
+                        .context("could not forward debug step to bucket")
+                        .unwrap();
+                }
+            }
+            Touch(touch) => {
+                let event = self.interactions.handle(
+                    window_id,
+                    PointerId::Touch(touch.id),
+                    touch.phase.into(),
+                    touch.location,
+                );
+                if let Some(event) = event {
+                    self.forward_interaction(window_id, event);
+                }
+            }
+            _ => (),
+        }
+    }
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: ManagerEvent) {
+        log::debug!("UserEvent: {event:?}");
+        match event {
+            ManagerEvent::StartParty => self.start_party(event_loop),
+            ManagerEvent::EndParty => self.end_party(),
+            ManagerEvent::Spawn(name) => {
+                if self.add_shimeji_by_name(&name) {
+                    self.address_pending_shimejis(event_loop);
+                }
+            }
+            ManagerEvent::Exit => self.shut_down(event_loop),
+            ManagerEvent::RemoveOne => self.remove_one(),
+            ManagerEvent::Remove(id) => self.remove_shimeji(id),
+            ManagerEvent::TeleportToCursor(id) => self.teleport_to_cursor(event_loop, id),
+            ManagerEvent::SummonToCursor => {
+                let ids: Vec<_> = self.live_shimejis.keys().copied().collect();
+                for id in ids {
+                    self.teleport_to_cursor(event_loop, id);
+                }
+            }
+            ManagerEvent::ChangePack(id, pack) => self.change_pack(id, &pack),
+            ManagerEvent::ReloadSettings => {
+                if let Err(why) = self.reload_settings() {
+                    log::error!("Could not reload settings: {why:#}");
+                }
+            }
+            ManagerEvent::Ipc(command, reply) => {
+                // the client may have given up waiting
+                reply.send(self.ipc(event_loop, command)).ok();
+            }
+            ManagerEvent::IpcHandle(id, behavior, reply) => {
+                reply.send(self.ipc_handle(id, &behavior)).ok();
+            }
+            ManagerEvent::Multiply(parent, position) => self.multiply(parent, position),
+            ManagerEvent::Control(instance, control) => self.control(instance, control),
+            ManagerEvent::SpawnPending => self.address_pending_shimejis(event_loop),
+            ManagerEvent::WindowSurfaces(surfaces) => {
+                self.window_surfaces = surfaces;
+                self.broadcast_world();
+            }
+            ManagerEvent::CalibrateFloor => {
+                if self.calibration.is_some() {
+                    log::debug!("Already calibrating the floor");
+                    return;
+                }
+                self.calibration = Some(Calibration::start(
+                    event_loop,
+                    &Self::titled_attributes(self.anonymous_windows, String::new),
+                    &self.world(),
+                ));
+            }
+            ManagerEvent::Clock(time) => {
+                let night = ambient::is_night(time.hour);
+                if self
+                    .clock
+                    .is_none_or(|clock| ambient::is_night(clock.hour) != night)
+                {
+                    log::info!("It is {}", if night { "night" } else { "day" });
+                }
+                self.clock = Some(time);
+                self.broadcast_world();
+            }
+            ManagerEvent::Idle(idle) => {
+                log::info!("The user is {}", if idle { "away" } else { "back" });
+                self.user_idle = idle;
+                self.broadcast_world();
+            }
+            ManagerEvent::Fullscreen(windows) => {
+                log::info!(
+                    "{} fullscreen windows, {}",
+                    windows.len(),
+                    match windows.is_empty() {
+                        true => "shimejis come back",
+                        false => "shimejis on their monitors hide",
+                    }
+                );
+                self.fullscreen = windows;
+                self.broadcast_world();
+            }
+            ManagerEvent::Focused(focused) => {
+                match &focused {
+                    Some(window) => log::debug!("{} is focused: {}", window.app, window.title),
+                    None => log::debug!("Nothing is focused"),
+                }
+                self.focused = focused;
+                self.broadcast_world();
+            }
+            ManagerEvent::Notification => self.alert(),
+            ManagerEvent::TogglePause => self.set_paused(!self.paused),
+            ManagerEvent::OnBattery(on_battery) => {
+                log::info!(
+                    "Running on {}",
+                    if on_battery { "battery" } else { "mains power" }
+                );
+                self.on_battery = on_battery;
+                self.broadcast_world();
+            }
+            ManagerEvent::ToggleLowPower => {
+                self.settings.low_power = !self.settings.low_power;
+                if let Err(why) = self.settings.save() {
+                    log::error!("Could not save power saving setting: {why:#}");
+                }
+                self.broadcast_world();
+            }
+            ManagerEvent::ToggleAmbientTint => {
+                self.settings.ambient_tint = !self.settings.ambient_tint;
+                if let Err(why) = self.settings.save() {
+                    log::error!("Could not save ambient tint setting: {why:#}");
+                }
+                self.broadcast_world();
+            }
+            ManagerEvent::ToggleMute => {
+                self.settings.muted = !self.settings.muted;
+                if let Err(why) = self.settings.save() {
+                    log::error!("Could not save mute setting: {why:#}");
+                }
+                self.broadcast_world();
+            }
+            ManagerEvent::LibraryReloaded(library) => {
+                self.swap_library(library);
+                self.decode_frames_in_background();
+            }
+            ManagerEvent::FramesDecoded(generation, data) => self.swap_frames(generation, data),
+            ManagerEvent::Soak(tick) => self.soak_tick(event_loop, tick),
+            ManagerEvent::Entered(id, behavior, position) => {
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.entered(id, behavior, position);
+                }
+            }
+            ManagerEvent::Replay(entry) => self.replay_entry(entry),
+            ManagerEvent::BucketCrashed(id, orphans) => self.bucket_crashed(id, orphans),
+            ManagerEvent::Report(id, report) => {
+                // shimejis without handles are only told to play by their own behaviors,
+                // and handles dropped since are forgotten
+                self.instances.retain(|_, instance| {
+                    instance.window != Some(id) || instance.events.send(report.clone()).is_ok()
+                });
+            }
+            ManagerEvent::Removed(id) => {
+                if self.buckets_windows_map.remove(&id).is_none() {
+                    log::warn!("Bucket removed a window we didn't know about: {id:?}");
+                }
+            }
+        }
+    }
+}
+
+impl BucketManager {
+    /// # Panics
+    /// Panics if `amount == 0`.
+    pub fn new(amount: usize) -> Self {
+        assert!(amount != 0);
+        let mut buckets = Vec::with_capacity(amount);
+        let should_exit = Arc::new(AtomicBool::new(false));
+        let neighborhood = neighbors::Neighborhood::default();
+        for i in 0..amount {
+            let mut bucket = ShimejiBucket::new(i, should_exit.clone(), neighborhood.clone());
+            bucket.init().expect("should be able to init bucket");
+            buckets.push(Rc::new(RefCell::new(bucket)));
+        }
+        Self {
+            pending_shimejis: vec![],
+            should_exit,
+            buckets,
+            buckets_windows_map: HashMap::new(),
+            live_shimejis: HashMap::new(),
+            monitor_of: HashMap::new(),
+            positions: HashMap::new(),
+            population_limit: DEFAULT_POPULATION_LIMIT,
+            party_guests: vec![],
+            proxy: Arc::new(OnceLock::new()),
+            interactions: InteractionTracker::new(),
+            cursor: None,
+            packs_with_props: HashSet::new(),
+            #[cfg(feature = "gamepad")]
+            gamepad_thread: None,
+            #[cfg(target_os = "linux")]
+            backend: backend::Backend::detect(),
+            library: ShimejiLibrary::default(),
+            anonymous_windows: false,
+            spawned_count: 0,
+            spawn_order: vec![],
+            compositor_effects: false,
+            shaped_windows: false,
+            overlay: false,
+            overlay_windows: HashSet::new(),
+            #[cfg(target_os = "linux")]
+            compositor_hints: None,
+            settings: Settings::default(),
+            calibration: None,
+            pick_on_start: false,
+            picker: None,
+            instances: HashMap::new(),
+            next_instance: 0,
+            window_surfaces: vec![],
+            fullscreen: vec![],
+            fullscreen_thread: None,
+            monitors: monitors::Monitors::default(),
+            window_surfaces_thread: None,
+            debug_stepping: false,
+            dark_theme: false,
+            clock: None,
+            ambient_thread: None,
+            user_idle: false,
+            idle_thread: None,
+            focused: None,
+            focus_thread: None,
+            last_alert: None,
+            notifications_thread: None,
+            paused: false,
+            on_battery: false,
+            power_thread: None,
+            ipc_thread: None,
+            hotkeys_thread: None,
+            tray: None,
+            hot_reload: false,
+            hot_reload_thread: None,
+            frames_generation: Arc::new(AtomicUsize::new(0)),
+            saved_population: None,
+            session: None,
+            soak: None,
+            soak_thread: None,
+            recorder: None,
+            replay: vec![],
+            replay_thread: None,
+            replayed: HashMap::new(),
+        }
+    }
+    fn forward_interaction(&mut self, window_id: WindowId, event: InteractionEvent) {
+        let Some(bucket) = self.buckets_windows_map.get(&window_id) else {
+            log::warn!("Interaction on a window with no bucket: {window_id:?}");
+            return;
+        };
+        unless_thread_gone(bucket.borrow_mut().interact(event))
+            .context("could not forward interaction to bucket")
+            .unwrap();
+    }
+    pub fn add_shimeji(&mut self, pending: Arc<ShimejiData>) {
+        self.pending_shimejis.push(PendingShimeji {
+            data: pending,
+            party_guest: false,
+            instance: None,
+        })
+    }
+    /// Spawn a shimeji from the library, returning a handle to control it with,
+    /// or `None` if the library has no shimeji called `config.name`.
+    ///
+    /// Can be called before or after the manager starts running.
+    pub fn spawn(&mut self, config: &SpawnConfig) -> Option<ShimejiHandle> {
+        let Some(data) = self.library.get(&config.name) else {
+            log::warn!("No shimeji called {} to spawn", config.name);
+            return None;
+        };
+        let id = ShimejiId(self.next_instance);
+        self.next_instance += 1;
+        let (sender, receiver) = mpsc::channel();
+        // the seed first, so it's in place before the first behavior is chosen
+        let queued = config
+            .seed
+            .map(Control::SetSeed)
+            .into_iter()
+            .chain(config.position.map(Control::SetPosition))
+            .chain(config.behavior.clone().map(Control::SetBehavior))
+            .chain(config.variant.map(Control::SetVariant))
+            .collect();
+        self.instances.insert(
+            id,
+            Instance {
+                window: None,
+                events: sender,
+                queued,
+            },
+        );
+        self.pending_shimejis.push(PendingShimeji {
+            data,
+            party_guest: false,
+            instance: Some(id),
+        });
+        // already running, so nothing else would get around to it
+        if let Some(proxy) = self.proxy.get() {
+            proxy.send_event(ManagerEvent::SpawnPending).ok();
+        }
+        Some(ShimejiHandle::new(id, Arc::clone(&self.proxy), receiver))
+    }
+    /// Apply a [`Control`] sent through the handle of `instance`.
+    fn control(&mut self, instance: ShimejiId, control: Control) {
+        let Some(entry) = self.instances.get_mut(&instance) else {
+            log::warn!("Shimeji {instance} is already gone");
+            return;
+        };
+        let Some(window) = entry.window else {
+            entry.queued.push(control);
+            return;
+        };
+        if control == Control::Despawn {
+            self.remove_shimeji(window);
+            return;
+        }
+        let Some(bucket) = self.buckets_windows_map.get(&window) else {
+            log::warn!("Shimeji {instance} has no bucket");
+            return;
+        };
+        unless_thread_gone(bucket.borrow_mut().control(window, control))
+            .context("could not forward control to bucket")
+            .unwrap();
+    }
+    /// Let the handle of `instance`, if it has one, know it's gone, and forget it.
+    fn despawn_instance(&mut self, instance: ShimejiId) {
+        if let Some(entry) = self.instances.remove(&instance) {
+            entry.events.send(ShimejiEvent::Despawned).ok();
+        }
+    }
+    pub fn set_library(&mut self, library: ShimejiLibrary) {
+        self.library = library;
+    }
+    /// Spawn a shimeji from the library.
+    /// Returns `false` if the library has no shimeji called `name`.
+    pub fn add_shimeji_by_name(&mut self, name: &str) -> bool {
+        let Some(data) = self.library.get(name) else {
+            log::warn!("No shimeji called {name} to spawn");
+            return false;
+        };
+        self.add_shimeji(data);
+        true
+    }
+    /// Title every window just `shimeji`, for users who'd rather not have
+    /// their packs show up in window lists and screen shares.
+    pub fn set_anonymous_windows(&mut self, anonymous: bool) {
+        self.anonymous_windows = anonymous;
+    }
+    /// Draw each bucket's shimejis on one window over the whole desktop instead of
+    /// a window each, which is much lighter with dozens of them. They can't be grabbed then.
+    pub fn set_overlay(&mut self, overlay: bool) {
+        self.overlay = overlay;
+    }
+    /// Give every bucket a window over the whole desktop to draw its shimejis on,
+    /// if it's meant to have one and doesn't yet.
+    fn open_overlays(&mut self, event_loop: &ActiveEventLoop) {
+        if !self.overlay || !self.overlay_windows.is_empty() {
+            return;
+        }
+        let Some((position, size)) = self.monitors.bounds() else {
+            log::warn!("No monitors to put overlays over, giving each shimeji a window");
+            return;
+        };
+        for bucket in self.buckets.iter() {
+            let attributes =
+                Self::titled_attributes(self.anonymous_windows, || String::from("shimeji overlay"))
+                    .with_position(position)
+                    .with_inner_size(size);
+            let window = match event_loop.create_window(attributes) {
+                Ok(window) => window,
+                Err(why) => {
+                    log::error!("Could not create overlay window: {why}");
+                    continue;
+                }
+            };
+            if let Err(why) = window.set_cursor_hittest(false) {
+                log::warn!("Overlay will catch clicks meant for other windows: {why}");
+            }
+            #[cfg(target_os = "windows")]
+            tool_window::apply(&window);
+            self.overlay_windows.insert(window.id());
+            if let Err(why) = bucket.borrow_mut().set_overlay(window) {
+                log::error!("Could not give bucket an overlay: {why}");
+            }
+        }
+    }
+    /// Let compositors draw their shadows and blur around windows, off by default.
+    pub fn set_compositor_effects(&mut self, allowed: bool) {
+        self.compositor_effects = allowed;
+    }
+    /// Cut shimeji windows to the shape of their sprites even if transparency works,
+    /// which it's otherwise only done without.
+    pub fn set_shaped_windows(&mut self, shaped: bool) {
+        self.shaped_windows = shaped;
+    }
+    /// Window attributes titled `title`, or anonymously if `anonymous`.
+    /// Let the user pick which packs to spawn in a [`picker`] window once the event loop
+    /// starts, for the first run.
+    pub fn set_picker(&mut self, pick: bool) {
+        self.pick_on_start = pick;
+    }
+    fn open_picker(&mut self, event_loop: &ActiveEventLoop) {
+        if !std::mem::take(&mut self.pick_on_start) {
+            return;
+        }
+        match Picker::open(event_loop, &self.library) {
+            Ok(picker) => self.picker = Some(picker),
+            Err(why) => {
+                log::error!("Could not open the pack picker, spawning every pack: {why:#}");
+                for name in self.library.names() {
+                    self.add_shimeji_by_name(&name);
+                }
+            }
+        }
+    }
+    /// Spawn one shimeji of each pack the user picked.
+    fn spawn_picked(&mut self, event_loop: &ActiveEventLoop, picked: Vec<Arc<str>>) {
+        if picked.is_empty() {
+            log::info!("No packs picked, spawn them from the tray");
+            return;
+        }
+        log::info!("Spawning {}", picked.iter().join(", "));
+        for name in picked.iter() {
+            self.add_shimeji_by_name(name);
+        }
+        self.address_pending_shimejis(event_loop);
+    }
+    fn titled_attributes(anonymous: bool, title: impl FnOnce() -> String) -> WindowAttributes {
+        let title = if anonymous {
+            String::from("shimeji")
+        } else {
+            title()
+        };
+        WINDOW_ATTRIBS.clone().with_title(title)
+    }
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+    }
+    /// Load the settings again from their file, keeping the current ones if they can't be.
+    fn reload_settings(&mut self) -> anyhow::Result<()> {
+        let settings = Settings::load(self.settings.path().to_owned())?;
+        log::info!("Reloaded settings from {}", settings.path().display());
+        self.settings = settings;
+        self.broadcast_world();
+        Ok(())
+    }
+    /// Freeze every shimeji where it is, or let them all carry on.
+    fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            return;
+        }
+        self.paused = paused;
+        log::info!("{}", if paused { "Pausing" } else { "Resuming" });
+        for bucket in self.buckets.iter() {
+            unless_thread_gone(bucket.borrow_mut().set_paused(paused))
+                .context("could not pause bucket")
+                .unwrap();
+        }
+    }
+    /// Have every shimeji react to a notification, unless they just did or are frozen.
+    fn alert(&mut self) {
+        let Some(behavior) = self.settings.notification_behavior.clone() else {
+            return;
+        };
+        let now = Instant::now();
+        if self.paused
+            || self
+                .last_alert
+                .is_some_and(|last| now < last + notifications::COOLDOWN)
+        {
+            return;
+        }
+        self.last_alert = Some(now);
+        log::debug!("A notification came in, shimejis {behavior}");
+        for bucket in self.buckets.iter() {
+            unless_thread_gone(
+                bucket
+                    .borrow_mut()
+                    .alert(behavior.clone(), self.settings.notification_corner),
+            )
+            .context("could not alert bucket")
+            .unwrap();
+        }
+    }
+    /// The shimeji a client of the control channel knows by `id`, its window's id.
+    fn ipc_shimeji(&self, id: u64) -> Result<(WindowId, Arc<ShimejiData>), String> {
+        let window = WindowId::from(id);
+        match self.live_shimejis.get(&window) {
+            Some(data) => Ok((window, Arc::clone(data))),
+            None => Err(format!("there's no shimeji {id}")),
+        }
+    }
+    /// A handle on the shimeji `id` for a client of the control channel to play
+    /// `behavior` on and wait for it with, see [`ipc`].
+    fn ipc_handle(&mut self, id: u64, behavior: &str) -> Result<ShimejiHandle, String> {
+        let window = self.ipc_behavior(id, behavior)?;
+        let instance = ShimejiId(self.next_instance);
+        self.next_instance += 1;
+        let (sender, receiver) = mpsc::channel();
+        self.instances.insert(
+            instance,
+            Instance {
+                window: Some(window),
+                events: sender,
+                queued: vec![],
+            },
+        );
+        Ok(ShimejiHandle::new(
+            instance,
+            Arc::clone(&self.proxy),
+            receiver,
+        ))
+    }
+    /// Do what a client of the control channel asked for, see [`ipc`].
+    fn ipc(&mut self, event_loop: &ActiveEventLoop, command: ipc::Command) -> ipc::Reply {
+        match command {
+            ipc::Command::Spawn(name) => {
+                if !self.add_shimeji_by_name(&name) {
+                    return Err(format!("there's no shimeji called {name}"));
+                }
+                self.address_pending_shimejis(event_loop);
+            }
+            ipc::Command::Remove(Some(id)) => {
+                let (window, _) = self.ipc_shimeji(id)?;
+                self.remove_shimeji(window);
+            }
+            ipc::Command::Remove(None) => self.remove_one(),
+            ipc::Command::List => {
+                return Ok(self
+                    .spawn_order
+                    .iter()
+                    .filter_map(|&window| {
+                        let data = self.live_shimejis.get(&window)?;
+                        Some(format!("{} {}", u64::from(window), data.name))
+                    })
+                    .collect());
+            }
+            ipc::Command::Pause => self.set_paused(true),
+            ipc::Command::Resume => self.set_paused(false),
+            ipc::Command::ReloadConfig => {
+                self.reload_settings().map_err(|why| format!("{why:#}"))?;
+            }
+            ipc::Command::SetBehavior { id, behavior } => {
+                self.ipc_control(id, &behavior, Control::SetBehavior(behavior.clone()))?;
+            }
+            // waiting is left to the control channel's thread, through a handle on it
+            ipc::Command::Play { id, behavior, .. } => {
+                self.ipc_control(id, &behavior, Control::Play(behavior.clone()))?;
+            }
+            ipc::Command::Party => self.start_party(event_loop),
+        }
+        Ok(vec![])
+    }
+    /// The window of the shimeji a client of the control channel knows by `id`,
+    /// if it has a behavior called `behavior`.
+    fn ipc_behavior(&self, id: u64, behavior: &str) -> Result<WindowId, String> {
+        let (window, data) = self.ipc_shimeji(id)?;
+        if !data.behaviors.iter().any(|known| known.name == behavior) {
+            return Err(format!("{} has no behavior called {behavior}", data.name));
+        }
+        Ok(window)
+    }
+    /// Switch the shimeji a client of the control channel knows by `id` to `behavior`
+    /// with `control`, if it has a behavior by that name.
+    fn ipc_control(&mut self, id: u64, behavior: &str, control: Control) -> Result<(), String> {
+        let window = self.ipc_behavior(id, behavior)?;
+        let Some(bucket) = self.buckets_windows_map.get(&window) else {
+            return Err(format!("shimeji {id} has no bucket"));
+        };
+        unless_thread_gone(bucket.borrow_mut().control(window, control))
+            .context("could not forward control to bucket")
+            .unwrap();
+        Ok(())
+    }
+    /// Write down every shimeji's spawn and behaviors with `recorder`, see [`recording`].
+    pub fn set_recorder(&mut self, recorder: recording::Recorder) {
+        self.recorder = Some(recorder);
+    }
+    /// Replay `entries` once running, instead of the shimejis doing as they please.
+    pub fn set_replay(&mut self, entries: Vec<recording::Entry>) {
+        self.replay = entries;
+    }
+    /// Make one thing from a recording happen again.
+    fn replay_entry(&mut self, entry: recording::Entry) {
+        use recording::What;
+        match entry.what {
+            What::Spawn {
+                seed,
+                position,
+                pack,
+            } => {
+                let config = SpawnConfig {
+                    position: Some(position),
+                    seed: Some(seed),
+                    ..SpawnConfig::new(pack)
+                };
+                if let Some(handle) = self.spawn(&config) {
+                    self.replayed.insert(entry.shimeji, handle.id());
+                }
+            }
+            What::Enter { position, behavior } => {
+                let Some(&instance) = self.replayed.get(&entry.shimeji) else {
+                    log::warn!("Shimeji {} of the recording never spawned", entry.shimeji);
+                    return;
+                };
+                self.control(instance, Control::SetPosition(position));
+                self.control(instance, Control::SetBehavior(behavior));
+            }
+            What::Despawn => {
+                if let Some(instance) = self.replayed.remove(&entry.shimeji) {
+                    self.control(instance, Control::Despawn);
+                }
+            }
+        }
+    }
+    /// Run a soak test instead of waiting for the user, exiting once it's over.
+    pub fn set_soak(&mut self, config: soak::SoakConfig) {
+        self.soak = Some(soak::SoakRun::new(config));
+    }
+    /// Move the soak test along by one `tick`, checking everything still adds up.
+    fn soak_tick(&mut self, event_loop: &ActiveEventLoop, tick: soak::SoakTick) {
+        let Some(mut run) = self.soak.take() else {
+            log::warn!("Soak test tick without a soak test running");
+            return;
+        };
+        match tick {
+            soak::SoakTick::Step => {
+                self.soak_step(event_loop, &mut run);
+                for violation in self.soak_violations() {
+                    run.violation(violation);
+                }
+                run.sample_memory();
+            }
+            soak::SoakTick::Drain => {
+                let live: Vec<_> = self.live_shimejis.keys().copied().collect();
+                for id in live {
+                    self.remove_shimeji(id);
+                }
+            }
+            soak::SoakTick::Report => {
+                let mut violations = self.soak_violations();
+                // props stay for good once their pack has been spawned
+                let props: usize = self
+                    .packs_with_props
+                    .iter()
+                    .filter_map(|pack| self.library.get(pack))
+                    .map(|data| {
+                        data.props
+                            .iter()
+                            .map(|prop| prop.count as usize)
+                            .sum::<usize>()
+                    })
+                    .sum();
+                let windows = self.buckets_windows_map.len();
+                if windows > self.live_shimejis.len() + props {
+                    violations.push(format!(
+                        "{} windows left behind after removing every shimeji",
+                        windows - self.live_shimejis.len() - props
+                    ));
+                }
+                for violation in violations {
+                    run.violation(violation);
+                }
+                run.sample_memory();
+                print!("{run}");
+                event_loop.exit();
+            }
+        }
+        self.soak = Some(run);
+    }
+    /// Do one random thing to the shimejis for the soak test.
+    fn soak_step(&mut self, event_loop: &ActiveEventLoop, run: &mut soak::SoakRun) {
+        // a shimeji to drag or switch the behavior of, if there are any
+        let pick_shimeji = |spawn_order: &[WindowId], run: &mut soak::SoakRun| {
+            let index = run.pick(spawn_order.len());
+            spawn_order.get(index).copied()
+        };
+        match run.next_op(self.live_shimejis.len()) {
+            soak::SoakOp::Spawn => {
+                let names = self.library.names();
+                if names.is_empty() {
+                    return;
+                }
+                let name = Arc::clone(&names[run.pick(names.len())]);
+                if self.add_shimeji_by_name(&name) {
+                    self.address_pending_shimejis(event_loop);
+                }
+            }
+            soak::SoakOp::Remove => self.remove_one(),
+            soak::SoakOp::Drag => {
+                let Some(id) = pick_shimeji(&self.spawn_order, run) else {
+                    return;
+                };
+                let offset = PhysicalPosition::new(4.0, 4.0);
+                self.forward_interaction(id, InteractionEvent::Grab { id, offset });
+                for step in 1..=5 {
+                    let position = PhysicalPosition::new(4.0 + step as f64, 4.0 - step as f64);
+                    self.forward_interaction(id, InteractionEvent::Drag { id, position });
+                }
+                let velocity = (run.pick(800) as f64 - 400.0, -(run.pick(400) as f64));
+                self.forward_interaction(id, InteractionEvent::Release { id, velocity });
+            }
+            soak::SoakOp::SwitchBehavior => {
+                let Some(id) = pick_shimeji(&self.spawn_order, run) else {
+                    return;
+                };
+                let Some(data) = self.live_shimejis.get(&id) else {
+                    return;
+                };
+                let names: Vec<_> = data.behaviors.iter().map(|b| b.name.clone()).collect();
+                if names.is_empty() {
+                    return;
+                }
+                let name = names[run.pick(names.len())].clone();
+                if let Some(bucket) = self.buckets_windows_map.get(&id) {
+                    unless_thread_gone(bucket.borrow_mut().control(id, Control::SetBehavior(name)))
+                        .context("could not switch behavior for the soak test")
+                        .unwrap();
+                }
+            }
+        }
+    }
+    /// Everything that doesn't add up about the manager's bookkeeping right now.
+    fn soak_violations(&self) -> Vec<String> {
+        let mut violations = vec![];
+        let live = self.live_shimejis.len();
+        if live > self.population_limit {
+            violations.push(format!(
+                "{live} shimejis alive, over the limit of {}",
+                self.population_limit
+            ));
+        }
+        let in_buckets: usize = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.borrow().contained_shimejis())
+            .sum();
+        if in_buckets != live {
+            violations.push(format!(
+                "buckets hold {in_buckets} shimejis, but {live} are alive"
+            ));
+        }
+        for id in self.live_shimejis.keys() {
+            if !self.buckets_windows_map.contains_key(id) {
+                violations.push(format!("shimeji {id:?} is alive without a bucket"));
+            }
+        }
+        if self.spawn_order.len() != live
+            || self
+                .spawn_order
+                .iter()
+                .any(|id| !self.live_shimejis.contains_key(id))
+        {
+            violations.push(String::from("the spawn order doesn't match who is alive"));
+        }
+        if let Some(id) = self
+            .monitor_of
+            .keys()
+            .find(|id| !self.live_shimejis.contains_key(id))
+        {
+            violations.push(format!("shimeji {id:?} is gone but still has a monitor"));
+        }
+        if let Some(id) = self
+            .instances
+            .values()
+            .filter_map(|instance| instance.window)
+            .find(|id| !self.live_shimejis.contains_key(id))
+        {
+            violations.push(format!("shimeji {id:?} is gone but still has a handle"));
+        }
+        violations
+    }
+    /// Replace the filters interactions have to get past before reaching the shimejis'
+    /// behaviors, which otherwise come from the settings.
+    pub fn set_event_filters(&mut self, filters: FilterChain) {
+        self.settings.filters = filters;
+        self.broadcast_world();
+    }
+    /// Tint every shimeji while the desktop has a dark theme, unless that's turned off.
+    fn set_dark_theme(&mut self, dark: bool) {
+        if self.dark_theme != dark {
+            self.dark_theme = dark;
+            self.broadcast_world();
+        }
+    }
+    /// Remember where the user put a monitor's floor, and tell every shimeji.
+    fn save_floor(&mut self, floor: CalibratedFloor) {
+        log::info!("Floor of {} is {} pixels up", floor.monitor, floor.offset);
+        self.settings
+            .floor_offsets
+            .insert(floor.monitor, floor.offset);
+        if let Err(why) = self.settings.save() {
+            log::error!("Could not save floor calibration: {why:#}");
+        }
+        if self.calibration.as_ref().is_some_and(Calibration::is_done) {
+            self.calibration = None;
+        }
+        self.broadcast_world();
+    }
+    /// Freeze the focused shimeji with F9 or Pause, then step through its animation
+    /// a frame at a time with `.` and its physics a tick at a time with `,`.
+    pub fn set_debug_stepping(&mut self, enabled: bool) {
+        self.debug_stepping = enabled;
+    }
+    /// Load the library again whenever one of its files or images changes,
+    /// swapping the new shimejis into running windows.
+    pub fn set_hot_reload(&mut self, enabled: bool) {
+        self.hot_reload = enabled;
+    }
+    /// Use the reloaded `library` from now on, and for every shimeji already alive.
+    /// Props stay as they were.
+    fn swap_library(&mut self, library: ShimejiLibrary) {
+        self.library = library;
+        for pending in self.pending_shimejis.iter_mut() {
+            if let Some(data) = self.library.get(&pending.data.name) {
+                pending.data = data;
+            }
+        }
+        for (id, live) in self.live_shimejis.iter_mut() {
+            let Some(data) = self.library.get(&live.name) else {
+                log::warn!(
+                    "{} is gone from the library, keeping it as it was",
+                    live.name
+                );
+                continue;
+            };
+            *live = Arc::clone(&data);
+            if let Some(bucket) = self.buckets_windows_map.get(id) {
+                unless_thread_gone(bucket.borrow_mut().reload(*id, data))
+                    .context("could not send reloaded shimeji to bucket")
+                    .unwrap();
+            }
+        }
+        // packs may have come or gone
+        self.refresh_tray();
+    }
+    /// Decode the frames the library's packs were loaded without on a thread of its own,
+    /// see [`loader::PackLibrary::with_lazy_frames`], sending each pack back as it goes.
+    /// Stops decoding for the library it replaced, if it was still at it.
+    fn decode_frames_in_background(&mut self) {
+        let generation = self
+            .frames_generation
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+            + 1;
+        let undecoded = self.library.take_undecoded();
+        if undecoded.is_empty() {
+            return;
+        }
+        let Some(proxy) = self.proxy.get().cloned() else {
+            log::error!("Cannot decode frames in the background without an event loop proxy");
+            return;
+        };
+        let current = Arc::clone(&self.frames_generation);
+        let should_exit = Arc::clone(&self.should_exit);
+        let wanted = move || {
+            current.load(std::sync::atomic::Ordering::Relaxed) == generation
+                && !should_exit.load(std::sync::atomic::Ordering::Relaxed)
+        };
+        let spawned = thread::Builder::new()
+            .name(String::from("Frame decoding thread"))
+            .spawn(move || {
+                for pack in undecoded {
+                    pack.decode(|data| {
+                        wanted()
+                            && proxy
+                                .send_event(ManagerEvent::FramesDecoded(generation, Arc::new(data)))
+                                .is_ok()
+                    });
+                    if !wanted() {
+                        return;
+                    }
+                }
+            });
+        if let Err(why) = spawned {
+            log::warn!("Could not start frame decoding thread, some frames won't show: {why}");
+        }
+    }
+    /// Use `data`, a pack of the library with more of its frames decoded, from now on and
+    /// for every shimeji of it already alive, unless it was decoded for a library since replaced.
+    fn swap_frames(&mut self, generation: usize, data: Arc<ShimejiData>) {
+        if generation
+            != self
+                .frames_generation
+                .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            return;
+        }
+        self.library.replace(Arc::clone(&data));
+        for pending in self.pending_shimejis.iter_mut() {
+            if pending.data.name == data.name {
+                pending.data = Arc::clone(&data);
+            }
+        }
+        for (id, live) in self.live_shimejis.iter_mut() {
+            if live.name != data.name {
+                continue;
+            }
+            *live = Arc::clone(&data);
+            if let Some(bucket) = self.buckets_windows_map.get(id) {
+                unless_thread_gone(bucket.borrow_mut().swap_frames(*id, Arc::clone(&data)))
+                    .context("could not send decoded frames to bucket")
+                    .unwrap();
+            }
+        }
+    }
+    /// Keep the names of the live shimejis saved to `population` as they come and go,
+    /// see [`supervisor`]. Party guests aren't saved, they'd only leave again.
+    pub fn set_saved_population(&mut self, population: Option<SavedPopulation>) {
+        self.saved_population = population;
+    }
+    /// What the tray menu lists as it is now.
+    fn tray_menu(&self) -> tray::TrayMenu {
+        let shimejis = self.spawn_order.iter().filter_map(|&id| {
+            let data = self.live_shimejis.get(&id)?;
+            Some((id, Arc::clone(&data.name)))
+        });
+        tray::TrayMenu::new(self.library.names(), shimejis)
+    }
+    fn refresh_tray(&self) {
+        if let Some(tray) = &self.tray {
+            tray.show(self.tray_menu());
+        }
+    }
+    /// Move the shimeji in window `id` so it's centered on the mouse cursor.
+    fn teleport_to_cursor(&mut self, event_loop: &ActiveEventLoop, id: WindowId) {
+        let (Some(data), Some(bucket)) = (
+            self.live_shimejis.get(&id),
+            self.buckets_windows_map.get(&id),
+        ) else {
+            return;
+        };
+        let cursor = match monitors::cursor_position(event_loop) {
+            Ok(Some(cursor)) => cursor,
+            Ok(None) => {
+                log::warn!("Can't tell where the cursor is on this platform");
+                return;
+            }
+            Err(why) => {
+                log::warn!("Could not find the cursor: {why:#}");
+                return;
+            }
+        };
+        let position = PhysicalPosition::new(
+            cursor.x - data.width as i32 / 2,
+            cursor.y - data.height as i32 / 2,
+        );
+        unless_thread_gone(
+            bucket
+                .borrow_mut()
+                .control(id, Control::SetPosition(position)),
+        )
+        .context("could not move shimeji to the cursor")
+        .unwrap();
+    }
+    /// Remove the shimeji in window `id`, and spawn one of `pack` where it was.
+    fn change_pack(&mut self, id: WindowId, pack: &str) {
+        if !self.live_shimejis.contains_key(&id) {
+            return;
+        }
+        let config = SpawnConfig {
+            position: self.positions.get(&id).copied(),
+            ..SpawnConfig::new(pack)
+        };
+        if self.spawn(&config).is_some() {
+            self.remove_shimeji(id);
+        }
+    }
+    fn save_population(&self) {
+        let Some(population) = &self.saved_population else {
+            return;
+        };
+        let names = self
+            .spawn_order
+            .iter()
+            .filter(|id| !self.party_guests.contains(id))
+            .filter_map(|id| self.live_shimejis.get(id))
+            .map(|data| &*data.name);
+        if let Err(why) = population.save(names) {
+            log::error!("{why:#}");
+        }
+    }
+    /// Save the shimejis and where they are to `session` on exit, see [`Session`].
+    /// Party guests aren't saved, like with [`BucketManager::set_saved_population`].
+    pub fn set_session(&mut self, session: Option<Session>) {
+        self.session = session;
+    }
+    fn save_session(&self) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        let shimejis: Vec<_> = self
+            .spawn_order
+            .iter()
+            .filter(|id| !self.party_guests.contains(id))
+            .filter_map(|id| {
+                self.live_shimejis.get(id).map(|data| SavedShimeji {
+                    name: data.name.to_string(),
+                    position: self.positions.get(id).copied(),
+                })
+            })
+            .collect();
+        if let Err(why) = session.save(&shimejis) {
+            log::error!("{why:#}");
+        }
+    }
+    pub fn set_population_limit(&mut self, limit: usize) {
+        self.population_limit = limit;
+    }
+    /// Take the shimeji in window `id` out of its bucket, closing its window.
+    ///
+    /// The window stays in the bucket map until its bucket reports
+    /// it is gone with [`ManagerEvent::Removed`].
+    fn remove_shimeji(&mut self, id: WindowId) {
+        if !self.forget_shimeji(id) {
+            log::warn!("Tried to remove a shimeji that isn't alive: {id:?}");
+            return;
+        }
+        let Some(bucket) = self.buckets_windows_map.get(&id) else {
+            log::warn!("Tried to remove a shimeji with no bucket: {id:?}");
+            return;
+        };
+        unless_thread_gone(bucket.borrow_mut().remove(id))
+            .context("could not remove shimeji from bucket")
+            .unwrap();
+        self.broadcast_world();
+        self.save_population();
+        self.refresh_tray();
+    }
+    /// Forget everything about the shimeji in window `id` but its bucket,
+    /// returning whether it was alive.
+    fn forget_shimeji(&mut self, id: WindowId) -> bool {
+        if self.live_shimejis.remove(&id).is_none() {
+            return false;
+        }
+        let instances: Vec<_> = self
+            .instances
+            .iter()
+            .filter(|(_, instance)| instance.window == Some(id))
+            .map(|(instance, _)| *instance)
+            .collect();
+        for instance in instances {
+            self.despawn_instance(instance);
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.despawned(id);
+        }
+        self.spawn_order.retain(|spawned| *spawned != id);
+        self.party_guests.retain(|guest| *guest != id);
+        self.monitor_of.remove(&id);
+        self.positions.remove(&id);
+        self.interactions.forget_window(id);
+        if self.cursor.is_some_and(|(window, _)| window == id) {
+            self.cursor = None;
+        }
+        true
+    }
+    /// Start the thread of bucket `id` again after it panicked, giving it back `orphans`,
+    /// or give up on them if it keeps crashing, see [`MAX_BUCKET_RESPAWNS`].
+    fn bucket_crashed(&mut self, id: usize, orphans: bucket::Orphans) {
+        let Some(bucket) = self.buckets.iter().find(|bucket| bucket.borrow().id == id) else {
+            log::warn!("Bucket {id} crashed but isn't ours");
+            return;
+        };
+        let bucket = Rc::clone(bucket);
+        let Some(proxy) = self.proxy.get().cloned() else {
+            log::error!("Bucket {id} crashed before the event loop started");
+            return;
+        };
+        let given_up = bucket.borrow().respawns() >= MAX_BUCKET_RESPAWNS;
+        let windows: Vec<_> = orphans
+            .shimejis
+            .iter()
+            .map(|(window, _)| window.id())
+            .collect();
+        let (orphans, lost) = match given_up {
+            true => {
+                log::error!(
+                    "Bucket {id} crashed again, giving up on its {} shimejis",
+                    windows.len()
+                );
+                (bucket::Orphans::default(), orphans)
+            }
+            false => {
+                log::error!(
+                    "Bucket {id} crashed, starting it again with its {} shimejis",
+                    windows.len()
+                );
+                (orphans, bucket::Orphans::default())
+            }
+        };
+        for (window, _) in lost.props.iter() {
+            self.buckets_windows_map.remove(&window.id());
+        }
+        for id in windows.iter().filter(|_| given_up) {
+            self.forget_shimeji(*id);
+            self.buckets_windows_map.remove(id);
+        }
+        // closes the windows given up on
+        drop(lost);
+        if let Err(why) = bucket.borrow_mut().restart(orphans, proxy) {
+            log::error!("Could not start bucket {id} again: {why}");
+            return;
+        }
+        // it may crash again straight away, which it will report like the first time
+        let mut bucket = bucket.borrow_mut();
+        if let Err(why) = bucket.update_world(self.world()) {
+            log::error!("Could not update restarted bucket {id}: {why}");
+        }
+        if self.paused {
+            if let Err(why) = bucket.set_paused(true) {
+                log::error!("Could not pause restarted bucket {id}: {why}");
+            }
+        }
+        for &window in windows.iter().filter(|_| !given_up) {
+            // where the window still is, rather than wherever a new shimeji would go
+            if let Some(&position) = self.positions.get(&window) {
+                if let Err(why) = bucket.control(window, Control::SetPosition(position)) {
+                    log::error!("Could not put shimeji {window:?} back: {why}");
+                }
+            }
+            if self.party_guests.contains(&window) {
+                if let Err(why) = bucket.start_partying(window) {
+                    log::error!("Could not start shimeji {window:?} partying again: {why}");
+                }
+            }
+        }
+        drop(bucket);
+        if given_up {
+            self.broadcast_world();
+            self.save_population();
+            self.refresh_tray();
+        }
+    }
+    /// Every named monitor, with how many shimejis live on it.
+    fn monitor_crowds(&self, event_loop: &ActiveEventLoop) -> Vec<(MonitorHandle, String, usize)> {
+        event_loop
+            .available_monitors()
+            .filter_map(|monitor| {
+                let name = monitor.name()?;
+                let crowd = self.monitor_of.values().filter(|on| **on == name).count();
+                Some((monitor, name, crowd))
+            })
+            .collect()
+    }
+    /// The least crowded monitor a shimeji of `pack` may live on, see [`Settings::pick_monitor`].
+    fn pick_monitor(&self, event_loop: &ActiveEventLoop, pack: &str) -> Option<MonitorHandle> {
+        let crowds = self.monitor_crowds(event_loop);
+        let picked = self
+            .settings
+            .pick_monitor(
+                pack,
+                crowds
+                    .iter()
+                    .map(|(_, name, crowd)| (name.as_str(), *crowd)),
+            )?
+            .to_owned();
+        crowds
+            .into_iter()
+            .find(|(_, name, _)| *name == picked)
+            .map(|(monitor, ..)| monitor)
+    }
+    /// Whether any packs are pinned or monitors capped.
+    fn has_monitor_rules(&self) -> bool {
+        !self.settings.monitor_caps.is_empty() || !self.settings.pins.is_empty()
+    }
+    /// Follow a shimeji onto another monitor, sending it on to the least crowded monitor
+    /// it's allowed on if its pack isn't pinned there or there are too many shimejis there.
+    fn shimeji_moved(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        id: WindowId,
+        position: PhysicalPosition<i32>,
+    ) {
+        // wait until it's let go of, so it isn't pulled out from under the pointer
+        if self.interactions.is_held(id) {
+            return;
+        }
+        let Some(pack) = self
+            .live_shimejis
+            .get(&id)
+            .map(|data| Arc::clone(&data.name))
+        else {
+            return;
+        };
+        let Some(name) = monitor_at(event_loop, position).and_then(|monitor| monitor.name()) else {
+            return;
+        };
+        if self.monitor_of.get(&id) == Some(&name) {
+            return;
+        }
+        self.monitor_of.insert(id, name.clone());
+        let crowd = self.monitor_of.values().filter(|on| **on == name).count();
+        if self.settings.allows(&pack, &name) && !self.settings.is_overcrowded(&name, crowd) {
+            return;
+        }
+        self.monitor_of.remove(&id);
+        let Some(monitor) = self.pick_monitor(event_loop, &pack) else {
+            log::warn!("No other monitor has room for {pack}, leaving it on {name}");
+            self.monitor_of.insert(id, name);
+            return;
+        };
+        let Some(bucket) = self.buckets_windows_map.get(&id) else {
+            return;
+        };
+        log::info!(
+            "Moving {pack} from {name} to {}",
+            monitor.name().unwrap_or_default()
+        );
+        unless_thread_gone(
+            bucket
+                .borrow_mut()
+                .control(id, Control::SetPosition(monitor.position())),
+        )
+        .context("could not move shimeji to another monitor")
+        .unwrap();
+        self.monitor_of
+            .insert(id, monitor.name().unwrap_or_default());
+    }
+    fn world(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            population: self.live_shimejis.len(),
+            floor_offsets: self.settings.floor_offsets.clone(),
+            window_surfaces: self.window_surfaces.clone(),
+            fullscreen: self.fullscreen.clone(),
+            tint: (self.settings.ambient_tint
+                && (self
+                    .clock
+                    .is_some_and(|clock| ambient::is_night(clock.hour))
+                    || self.dark_theme))
+                .then_some(ambient::Tint::NIGHT),
+            filters: self.settings.filters.clone(),
+            monitors: self.monitors.clone(),
+            muted: self.settings.muted,
+            user_idle: self.user_idle,
+            focused: self.focused.clone(),
+            max_fps: self.settings.max_fps,
+            saving_power: self.settings.low_power && self.on_battery,
+            shaped_windows: self.shaped_windows,
+            wrap_around: self.settings.wrap_around,
+            recording: self.recorder.is_some(),
+            clock: self.clock,
+        }
+    }
+    /// Look the monitors up again, telling the buckets if they were plugged in,
+    /// unplugged or moved around.
+    fn refresh_monitors(&mut self, event_loop: &ActiveEventLoop) {
+        let monitors = monitors::Monitors::from_event_loop(event_loop);
+        if monitors != self.monitors {
+            log::debug!("Monitors changed: {monitors:?}");
+            self.monitors = monitors;
+            self.broadcast_world();
+        }
+    }
+    /// Send every bucket a fresh [`WorldSnapshot`].
+    fn broadcast_world(&self) {
+        let world = self.world();
+        for bucket in self.buckets.iter() {
+            unless_thread_gone(bucket.borrow_mut().update_world(world.clone()))
+                .context("could not send world snapshot to bucket")
+                .unwrap();
+        }
+    }
+    /// Spawn another shimeji like the one in `parent` at `position`,
+    /// unless multiplying already brought about [`Settings::max_population`] shimejis.
+    fn multiply(&mut self, parent: WindowId, position: PhysicalPosition<i32>) {
+        let Some(data) = self.live_shimejis.get(&parent) else {
+            return;
+        };
+        let population = self.live_shimejis.len() + self.pending_shimejis.len();
+        if population >= self.settings.max_population {
+            log::debug!(
+                "{} can't multiply, there are already {population} shimejis",
+                data.name
+            );
+            return;
+        }
+        let config = SpawnConfig {
+            position: Some(position),
+            ..SpawnConfig::new(data.name.clone())
+        };
+        let spawned = self.spawn(&config).is_some();
+        // a guest's children go home with it, spawn having queued them last
+        if spawned && self.party_guests.contains(&parent) {
+            if let Some(child) = self.pending_shimejis.last_mut() {
+                child.party_guest = true;
+            }
+        }
+    }
+    /// Close every window and stop every thread, then exit the event loop.
+    ///
+    /// Bucket threads close their shimejis' windows as they stop; any that don't
+    /// within [`SHUTDOWN_TIMEOUT`] are left behind so exiting never hangs.
+    fn shut_down(&mut self, event_loop: &ActiveEventLoop) {
+        log::info!("Shutting down");
+        // before the shimejis are taken away below
+        self.save_session();
+        // calibration and picker windows belong to the manager
+        self.calibration = None;
+        self.picker = None;
+        for (id, bucket) in self.buckets_windows_map.drain() {
+            if let Err(why) = bucket.borrow_mut().remove(id) {
+                log::warn!("Could not remove {id:?} while shutting down: {why}");
+            }
+        }
+        self.pending_shimejis.clear();
+        let instances: Vec<_> = self.instances.keys().copied().collect();
+        for instance in instances {
+            self.despawn_instance(instance);
+        }
+        // also stops the threads polling it
+        self.should_exit
+            .store(true, std::sync::atomic::Ordering::Release);
+        for bucket in self.buckets.iter() {
+            bucket.borrow_mut().join_thread_timeout(SHUTDOWN_TIMEOUT);
+        }
+        event_loop.exit();
+    }
+    /// Remove the most recently spawned shimeji that isn't a party guest.
+    fn remove_one(&mut self) {
+        let Some(id) = self
+            .spawn_order
+            .iter()
+            .rev()
+            .find(|id| !self.party_guests.contains(id))
+            .copied()
+        else {
+            log::debug!("No shimeji to remove");
+            return;
+        };
+        self.remove_shimeji(id);
+    }
+    fn start_party(&mut self, event_loop: &ActiveEventLoop) {
+        if !self.party_guests.is_empty() {
+            log::debug!("A party is already going on");
+            return;
+        }
+        let Some(proxy) = self.proxy.get().cloned() else {
+            log::warn!("Cannot throw a party without an event loop proxy");
+            return;
+        };
+        // every guest is a copy of someone already here
+        let hosts: Vec<_> = self.live_shimejis.values().cloned().collect();
+        if hosts.is_empty() {
+            return;
+        }
+        for data in hosts.into_iter().cycle().take(PARTY_GUESTS) {
+            self.pending_shimejis.push(PendingShimeji {
+                data,
+                party_guest: true,
+                instance: None,
+            });
+        }
+        self.address_pending_shimejis(event_loop);
+        log::info!("Party started with {} guests", self.party_guests.len());
+
+        thread::Builder::new()
+            .name(String::from("Party timer"))
+            .spawn(move || {
+                thread::sleep(PARTY_DURATION);
+                proxy.send_event(ManagerEvent::EndParty).ok();
+            })
+            .expect("should be able to spawn party timer thread");
+    }
+    fn end_party(&mut self) {
+        log::info!(
+            "Party over, sending {} guests home",
+            self.party_guests.len()
+        );
+        // ones still waiting for a window stay, or there'd never be a next party
+        for pending in self.pending_shimejis.iter_mut() {
+            pending.party_guest = false;
+        }
+        for id in std::mem::take(&mut self.party_guests) {
+            // guests may have already been removed some other way
+            if self.live_shimejis.contains_key(&id) {
+                self.remove_shimeji(id);
+            }
+        }
+    }
+    #[cfg(target_os = "linux")]
+    pub fn set_backend(&mut self, backend: backend::Backend) {
+        self.backend = backend;
+    }
+    /// What the platform supports, on the backend the manager will use.
+    pub fn capabilities(&self) -> capabilities::Capabilities {
+        cfg_if! {
+            if #[cfg(target_os = "linux")] {
+                capabilities::for_backend(self.backend)
+            } else {
+                capabilities::capabilities()
+            }
+        }
+    }
+    fn build_event_loop(&self) -> Result<EventLoop<ManagerEvent>, ManagerError> {
+        cfg_if! {
+            if #[cfg(target_os = "linux")] {
+                log::debug!("Using the {:?} backend", self.backend);
+                self.backend.ensure_available()?;
+                Ok(self.backend.build_event_loop()?)
+            } else if #[cfg(target_os = "macos")] {
+                use winit::platform::macos::{ActivationPolicy, EventLoopBuilderExtMacOS};
+                // an accessory app has no Dock icon and no menu bar of its own
+                Ok(EventLoop::with_user_event()
+                    .with_activation_policy(ActivationPolicy::Accessory)
+                    .with_default_menu(false)
+                    .build()?)
+            } else {
+                Ok(EventLoop::with_user_event().build()?)
+            }
+        }
+    }
+    /// Run with the tray icon showing, see [`tray`].
+    pub fn run_with_tray(mut self) -> Result<(), ManagerError> {
+        let event_loop = self.build_event_loop()?;
+        self.tray = tray::Tray::new(event_loop.create_proxy(), self.tray_menu())
+            .inspect_err(|why| log::warn!("Could not show the tray icon: {why:#}"))
+            .ok();
+        self.run_event_loop(event_loop)
+    }
+    pub fn run(self) -> Result<(), ManagerError> {
+        let event_loop = self.build_event_loop()?;
+        self.run_event_loop(event_loop)
+    }
+    fn run_event_loop(mut self, event_loop: EventLoop<ManagerEvent>) -> Result<(), ManagerError> {
+        if self.proxy.set(event_loop.create_proxy()).is_err() {
+            log::warn!("Manager was already running");
+        }
+        for (feature, support) in self.capabilities().features() {
+            if let capabilities::Support::No(why) = support {
+                log::info!("No {feature}: {why}");
+            }
+        }
+        if !self.shaped_windows && !self.capabilities().transparency.is_supported() {
+            log::info!("Cutting windows to the shape of their sprites instead");
+            self.shaped_windows = true;
+        }
+        for bucket in self.buckets.iter() {
+            bucket
+                .borrow_mut()
+                .connect_manager(event_loop.create_proxy())?;
+        }
+        #[cfg(target_os = "linux")]
+        if !self.compositor_effects && self.backend == backend::Backend::X11 {
+            self.compositor_hints = compositor::CompositorHints::connect()
+                .inspect_err(|why| log::warn!("Compositor hints unavailable: {why}"))
+                .ok();
+        }
+        // other windows can only be found on X11, not Wayland
+        #[cfg(target_os = "linux")]
+        let can_see_windows = self.backend == backend::Backend::X11;
+        #[cfg(not(target_os = "linux"))]
+        let can_see_windows = true;
+        if can_see_windows {
+            self.window_surfaces_thread =
+                window_surfaces::spawn(event_loop.create_proxy(), Arc::clone(&self.should_exit))
+                    .inspect_err(|why| log::warn!("Could not start window surfaces thread: {why}"))
+                    .ok();
+        }
+        if can_see_windows && self.settings.hide_in_fullscreen {
+            self.fullscreen_thread =
+                fullscreen::spawn(event_loop.create_proxy(), Arc::clone(&self.should_exit))
+                    .inspect_err(|why| {
+                        log::warn!("Could not start fullscreen detection thread: {why}")
+                    })
+                    .ok();
+        }
+        self.decode_frames_in_background();
+        if self.hot_reload {
+            self.hot_reload_thread = hot_reload::spawn(
+                self.library.clone(),
+                event_loop.create_proxy(),
+                Arc::clone(&self.should_exit),
+            )
+            .inspect_err(|why| log::warn!("Could not start hot reload thread: {why}"))
+            .ok();
+        }
+        self.ambient_thread =
+            ambient::spawn(event_loop.create_proxy(), Arc::clone(&self.should_exit))
+                .inspect_err(|why| log::warn!("Could not start ambient clock thread: {why}"))
+                .ok();
+        // XWayland would only see input to X11 windows, and think the user is always away
+        if self.capabilities().idle_detection.is_supported() {
+            self.idle_thread = idle::spawn(
+                self.settings.idle_after,
+                event_loop.create_proxy(),
+                Arc::clone(&self.should_exit),
+            )
+            .inspect_err(|why| log::warn!("Could not start idle detection thread: {why}"))
+            .ok();
+        }
+        if self.capabilities().focus_tracking.is_supported() {
+            self.focus_thread =
+                focus::spawn(event_loop.create_proxy(), Arc::clone(&self.should_exit))
+                    .inspect_err(|why| log::warn!("Could not start focus tracking thread: {why}"))
+                    .ok();
+        }
+        if self.settings.notification_behavior.is_some()
+            && self.capabilities().notifications.is_supported()
+        {
+            self.notifications_thread =
+                notifications::spawn(event_loop.create_proxy(), Arc::clone(&self.should_exit))
+                    .inspect_err(|why| {
+                        log::warn!("Could not start notification listener thread: {why}")
+                    })
+                    .ok();
+        }
+        self.power_thread = power::spawn(event_loop.create_proxy(), Arc::clone(&self.should_exit))
+            .inspect_err(|why| log::warn!("Could not start battery thread: {why}"))
+            .ok();
+        self.ipc_thread = ipc::spawn(event_loop.create_proxy(), Arc::clone(&self.should_exit))
+            .inspect_err(|why| log::warn!("Could not start IPC thread: {why}"))
+            .ok();
+        if !self.settings.hotkeys.is_empty() {
+            self.hotkeys_thread = hotkeys::spawn(
+                self.settings.hotkeys.clone(),
+                event_loop.create_proxy(),
+                Arc::clone(&self.should_exit),
+            )
+            .inspect_err(|why| log::warn!("Could not start hotkey thread: {why}"))
+            .ok();
+        }
+        if let Some(run) = &self.soak {
+            self.soak_thread = soak::spawn(
+                run.config.duration,
+                event_loop.create_proxy(),
+                Arc::clone(&self.should_exit),
+            )
+            .inspect_err(|why| log::error!("Could not start soak test thread: {why}"))
+            .ok();
+        }
+        if !self.replay.is_empty() {
+            self.replay_thread = recording::spawn_replay(
+                std::mem::take(&mut self.replay),
+                event_loop.create_proxy(),
+                Arc::clone(&self.should_exit),
+            )
+            .inspect_err(|why| log::error!("Could not start replay thread: {why}"))
+            .ok();
+        }
+        event_loop.run_app(&mut self)?;
+        log::debug!("Manager returned");
+        if let Some(run) = &self.soak {
+            run.result()?;
+        }
+        Ok(())
+    }
+
+    // pub fn run(mut self, tray_handle: Option<tray_item::TrayItem>) -> Result<(), ManagerError> {
+    //     let copy = Arc::clone(&self.should_exit);
+    //     if let Some(mut handle) = tray_handle {
+    //         handle
+    //             .add_menu_item("Kill", move || {
+    //                 copy.store(true, std::sync::atomic::Ordering::SeqCst);
+    //             })
+    //             .unwrap();
+    //     }
+
+    //     cfg_if! {
+    //         if #[cfg(target_os = "linux")] {
+    //             let event_loop = EventLoop::builder().with_x11().build().unwrap();
+    //         } else {
+    //             let event_loop = EventLoop::new().unwrap();
+    //         }
+    //     }
+    //     event_loop.run_app(&mut self)?;
+    //     log::debug!("Manager returned");
+    //     Ok(())
+    // }
+
+    fn address_pending_shimejis(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        // If we don't collect here, the compiler
+        // believes a reference is still in use
+        let mut buckets_by_count = self
+            .buckets
+            .iter()
+            .sorted_by_key(|x| Rc::deref(x).borrow_mut().contained_shimejis())
+            .enumerate()
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .cycle();
+
+        let buckets: Vec<_> = self
+            .buckets
+            .iter()
+            .sorted_by_key(|x| Rc::deref(x).borrow_mut().contained_shimejis())
+            .collect();
+
+        // controls sent to shimejis before they had windows
+        let mut queued = vec![];
+        // while we still have pending shimejis...
+        while let Some(PendingShimeji {
+            data: pending_shimeji,
+            party_guest,
+            instance,
+        }) = self.pending_shimejis.pop()
+        {
+            if self.live_shimejis.len() >= self.population_limit {
+                log::warn!(
+                    "Population limit of {} reached, not spawning {}",
+                    self.population_limit,
+                    pending_shimeji.name
+                );
+                if let Some(entry) = instance.and_then(|id| self.instances.remove(&id)) {
+                    entry.events.send(ShimejiEvent::Despawned).ok();
+                }
+                continue;
+            }
+            let monitor = match self.has_monitor_rules() {
+                true => match self.pick_monitor(event_loop, &pending_shimeji.name) {
+                    Some(monitor) => Some(monitor),
+                    None => {
+                        log::warn!(
+                            "Every monitor {} may live on is full, not spawning it",
+                            pending_shimeji.name
+                        );
+                        if let Some(entry) = instance.and_then(|id| self.instances.remove(&id)) {
+                            entry.events.send(ShimejiEvent::Despawned).ok();
+                        }
+                        continue;
+                    }
+                },
+                false => None,
+            };
+            let index = buckets_by_count.next().unwrap();
+            self.spawned_count += 1;
+            let number = self.spawned_count;
+            let mut attributes = Self::titled_attributes(self.anonymous_windows, || {
+                format!("shimeji: {}#{number}", pending_shimeji.name)
+            });
+            if let Some(monitor) = monitor.as_ref() {
+                attributes = attributes.with_position(monitor.position());
+            }
+            let window = event_loop
+                .create_window(attributes)
+                .expect("should be able to create window for shimeji");
+
+            window
+                .window_handle()
+                .expect("window handloe should be able to be grabbed");
+            #[cfg(target_os = "linux")]
+            compositor::suppress(self.compositor_hints.as_ref(), &window);
+            #[cfg(target_os = "windows")]
+            tool_window::apply(&window);
+
+            let id = window.id();
+            let position = window.outer_position().unwrap_or_default();
+
+            let bucket_rc = &buckets[index];
+            let bucket_to_add_to: &RefCell<ShimejiBucket> = Rc::deref(bucket_rc);
+
+            // props live in the same bucket as the first shimeji of their pack,
+            // so that shimeji's thread can see them
+            if self
+                .packs_with_props
+                .insert(Arc::clone(&pending_shimeji.name))
+            {
+                for prop in pending_shimeji.props.iter() {
+                    for _ in 0..prop.count {
+                        let attributes = Self::titled_attributes(self.anonymous_windows, || {
+                            format!("shimeji prop: {}/{}", pending_shimeji.name, prop.name)
+                        });
+                        let window = event_loop
+                            .create_window(attributes)
+                            .expect("should be able to create window for prop");
+                        #[cfg(target_os = "linux")]
+                        compositor::suppress(self.compositor_hints.as_ref(), &window);
+                        #[cfg(target_os = "windows")]
+                        tool_window::apply(&window);
+                        let prop_id = window.id();
+                        match bucket_to_add_to
+                            .borrow_mut()
+                            .add_prop(Arc::clone(prop), window)
+                        {
+                            Ok(()) => {
+                                self.buckets_windows_map
+                                    .insert(prop_id, Rc::clone(bucket_rc));
+                            }
+                            Err(why) => log::error!("Could not add prop {}: {why}", prop.name),
+                        }
+                    }
+                }
+            }
+
+            // the next of the pack's colors, counting the shimejis of it already out
+            let variant = self
+                .settings
+                .variants
+                .get(&*pending_shimeji.name)
+                .filter(|variants| !variants.is_empty())
+                .map(|variants| {
+                    let siblings = self
+                        .live_shimejis
+                        .values()
+                        .filter(|data| data.name == pending_shimeji.name)
+                        .count();
+                    variants[siblings % variants.len()]
+                });
+            let monitor_name = monitor
+                .or_else(|| window.current_monitor())
+                .and_then(|monitor| monitor.name());
+            // its window is closed with it
+            if let Err(why) = bucket_to_add_to
+                .borrow_mut()
+                .add(Arc::clone(&pending_shimeji), window)
+            {
+                log::error!("Could not spawn {}: {why}", pending_shimeji.name);
+                if let Some(entry) = instance.and_then(|id| self.instances.remove(&id)) {
+                    entry.events.send(ShimejiEvent::Despawned).ok();
+                }
+                continue;
+            }
+            self.live_shimejis.insert(id, Arc::clone(&pending_shimeji));
+            if let Some(name) = monitor_name {
+                self.monitor_of.insert(id, name);
+            }
+            self.spawn_order.push(id);
+            let seed = self
+                .recorder
+                .as_mut()
+                .map(|recorder| recorder.spawned(id, &pending_shimeji.name, position));
+            if let Some(variant) = variant {
+                bucket_to_add_to
+                    .borrow_mut()
+                    .control(id, Control::SetVariant(variant))
+                    .expect("should be able to recolor shimeji");
+            }
+            if let Some(seed) = seed {
+                bucket_to_add_to
+                    .borrow_mut()
+                    .control(id, Control::SetSeed(seed))
+                    .expect("should be able to seed shimeji");
+            }
+            if party_guest {
+                bucket_to_add_to
+                    .borrow_mut()
+                    .start_partying(id)
+                    .expect("should be able to start partying");
+                self.party_guests.push(id);
+            }
+            #[cfg(feature = "gamepad")]
+            if self.gamepad_thread.is_none() {
+                let handle = bucket_to_add_to
+                    .borrow()
+                    .steering_handle(id)
+                    .expect("bucket should be running after adding a shimeji");
+                self.gamepad_thread = gamepad::spawn(handle, Arc::clone(&self.should_exit))
+                    .inspect_err(|why| log::warn!("Could not start gamepad thread: {why}"))
+                    .ok();
+            }
+            let clone = Rc::clone(bucket_rc);
+            self.buckets_windows_map.insert(id, clone);
+            if let Some((instance, entry)) =
+                instance.and_then(|instance| Some((instance, self.instances.get_mut(&instance)?)))
+            {
+                entry.window = Some(id);
+                entry.events.send(ShimejiEvent::Spawned(id)).ok();
+                queued.push((instance, std::mem::take(&mut entry.queued)));
+            }
+        }
+        for (instance, controls) in queued {
+            for control in controls {
+                self.control(instance, control);
+            }
+        }
+        self.broadcast_world();
+        self.save_population();
+        self.refresh_tray();
+    }
+}
+/// `sent`, unless it only failed because the bucket's thread is gone: that's logged, and
+/// left to [`BucketManager::bucket_crashed`] to start it again with its windows.
+fn unless_thread_gone(sent: Result<(), BucketError>) -> Result<(), BucketError> {
+    match sent {
+        Err(BucketError::ThreadGone) => {
+            log::warn!("A bucket's thread is gone, waiting for it to be restarted");
+            Ok(())
+        }
+        sent => sent,
+    }
+}
+/// The monitor `position` is on, if any.
+fn monitor_at(
+    event_loop: &ActiveEventLoop,
+    position: PhysicalPosition<i32>,
+) -> Option<MonitorHandle> {
+    event_loop.available_monitors().find(|monitor| {
+        let (origin, size) = (monitor.position(), monitor.size());
+        (origin.x..origin.x + size.width as i32).contains(&position.x)
+            && (origin.y..origin.y + size.height as i32).contains(&position.y)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use loader::PackLibrary;
+    fn init_logger() {
+        // It is non-essential if the logger fails, which can often happen
+        // since Rust by default runs threads in parallel.
+        // SimpleLogger errors if it was already initialized.
+        simple_logger::SimpleLogger::new()
+            .with_level(log::LevelFilter::Trace)
+            .env()
+            .init()
+            .ok();
+    }
+    /// An animation of `frames` placeholder frames, with nothing else to it.
+    fn animation(frames: usize) -> loader::AnimationData {
+        loader::AnimationData {
+            durations: vec![],
+            weight: None,
+            next: None,
+            loop_mode: Default::default(),
+            frames: vec![loader::Frame::placeholder(1, 1); frames],
+            sounds: vec![],
+            anchors: vec![],
+            hitboxes: vec![],
+            steps: vec![],
+        }
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_first_then_check)]
+    fn buckets_are_created_successfully() {
+        init_logger();
+        let manager = BucketManager::new(1);
+
+        assert!(manager.buckets.first().is_some());
+    }
+
+    #[test]
+    fn sends_to_a_crashed_bucket_wait_for_its_restart() {
+        assert!(unless_thread_gone(Err(BucketError::ThreadGone)).is_ok());
+        assert!(unless_thread_gone(Err(BucketError::NotRunning)).is_err());
+    }
+
+    #[test]
+    fn settings_reload_from_their_file() {
+        init_logger();
+        let path = std::env::temp_dir().join(format!("reload-{}.conf", std::process::id()));
+        std::fs::write(&path, "max_population = 5\n").unwrap();
+        let mut manager = BucketManager::new(1);
+        manager.set_settings(Settings::load(path.clone()).unwrap());
+        std::fs::write(&path, "max_population = 7\n").unwrap();
+        manager.reload_settings().unwrap();
+        assert_eq!(manager.settings.max_population, 7);
+        // a broken file leaves the settings as they were
+        std::fs::write(&path, "max_population = lots\n").unwrap();
+        let reloaded = manager.reload_settings();
+        std::fs::remove_file(&path).unwrap();
+        assert!(reloaded.is_err());
+        assert_eq!(manager.settings.max_population, 7);
+    }
+
+    #[test]
+    fn spawned_handles_wait_for_the_event_loop() {
+        init_logger();
+        let mut manager = BucketManager::new(1);
+        let library = ShimejiLibrary::load("./default.xml", &PackLibrary::new(".")).unwrap();
+        let name = library.names()[0].clone();
+        manager.set_library(library);
+
+        assert!(manager.spawn(&SpawnConfig::new("nobody")).is_none());
+        let handle = manager.spawn(&SpawnConfig::new(name)).unwrap();
+        assert_eq!(manager.pending_shimejis.len(), 1);
+        assert_eq!(
+            handle.set_visible(false),
+            Err(handle::HandleError::NotRunning)
+        );
+        assert_eq!(
+            handle.play_and_wait("anything"),
+            Err(handle::HandleError::NotRunning)
+        );
+        assert_eq!(handle.try_next_event(), None);
+    }
+
+    #[test]
+    fn waiting_on_a_played_behavior_keeps_everything_else() {
+        let (sender, receiver) = mpsc::channel();
+        let handle = ShimejiHandle::new(ShimejiId(0), Arc::new(OnceLock::new()), receiver);
+        let events = [
+            ShimejiEvent::Spawned(WindowId::from(1)),
+            ShimejiEvent::Interrupted(String::from("wave")),
+            ShimejiEvent::Finished(String::from("bow")),
+            ShimejiEvent::Interrupted(String::from("bow")),
+            ShimejiEvent::Despawned,
+        ];
+        for event in events {
+            sender.send(event).unwrap();
+        }
+        assert_eq!(handle.wait_for("bow"), Ok(handle::Playback::Finished));
+        assert_eq!(handle.wait_for("bow"), Ok(handle::Playback::Interrupted));
+        assert_eq!(handle.wait_for("wave"), Ok(handle::Playback::Interrupted));
+        assert_eq!(handle.wait_for("bow"), Ok(handle::Playback::Despawned));
+        drop(sender);
+        assert_eq!(
+            handle.wait_for("bow"),
+            Err(handle::HandleError::ManagerGone)
+        );
+        assert_eq!(
+            handle.try_next_event(),
+            Some(ShimejiEvent::Spawned(WindowId::from(1)))
+        );
+        assert_eq!(handle.try_next_event(), None);
+    }
+
+    mod interaction {
+        use super::super::interaction::*;
+        use winit::{dpi::PhysicalPosition, window::WindowId};
+
+        #[test]
+        fn tap_without_moving_is_a_pet() {
+            let mut tracker = InteractionTracker::new();
+            let window = WindowId::from(1);
+            let at = PhysicalPosition::new(10.0, 10.0);
+            let finger = PointerId::Touch(0);
+
+            assert_eq!(
+                tracker.handle(window, finger, PointerPhase::Pressed, at),
+                None
+            );
+            assert_eq!(
+                tracker.handle(window, finger, PointerPhase::Released, at),
+                Some(InteractionEvent::Pet(window))
+            );
+            assert!(!tracker.is_held(window));
+        }
+
+        #[test]
+        fn the_mouse_drags_a_shimeji_once_it_moves_far_enough() {
+            let mut tracker = InteractionTracker::new();
+            let window = WindowId::from(1);
+            let mouse = PointerId::Mouse;
+            let at = |x, y| PhysicalPosition::new(x, y);
+
+            tracker.handle(window, mouse, PointerPhase::Pressed, at(10.0, 10.0));
+            assert_eq!(
+                tracker.handle(window, mouse, PointerPhase::Moved, at(12.0, 11.0)),
+                None
+            );
+            // picked up where it was pressed, not where the drag was noticed
+            assert_eq!(
+                tracker.handle(window, mouse, PointerPhase::Moved, at(30.0, 10.0)),
+                Some(InteractionEvent::Grab {
+                    id: window,
+                    offset: at(10.0, 10.0)
+                })
+            );
+            assert_eq!(
+                tracker.handle(window, mouse, PointerPhase::Moved, at(40.0, 20.0)),
+                Some(InteractionEvent::Drag {
+                    id: window,
+                    position: at(40.0, 20.0)
+                })
+            );
+            // a finger can't take it off the mouse
+            let finger = PointerId::Touch(0);
+            tracker.handle(window, finger, PointerPhase::Pressed, at(0.0, 0.0));
+            assert_eq!(
+                tracker.handle(window, finger, PointerPhase::Moved, at(50.0, 50.0)),
+                None
+            );
+            assert!(matches!(
+                tracker.handle(window, mouse, PointerPhase::Released, at(40.0, 20.0)),
+                Some(InteractionEvent::Release { id, .. }) if id == window
+            ));
+            assert!(!tracker.is_held(window));
+        }
+
+        #[test]
+        fn two_fingers_drag_two_shimejis() {
+            let mut tracker = InteractionTracker::new();
+            let (first, second) = (WindowId::from(1), WindowId::from(2));
+            let start = PhysicalPosition::new(5.0, 5.0);
+            let moved = PhysicalPosition::new(50.0, 5.0);
+
+            tracker.handle(first, PointerId::Touch(0), PointerPhase::Pressed, start);
+            tracker.handle(second, PointerId::Touch(1), PointerPhase::Pressed, start);
+            assert!(matches!(
+                tracker.handle(first, PointerId::Touch(0), PointerPhase::Moved, moved),
+                Some(InteractionEvent::Grab { id, .. }) if id == first
+            ));
+            assert!(matches!(
+                tracker.handle(second, PointerId::Touch(1), PointerPhase::Moved, moved),
+                Some(InteractionEvent::Grab { id, .. }) if id == second
+            ));
+            assert!(matches!(
+                tracker.handle(first, PointerId::Touch(0), PointerPhase::Moved, moved),
+                Some(InteractionEvent::Drag { id, .. }) if id == first
+            ));
+            assert_eq!(
+                tracker.handle(second, PointerId::Touch(1), PointerPhase::Cancelled, moved),
+                Some(InteractionEvent::Release {
+                    id: second,
+                    velocity: (0.0, 0.0)
+                })
+            );
+            assert!(tracker.is_held(first));
+            assert!(!tracker.is_held(second));
+        }
+
+        #[test]
+        fn filters_hold_back_interactions() {
+            let settings = super::super::settings::Settings::parse(
+                "filter = ignore click while sleeping\nfilter = require double-click to grab\n",
+            )
+            .unwrap();
+            let filters = &settings.filters;
+            let sleeping = FilterContext {
+                behavior: Some("sleeping"),
+                last_click: None,
+            };
+            let walking = FilterContext {
+                behavior: Some("walking"),
+                ..sleeping
+            };
+            assert!(!filters.allows(InteractionKind::Click, sleeping));
+            assert!(filters.allows(InteractionKind::Click, walking));
+            assert!(!filters.allows(InteractionKind::Grab, walking));
+            let just_clicked = FilterContext {
+                last_click: Some(std::time::Instant::now()),
+                ..walking
+            };
+            assert!(filters.allows(InteractionKind::Grab, just_clicked));
+            assert!(settings
+                .to_string()
+                .contains("filter = ignore click while sleeping\n"));
+            assert!("ignore hugs".parse::<EventFilter>().is_err());
+        }
+    }
+
+    mod movement {
+        use super::super::movement::*;
+        use std::time::Duration;
+        use winit::dpi::PhysicalPosition;
+
+        #[test]
+        fn jump_lands_where_it_started() {
+            let mut movement = Movement::new(PhysicalPosition::new(0.0, 100.0));
+            assert!(movement.is_still());
+            movement.command(MovementCommand::Jump);
+            assert!(!movement.is_still());
+            movement.step(Duration::from_millis(100));
+            assert!(movement.position().y < 100.0);
+            for _ in 0..20 {
+                movement.step(Duration::from_millis(100));
+            }
+            assert!(!movement.is_jumping());
+            assert_eq!(movement.position().y, 100.0);
+            assert!(movement.is_still());
+        }
+
+        #[test]
+        fn thrown_shimejis_bounce_before_landing() {
+            let mut movement = Movement::new(PhysicalPosition::new(0.0, 500.0)).with_gravity(true);
+            movement.set_floor(Some(500.0));
+            movement.throw((400.0, -900.0));
+            let mut bounces = 0;
+            let mut falling = false;
+            for _ in 0..200 {
+                movement.step(Duration::from_millis(10));
+                let rising = movement.velocity().1 < 0.0;
+                if falling && rising {
+                    bounces += 1;
+                }
+                falling = movement.velocity().1 > 0.0;
+                // it can't walk off mid-flight
+                if movement.is_airborne() {
+                    movement.command(MovementCommand::Walk(-50.0));
+                }
+            }
+            assert!(bounces >= 1);
+            assert!(movement.is_still());
+            assert_eq!(movement.position().y, 500.0);
+            assert!(movement.position().x > 0.0);
+        }
+
+        #[test]
+        fn falling_lands_on_the_floor() {
+            let mut movement = Movement::new(PhysicalPosition::new(0.0, 0.0)).with_gravity(true);
+            movement.set_floor(Some(500.0));
+            assert!(movement.is_airborne());
+            for _ in 0..20 {
+                movement.step(Duration::from_millis(100));
+            }
+            assert!(!movement.is_airborne());
+            assert_eq!(movement.position().y, 500.0);
+
+            movement.command(MovementCommand::Jump);
+            movement.step(Duration::from_millis(100));
+            assert!(movement.position().y < 500.0);
+            for _ in 0..20 {
+                movement.step(Duration::from_millis(100));
+            }
+            assert_eq!(movement.position().y, 500.0);
+        }
+
+        #[test]
+        fn walking_into_a_wall_turns_around() {
+            let mut movement = Movement::new(PhysicalPosition::new(50.0, 0.0));
+            movement.set_walls(Some((0.0, 100.0)));
+            movement.command(MovementCommand::Walk(-100.0));
+            movement.step(Duration::from_secs(1));
+            assert_eq!(movement.position().x, 0.0);
+            assert_eq!(movement.velocity().0, 100.0);
+            movement.step(Duration::from_secs(2));
+            assert_eq!(movement.position().x, 100.0);
+            assert_eq!(movement.velocity().0, -100.0);
+        }
+
+        #[test]
+        fn wrapping_walks_back_in_at_the_other_wall() {
+            let mut movement = Movement::new(PhysicalPosition::new(50.0, 0.0));
+            movement.set_walls(Some((0.0, 100.0)));
+            movement.set_wrapping(true);
+            movement.command(MovementCommand::Walk(100.0));
+            movement.step(Duration::from_millis(500));
+            assert_eq!(movement.position().x, 100.0);
+            assert_eq!(movement.touching(), None);
+            movement.step(Duration::from_millis(100));
+            assert_eq!(movement.position().x, 0.0);
+            assert_eq!(movement.velocity().0, 100.0);
+            movement.command(MovementCommand::Climb(-100.0));
+            assert_eq!(movement.clinging_to(), None);
+        }
+
+        #[test]
+        fn climbing_up_a_wall_hangs_from_the_ceiling() {
+            let mut movement = Movement::new(PhysicalPosition::new(50.0, 500.0)).with_gravity(true);
+            movement.set_floor(Some(500.0));
+            movement.set_walls(Some((0.0, 100.0)));
+            movement.set_ceiling(Some(0.0));
+            movement.command(MovementCommand::Climb(-100.0));
+            assert_eq!(movement.clinging_to(), None);
+
+            movement.command(MovementCommand::Walk(100.0));
+            movement.step(Duration::from_secs(1));
+            assert_eq!(movement.touching(), Some(Edge::RightWall));
+            movement.command(MovementCommand::Climb(-100.0));
+            for _ in 0..10 {
+                movement.step(Duration::from_secs(1));
+            }
+            assert_eq!(movement.position().y, 0.0);
+            assert_eq!(movement.clinging_to(), Some(Surface::Ceiling));
+
+            movement.command(MovementCommand::Walk(-100.0));
+            movement.step(Duration::from_millis(500));
+            assert_eq!(movement.position(), PhysicalPosition::new(50.0, 0.0));
+            movement.command(MovementCommand::Stop);
+            movement.step(Duration::from_millis(100));
+            assert!(movement.is_airborne());
+        }
+    }
+
+    mod behavior {
+        use super::super::behavior::*;
+        use super::super::focus::FocusedWindow;
+        use super::super::formula::Formula;
+        use super::super::rng::Rng;
+        use super::super::world::WorldSnapshot;
+        use std::{collections::HashMap, time::Duration};
+
+        fn behavior(name: &str, transitions: Vec<Transition>) -> Behavior {
+            Behavior {
+                name: String::from(name),
+                animation: String::from("idle"),
+                action: Action::Stand,
+                weight: None,
+                transitions,
+                next: vec![],
+                script: None,
+                condition: None,
+            }
+        }
+
+        #[test]
+        fn first_firing_transition_wins() {
+            let animations = HashMap::from([(String::from("idle"), super::animation(0))]);
+            let to = |to: &str, when, after| Transition {
+                to: String::from(to),
+                when,
+                after,
+                apps: vec![],
+            };
+            let table = BehaviorTable::new(
+                vec![
+                    behavior(
+                        "sit",
+                        vec![
+                            to("nap", Condition::Always, Some(Duration::from_secs(60))),
+                            to("fall", Condition::Airborne, None),
+                            to("dangle", Condition::Held, None),
+                        ],
+                    ),
+                    behavior("nap", vec![]),
+                    behavior("fall", vec![]),
+                    behavior("dangle", vec![]),
+                ],
+                String::from("sit"),
+                &animations,
+            )
+            .unwrap();
+            let state = BehaviorState::new(&table);
+            let world = WorldSnapshot::default();
+            let mut rng = Rng::with_seed(1);
+
+            let calm = Situation::default();
+            assert!(state.next(&table, calm, &world, &mut rng).is_none());
+            let chaos = Situation {
+                held: true,
+                airborne: true,
+                animation_finished: false,
+                ..Default::default()
+            };
+            let next = state.next(&table, chaos, &world, &mut rng).unwrap();
+            assert_eq!(next.name, "fall");
+
+            assert!(BehaviorTable::new(
+                vec![behavior(
+                    "sit",
+                    vec![to("nowhere", Condition::Always, None)]
+                )],
+                String::from("sit"),
+                &animations,
+            )
+            .is_err());
+        }
+
+        #[test]
+        fn focused_apps_fire_transitions() {
+            let animations = HashMap::from([(String::from("idle"), super::animation(0))]);
+            let to = |to: &str, when| Transition {
+                to: String::from(to),
+                when,
+                after: None,
+                apps: vec![String::from("spotify"), String::from("rhythmbox")],
+            };
+            let table = BehaviorTable::new(
+                vec![
+                    behavior("sit", vec![to("dance", Condition::Focused)]),
+                    behavior("dance", vec![to("sit", Condition::Unfocused)]),
+                ],
+                String::from("sit"),
+                &animations,
+            )
+            .unwrap();
+            let mut state = BehaviorState::new(&table);
+            let mut rng = Rng::with_seed(1);
+            let in_app = |app: &str, title: &str| WorldSnapshot {
+                focused: Some(FocusedWindow {
+                    app: String::from(app),
+                    title: String::from(title),
+                }),
+                ..Default::default()
+            };
+
+            let calm = Situation::default();
+            let editing = in_app("code", "main.rs");
+            assert!(state.next(&table, calm, &editing, &mut rng).is_none());
+            let listening = in_app("Spotify", "Spotify Premium");
+            let next = state.next(&table, calm, &listening, &mut rng).unwrap();
+            assert_eq!(next.name, "dance");
+            state.enter(next);
+            assert!(state.next(&table, calm, &listening, &mut rng).is_none());
+            let next = state.next(&table, calm, &WorldSnapshot::default(), &mut rng);
+            assert_eq!(next.map(|next| next.name.as_str()), Some("sit"));
+        }
+
+        #[test]
+        fn finished_behaviors_pick_what_follows_by_weight() {
+            let animations = HashMap::from([(String::from("idle"), super::animation(0))]);
+            let next = |to: &str, weight| NextBehavior {
+                to: String::from(to),
+                weight: Formula::Number(weight),
+            };
+            let mut idle = behavior("idle", vec![]);
+            idle.next = vec![next("walk", 60.0), next("sit", 30.0), next("sleep", 10.0)];
+            // never picked from idle, even though it's the heaviest of all
+            let mut dance = behavior("dance", vec![]);
+            dance.weight = Some(Formula::Number(1000.0));
+            let behaviors = vec![
+                idle,
+                dance,
+                behavior("walk", vec![]),
+                behavior("sit", vec![]),
+                behavior("sleep", vec![]),
+            ];
+            let table = BehaviorTable::new(behaviors, String::from("idle"), &animations).unwrap();
+            let state = BehaviorState::new(&table);
+            let (world, mut rng) = (WorldSnapshot::default(), Rng::with_seed(3));
+            let finished = Situation {
+                animation_finished: true,
+                ..Default::default()
+            };
+            let mut picks = HashMap::new();
+            for _ in 0..1000 {
+                let next = state.next(&table, finished, &world, &mut rng).unwrap();
+                *picks.entry(next.name.as_str()).or_insert(0) += 1;
+            }
+            assert_eq!(picks.get("dance"), None);
+            assert!((550..650).contains(&picks["walk"]), "{picks:?}");
+            assert!((250..350).contains(&picks["sit"]), "{picks:?}");
+            assert!((60..140).contains(&picks["sleep"]), "{picks:?}");
+        }
+
+        #[test]
+        fn partying_boosts_running_jumping_and_multiplying() {
+            let animations = HashMap::from([(String::from("idle"), super::animation(0))]);
+            let weighted = |name: &str, action, weight| Behavior {
+                action,
+                weight: Some(Formula::Number(weight)),
+                ..behavior(name, vec![])
+            };
+            let behaviors = vec![
+                weighted("sit", Action::Stand, 70.0),
+                weighted("run", Action::Walk(300.0), 10.0),
+                weighted("hop", Action::Jump, 10.0),
+                weighted("split", Action::Multiply, 10.0),
+            ];
+            let table = BehaviorTable::new(behaviors, String::from("sit"), &animations).unwrap();
+            let mut state = BehaviorState::new(&table);
+            let (world, mut rng) = (WorldSnapshot::default(), Rng::with_seed(5));
+            let finished = Situation {
+                animation_finished: true,
+                ..Default::default()
+            };
+            let mut sits = |state: &BehaviorState| {
+                (0..1000)
+                    .filter(|_| {
+                        state.next(&table, finished, &world, &mut rng).unwrap().name == "sit"
+                    })
+                    .count()
+            };
+            assert!((650..750).contains(&sits(&state)));
+            state.start_partying();
+            // 70 against 3 * 10 * PARTY_BOOST
+            assert!((270..400).contains(&sits(&state)));
+        }
+
+        #[test]
+        fn the_likeliest_behaviors_and_their_animations_come_first() {
+            use super::super::formula::Formula;
+
+            let animation = |next: Option<&str>| super::super::loader::AnimationData {
+                next: next.map(String::from),
+                ..super::animation(0)
+            };
+            let animations = HashMap::from(
+                [
+                    ("idle", Some("yawn")),
+                    ("yawn", None),
+                    ("walk", None),
+                    ("sit", None),
+                    ("dance", None),
+                    ("sleep", None),
+                ]
+                .map(|(name, next)| (String::from(name), animation(next))),
+            );
+            let playing = |name: &str, weight: Option<f64>, transitions| Behavior {
+                animation: String::from(name),
+                weight: weight.map(Formula::Number),
+                ..behavior(name, transitions)
+            };
+            let to = |to: &str, when| Transition {
+                to: String::from(to),
+                when,
+                after: None,
+                apps: vec![],
+            };
+            let next = |to: &str, weight| NextBehavior {
+                to: String::from(to),
+                weight: Formula::Number(weight),
+            };
+            let mut idle = playing("idle", None, vec![to("sit", Condition::Petted)]);
+            idle.next = vec![next("sleep", 10.0), next("walk", 60.0)];
+            let behaviors = vec![
+                playing("sleep", Some(5.0), vec![]),
+                playing("dance", Some(100.0), vec![]),
+                playing("sit", None, vec![]),
+                playing("walk", Some(1.0), vec![]),
+                idle,
+            ];
+            let table = BehaviorTable::new(behaviors, String::from("idle"), &animations).unwrap();
+            let order: Vec<_> = table
+                .likeliest_first(&WorldSnapshot::default(), &mut Rng::with_seed(1))
+                .into_iter()
+                .map(|behavior| behavior.name.as_str())
+                .collect();
+            // where idle's transitions go, what may follow it, then the heaviest of the rest
+            assert_eq!(order, ["idle", "sit", "walk", "sleep", "dance"]);
+            assert_eq!(
+                super::super::loader::likeliest_animations(&table, &animations),
+                ["idle", "yawn", "sit", "walk", "sleep", "dance"]
+            );
+        }
+    }
+
+    mod rgba {
+        use super::super::rgba::*;
+
+        #[test]
+        fn formats_put_channels_in_the_right_place() {
+            let color = Rgba::new(0x11, 0x22, 0x33, 0x44);
+            let mut out = [0; 4];
+            color.write_as(PixelFormat::Rgba8, &mut out);
+            assert_eq!(out, [0x11, 0x22, 0x33, 0x44]);
+            color.write_as(PixelFormat::Bgra8, &mut out);
+            assert_eq!(out, [0x33, 0x22, 0x11, 0x44]);
+            color.write_as(PixelFormat::Softbuffer0rgb, &mut out);
+            assert_eq!(u32::from_ne_bytes(out), 0x00112233);
+            assert_eq!(Rgba::new(0xff, 0xff, 0xff, 0).to_softbuf_u32(), 0);
+        }
+
+        #[test]
+        fn texture_formats_map_to_pixel_formats() {
+            use pixels::wgpu::TextureFormat;
+            assert_eq!(
+                PixelFormat::from_texture_format(TextureFormat::Rgba8UnormSrgb),
+                Some(PixelFormat::Rgba8)
+            );
+            assert_eq!(
+                PixelFormat::from_texture_format(TextureFormat::Bgra8Unorm),
+                Some(PixelFormat::Bgra8)
+            );
+            assert_eq!(
+                PixelFormat::from_texture_format(TextureFormat::R8Unorm),
+                None
+            );
+        }
+
+        #[test]
+        fn premultiplying_scales_colors_by_alpha_and_back() {
+            let straight = Rgba::new(200, 100, 50, 128);
+            let premultiplied = straight.premultiplied();
+            assert_eq!(premultiplied, Rgba::new(100, 50, 25, 128));
+            assert_eq!(
+                premultiplied.unpremultiplied(),
+                Rgba::new(199, 100, 50, 128)
+            );
+            assert_eq!(
+                Rgba::new(10, 20, 30, 0).unpremultiplied(),
+                Rgba::new(0, 0, 0, 0)
+            );
+        }
+    }
+
+    mod blit {
+        use super::super::blit::*;
+        use super::super::loader::Frame;
+        use super::super::rgba::{PixelFormat, Rgba};
+
+        /// A 2x2 frame with each pixel's red channel set to its index + 1.
+        fn frame() -> Frame {
+            Frame {
+                width: 2,
+                height: 2,
+                pixels_row_major: (1..=4).map(|red| Rgba::new(red, 0, 0, 255)).collect(),
+            }
+        }
+        fn reds(buffer: &[u8]) -> Vec<u8> {
+            buffer.chunks_exact(4).map(|pixel| pixel[0]).collect()
+        }
+
+        #[test]
+        fn frames_grow_blocky_or_smooth() {
+            let frame = Frame {
+                width: 2,
+                height: 1,
+                pixels_row_major: [0, 200].map(|red| Rgba::new(red, 0, 0, 255)).into(),
+            };
+            let reds = |frame: Frame| -> Vec<u8> {
+                frame
+                    .pixels_row_major
+                    .iter()
+                    .map(|pixel| pixel.red)
+                    .collect()
+            };
+            assert_eq!(
+                reds(frame.upscaled(2.0, Filter::Nearest)),
+                [0, 0, 200, 200].repeat(2)
+            );
+            assert_eq!(
+                reds(frame.upscaled(2.0, Filter::Linear)),
+                [0, 50, 150, 200].repeat(2)
+            );
+        }
+
+        #[test]
+        fn wider_buffer_keeps_rows_aligned() {
+            let mut buffer = vec![0xff; 4 * 4 * 2];
+            let size = BufferSize {
+                width: 4,
+                height: 2,
+            };
+            blit(
+                &frame(),
+                &mut buffer,
+                size,
+                PixelFormat::Rgba8,
+                BlitPolicy::Clip,
+                Facing::Left,
+                Effects::default(),
+            );
+            assert_eq!(reds(&buffer), [1, 2, 0, 0, 3, 4, 0, 0]);
+            blit(
+                &frame(),
+                &mut buffer,
+                size,
+                PixelFormat::Rgba8,
+                BlitPolicy::Center,
+                Facing::Left,
+                Effects::default(),
+            );
+            assert_eq!(reds(&buffer), [0, 1, 2, 0, 0, 3, 4, 0]);
+        }
+
+        #[test]
+        fn fitting_letterboxes_and_filters() {
+            let mut buffer = vec![0; 4 * 4 * 2];
+            let size = BufferSize {
+                width: 4,
+                height: 2,
+            };
+            let effects = Effects {
+                letterbox: Some(Rgba::new(9, 0, 0, 255)),
+                ..Default::default()
+            };
+            let policy = BlitPolicy::Fit(Filter::Nearest);
+            blit(
+                &frame(),
+                &mut buffer,
+                size,
+                PixelFormat::Rgba8,
+                policy,
+                Facing::Left,
+                effects,
+            );
+            assert_eq!(reds(&buffer), [9, 1, 2, 9, 9, 3, 4, 9]);
+            let mut buffer = vec![0; 4 * 16];
+            let size = BufferSize {
+                width: 4,
+                height: 4,
+            };
+            let policy = BlitPolicy::Fit(Filter::Linear);
+            blit(
+                &frame(),
+                &mut buffer,
+                size,
+                PixelFormat::Rgba8,
+                policy,
+                Facing::Left,
+                effects,
+            );
+            let reds = reds(&buffer);
+            assert_eq!((reds[0], reds[15]), (1, 4));
+            // a quarter of the way from the first pixel to the others
+            assert_eq!(reds[5], 2);
+        }
+
+        #[test]
+        fn shadows_sit_under_the_feet() {
+            let empty = Frame {
+                width: 8,
+                height: 8,
+                pixels_row_major: vec![Rgba::new(0, 0, 0, 0); 64].into(),
+            };
+            let size = BufferSize {
+                width: 8,
+                height: 8,
+            };
+            let shadow = Shadow {
+                opacity: 1.0,
+                size: 1.0,
+            };
+            let alphas = |format| {
+                let mut buffer = vec![0; 4 * 64];
+                blit(
+                    &empty,
+                    &mut buffer,
+                    size,
+                    format,
+                    BlitPolicy::Clip,
+                    Facing::Left,
+                    Effects {
+                        shadow: Some(shadow),
+                        ..Default::default()
+                    },
+                );
+                buffer
+                    .chunks_exact(4)
+                    .map(|pixel| pixel[3])
+                    .collect::<Vec<_>>()
+            };
+            let rgba = alphas(PixelFormat::Rgba8);
+            assert!(rgba[..32].iter().all(|alpha| *alpha == 0));
+            assert!(rgba[7 * 8 + 3] > 0);
+            // without an alpha channel it would be a black blob
+            assert!(alphas(PixelFormat::Softbuffer0rgb)
+                .iter()
+                .all(|alpha| *alpha == 0));
+            assert!(shadow.lifted(16.0, 16.0).is_none());
+        }
+
+        #[test]
+        fn smaller_buffer_is_clipped_or_scaled() {
+            let mut buffer = vec![0; 4];
+            let size = BufferSize {
+                width: 1,
+                height: 1,
+            };
+            blit(
+                &frame(),
+                &mut buffer,
+                size,
+                PixelFormat::Rgba8,
+                BlitPolicy::Clip,
+                Facing::Left,
+                Effects::default(),
+            );
+            assert_eq!(reds(&buffer), [1]);
+            let mut buffer = vec![0; 4 * 16];
+            let size = BufferSize {
+                width: 4,
+                height: 4,
+            };
+            blit(
+                &frame(),
+                &mut buffer,
+                size,
+                PixelFormat::Rgba8,
+                BlitPolicy::Scale,
+                Facing::Left,
+                Effects::default(),
+            );
+            assert_eq!(
+                reds(&buffer),
+                [1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]
+            );
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod macos {
+        use winit::window::WindowLevel;
+
+        #[test]
+        fn shimeji_windows_float_without_a_frame() {
+            let attributes = &*super::super::WINDOW_ATTRIBS;
+            assert!(attributes.transparent && !attributes.decorations);
+            assert_eq!(attributes.window_level, WindowLevel::AlwaysOnTop);
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    mod tool_window {
+        use super::super::tool_window::tool_style;
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            WS_EX_LAYERED, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW, WS_EX_TOPMOST,
+        };
+
+        #[test]
+        fn shimeji_windows_stay_out_of_alt_tab() {
+            let style = tool_style(WS_EX_TOPMOST as isize);
+            for kept in [WS_EX_TOPMOST, WS_EX_TOOLWINDOW, WS_EX_NOACTIVATE] {
+                assert_ne!(style & kept as isize, 0);
+            }
+            // DXGI swap chains can't present to layered windows
+            assert_eq!(style & WS_EX_LAYERED as isize, 0);
+            let attributes = &*super::super::WINDOW_ATTRIBS;
+            assert!(attributes.transparent && !attributes.decorations && !attributes.active);
+        }
+    }
+
+    mod archive {
+        use super::super::archive::*;
+        use std::{io::Write as _, path::Path};
+        use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+        fn zipped(files: &[(&str, &[u8])]) -> Vec<u8> {
+            let mut writer = ZipWriter::new(std::io::Cursor::new(vec![]));
+            let options =
+                SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+            for (name, contents) in files {
+                writer.start_file(*name, options).unwrap();
+                writer.write_all(contents).unwrap();
+            }
+            writer.finish().unwrap().into_inner()
+        }
+
+        #[test]
+        fn archives_are_found_whatever_the_case_of_their_extension() {
+            assert!(is_archive(Path::new("pack.zip")));
+            assert!(is_archive(Path::new("Pack.SHIMEJI")));
+            assert!(is_archive(Path::new("PACK.Zip")));
+            assert!(!is_archive(Path::new("pack.xml")));
+        }
+
+        #[test]
+        fn entries_are_unpacked_within_their_caps() {
+            let archive = zipped(&[("pack.xml", b"<Shimeji/>"), ("img/a.png", &[0; 100])]);
+            let unpacked = entries(&archive).unwrap();
+            assert_eq!(unpacked.len(), 2);
+            assert_eq!(unpacked[0].path, Path::new("pack.xml"));
+            assert_eq!(unpacked[1].contents, [0; 100]);
+
+            // each file, then all of them together
+            assert!(entries_capped(&archive, 99, 1000).is_err());
+            assert!(entries_capped(&archive, 100, 109).is_err());
+            assert!(entries_capped(&archive, 100, 110).is_ok());
+
+            let escaping = zipped(&[("../outside.xml", b"")]);
+            assert!(entries(&escaping).is_err());
+            assert!(entries(b"not a zip").is_err());
+        }
+    }
+
+    mod download {
+        use super::super::download::*;
+
+        #[test]
+        fn pack_urls_come_with_a_checksum_to_check() {
+            assert_eq!(
+                sha256(b"abc")[..4],
+                [0xba, 0x78, 0x16, 0xbf],
+                "the FIPS 180-4 example"
+            );
+            let checksum = "sha256:".to_owned() + &"ab".repeat(32);
+            let args = ["--pack-url", "https://example.com/a.zip"].map(String::from);
+            let packs = from_args(
+                args.into_iter()
+                    .chain([format!("--pack-url=https://example.com/b?c=d {checksum}")]),
+            )
+            .unwrap();
+            assert_eq!(packs[0].sha256, None);
+            assert_eq!(packs[1].sha256, Some([0xab; 32]));
+            assert_eq!(
+                packs[1].to_string(),
+                format!("https://example.com/b?c=d {checksum}")
+            );
+            assert!("http://example.com/a.zip".parse::<PackUrl>().is_err());
+        }
+
+        #[test]
+        fn packs_without_a_checksum_are_refused_unless_allowed() {
+            let pack: PackUrl = "https://example.invalid/a.zip".parse().unwrap();
+            let refused = fetch(&pack, false).unwrap_err();
+            assert!(format!("{refused:#}").contains(ALLOW_UNPINNED));
+        }
+    }
+
+    mod picker {
+        use winit::dpi::PhysicalPosition;
+
+        use super::super::picker::*;
+
+        #[test]
+        fn clicks_land_on_the_tile_under_them() {
+            assert_eq!(columns(5), 3);
+            let at = |x, y| tile_at(PhysicalPosition::new(x, y), 3, 5);
+            assert_eq!(at(10.0, 10.0), Some(0));
+            assert_eq!(at(130.0, 10.0), Some(1));
+            assert_eq!(at(10.0, 130.0), Some(3));
+            assert_eq!(at(300.0, 130.0), None, "there is no sixth tile");
+            assert_eq!(at(400.0, 10.0), None, "past the last column");
+            assert_eq!(at(-1.0, 10.0), None);
+        }
+    }
+
+    mod preview {
+        use std::time::Duration;
+
+        use super::super::{
+            loader::{PackLibrary, ShimejiLibrary},
+            preview::*,
+        };
+
+        #[test]
+        fn previews_find_their_animation_and_its_frames() {
+            let library = ShimejiLibrary::load("./default.xml", &PackLibrary::new(".")).unwrap();
+            let (data, animation) = find(&library, "idle").unwrap();
+            assert_eq!(&*data.name, "default");
+            assert_eq!(find(&library, "default/idle").unwrap().1, animation);
+            assert!(find(&library, "default/nonexistent").is_err());
+            assert!(find(&library, "nobody/idle").is_err());
+
+            // three frames at 2 fps
+            let idle = &data.animations[&animation];
+            assert_eq!(frame_at(idle, Duration::ZERO), 0);
+            assert_eq!(frame_at(idle, Duration::from_millis(600)), 1);
+            assert_eq!(frame_at(idle, Duration::from_secs(10)), 2);
+            assert_eq!(
+                from_args(["--preview=default/idle".to_owned()].into_iter()).as_deref(),
+                Some("default/idle")
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod tray {
+        use std::sync::Arc;
+
+        use winit::window::WindowId;
+
+        use super::super::tray::*;
+
+        #[test]
+        fn shimejis_are_numbered_among_their_own_pack() {
+            let shimejis = [(1, "Shimeji"), (2, "Neko"), (3, "Shimeji")]
+                .map(|(id, name)| (WindowId::from(id), Arc::from(name)));
+            let menu = TrayMenu::new(vec![Arc::from("Shimeji")], shimejis);
+            let labels: Vec<_> = menu.shimejis.iter().map(|(_, label)| &**label).collect();
+            assert_eq!(labels, ["Shimeji 1", "Neko 1", "Shimeji 2"]);
+        }
+
+        #[test]
+        #[cfg(target_os = "linux")]
+        fn the_built_in_icon_decodes() {
+            let (width, height, pixels) = icon().unwrap();
+            assert!(width > 0 && height > 0);
+            assert_eq!(pixels.len(), width as usize * height as usize);
+        }
+    }
+
+    mod variant {
+        use std::sync::Arc;
+
+        use super::super::{loader::Frame, rgba::Rgba, variant::*};
+
+        #[test]
+        fn variants_recolor_frames_once_per_color() {
+            let turned: Variant = "hue:120".parse().unwrap();
+            assert_eq!(
+                turned.apply(Rgba::new(255, 0, 0, 40)),
+                Rgba::new(0, 255, 0, 40)
+            );
+            assert_eq!(
+                turned.apply(Rgba::new(90, 90, 90, 255)),
+                Rgba::new(90, 90, 90, 255)
+            );
+            let tinted: Variant = "hue:240 tint:#808080".parse().unwrap();
+            assert_eq!(
+                tinted.apply(Rgba::new(255, 0, 0, 255)),
+                Rgba::new(0, 0, 128, 255)
+            );
+            assert_eq!(tinted.to_string().parse::<Variant>().unwrap(), tinted);
+            assert!("hue:360".parse::<Variant>().unwrap().is_plain());
+            assert!("shade:#000000".parse::<Variant>().is_err());
+
+            let cache = VariantCache::default();
+            let frames = [Frame {
+                width: 1,
+                height: 1,
+                pixels_row_major: [Rgba::new(255, 0, 0, 255)].into(),
+            }];
+            let first = cache.get_or_recolor(&turned, "idle", &frames);
+            let second = cache.get_or_recolor(&turned, "idle", &frames);
+            assert!(Arc::ptr_eq(&first, &second));
+            assert_eq!(first[0].pixels_row_major[0], Rgba::new(0, 255, 0, 255));
+        }
+    }
+
+    mod headless {
+        use std::time::Duration;
+
+        use winit::dpi::PhysicalSize;
+
+        use super::super::{
+            headless::*,
+            loader::{PackLibrary, ShimejiLibrary},
+        };
+
+        #[test]
+        fn headless_runs_simulate_every_tick_without_windows() {
+            let library = ShimejiLibrary::load("./default.xml", &PackLibrary::new(".")).unwrap();
+            let config = HeadlessConfig {
+                duration: Duration::from_secs(5),
+                population: 4,
+                monitor: PhysicalSize::new(800, 600),
+            };
+            let report = run(&config, &library).unwrap();
+            assert_eq!(report.population, 4);
+            assert_eq!(report.ticks, 300);
+            assert!(report.simulated >= config.duration);
+            // every shimeji draws its first frame at least
+            assert!(report.frames_drawn >= 4);
+            assert!(report.to_string().contains("ticks per second"));
+        }
+    }
+
+    mod hotkeys {
+        use super::super::hotkeys::*;
+        use super::super::ManagerEvent;
+
+        #[test]
+        fn hotkeys_parse_in_any_order_and_case() {
+            let hotkey: Hotkey = "Alt + CTRL + s".parse().unwrap();
+            assert!(hotkey.modifiers.ctrl && hotkey.modifiers.alt && !hotkey.modifiers.shift);
+            assert_eq!(hotkey.key, Key::Char('s'));
+            assert_eq!(hotkey.to_string(), "ctrl+alt+s");
+            assert_eq!(
+                "super+f12".parse::<Hotkey>().unwrap().key,
+                Key::Function(12)
+            );
+            assert!("ctrl+alt".parse::<Hotkey>().is_err());
+            assert!("ctrl+f13".parse::<Hotkey>().is_err());
+
+            let spawn: HotkeyAction = "spawn Gon".parse().unwrap();
+            assert_eq!(spawn, HotkeyAction::Spawn("Gon".into()));
+            assert_eq!(spawn.to_string().parse::<HotkeyAction>().unwrap(), spawn);
+            assert_eq!(
+                "pause".parse::<HotkeyAction>().unwrap(),
+                HotkeyAction::TogglePause
+            );
+            let party: HotkeyAction = "party".parse().unwrap();
+            assert_eq!(party.to_string(), "party");
+            assert!(matches!(party.event(), ManagerEvent::StartParty));
+            assert!("spawn".parse::<HotkeyAction>().is_err());
+        }
+    }
+
+    mod window_shape {
+        use super::super::blit::Facing;
+        use super::super::loader::Frame;
+        use super::super::rgba::Rgba;
+        use super::super::window_shape::*;
+        use super::super::xml_parser::FrameRegion;
+
+        #[test]
+        fn windows_keep_only_their_opaque_rows() {
+            // an L: the left column, then the whole bottom row
+            let frame = Frame {
+                width: 2,
+                height: 3,
+                pixels_row_major: [255, 0, 255, 0, 255, 255]
+                    .map(|alpha| Rgba::new(0, 0, 0, alpha))
+                    .into(),
+            };
+            let rect = |x, y, width, height| Rect {
+                x,
+                y,
+                width,
+                height,
+            };
+            assert_eq!(
+                opaque_rects(&frame, Facing::Left, 4, 6),
+                [rect(0, 0, 2, 4), rect(0, 4, 4, 2)]
+            );
+            assert_eq!(
+                opaque_rects(&frame, Facing::Right, 2, 3),
+                [rect(1, 0, 1, 2), rect(0, 2, 2, 1)]
+            );
+        }
+
+        #[test]
+        fn hitboxes_take_clicks_instead_of_opaque_pixels() {
+            let see_through = Frame {
+                width: 4,
+                height: 4,
+                pixels_row_major: [Rgba::new(0, 0, 0, 0); 16].into(),
+            };
+            let rect = |x, y, width, height| Rect {
+                x,
+                y,
+                width,
+                height,
+            };
+            assert!(hit_rects(&see_through, &[], Facing::Left, 8, 8).is_empty());
+            // the left half, stretched over a window twice as big
+            let hitboxes = [FrameRegion {
+                x: 0,
+                y: 0,
+                width: 2,
+                height: 4,
+            }];
+            assert_eq!(
+                hit_rects(&see_through, &hitboxes, Facing::Left, 8, 8),
+                [rect(0, 0, 4, 8)]
+            );
+            assert_eq!(
+                hit_rects(&see_through, &hitboxes, Facing::Right, 8, 8),
+                [rect(4, 0, 4, 8)]
+            );
+            assert!(rect(4, 0, 4, 8).contains(7, 7));
+            assert!(!rect(4, 0, 4, 8).contains(8, 7));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod compositor {
+        use super::super::compositor::*;
+
+        #[test]
+        fn shadows_are_turned_off_for_picom_and_kwin() {
+            let atoms = EffectAtoms {
+                compton_shadow: 1,
+                kde_shadow: 2,
+                kde_blur: 3,
+            };
+            assert_eq!(
+                atoms.edits(),
+                [
+                    PropertyEdit::Set(1, 0),
+                    PropertyEdit::Delete(2),
+                    PropertyEdit::Delete(3)
+                ]
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod backend {
+        use super::super::backend::*;
+
+        #[test]
+        fn backend_is_picked_from_flag_or_environment() {
+            let args = |args: &[&str]| {
+                args.iter()
+                    .map(|arg| String::from(*arg))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                Backend::from_args(args(&["--backend", "wayland"]).into_iter()).unwrap(),
+                Some(Backend::Wayland)
+            );
+            assert_eq!(
+                Backend::from_args(args(&["--backend=X11"]).into_iter()).unwrap(),
+                Some(Backend::X11)
+            );
+            assert!(Backend::from_args(args(&["--backend", "mir"]).into_iter()).is_err());
+            assert_eq!(Backend::from_args(args(&[]).into_iter()).unwrap(), None);
+
+            assert_eq!(
+                Backend::detect_from(None, Some("wayland-0".into())),
+                Backend::Wayland
+            );
+            assert_eq!(
+                Backend::detect_from(Some(":0".into()), Some("wayland-0".into())),
+                Backend::X11
+            );
+        }
+
+        #[test]
+        fn backends_without_a_display_are_reported() {
+            assert!(Backend::X11
+                .ensure_available_from(Some(":0".into()))
+                .is_ok());
+            let error = Backend::Wayland.ensure_available_from(None).unwrap_err();
+            assert!(error.to_string().contains("WAYLAND_DISPLAY"), "{error}");
+            assert!(Backend::X11.ensure_available_from(Some("".into())).is_err());
+        }
+
+        #[test]
+        fn wayland_reports_what_it_cannot_do() {
+            use super::super::capabilities::{for_backend, Support};
+
+            let capabilities = for_backend(Backend::Wayland);
+            assert_eq!(capabilities.transparency, Support::Yes);
+            assert!(!capabilities.window_enumeration.is_supported());
+            assert!(!capabilities.layer_shell.is_supported());
+            let report = capabilities.to_string();
+            assert!(report.contains("transparency: yes\n"));
+            assert!(report.contains("other-window enumeration: no, Wayland"));
+        }
+    }
+
+    mod soak {
+        use super::super::soak::*;
+        use std::time::Duration;
+
+        #[test]
+        fn soak_keeps_the_population_near_its_target() {
+            let args = |args: &[&str]| {
+                args.iter()
+                    .map(|arg| String::from(*arg))
+                    .collect::<Vec<_>>()
+            };
+            let config = SoakConfig::from_args(args(&["--soak", "1.5"]).into_iter())
+                .unwrap()
+                .unwrap();
+            assert_eq!(config.duration, Duration::from_secs(90));
+            assert!(SoakConfig::from_args(args(&["--soak=long"]).into_iter()).is_err());
+            assert!(SoakConfig::from_args(args(&[]).into_iter())
+                .unwrap()
+                .is_none());
+
+            let mut run = SoakRun::new(SoakConfig {
+                population: 10,
+                ..config
+            });
+            assert_eq!(run.next_op(2), SoakOp::Spawn);
+            assert_eq!(run.next_op(20), SoakOp::Remove);
+            assert!(run.result().is_ok());
+            run.violation(String::from("a window was left behind"));
+            assert!(run.result().is_err());
+            assert!(run.to_string().contains("a window was left behind"));
+        }
+    }
+
+    mod recording {
+        use std::time::Duration;
+
+        use winit::dpi::PhysicalPosition;
+
+        use super::super::recording::*;
+
+        #[test]
+        fn recordings_read_back_what_they_wrote() {
+            let lines = [
+                "1.250 3 spawn 12345 100,900 Shimeji Deluxe",
+                "4.000 3 enter -40,900 Walk Along",
+                "9.500 3 despawn",
+            ];
+            for line in lines {
+                let entry: Entry = line.parse().unwrap();
+                assert_eq!(entry.to_string(), line);
+            }
+            let spawn: Entry = lines[0].parse().unwrap();
+            assert_eq!(spawn.at, Duration::from_millis(1250));
+            assert_eq!(
+                spawn.what,
+                What::Spawn {
+                    seed: 12345,
+                    position: PhysicalPosition::new(100, 900),
+                    pack: "Shimeji Deluxe".into(),
+                }
+            );
+            assert!("1.0 3 enter Walk".parse::<Entry>().is_err());
+            assert!("1.0 3 despawn now".parse::<Entry>().is_err());
+            assert!("soon 3 despawn".parse::<Entry>().is_err());
+            assert_eq!(
+                Mode::from_args(["--replay=run.txt".to_owned()].into_iter()),
+                Some(Mode::Replay("run.txt".into()))
+            );
+        }
+    }
+
+    mod log_throttle {
+        use super::super::log_throttle::*;
+        use std::time::Duration;
+
+        #[test]
+        fn held_back_messages_are_counted() {
+            let throttle = Throttle::new();
+            assert_eq!(throttle.check(Duration::from_secs(60)), Some(0));
+            assert_eq!(throttle.check(Duration::from_secs(60)), None);
+            assert_eq!(throttle.check(Duration::from_secs(60)), None);
+            assert_eq!(throttle.check(Duration::ZERO), Some(2));
+        }
+    }
+
+    mod monitors {
+        use super::super::monitors::*;
+        use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+        fn area(x: i32, y: i32, width: u32, height: u32) -> MonitorArea {
+            MonitorArea::new(
+                None,
+                PhysicalPosition::new(x, y),
+                PhysicalSize::new(width, height),
+            )
+        }
+
+        #[test]
+        fn shimejis_roam_onto_the_monitor_next_door() {
+            // a 1080p monitor with a shorter one to its right, and one off on its own
+            let monitors = Monitors::new(vec![
+                area(0, 0, 1920, 1080),
+                area(1920, 0, 1280, 720),
+                area(5000, 0, 800, 600),
+            ]);
+            let size = PhysicalSize::new(100, 100);
+            let left = monitors
+                .under(PhysicalPosition::new(100.0, 980.0), size)
+                .unwrap();
+            assert_eq!(monitors.roaming_walls(left, 100), (0.0, 3100.0));
+            assert_eq!(left.floor(100, 0), 980.0);
+            let right = monitors
+                .under(PhysicalPosition::new(1900.0, 500.0), size)
+                .unwrap();
+            assert_eq!(right.floor(100, 20), 600.0);
+            let alone = monitors
+                .under(PhysicalPosition::new(5000.0, 0.0), size)
+                .unwrap();
+            assert_eq!(monitors.roaming_walls(alone, 100), (5000.0, 5700.0));
+            // hanging below the shorter monitor, it belongs to the one above
+            let below = monitors.under(PhysicalPosition::new(2500.0, 900.0), size);
+            assert_eq!(below, Some(&area(1920, 0, 1280, 720)));
+        }
+
+        #[test]
+        fn shimejis_land_on_top_of_the_taskbar() {
+            // a 40 pixel taskbar along the bottom of the left monitor only
+            let desktop = WorkArea {
+                left: 0,
+                top: 0,
+                right: 1920,
+                bottom: 1040,
+            };
+            let left = area(0, 0, 1920, 1080).clipped_to(&desktop);
+            assert_eq!(left.floor(100, 0), 940.0);
+            assert_eq!(left.ceiling(), 0.0);
+            let right = area(1920, 0, 1280, 720).clipped_to(&desktop);
+            assert_eq!(right.floor(100, 0), 620.0);
+        }
+
+        #[test]
+        fn shimejis_hide_only_on_the_fullscreen_monitor() {
+            use super::super::world::WorldSnapshot;
+
+            let (left, right) = (area(0, 0, 1920, 1080), area(1920, 0, 1280, 720));
+            let video = WorkArea {
+                left: 1920,
+                top: 0,
+                right: 3200,
+                bottom: 720,
+            };
+            let mut world = WorldSnapshot {
+                monitors: Monitors::new(vec![left.clone(), right.clone()]),
+                ..Default::default()
+            };
+            assert!(!world.fullscreen_on(&right));
+            world.fullscreen = vec![video];
+            assert!(world.fullscreen_on(&right));
+            assert!(!world.fullscreen_on(&left));
+            assert!(
+                !super::super::settings::Settings::parse("hide_in_fullscreen = false")
+                    .unwrap()
+                    .hide_in_fullscreen
+            );
+        }
+    }
+
+    mod neighbors {
+        use super::super::neighbors::*;
+        use winit::{
+            dpi::{PhysicalPosition, PhysicalSize},
+            window::WindowId,
+        };
+
+        #[test]
+        fn shimejis_see_each_other_across_buckets() {
+            let at = |id, x| Neighbor {
+                id: WindowId::from(id),
+                position: PhysicalPosition::new(x, 0.0),
+                size: PhysicalSize::new(32, 32),
+            };
+            let neighborhood = Neighborhood::default();
+            neighborhood.publish(0, vec![at(1, 0.0), at(2, 500.0)]);
+            neighborhood.publish(1, vec![at(3, 60.0)]);
+            let everyone = neighborhood.everyone();
+            assert_eq!(everyone.len(), 3);
+            let (nearest, distance) = at(1, 0.0).nearest(&everyone).unwrap();
+            assert_eq!((nearest.id, distance), (WindowId::from(3), 60.0));
+            // a bucket's shimejis are replaced, not added to
+            neighborhood.publish(1, vec![]);
+            let everyone = neighborhood.everyone();
+            let (nearest, _) = at(1, 0.0).nearest(&everyone).unwrap();
+            assert_eq!(nearest.id, WindowId::from(2));
+            neighborhood.leave(0);
+            assert!(neighborhood.everyone().is_empty());
+        }
+
+        #[test]
+        fn approach_prop_walks_into_the_nearest_prop() {
+            use super::super::behavior::Action;
+            use super::super::movement::MovementCommand;
+            use super::super::shimeji::walk_toward;
+
+            let at = |id, x| Neighbor {
+                id: WindowId::from(id),
+                position: PhysicalPosition::new(x, 0.0),
+                size: PhysicalSize::new(32, 32),
+            };
+            assert_eq!(
+                Action::parse("approach-prop", 60.0),
+                Some(Action::ApproachProp(60.0))
+            );
+            let me = at(1, 100.0);
+            assert_eq!(
+                walk_toward(&me, Some(&at(2, 300.0)), -60.0),
+                MovementCommand::Walk(60.0)
+            );
+            assert_eq!(
+                walk_toward(&me, Some(&at(2, 0.0)), 60.0),
+                MovementCommand::Walk(-60.0)
+            );
+            // right on top of it, or with nothing to go after
+            assert_eq!(
+                walk_toward(&me, Some(&at(2, 100.0)), 60.0),
+                MovementCommand::Stop
+            );
+            assert_eq!(walk_toward(&me, None, 60.0), MovementCommand::Stop);
+        }
+    }
+
+    mod window_surfaces {
+        use super::super::shimeji::floor_among;
+        use super::super::window_surfaces::WindowSurface;
+        use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+        #[test]
+        fn shimejis_land_on_windows_under_them() {
+            let surface = |left, right, top| WindowSurface { left, right, top };
+            let size = PhysicalSize::new(32, 32);
+            let falling = PhysicalPosition::new(100.0, 0.0);
+            let monitor = Some(968.0);
+            let desk = surface(0, 200, 300);
+            assert_eq!(floor_among([desk], falling, size, monitor), Some(268.0));
+            // off to the side, so it falls past
+            let aside = surface(500, 600, 300);
+            assert_eq!(floor_among([aside], falling, size, monitor), monitor);
+            assert_eq!(floor_among([], falling, size, None), None);
+            // the highest one below it wins
+            let shelf = surface(50, 150, 200);
+            assert_eq!(
+                floor_among([desk, shelf], falling, size, monitor),
+                Some(168.0)
+            );
+            // already past it, unless it only slid up a little
+            let standing = PhysicalPosition::new(100.0, 300.0);
+            assert_eq!(
+                floor_among([surface(0, 200, 320)], standing, size, monitor),
+                monitor
+            );
+            assert_eq!(
+                floor_among([surface(0, 200, 326)], standing, size, monitor),
+                Some(294.0)
+            );
+        }
+    }
+
+    mod shimeji {
+        use super::super::shimeji::*;
+
+        #[test]
+        fn paused_shimejis_only_move_a_step_at_a_time() {
+            let mut stepper = Stepper::default();
+            assert!(stepper.take_frame() && stepper.take_tick());
+            // nothing to step through until it's paused
+            assert!(!stepper.apply(StepCommand::Frame));
+            assert!(stepper.apply(StepCommand::TogglePause));
+            assert!(stepper.is_paused());
+            assert!(!stepper.take_frame() && !stepper.take_tick());
+            stepper.apply(StepCommand::Frame);
+            stepper.apply(StepCommand::Frame);
+            stepper.apply(StepCommand::Tick);
+            assert!(stepper.take_frame() && stepper.take_frame() && !stepper.take_frame());
+            assert!(stepper.take_tick() && !stepper.take_tick());
+            // steps left over are forgotten when it carries on
+            stepper.apply(StepCommand::Tick);
+            stepper.apply(StepCommand::TogglePause);
+            assert_eq!(stepper, Stepper::default());
+        }
+
+        #[test]
+        fn shimejis_look_as_big_on_every_monitor() {
+            use super::super::loader::{PackLibrary, ShimejiLibrary};
+            use winit::dpi::PhysicalSize;
+
+            let library = ShimejiLibrary::load("./default.xml", &PackLibrary::new(".")).unwrap();
+            let data = library.get(&library.names()[0]).unwrap();
+            let (width, height) = (data.width, data.height);
+            assert_eq!(data.size_at(1.0), PhysicalSize::new(width, height));
+            assert_eq!(data.size_at(2.0), PhysicalSize::new(width * 2, height * 2));
+            let size = data.size_at(1.25);
+            assert_eq!(size.width, (width as f64 * 1.25).round() as u32);
+        }
+    }
+
+    mod renderer {
+        use super::super::blit::BufferSize;
+        use super::super::renderer::*;
+        use super::super::rgba::Rgba;
+
+        fn args(args: &[&str]) -> impl Iterator<Item = String> {
+            args.iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>()
+                .into_iter()
+        }
+
+        #[test]
+        fn renderers_are_picked_by_name() {
+            assert_eq!(
+                RendererKind::from_args(args(&["--renderer", "software"])).unwrap(),
+                Some(RendererKind::Software)
+            );
+            assert_eq!(
+                RendererKind::from_args(args(&["--renderer=pixels"])).unwrap(),
+                Some(RendererKind::Pixels)
+            );
+            assert!(RendererKind::from_args(args(&["--renderer=vulkan"])).is_err());
+        }
+
+        #[test]
+        fn software_images_are_premultiplied_and_centered() {
+            let size = |width, height| BufferSize { width, height };
+            // one half see-through pixel, in the middle of a 3x1 window
+            let image = premultiplied_image(&[200, 100, 50, 128], size(1, 1), size(3, 1), None);
+            assert_eq!(image, [0, 0, 0, 0, 100, 50, 25, 128, 0, 0, 0, 0]);
+            let letterbox = Rgba::new(0, 0, 255, 255);
+            let image = premultiplied_image(&[], size(0, 0), size(1, 1), Some(letterbox));
+            assert_eq!(image, [255, 0, 0, 255]);
+        }
+
+        #[test]
+        fn lost_surfaces_are_tried_less_often_until_they_come_back() {
+            let now = std::time::Instant::now();
+            let mut backoff = Backoff::default();
+            assert!(backoff.ready(now));
+            let first = backoff.failed(now);
+            assert!(!backoff.ready(now));
+            assert!(backoff.ready(now + first));
+            assert_eq!(backoff.failed(now), first * 2);
+            let longest = (0..32).map(|_| backoff.failed(now)).last().unwrap();
+            assert_eq!(longest, std::time::Duration::from_secs(5));
+            backoff.succeeded();
+            assert!(backoff.ready(now));
+            assert_eq!(backoff.failed(now), first);
+        }
+    }
+
+    mod overlay {
+        use super::super::blit::BufferSize;
+        use super::super::overlay::lay_over;
+        use super::super::rgba::PixelFormat;
+        use winit::dpi::PhysicalPosition;
+
+        #[test]
+        fn sprites_are_laid_over_the_overlay_and_cut_off_at_its_edges() {
+            let size = BufferSize {
+                width: 3,
+                height: 2,
+            };
+            let mut overlay = vec![0; 3 * 2 * 4];
+            // an opaque pixel, then a see-through one
+            let sprite = [[9, 9, 9, 255], [5, 5, 5, 0]].concat();
+            let sprite_size = BufferSize {
+                width: 2,
+                height: 1,
+            };
+            let at = |x, y| PhysicalPosition::new(x, y);
+            lay_over(
+                &mut overlay,
+                size,
+                PixelFormat::Rgba8,
+                &sprite,
+                at(2, 1),
+                sprite_size,
+            );
+            lay_over(
+                &mut overlay,
+                size,
+                PixelFormat::Rgba8,
+                &sprite,
+                at(-1, 0),
+                sprite_size,
+            );
+            let pixel = |x: usize, y: usize| &overlay[(y * 3 + x) * 4..][..4];
+            assert_eq!(pixel(2, 1), [9, 9, 9, 255]);
+            // the opaque half of the second was cut off, and the other half left it untouched
+            assert_eq!(pixel(0, 0), [0, 0, 0, 0]);
+            assert_eq!(overlay.iter().filter(|byte| **byte != 0).count(), 4);
+        }
+    }
+
+    mod bucket {
+        use std::sync::{atomic::AtomicBool, Arc};
+
+        use winit::event::WindowEvent;
+
+        use super::super::{
+            bucket::{ShimejiBucket, ShimejiInputEvent},
+            neighbors::Neighborhood,
+            SHUTDOWN_TIMEOUT,
+        };
+
+        #[test]
+        fn buckets_stop_in_time_when_shutting_down() {
+            let should_exit = Arc::new(AtomicBool::new(false));
+            let mut bucket =
+                ShimejiBucket::new(0, Arc::clone(&should_exit), Neighborhood::default());
+            bucket.init().unwrap();
+            should_exit.store(true, std::sync::atomic::Ordering::Release);
+            assert!(bucket.join_thread_timeout(SHUTDOWN_TIMEOUT));
+            assert!(!bucket.is_running());
+        }
+
+        #[test]
+        fn paused_buckets_still_stop_when_shutting_down() {
+            let should_exit = Arc::new(AtomicBool::new(false));
+            let mut bucket =
+                ShimejiBucket::new(0, Arc::clone(&should_exit), Neighborhood::default());
+            bucket.init().unwrap();
+            bucket.set_paused(true).unwrap();
+            bucket.set_paused(false).unwrap();
+            bucket.set_paused(true).unwrap();
+            should_exit.store(true, std::sync::atomic::Ordering::Release);
+            assert!(bucket.join_thread_timeout(SHUTDOWN_TIMEOUT));
+            assert!(bucket.set_paused(false).is_err());
+        }
+
+        #[test]
+        fn only_events_buckets_react_to_are_forwarded() {
+            assert_eq!(
+                ShimejiInputEvent::from_window_event(&WindowEvent::Occluded(true)),
+                Some(ShimejiInputEvent::Occluded(true))
+            );
+            assert_eq!(
+                ShimejiInputEvent::from_window_event(&WindowEvent::Focused(false)),
+                Some(ShimejiInputEvent::Focused(false))
+            );
+            assert_eq!(
+                ShimejiInputEvent::from_window_event(&WindowEvent::RedrawRequested),
+                None
+            );
+        }
+    }
+
+    mod settings {
+        use super::super::population::SavedPopulation;
+        use super::super::session::{SavedShimeji, Session};
+        use super::super::settings::*;
+        use winit::dpi::PhysicalPosition;
+
+        #[test]
+        fn floor_offsets_survive_a_round_trip() {
+            let settings = Settings::parse(
+                "# calibrated\nfloor_offset.DP-1 = 40\n\nfloor_offset.HDMI-A-1=-3\n",
+            )
+            .unwrap();
+            assert_eq!(settings.floor_offsets["DP-1"], 40);
+            assert_eq!(settings.floor_offsets["HDMI-A-1"], -3);
+            assert_eq!(Settings::parse(&settings.to_string()).unwrap(), settings);
+            assert!(Settings::parse("floor_offset.DP-1 = high").is_err());
+        }
+
+        #[test]
+        fn ambient_tint_can_be_turned_off() {
+            assert!(Settings::default().ambient_tint);
+            let settings = Settings::parse("ambient_tint = false").unwrap();
+            assert!(!settings.ambient_tint);
+            assert_eq!(Settings::parse(&settings.to_string()).unwrap(), settings);
+            assert!(Settings::parse("ambient_tint = sometimes").is_err());
+        }
+
+        #[test]
+        fn max_population_can_be_set() {
+            let settings = Settings::parse("max_population = 3").unwrap();
+            assert_eq!(settings.max_population, 3);
+            assert_eq!(Settings::parse(&settings.to_string()).unwrap(), settings);
+            assert!(Settings::parse("max_population = -1").is_err());
+        }
+
+        #[test]
+        fn frame_rate_is_capped_lower_while_saving_power() {
+            let settings = Settings::parse("max_fps = 20\nlow_power = true").unwrap();
+            assert_eq!(settings.max_fps, Some(20.0));
+            assert!(settings.low_power);
+            assert_eq!(Settings::parse(&settings.to_string()).unwrap(), settings);
+            assert!(Settings::parse("max_fps = 0").is_err());
+
+            let mut world = super::super::world::WorldSnapshot::default();
+            assert_eq!(world.min_frame_interval(), std::time::Duration::ZERO);
+            world.max_fps = settings.max_fps;
+            assert_eq!(world.min_frame_interval().as_millis(), 50);
+            world.saving_power = true;
+            assert_eq!(world.min_frame_interval().as_millis(), 200);
+        }
+
+        #[test]
+        fn notification_reactions_are_off_until_a_behavior_is_set() {
+            use super::super::notifications::Corner;
+
+            let settings = Settings::default();
+            assert_eq!(settings.notification_behavior, None);
+            assert_eq!(settings.notification_corner, Corner::TopRight);
+            let settings =
+                Settings::parse("notification_behavior = Alert\nnotification_corner = Bottom-Left")
+                    .unwrap();
+            assert_eq!(settings.notification_behavior.as_deref(), Some("Alert"));
+            assert_eq!(settings.notification_corner, Corner::BottomLeft);
+            assert!(!settings.notification_corner.is_top());
+            assert!(settings.notification_corner.is_left());
+            assert_eq!(Settings::parse(&settings.to_string()).unwrap(), settings);
+            assert!(Settings::parse("notification_corner = middle").is_err());
+            assert!(Settings::parse("notification_behavior =").is_err());
+        }
+
+        #[test]
+        fn pinned_packs_go_to_the_emptiest_allowed_monitor() {
+            let settings =
+                Settings::parse("monitor_cap.DP-1 = 2\npin.Gon = DP-1, HDMI-A-1").unwrap();
+            assert_eq!(settings.pins["Gon"], ["DP-1", "HDMI-A-1"]);
+            assert_eq!(Settings::parse(&settings.to_string()).unwrap(), settings);
+
+            let crowds = [("DP-1", 1), ("HDMI-A-1", 3), ("eDP-1", 0)];
+            assert_eq!(settings.pick_monitor("Gon", crowds), Some("DP-1"));
+            assert_eq!(settings.pick_monitor("Killua", crowds), Some("eDP-1"));
+            let crowds = [("DP-1", 2), ("HDMI-A-1", 3)];
+            assert_eq!(settings.pick_monitor("Gon", crowds), Some("HDMI-A-1"));
+            assert!(settings.is_overcrowded("DP-1", 3));
+            assert_eq!(settings.pick_monitor("Gon", [("DP-1", 2)]), None);
+        }
+
+        #[test]
+        fn saved_populations_come_back_in_order() {
+            let path = std::env::temp_dir().join(format!("population-{}.conf", std::process::id()));
+            let population = SavedPopulation::new(&path);
+            assert!(population.load().unwrap().is_empty());
+            population.save(["second", "first", "second"]).unwrap();
+            assert_eq!(population.load().unwrap(), ["second", "first", "second"]);
+            std::fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn sessions_come_back_where_they_were_left() {
+            let path = std::env::temp_dir()
+                .join(format!("shimeji-test-{}", std::process::id()))
+                .join("session.conf");
+            let session = Session::new(&path);
+            assert!(session.load().unwrap().is_empty());
+            let shimejis = [
+                SavedShimeji {
+                    name: String::from("Gon @ Whale Island"),
+                    position: Some(PhysicalPosition::new(-20, 340)),
+                },
+                SavedShimeji {
+                    name: String::from("Killua"),
+                    position: None,
+                },
+            ];
+            session.save(&shimejis).unwrap();
+            assert_eq!(session.load().unwrap(), shimejis);
+            assert!(Session::parse("Gon @ 1").is_err());
+            std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        }
+    }
+
+    mod formula {
+        use super::super::ambient::LocalTime;
+        use super::super::formula::*;
+        use super::super::rng::Rng;
+        use super::super::world::WorldSnapshot;
+
+        #[test]
+        fn formulas_follow_precedence() {
+            let world = WorldSnapshot {
+                population: 4,
+                ..Default::default()
+            };
+            let mut rng = Rng::with_seed(1);
+            let formula = Formula::parse("max(1, 10 - population * 2) / (1 + 1)").unwrap();
+            assert_eq!(formula.evaluate(&world, &mut rng), 1.0);
+            let formula = Formula::parse("-population + 5").unwrap();
+            assert_eq!(formula.evaluate(&world, &mut rng), 1.0);
+        }
+
+        #[test]
+        fn randoms_roll_with_the_shimejis_own_rng() {
+            let formula = Formula::parse("random(10, 20)").unwrap();
+            let roll =
+                |seed| formula.evaluate(&WorldSnapshot::default(), &mut Rng::with_seed(seed));
+            assert_eq!(roll(7), roll(7));
+            assert!((10.0..20.0).contains(&roll(7)));
+        }
+
+        #[test]
+        fn idle_weights_only_count_while_the_user_is_away() {
+            let mut rng = Rng::with_seed(1);
+            let formula = Formula::parse("idle * 10").unwrap();
+            assert_eq!(formula.evaluate(&WorldSnapshot::default(), &mut rng), 0.0);
+            let away = WorldSnapshot {
+                user_idle: true,
+                ..Default::default()
+            };
+            assert_eq!(formula.evaluate(&away, &mut rng), 10.0);
+        }
+
+        #[test]
+        fn bad_formulas_are_rejected() {
+            assert_eq!(
+                Formula::parse("populaton"),
+                Err(FormulaError::UnknownName {
+                    name: String::from("populaton")
+                })
+            );
+            assert_eq!(Formula::parse("1 +"), Err(FormulaError::UnexpectedEnd));
+            assert_eq!(Formula::parse("(1 2)"), Err(FormulaError::TrailingInput));
+        }
+
+        #[test]
+        fn zero_weights_are_never_chosen() {
+            let mut rng = Rng::with_seed(1234);
+            let choices = [("never", 0.0), ("always", 2.0), ("negative", -5.0)];
+            for _ in 0..100 {
+                assert_eq!(rng.choose_weighted(&choices), Some(&"always"));
+            }
+            assert_eq!(rng.choose_weighted(&[("never", 0.0)]), None);
+        }
+
+        #[test]
+        fn conditions_hold_at_the_right_times() {
+            let at = |month, hour, minute| WorldSnapshot {
+                clock: Some(LocalTime {
+                    month,
+                    day: 24,
+                    weekday: 3,
+                    hour,
+                    minute,
+                    second: 0,
+                }),
+                ..Default::default()
+            };
+            let mut rng = Rng::with_seed(1);
+            let night = Predicate::parse("clock >= 22:00 or clock < 6:00").unwrap();
+            assert!(night.holds(&at(6, 23, 15), &mut rng));
+            assert!(night.holds(&at(6, 5, 59), &mut rng));
+            assert!(!night.holds(&at(6, 21, 59), &mut rng));
+            let christmas_eve = Predicate::parse("month == 12 and day == 24").unwrap();
+            assert!(christmas_eve.holds(&at(12, 12, 0), &mut rng));
+            assert!(!christmas_eve.holds(&at(11, 12, 0), &mut rng));
+            // nothing holds when the time isn't known
+            assert!(!christmas_eve.holds(&WorldSnapshot::default(), &mut rng));
+            assert_eq!(
+                Predicate::parse("month"),
+                Err(FormulaError::ExpectedComparison)
+            );
+            assert!(Predicate::parse("clock > 25:61").is_err());
+        }
+    }
+
+    mod script {
+        use super::super::rng::Rng;
+        use super::super::script::*;
+        use super::super::world::WorldSnapshot;
+
+        #[test]
+        fn scripts_steer_and_keep_time() {
+            let script = Script::parse(
+                "if every(2) then set_velocity(random(10, 20), 0) end
+                 local x = position()
+                 if x < 100 then animation('run') elseif time() >= 5 then behavior \"sit\" end",
+            )
+            .unwrap();
+            assert_eq!(script.animations(), ["run"]);
+            assert_eq!(script.behaviors(), ["sit"]);
+            let (world, mut rng, mut state) = (
+                WorldSnapshot::default(),
+                Rng::with_seed(7),
+                ScriptState::default(),
+            );
+            let mut run = |x, time| {
+                let locals = Locals {
+                    x,
+                    time,
+                    ..Default::default()
+                };
+                script.run(&world, &locals, &mut state, &mut rng)
+            };
+            let effects = run(50.0, 3.0);
+            let [ScriptEffect::Velocity(vx, 0.0), ScriptEffect::Animation(run_animation)] =
+                effects.as_slice()
+            else {
+                panic!("unexpected {effects:?}");
+            };
+            assert!((10.0..20.0).contains(vx));
+            assert_eq!(run_animation, "run");
+            // the timer was reset at 3 seconds, so it's another 2 until it fires again
+            assert_eq!(run(500.0, 4.0), []);
+            let effects = run(500.0, 5.0);
+            assert!(matches!(effects[0], ScriptEffect::Velocity(..)));
+            assert_eq!(effects[1..], [ScriptEffect::Behavior(String::from("sit"))]);
+            assert!(Script::parse("if x then").is_err());
+        }
+
+        #[test]
+        fn scripts_keep_their_globals_and_are_stopped_when_stuck() {
+            let world = WorldSnapshot::default();
+            let (mut rng, mut state) = (Rng::with_seed(7), ScriptState::default());
+            let counting = Script::parse(
+                "runs = (runs or 0) + 1
+                 if runs == 3 then behavior('sit') end
+                 animation('after')",
+            )
+            .unwrap();
+            let mut run = |script: &Script, state: &mut ScriptState| {
+                script.run(&world, &Locals::default(), state, &mut rng)
+            };
+            assert_eq!(
+                run(&counting, &mut state),
+                [ScriptEffect::Animation("after".into())]
+            );
+            run(&counting, &mut state);
+            // nothing after switching behaviors happens
+            assert_eq!(
+                run(&counting, &mut state),
+                [ScriptEffect::Behavior("sit".into())]
+            );
+            // a copy starts over
+            let mut copy = state.clone();
+            assert_eq!(
+                run(&counting, &mut copy),
+                [ScriptEffect::Animation("after".into())]
+            );
+
+            let stuck = Script::parse("while true do end").unwrap();
+            assert_eq!(run(&stuck, &mut ScriptState::default()), []);
+            let sneaky = Script::parse("os.execute('true'); animation('run')").unwrap();
+            assert_eq!(run(&sneaky, &mut ScriptState::default()), []);
+        }
+    }
+
+    mod frame_cache {
+        use super::super::blit::{BufferSize, Facing};
+        use super::super::frame_cache::*;
+        use super::super::rgba::PixelFormat;
+        use std::sync::Arc;
+
+        #[test]
+        fn shimejis_share_drawn_frames() {
+            let cache = FrameCache::default();
+            let key = |facing| FrameKey {
+                animation: String::from("idle"),
+                frame: 0,
+                size: BufferSize {
+                    width: 1,
+                    height: 1,
+                },
+                format: PixelFormat::Rgba8,
+                facing,
+                tint: None,
+                variant: None,
+            };
+            let mut draws = 0;
+            let mut draw = |buffer: &mut [u8]| {
+                draws += 1;
+                buffer.fill(7);
+            };
+            let first = cache.get_or_draw(key(Facing::Left), 4, &mut draw);
+            let second = cache.get_or_draw(key(Facing::Left), 4, &mut draw);
+            assert!(Arc::ptr_eq(&first, &second));
+            assert_eq!(&*first, &[7; 4]);
+            cache.get_or_draw(key(Facing::Right), 4, &mut draw);
+            assert_eq!(draws, 2);
+        }
+    }
+
+    mod frame_store {
+        use super::super::frame_store::*;
+        use super::super::loader::Frame;
+        use super::super::rgba::Rgba;
+        use std::time::Duration;
+
+        #[test]
+        fn stored_frames_are_mapped_back_as_they_were() {
+            let path = std::env::temp_dir().join(format!("frames-{}.bin", std::process::id()));
+            let store = FrameStore::new(&path);
+            assert!(store.load().unwrap().is_none());
+            let frame = |red| Frame {
+                width: 2,
+                height: 1,
+                pixels_row_major: [Rgba::new(red, 2, 3, 4), Rgba::new(5, 6, 7, 8)].into(),
+            };
+            let animations = [
+                (vec![frame(1), frame(9)], None),
+                (vec![frame(20)], Some(vec![Duration::from_millis(40)])),
+            ];
+            store.save(&animations).unwrap();
+            let loaded = store.load().unwrap().unwrap();
+            assert_eq!(loaded.len(), 2);
+            for ((frames, delays), (loaded, loaded_delays)) in animations.iter().zip(&loaded) {
+                assert_eq!(delays, loaded_delays);
+                for (frame, loaded) in frames.iter().zip(loaded) {
+                    assert!(matches!(
+                        loaded.pixels_row_major,
+                        FramePixels::Mapped { .. }
+                    ));
+                    assert_eq!(frame.pixels_row_major, loaded.pixels_row_major);
+                }
+            }
+            std::fs::write(&path, b"SHIMFRM1\x01").unwrap();
+            assert!(store.load().is_err());
+            std::fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn frames_too_big_to_address_are_rejected() {
+            let path = std::env::temp_dir().join(format!("huge-frames-{}.bin", std::process::id()));
+            let mut bytes = b"SHIMFRM1".to_vec();
+            bytes.extend(1u32.to_le_bytes());
+            bytes.extend(1u32.to_le_bytes());
+            bytes.push(0);
+            bytes.extend(u32::MAX.to_le_bytes());
+            bytes.extend(u32::MAX.to_le_bytes());
+            std::fs::write(&path, bytes).unwrap();
+            let error = FrameStore::new(&path).load().unwrap_err();
+            std::fs::remove_file(path).unwrap();
+            assert!(format!("{error:#}").contains("too big"));
+        }
+
+        #[test]
+        fn debugging_pixels_does_not_print_each_one() {
+            let pixels = FramePixels::from(vec![Rgba::new(1, 2, 3, 4); 1000]);
+            assert_eq!(format!("{pixels:?}"), "Owned(1000 pixels)");
+        }
+    }
+
+    mod frame_cursor {
+        use super::super::frame_cursor::*;
+
+        #[test]
+        fn looping_cursor_wraps() {
+            let mut cursor = FrameCursor::new(3, LoopMode::Loop);
+            assert_eq!(cursor.index(), Some(0));
+            assert!(!cursor.advance());
+            assert!(!cursor.advance());
+            assert_eq!(cursor.index(), Some(2));
+            assert!(cursor.advance());
+            assert_eq!(cursor.index(), Some(0));
+        }
+
+        #[test]
+        fn one_shot_cursor_holds_last_frame() {
+            let mut cursor = FrameCursor::new(2, LoopMode::Once);
+            assert!(!cursor.advance());
+            assert!(cursor.advance());
+            assert!(cursor.is_finished());
+            assert!(!cursor.advance());
+            assert_eq!(cursor.index(), Some(1));
+            cursor.reset();
+            assert_eq!(cursor.index(), Some(0));
+        }
+
+        #[test]
+        fn ping_pong_cursor_bounces_back_to_the_start() {
+            let mut cursor = FrameCursor::new(3, LoopMode::PingPong);
+            let mut played = vec![];
+            let mut passes = 0;
+            for _ in 0..8 {
+                played.push(cursor.index().unwrap());
+                passes += usize::from(cursor.advance());
+            }
+            assert_eq!(played, [0, 1, 2, 1, 0, 1, 2, 1]);
+            assert_eq!(passes, 2);
+        }
+
+        #[test]
+        fn held_cursor_stays_on_its_last_frame_without_finishing_a_pass() {
+            let mut cursor = FrameCursor::new(2, LoopMode::HoldLast);
+            assert!(!cursor.advance());
+            assert!(!cursor.advance());
+            assert!(!cursor.advance());
+            assert_eq!(cursor.index(), Some(1));
+            assert_eq!("hold-last".parse(), Ok(LoopMode::HoldLast));
+        }
+
+        #[test]
+        fn empty_cursor_has_no_frame() {
+            let mut cursor = FrameCursor::new(0, LoopMode::Loop);
+            assert_eq!(cursor.index(), None);
+            assert!(!cursor.advance());
+        }
+    }
+
+    mod sequencer {
+        use super::super::sequencer::*;
+
+        #[test]
+        fn sequences_repeat_each_step_then_move_on() {
+            let step = |animation: &str, repeat| SequenceStep {
+                animation: String::from(animation),
+                repeat,
+            };
+            let steps = [step("sit_down", 1), step("sit", 2), step("stand_up", 1)];
+            let mut sequencer = Sequencer::new("sit", &steps).unwrap();
+            assert_eq!(sequencer.animation(), "sit_down");
+            assert_eq!(sequencer.finished_pass(), Pass::Next(String::from("sit")));
+            assert_eq!(sequencer.finished_pass(), Pass::Again);
+            assert_eq!(
+                sequencer.finished_pass(),
+                Pass::Next(String::from("stand_up"))
+            );
+            assert_eq!(sequencer.finished_pass(), Pass::Done);
+            assert_eq!(sequencer.animation(), "stand_up");
+            assert!(Sequencer::new("nothing", &[]).is_none());
+        }
+    }
+
+    mod ipc {
+        use std::sync::Arc;
+
+        use super::super::ipc::*;
+
+        #[test]
+        fn commands_parse_from_lines() {
+            assert_eq!("list\n".parse(), Ok(Command::List));
+            assert_eq!("remove".parse(), Ok(Command::Remove(None)));
+            assert_eq!("remove 42".parse(), Ok(Command::Remove(Some(42))));
+            assert_eq!(
+                "spawn Big Shimeji".parse(),
+                Ok(Command::Spawn(Arc::from("Big Shimeji")))
+            );
+            assert_eq!(
+                "set-behavior 7 SitDown".parse(),
+                Ok(Command::SetBehavior {
+                    id: 7,
+                    behavior: String::from("SitDown")
+                })
+            );
+            assert_eq!(
+                "play 7 Sit Down --wait".parse(),
+                Ok(Command::Play {
+                    id: 7,
+                    behavior: String::from("Sit Down"),
+                    wait: true
+                })
+            );
+            assert_eq!(
+                "play 7 Sit--wait".parse(),
+                Ok(Command::Play {
+                    id: 7,
+                    behavior: String::from("Sit--wait"),
+                    wait: false
+                })
+            );
+            assert!("play 7 --wait".parse::<Command>().is_err());
+            assert_eq!("party".parse(), Ok(Command::Party));
+            assert!("remove someone".parse::<Command>().is_err());
+            assert!("pause now".parse::<Command>().is_err());
+            assert!("dance".parse::<Command>().is_err());
+        }
+    }
+
+    mod validate {
+        use std::path::Path;
+
+        use super::super::loader::PackLibrary;
+        use super::super::validate::*;
+        use super::init_logger;
+
+        #[test]
+        fn every_undecodable_frame_is_an_error() {
+            init_logger();
+            let path = Path::new("./fuzz/undecodable-frame.xml");
+            let problems = validate(path, &PackLibrary::new("./fuzz"));
+            assert_eq!(problems.len(), 1, "{problems:?}");
+            assert_eq!(problems[0].severity, Severity::Error);
+            assert!(problems[0]
+                .message
+                .contains("undecodable-frame.xml for idle"));
+        }
+
+        #[test]
+        fn unknown_attributes_are_warned_about_where_they_are() {
+            init_logger();
+            let path = std::env::temp_dir().join(format!("unknown-{}.xml", std::process::id()));
+            let pack = std::fs::read_to_string("./fuzz/sprite-sheet.xml")
+                .unwrap()
+                .replace(r#"fps="2">"#, r#"fps="2" speed="3">"#);
+            std::fs::write(&path, pack).unwrap();
+            let problems = validate(&path, &PackLibrary::new("./fuzz"));
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(problems.len(), 1, "{problems:?}");
+            assert_eq!(problems[0].severity, Severity::Warning);
+            assert_eq!(problems[0].position, Some((4, 3)));
+            assert!(problems[0]
+                .to_string()
+                .ends_with("warning: unknown attribute speed of <Animation>"));
+        }
+    }
+
+    mod fuzz {
+        use std::{fs::File, path::PathBuf};
+
+        use xml_parser::XmlParseError;
+
+        use super::*;
+
+        #[test]
+        fn bad_filename() {
+            init_logger();
+            let err =
+                xml_parser::parse(File::open("./fuzz/bad-filename.xml").unwrap()).unwrap_err();
+            dbg!(&err);
+            assert!(matches!(err.error, XmlParseError::MissingImageFile { .. }))
+        }
+
+        #[test]
+        fn unknown_behavior_condition() {
+            init_logger();
+            let err =
+                xml_parser::parse(File::open("./fuzz/unknown-behavior-condition.xml").unwrap())
+                    .unwrap_err();
+            dbg!(&err);
+            assert!(matches!(err.error, XmlParseError::InvalidValue { .. }));
+            // pointing at the <Transition> it's in
+            assert_eq!(
+                err.in_file("pack.xml").to_string(),
+                "pack.xml:9:7: \"sometimes\" is not a valid value for when"
+            );
+        }
+
+        #[test]
+        fn unknown_next_animation() {
+            init_logger();
+            let err =
+                loader::create_shimeji_data_from_file_name("./fuzz/unknown-next-animation.xml")
+                    .unwrap_err();
+            dbg!(&err);
+            assert!(err.to_string().contains("land"))
+        }
+
+        #[test]
+        fn use_other_pack() {
+            init_logger();
+            let file = "./fuzz/use-other-pack.xml";
+            let data =
+                loader::create_shimeji_data_with_library(file, &loader::PackLibrary::new("."))
+                    .unwrap();
+            assert!(data.animations.contains_key("borrowed"));
+            let err =
+                loader::create_shimeji_data_with_library(file, &loader::PackLibrary::new("./fuzz"))
+                    .unwrap_err();
+            dbg!(&err);
+            assert!(err.to_string().contains("not installed"))
+        }
+
+        #[test]
+        fn shimeji_ee_pack() {
+            use super::super::behavior::Action;
+            init_logger();
+            let data =
+                loader::create_shimeji_data_from_file_name("./fuzz/shimeji-ee-pack").unwrap();
+            assert_eq!((data.width, data.height), (32, 32));
+            // poses of 6 and 12 ticks become three frames of 6 ticks at 25 ticks a second
+            let walk = &data.animations["Walk"];
+            assert_eq!(walk.frames.len(), 3);
+            assert_eq!(walk.durations, [Duration::from_secs_f64(6.0 / 25.0); 3]);
+            assert_eq!(data.behaviors.initial().name, "Fall");
+            assert_eq!(data.behaviors.initial().action, Action::Fall);
+            assert_eq!(
+                data.behaviors.get("WalkLeft").unwrap().action,
+                Action::Walk(-50.0)
+            );
+            assert_eq!(data.behaviors.get("StandUp").unwrap().animation, "Stand");
+            // embedded actions without an animation can't be shown
+            assert!(data.behaviors.get("LookAround").is_none());
+        }
+
+        #[test]
+        fn animated_png() {
+            init_logger();
+            let data = loader::create_shimeji_data_from_file_name("./fuzz/animated.xml").unwrap();
+            let idle = &data.animations["idle"];
+            assert_eq!(idle.durations, [Duration::from_millis(100); 2]);
+            assert_eq!(idle.frames.len(), 2);
+            // the second frame only covers the bottom right corner
+            let second = &idle.frames[1].pixels_row_major;
+            assert_eq!(second[0].red, 255);
+            assert_eq!(second[15].blue, 255);
+        }
+
+        #[test]
+        fn pngs_of_every_color_type_become_rgba() {
+            use png::{BitDepth, ColorType};
+            init_logger();
+            let path = std::env::temp_dir().join(format!("color-type-{}.png", std::process::id()));
+            let decode = |color_type, data: &[u8], palette: Option<(&[u8], &[u8])>| {
+                let mut encoder = png::Encoder::new(File::create(&path).unwrap(), 2, 1);
+                encoder.set_color(color_type);
+                encoder.set_depth(BitDepth::Eight);
+                if let Some((palette, transparency)) = palette {
+                    encoder.set_palette(palette);
+                    encoder.set_trns(transparency);
+                }
+                encoder
+                    .write_header()
+                    .unwrap()
+                    .write_image_data(data)
+                    .unwrap();
+                let mut reader = loader::png_decoder(File::open(&path).unwrap())
+                    .read_info()
+                    .unwrap();
+                let mut buf = vec![0; reader.output_buffer_size()];
+                let info = reader.next_frame(&mut buf).unwrap();
+                loader::to_rgba(info.color_type, &buf[..info.buffer_size()]).unwrap()
+            };
+            let rgba = crate::rgba::Rgba::new;
+            assert_eq!(
+                decode(ColorType::Grayscale, &[0x40, 0xff], None),
+                [rgba(0x40, 0x40, 0x40, 255), rgba(255, 255, 255, 255)]
+            );
+            assert_eq!(
+                decode(ColorType::GrayscaleAlpha, &[0x40, 0x80, 0xff, 0xff], None),
+                [rgba(0x40, 0x40, 0x40, 0x80), rgba(255, 255, 255, 255)]
+            );
+            assert_eq!(
+                decode(ColorType::Rgb, &[1, 2, 3, 4, 5, 6], None),
+                [rgba(1, 2, 3, 255), rgba(4, 5, 6, 255)]
+            );
+            assert_eq!(
+                decode(ColorType::Rgba, &[1, 2, 3, 4, 5, 6, 7, 8], None),
+                [rgba(1, 2, 3, 4), rgba(5, 6, 7, 8)]
+            );
+            // only the first entry of the palette has an alpha, so the second is opaque
+            let palette: (&[u8], &[u8]) = (&[1, 2, 3, 4, 5, 6], &[0x80]);
+            assert_eq!(
+                decode(ColorType::Indexed, &[0, 1], Some(palette)),
+                [rgba(1, 2, 3, 0x80), rgba(4, 5, 6, 255)]
+            );
+            std::fs::remove_file(&path).unwrap();
+            // the decoder expands palettes, so indices reaching this are a mistake
+            assert!(loader::to_rgba(ColorType::Indexed, &[0, 1]).is_err());
+        }
+
+        #[test]
+        fn animations_decoded_in_parallel_stay_in_order() {
+            let numbers: Vec<usize> = (0..100).collect();
+            let threads = std::sync::Mutex::new(HashSet::new());
+            let doubled = loader::in_parallel(&numbers, |number| {
+                threads.lock().unwrap().insert(thread::current().id());
+                number * 2
+            });
+            assert_eq!(doubled, (0..200).step_by(2).collect::<Vec<_>>());
+            let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+            assert_eq!(threads.into_inner().unwrap().len() > 1, cores > 1);
+            // a panic on one of the threads isn't lost
+            let panicked = std::panic::catch_unwind(|| {
+                loader::in_parallel(&numbers, |&number| assert_ne!(number, 70))
+            });
+            assert!(panicked.is_err());
+        }
+
+        #[test]
+        fn animated_gif() {
+            init_logger();
+            let data =
+                loader::create_shimeji_data_from_file_name("./fuzz/animated-gif.xml").unwrap();
+            let idle = &data.animations["idle"];
+            assert_eq!(idle.durations, [Duration::from_millis(100); 2]);
+            assert_eq!(idle.frames.len(), 2);
+            // the second frame is drawn over the first, only covering the bottom right corner
+            let second = &idle.frames[1].pixels_row_major;
+            assert_eq!(second[0].red, 255);
+            assert_eq!(second[15].blue, 255);
+        }
+
+        #[test]
+        fn undecodable_frames_become_placeholders() {
+            init_logger();
+            let data =
+                loader::create_shimeji_data_from_file_name("./fuzz/undecodable-frame.xml").unwrap();
+            let frames = &data.animations["idle"].frames;
+            assert_eq!(frames.len(), 2);
+            let placeholder = loader::Frame::placeholder(16, 16);
+            assert_eq!(frames[1].pixels_row_major, placeholder.pixels_row_major);
+            // a figure in the middle, nothing in the corners
+            assert!(placeholder.pixels_row_major[8 * 16 + 8].alpha > 0);
+            assert_eq!(placeholder.pixels_row_major[0].alpha, 0);
+        }
+
+        #[test]
+        fn packs_load_from_archives() {
+            init_logger();
+            let data =
+                loader::create_shimeji_data_from_file_name("./fuzz/zipped-pack.shimeji").unwrap();
+            assert_eq!(&*data.name, "zipped");
+            // the images are found beside the XML in the archive
+            let frames = &data.animations["idle"].frames;
+            assert_eq!(frames[0].pixels_row_major[0].red, 255);
+            assert_eq!(frames[1].pixels_row_major[0].blue, 255);
+        }
+
+        #[test]
+        fn sprite_sheet() {
+            init_logger();
+            let data =
+                loader::create_shimeji_data_from_file_name("./fuzz/sprite-sheet.xml").unwrap();
+            let frames = &data.animations["idle"].frames;
+            assert_eq!((frames[0].width, frames[0].height), (32, 32));
+            assert_eq!(frames[0].pixels_row_major[0].red, 255);
+            assert_eq!(frames[1].pixels_row_major[0].blue, 255);
+            let region = xml_parser::FrameRegion {
+                x: 48,
+                y: 0,
+                width: 32,
+                height: 32,
+            };
+            assert!(frames[0].cropped(region).is_none());
+        }
+
+        #[test]
+        fn sequences_play_animations_of_the_pack() {
+            init_logger();
+            let path = std::env::temp_dir().join(format!("sequence-{}.xml", std::process::id()));
+            let pack = |step: &str| {
+                std::fs::read_to_string("./fuzz/sprite-sheet.xml")
+                    .unwrap()
+                    .replace(
+                        "</Shimeji>",
+                        &format!(
+                            "  <Sequence name=\"fidget\" next=\"idle\">\n\
+                             <Step animation=\"idle\" repeat=\"2\" />\n\
+                             <Step animation=\"{step}\" />\n\
+                             </Sequence>\n</Shimeji>"
+                        ),
+                    )
+            };
+            std::fs::write(&path, pack("idle")).unwrap();
+            let data = loader::create_shimeji_data_from_file_name(&path).unwrap();
+            let fidget = &data.animations["fidget"];
+            assert_eq!(fidget.steps.len(), 2);
+            assert_eq!(fidget.steps[0].repeat, 2);
+            assert_eq!(fidget.next.as_deref(), Some("idle"));
+            std::fs::write(&path, pack("sit")).unwrap();
+            let error = loader::create_shimeji_data_from_file_name(&path)
+                .unwrap_err()
+                .to_string();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(error, "sequence fidget plays sit, which does not exist");
+        }
+
+        #[test]
+        fn frames_must_fit_the_shimeji() {
+            init_logger();
+            let path = std::env::temp_dir().join(format!("too-small-{}.xml", std::process::id()));
+            let pack = std::fs::read_to_string("./fuzz/sprite-sheet.xml")
+                .unwrap()
+                .replace(r#"width="32" height="32">"#, r#"width="16" height="16">"#);
+            std::fs::write(&path, pack).unwrap();
+            let error = loader::create_shimeji_data_from_file_name(path.to_str().unwrap())
+                .unwrap_err()
+                .to_string();
+            std::fs::remove_file(&path).unwrap();
+            assert!(error.contains("./fuzz/sprite-sheet.png"), "{error}");
+            assert!(error.contains("32x32"), "{error}");
+        }
+
+        #[test]
+        fn frames_can_time_themselves() {
+            init_logger();
+            let pack = |second_frame: &str| {
+                format!(
+                    "name = \"timed\"\nwidth = 32\nheight = 32\n\
+                     [[animations]]\nname = \"idle\"\nframes = [\n\
+                     {{ number = 1, file = \"./fuzz/sprite-sheet.png\", duration_ms = 250 }},\n\
+                     {{ number = 2, file = \"./fuzz/sprite-sheet.png\"{second_frame} }},\n]\n"
+                )
+            };
+            let data = config::parse(&pack(", duration_ms = 50"), config::Format::Toml).unwrap();
+            let durations: Vec<_> = data.animations[0]
+                .frames
+                .iter()
+                .map(|frame| frame.duration)
+                .collect();
+            assert_eq!(
+                durations,
+                [
+                    Some(Duration::from_millis(250)),
+                    Some(Duration::from_millis(50))
+                ]
+            );
+            // without an fps, every frame needs its own duration
+            let err = config::parse(&pack(""), config::Format::Toml).unwrap_err();
+            assert!(matches!(
+                err,
+                XmlParseError::MissingAttribute { attribute: "fps" }
+            ));
+        }
+
+        #[test]
+        fn frames_can_play_sounds() {
+            init_logger();
+            let pack = "name = \"noisy\"\nwidth = 32\nheight = 32\n\
+                        [[animations]]\nname = \"walk\"\nfps = 4\nframes = [\n\
+                        { number = 1, file = \"./fuzz/sprite-sheet.png\", sound = \"step.ogg\" },\n\
+                        { number = 2, file = \"./fuzz/sprite-sheet.png\" },\n]\n";
+            let data = config::parse(pack, config::Format::Toml).unwrap();
+            let sounds: Vec<_> = data.animations[0]
+                .frames
+                .iter()
+                .map(|frame| frame.sound.as_deref())
+                .collect();
+            assert_eq!(sounds, [Some("step.ogg"), None]);
+        }
+
+        #[test]
+        fn anchors_keep_their_point_where_the_feet_would_be() {
+            init_logger();
+            let pack = "name = \"jumpy\"\nwidth = 32\nheight = 32\n\
+                        [[animations]]\nname = \"jump\"\nfps = 4\nframes = [\n\
+                        { number = 1, file = \"./fuzz/sprite-sheet.png\", anchor_x = 10, anchor_y = 24 },\n\
+                        { number = 2, file = \"./fuzz/sprite-sheet.png\" },\n]\n";
+            let data = config::parse(pack, config::Format::Toml).unwrap();
+            let frames = &data.animations[0].frames;
+            assert_eq!(
+                (frames[0].anchor_x, frames[0].anchor_y),
+                (Some(10.0), Some(24.0))
+            );
+            assert_eq!((frames[1].anchor_x, frames[1].anchor_y), (None, None));
+
+            let anchor = loader::Anchor {
+                x: frames[0].anchor_x,
+                y: frames[0].anchor_y,
+            };
+            assert_eq!(
+                anchor.offset(32.0, 32.0, crate::blit::Facing::Left),
+                (6.0, 8.0)
+            );
+            assert_eq!(
+                anchor.offset(32.0, 32.0, crate::blit::Facing::Right),
+                (-6.0, 8.0)
+            );
+            assert_eq!(
+                loader::Anchor::default().offset(32.0, 32.0, crate::blit::Facing::Right),
+                (0.0, 0.0)
+            );
+        }
+
+        #[test]
+        fn toml_and_json_packs_match_xml() {
+            init_logger();
+            for file in ["./fuzz/sprite-sheet.toml", "./fuzz/sprite-sheet.json"] {
+                let data = loader::create_shimeji_data_from_file_name(file).unwrap();
+                assert_eq!((&*data.name, data.width, data.height), ("sheet", 32, 32));
+                let frames = &data.animations["idle"].frames;
+                assert_eq!(frames.len(), 2);
+                assert_eq!(frames[1].pixels_row_major[0].blue, 255);
+            }
+            let err = config::parse("{\"name\": \"sheet\",", config::Format::Json).unwrap_err();
+            assert!(matches!(err, XmlParseError::MalformedFile));
+        }
+
+        #[test]
+        fn frame_limits_shrink_packs() {
+            init_logger();
+            let limits = loader::FrameLimits {
+                max_dimension: Some(16),
+                memory_budget: None,
+            };
+            let packs = loader::PackLibrary::new(".").with_frame_limits(limits);
+            let data =
+                loader::create_shimeji_data_with_library("./fuzz/shimeji-ee-pack", &packs).unwrap();
+            assert_eq!((data.width, data.height), (16, 16));
+            let frame = &data.animations["Walk"].frames[0];
+            assert_eq!((frame.width, frame.height), (16, 16));
+            assert_eq!(frame.pixels_row_major.len(), 16 * 16);
+            // 32x32 frames, 4 bytes a pixel, in a budget of a quarter of that
+            let limits = loader::FrameLimits {
+                max_dimension: None,
+                memory_budget: Some(32 * 32),
+            };
+            assert_eq!(limits.scale_for(32, 32, 1), 0.5);
+        }
+
+        #[test]
+        fn many_shimejis() {
+            init_logger();
+            let file = File::open("./fuzz/many-shimejis.xml").unwrap();
+            let err = xml_parser::parse(file).unwrap_err();
+            assert!(matches!(err.error, XmlParseError::MultipleShimeji));
+            let library = loader::ShimejiLibrary::load(
+                "./fuzz/many-shimejis.xml",
+                &loader::PackLibrary::new("."),
+            )
+            .unwrap();
+            assert_eq!(library.names(), [Arc::from("first"), Arc::from("second")]);
+            assert!(!library.get("second").unwrap().gravity);
+        }
+
+        #[test]
+        fn libraries_know_their_files() {
+            init_logger();
+            let library = loader::ShimejiLibrary::load(
+                "./fuzz/sprite-sheet.xml",
+                &loader::PackLibrary::new("."),
+            )
+            .unwrap();
+            let sources = library.sources();
+            assert!(sources.contains(&PathBuf::from("./fuzz/sprite-sheet.xml")));
+            // used by two frames, watched once
+            let sheet = PathBuf::from("./fuzz/sprite-sheet.png");
+            assert_eq!(sources.iter().filter(|path| **path == sheet).count(), 1);
+            let reloaded = library.reload().unwrap().unwrap();
+            assert_eq!(reloaded.names(), library.names());
+        }
+
+        #[test]
+        fn missing_prop_image() {
+            init_logger();
+            let err = xml_parser::parse(File::open("./fuzz/missing-prop-image.xml").unwrap())
+                .unwrap_err();
+            dbg!(&err);
+            assert!(matches!(err.error, XmlParseError::MissingImageFile { .. }))
+        }
+
+        #[test]
+        fn missing_shimeji() {
+            init_logger();
+            let err =
+                xml_parser::parse(File::open("./fuzz/missing-shimeji.xml").unwrap()).unwrap_err();
+            dbg!(&err);
+            assert!(matches!(err.error, XmlParseError::NoShimeji))
+        }
+
+        #[test]
+        fn misspelled_elements_are_refused_when_strict() {
+            init_logger();
+            let path = std::env::temp_dir().join(format!("misspelled-{}.xml", std::process::id()));
+            let pack = std::fs::read_to_string("./fuzz/sprite-sheet.xml")
+                .unwrap()
+                .replace("</Shimeji>", "  <Animaton name=\"walk\" />\n</Shimeji>");
+            std::fs::write(&path, pack).unwrap();
+            let data = xml_parser::parse(File::open(&path).unwrap()).unwrap();
+            assert_eq!(data.warnings.len(), 1);
+            assert_eq!(data.warnings[0].position, Some((8, 3)));
+            assert!(matches!(
+                &data.warnings[0].error,
+                XmlParseError::UnknownElement { element } if element == "Animaton"
+            ));
+            let lenient = PackLibrary::new("./fuzz");
+            assert!(loader::create_shimeji_data_with_library(&path, &lenient).is_ok());
+            let strict = lenient.with_strict(true);
+            let error = loader::create_shimeji_data_with_library(&path, &strict)
+                .unwrap_err()
+                .to_string();
+            std::fs::remove_file(&path).unwrap();
+            assert!(
+                error.ends_with(":8:3: unknown element <Animaton>"),
+                "{error}"
+            );
+        }
+    }
+    // #[test]
+    // fn buckets_receive_shimeji_sequentially() -> anyhow::Result<()> {
+    //     init_logger();
+    //     let mut manager = BucketManager::new(1);
+
+    //     manager.add_shimeji(ShimejiConfig {
+    //         name: String::from("example"),
+    //     });
+
+    //     assert_eq!(manager.buckets.first().unwrap().contained_shimejis(), 1);
+    //     Ok(())
+    // }
+}
@@ -1,85 +1,1311 @@
 use anyhow::{bail, Context};
 use png::ColorType;
-use std::{collections::HashMap, ffi::OsString};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::OsString,
+    num::NonZero,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::{rgba::Rgba, shimeji::ShimejiData, xml_parser::parse};
+use crate::{
+    archive,
+    behavior::{Action, Behavior, BehaviorTable, Condition, Transition, DEFAULT_WALK_SPEED},
+    blit::{self, Filter, ScalingMode},
+    config,
+    formula::Formula,
+    frame_cache::FrameCache,
+    frame_cursor::LoopMode,
+    frame_store::{DecodedFrames, FramePixels, FrameStore},
+    log_throttle::once,
+    rgba::Rgba,
+    rng::Rng,
+    sequencer::SequenceStep,
+    shimeji::ShimejiData,
+    variant::VariantCache,
+    world::WorldSnapshot,
+    xml_parser::{
+        parse, parse_ee_actions, parse_ee_behaviors, parse_many, parse_many_in, AnimationXml,
+        EeActionXml, FrameRegion, PackError, SequenceXml, UseXml, XmlParseError, XmlReturnData,
+    },
+};
 use std::fs;
 
+/// How fast animations that don't say otherwise play.
+const DEFAULT_FPS: f64 = 24.0;
+
 #[derive(Debug, Clone)]
 pub struct AnimationData {
-    pub fps: f64,
+    /// How long each of `frames` shows for.
+    pub durations: Vec<Duration>,
+    pub weight: Option<Formula>,
+    /// The animation to play once this one has played through once.
+    pub next: Option<String>,
+    /// What happens when it runs out of frames.
+    pub loop_mode: LoopMode,
     pub frames: Vec<Frame>,
+    /// The sound each of `frames` plays when it shows, if any.
+    pub sounds: Vec<Option<String>>,
+    /// Where each of `frames` is anchored, scaled like the frames are.
+    /// Empty if the animation doesn't say.
+    pub anchors: Vec<Anchor>,
+    /// The parts of each of `frames` that can be clicked, scaled like the frames are.
+    /// Empty for a frame, or the whole animation, that goes by which pixels are opaque.
+    pub hitboxes: Vec<Vec<FrameRegion>>,
+    /// The animations a `<Sequence>` plays one after another instead of frames of its own,
+    /// see [`Sequencer`](crate::sequencer::Sequencer). Empty for any other animation.
+    pub steps: Vec<SequenceStep>,
+}
+impl AnimationData {
+    /// How long frame `index` shows for.
+    pub fn duration_of(&self, index: usize) -> Duration {
+        self.durations
+            .get(index)
+            .copied()
+            .unwrap_or(Duration::from_secs_f64(1.0 / DEFAULT_FPS))
+    }
+    /// The sound frame `index` plays, if any.
+    pub fn sound_of(&self, index: usize) -> Option<&str> {
+        self.sounds.get(index)?.as_deref()
+    }
+    /// Where frame `index` is anchored.
+    pub fn anchor_of(&self, index: usize) -> Anchor {
+        self.anchors.get(index).copied().unwrap_or_default()
+    }
+    /// The parts of frame `index` that can be clicked, or none to go by its opaque pixels.
+    pub fn hitboxes_of(&self, index: usize) -> &[FrameRegion] {
+        self.hitboxes.get(index).map_or(&[], Vec::as_slice)
+    }
+}
+/// The point of a frame that stays put when frames change, like where its feet are,
+/// from its `anchor_x` and `anchor_y`. Either left out is the bottom middle's.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Anchor {
+    pub x: Option<f64>,
+    pub y: Option<f64>,
+}
+impl Anchor {
+    /// How far to move a `width` by `height` frame drawn facing `facing` so that this anchor
+    /// lands where its bottom middle would have.
+    pub fn offset(self, width: f64, height: f64, facing: blit::Facing) -> (f64, f64) {
+        let x = self.x.map_or(0.0, |x| match facing {
+            blit::Facing::Left => width / 2.0 - x,
+            // mirrored, so the anchor is as far from the right as it was from the left
+            blit::Facing::Right => x - width / 2.0,
+        });
+        let y = self.y.map_or(0.0, |y| height - y);
+        (x, y)
+    }
 }
 #[derive(Debug, Clone)]
 pub struct Frame {
-    pub pixels_row_major: Box<[Rgba]>,
+    pub width: u32,
+    pub height: u32,
+    pub pixels_row_major: FramePixels,
+}
+impl Frame {
+    /// A see-through grey figure, a head over a body, to show in place of a frame
+    /// that isn't there or couldn't be decoded.
+    pub fn placeholder(width: u32, height: u32) -> Frame {
+        let grey = Rgba::new(128, 128, 128, 160);
+        let (width_f, height_f) = (width as f64, height as f64);
+        // (center x, center y, radius x, radius y) of each part, as fractions of the frame
+        let parts = [(0.5, 0.3, 0.18, 0.18), (0.5, 0.7, 0.28, 0.28)];
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x as f64 + 0.5, y as f64 + 0.5)))
+            .map(|(x, y)| {
+                let inside = parts
+                    .iter()
+                    .any(|&(center_x, center_y, radius_x, radius_y)| {
+                        let dx = (x / width_f - center_x) / radius_x;
+                        let dy = (y / height_f - center_y) / radius_y;
+                        dx * dx + dy * dy <= 1.0
+                    });
+                if inside {
+                    grey
+                } else {
+                    Rgba::new(0, 0, 0, 0)
+                }
+            })
+            .collect();
+        Frame {
+            width,
+            height,
+            pixels_row_major: pixels,
+        }
+    }
+    /// The frame with its pixels shared by its copies, see [`FramePixels::shared`].
+    pub fn shared(self) -> Frame {
+        Frame {
+            pixels_row_major: self.pixels_row_major.shared(),
+            ..self
+        }
+    }
+    /// The part of this frame inside `region`, or `None` if it doesn't fit.
+    pub fn cropped(&self, region: FrameRegion) -> Option<Frame> {
+        let fits = |start: u32, length: u32, total: u32| {
+            length > 0 && start.checked_add(length).is_some_and(|end| end <= total)
+        };
+        if !fits(region.x, region.width, self.width) || !fits(region.y, region.height, self.height)
+        {
+            return None;
+        }
+        let pixels = (region.y..region.y + region.height)
+            .flat_map(|y| {
+                let row = (y * self.width + region.x) as usize;
+                self.pixels_row_major[row..row + region.width as usize].iter()
+            })
+            .copied()
+            .collect();
+        Some(Frame {
+            width: region.width,
+            height: region.height,
+            pixels_row_major: pixels,
+        })
+    }
+    /// The size of this frame once scaled by `scale`, never smaller than a pixel.
+    /// The frame with straight alpha, from one saved with premultiplied alpha.
+    pub fn unpremultiplied(&self) -> Frame {
+        Frame {
+            width: self.width,
+            height: self.height,
+            pixels_row_major: self
+                .pixels_row_major
+                .iter()
+                .map(|pixel| pixel.unpremultiplied())
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+    fn scaled_size(width: u32, height: u32, scale: f64) -> (u32, u32) {
+        let scaled = |length: u32| ((length as f64 * scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+    /// Scale the frame by `scale`. Shrinking averages pixels together,
+    /// growing samples the frame with `filter`.
+    fn scaled(self, scale: f64, filter: Filter) -> Frame {
+        if scale < 1.0 {
+            self.downscaled(scale)
+        } else if scale > 1.0 {
+            self.upscaled(scale, filter)
+        } else {
+            self
+        }
+    }
+    /// Grow the frame by `scale`, which should be more than 1, sampling it with `filter`.
+    pub fn upscaled(&self, scale: f64, filter: Filter) -> Frame {
+        let (width, height) = Self::scaled_size(self.width, self.height, scale);
+        let step_x = self.width as f64 / width as f64;
+        let step_y = self.height as f64 / height as f64;
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (x, y) = ((x as f64 + 0.5) * step_x, (y as f64 + 0.5) * step_y);
+                blit::sample(self, x, y, filter).unwrap_or(Rgba::new(0, 0, 0, 0))
+            })
+            .collect();
+        Frame {
+            width,
+            height,
+            pixels_row_major: pixels,
+        }
+    }
+    /// Shrink the frame by `scale`, which should be at most 1, averaging every
+    /// pixel that ends up in the same place. Transparent pixels don't tint their neighbours.
+    pub fn downscaled(&self, scale: f64) -> Frame {
+        let (width, height) = Self::scaled_size(self.width, self.height, scale);
+        if (width, height) == (self.width, self.height) {
+            return self.clone();
+        }
+        // which source pixels, from the first up to but not including the second,
+        // land on the `index`th of `length` scaled pixels
+        let span = |index: u32, length: u32, source: u32| {
+            let start = (index as u64 * source as u64 / length as u64) as u32;
+            let end = ((index as u64 + 1) * source as u64 / length as u64) as u32;
+            (start, end.max(start + 1).min(source))
+        };
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            let (top, bottom) = span(y, height, self.height);
+            for x in 0..width {
+                let (left, right) = span(x, width, self.width);
+                let (mut red, mut green, mut blue, mut alpha) = (0u64, 0u64, 0u64, 0u64);
+                for source_y in top..bottom {
+                    let row = source_y as usize * self.width as usize;
+                    for pixel in &self.pixels_row_major[row + left as usize..row + right as usize] {
+                        let weight = pixel.alpha as u64;
+                        red += pixel.red as u64 * weight;
+                        green += pixel.green as u64 * weight;
+                        blue += pixel.blue as u64 * weight;
+                        alpha += weight;
+                    }
+                }
+                let count = ((bottom - top) * (right - left)) as u64;
+                pixels.push(match alpha {
+                    0 => Rgba::new(0, 0, 0, 0),
+                    _ => Rgba::new(
+                        (red / alpha) as u8,
+                        (green / alpha) as u8,
+                        (blue / alpha) as u8,
+                        (alpha / count) as u8,
+                    ),
+                });
+            }
+        }
+        Frame {
+            width,
+            height,
+            pixels_row_major: pixels.into(),
+        }
+    }
+}
+
+/// How much memory decoded frames may take up, for running many shimejis
+/// on machines short on RAM. Frames over the limits are downscaled as they're decoded,
+/// and their shimeji's window shrinks with them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameLimits {
+    /// The most pixels wide or tall a shimeji can be.
+    pub max_dimension: Option<u32>,
+    /// The most bytes every frame of one shimeji can take up together.
+    pub memory_budget: Option<usize>,
+}
+
+impl FrameLimits {
+    /// Limits from `SHIMEJI_MAX_FRAME_SIZE`, in pixels,
+    /// and `SHIMEJI_FRAME_BUDGET_MB`, in mebibytes per shimeji.
+    ///
+    /// # Errors
+    /// Errors if either is set but isn't a whole number.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let read = |variable: &str| -> anyhow::Result<Option<usize>> {
+            std::env::var(variable)
+                .ok()
+                .map(|value| {
+                    value
+                        .parse()
+                        .with_context(|| format!("{variable}={value} is not a whole number"))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            max_dimension: read("SHIMEJI_MAX_FRAME_SIZE")?.map(|size| size as u32),
+            memory_budget: read("SHIMEJI_FRAME_BUDGET_MB")?.map(|budget| budget << 20),
+        })
+    }
+    /// How much to scale the `frames` frames of a `width` by `height` shimeji
+    /// to fit in the limits, never more than 1.
+    pub fn scale_for(&self, width: u32, height: u32, frames: usize) -> f64 {
+        let mut scale: f64 = 1.0;
+        if let Some(max) = self.max_dimension {
+            scale = scale.min(max.max(1) as f64 / width.max(height).max(1) as f64);
+        }
+        if let Some(budget) = self.memory_budget {
+            let bytes = width as f64 * height as f64 * frames as f64 * size_of::<Rgba>() as f64;
+            if bytes > 0.0 {
+                // both sides shrink, so the area shrinks by the square
+                scale = scale.min((budget as f64 / bytes).sqrt());
+            }
+        }
+        scale
+    }
+}
+
+/// How frames are resized as they're decoded, see [`Frame::scaled`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Resample {
+    scale: f64,
+    filter: Filter,
+    /// Whether the images have premultiplied alpha, to make straight before anything else.
+    premultiplied: bool,
+}
+
+impl Resample {
+    /// Leave frames the size they are.
+    const NONE: Resample = Resample {
+        scale: 1.0,
+        filter: Filter::Nearest,
+        premultiplied: false,
+    };
+
+    fn apply(self, frame: Frame) -> Frame {
+        let frame = match self.premultiplied {
+            true => frame.unpremultiplied(),
+            false => frame,
+        };
+        frame.scaled(self.scale, self.filter)
+    }
+}
+
+/// A decoded prop, see [`PropXml`](crate::xml_parser::PropXml).
+#[derive(Debug, Clone)]
+pub struct PropData {
+    pub name: Arc<str>,
+    pub width: u32,
+    pub height: u32,
+    pub count: u32,
+    pub frame: Frame,
+}
+
+/// The width and height of a png, without decoding it.
+fn png_size(file_path: &str) -> anyhow::Result<(u32, u32)> {
+    let file = fs::File::open(file_path).context("File specified in frame data was invalid")?;
+    let info = png::Decoder::new(file).read_header_info()?.size();
+    Ok(info)
+}
+
+/// A decoder for the png read from `file` that turns palettes, grayscale and low bit depths
+/// into 8 bit channels, giving transparent colors an alpha channel, and cuts 16 bit
+/// channels down to 8, so every png comes out as one of the color types [`to_rgba`] reads.
+pub(crate) fn png_decoder<R: std::io::Read>(file: R) -> png::Decoder<R> {
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    decoder
+}
+
+/// The pixels in `bytes`, 8 bit channels of `color_type`, made RGBA.
+/// Grayscale is spread over every color channel, and colors with no alpha are opaque.
+pub(crate) fn to_rgba(color_type: ColorType, bytes: &[u8]) -> anyhow::Result<Vec<Rgba>> {
+    let pixels = match color_type {
+        ColorType::Rgba => bytes
+            .chunks_exact(4)
+            .map(|pixel| Rgba::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+            .collect(),
+        ColorType::Rgb => bytes
+            .chunks_exact(3)
+            .map(|pixel| Rgba::new(pixel[0], pixel[1], pixel[2], 255))
+            .collect(),
+        ColorType::GrayscaleAlpha => bytes
+            .chunks_exact(2)
+            .map(|pixel| Rgba::new(pixel[0], pixel[0], pixel[0], pixel[1]))
+            .collect(),
+        ColorType::Grayscale => bytes
+            .iter()
+            .map(|&gray| Rgba::new(gray, gray, gray, 255))
+            .collect(),
+        // expanded into Rgb or Rgba by the decoder's transformations
+        ColorType::Indexed => bail!("palette pngs should have been expanded while decoding"),
+    };
+    Ok(pixels)
+}
+
+/// Decode a png, resized by `resample`.
+fn decode_png(file_path: &str, resample: Resample) -> anyhow::Result<Frame> {
+    let file = fs::File::open(file_path).context("File specified in frame data was invalid")?;
+    let mut reader = png_decoder(file).read_info()?;
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buf)
+        .context("could not read first png image frame")?;
+    log::debug!("{info:?}");
+    let rgba_vec = to_rgba(info.color_type, &buf[..info.buffer_size()])?;
+    let frame = Frame {
+        width: info.width,
+        height: info.height,
+        pixels_row_major: rgba_vec.into(),
+    };
+    Ok(resample.apply(frame))
+}
+
+fn is_gif(file_path: &str) -> bool {
+    Path::new(file_path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"))
+}
+
+/// Decode every frame of a GIF, each drawn over what the ones before left behind,
+/// resized by `resample`, along with how long each one shows for.
+fn decode_gif(
+    file_path: &str,
+    resample: Resample,
+) -> anyhow::Result<(Vec<Frame>, Option<Vec<Duration>>)> {
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+    let file = fs::File::open(file_path).context("File specified in animation src was invalid")?;
+    let decoder = GifDecoder::new(std::io::BufReader::new(file))?;
+    let mut frames = vec![];
+    let mut delays = vec![];
+    for frame in decoder.into_frames() {
+        let frame = frame
+            .with_context(|| format!("could not read frame {} of {file_path}", frames.len()))?;
+        delays.push(Duration::from(frame.delay()));
+        let buffer = frame.into_buffer();
+        let (width, height) = buffer.dimensions();
+        let pixels: Vec<_> = buffer
+            .pixels()
+            .map(|&image::Rgba([red, green, blue, alpha])| Rgba::new(red, green, blue, alpha))
+            .collect();
+        frames.push(resample.apply(Frame {
+            width,
+            height,
+            pixels_row_major: pixels.into(),
+        }));
+    }
+    if frames.is_empty() {
+        bail!("{file_path} has no frames");
+    }
+    Ok((frames, Some(delays)))
+}
+
+/// Open an animated png, checking it is one we can read.
+fn open_apng(file_path: &str) -> anyhow::Result<png::Reader<fs::File>> {
+    let file = fs::File::open(file_path).context("File specified in animation src was invalid")?;
+    let reader = png_decoder(file).read_info()?;
+    let (color_type, bit_depth) = reader.output_color_type();
+    if bit_depth != png::BitDepth::Eight || color_type == ColorType::Indexed {
+        bail!("Color type unsupported: {color_type:?} at {bit_depth:?}")
+    }
+    Ok(reader)
+}
+
+/// How many frames the animated png at `file_path` has, without decoding them.
+/// GIFs don't say up front, so theirs are decoded to be counted.
+fn apng_frame_count(file_path: &str) -> anyhow::Result<usize> {
+    if is_gif(file_path) {
+        return Ok(decode_gif(file_path, Resample::NONE)?.0.len());
+    }
+    let reader = open_apng(file_path)?;
+    Ok(reader
+        .info()
+        .animation_control
+        .map_or(1, |control| control.num_frames as usize))
+}
+
+/// Decode every frame of an animated png, resized by `resample`,
+/// along with how long each one shows for.
+///
+/// A png that isn't animated is a single frame, and GIFs go to [`decode_gif`].
+fn decode_apng(
+    file_path: &str,
+    resample: Resample,
+) -> anyhow::Result<(Vec<Frame>, Option<Vec<Duration>>)> {
+    if is_gif(file_path) {
+        return decode_gif(file_path, resample);
+    }
+    let mut reader = open_apng(file_path)?;
+    let Some(control) = reader.info().animation_control else {
+        return Ok((vec![decode_png(file_path, resample)?], None));
+    };
+    let (width, height) = reader.info().size();
+    // without an fcTL before it, the first image is only for viewers that can't animate
+    let mut remaining = control.num_frames as usize;
+    if reader.info().frame_control.is_none() {
+        remaining += 1;
+    }
+
+    let blank = Rgba::new(0, 0, 0, 0);
+    let mut canvas = vec![blank; width as usize * height as usize];
+    let mut buf = vec![0; reader.output_buffer_size()];
+    // num_frames is only what the file claims, so nothing is reserved up front for it
+    let mut frames = vec![];
+    let mut delays = vec![];
+    for _ in 0..remaining {
+        let info = reader
+            .next_frame(&mut buf)
+            .with_context(|| format!("could not read frame {} of {file_path}", frames.len()))?;
+        let Some(control) = reader.info().frame_control else {
+            continue;
+        };
+        let previous = (control.dispose_op == png::DisposeOp::Previous).then(|| canvas.clone());
+
+        let (left, top) = (control.x_offset as usize, control.y_offset as usize);
+        for (y, row) in buf[..info.buffer_size()]
+            .chunks_exact(info.line_size)
+            .enumerate()
+        {
+            let start = (top + y) * width as usize + left;
+            let pixels = canvas[start..start + info.width as usize].iter_mut();
+            for (pixel, source) in pixels.zip(to_rgba(info.color_type, row)?) {
+                *pixel = match control.blend_op {
+                    png::BlendOp::Source => source,
+                    png::BlendOp::Over => source.over(*pixel),
+                };
+            }
+        }
+        frames.push(Frame {
+            width,
+            height,
+            pixels_row_major: canvas.clone().into(),
+        });
+
+        match control.dispose_op {
+            png::DisposeOp::None => (),
+            png::DisposeOp::Background => {
+                for y in top..top + info.height as usize {
+                    let start = y * width as usize + left;
+                    canvas[start..start + info.width as usize].fill(blank);
+                }
+            }
+            png::DisposeOp::Previous => canvas = previous.unwrap(),
+        }
+        // a denominator of 0 means hundredths of a second
+        let denominator = match control.delay_den {
+            0 => 100.0,
+            denominator => denominator as f64,
+        };
+        delays.push(Duration::from_secs_f64(
+            control.delay_num as f64 / denominator,
+        ));
+    }
+    let frames: Vec<_> = frames
+        .into_iter()
+        .map(|frame| resample.apply(frame))
+        .collect();
+    Ok((frames, Some(delays)))
+}
+
+/// `decoded`, or `placeholder` with the reason logged if it couldn't be decoded,
+/// once for each file.
+fn or_placeholder<T>(
+    decoded: anyhow::Result<T>,
+    file_path: &str,
+    placeholder: impl FnOnce() -> T,
+) -> T {
+    decoded.unwrap_or_else(|why| {
+        once!(
+            file_path,
+            log::Level::Error,
+            "Could not decode {file_path}, showing a placeholder instead: {why:#}"
+        );
+        placeholder()
+    })
+}
+
+/// How `animation` loops: as it says, or once if it chains into another and forever if not.
+fn loop_mode_of(animation: &AnimationXml) -> LoopMode {
+    animation.loop_mode.unwrap_or(match animation.next {
+        Some(_) => LoopMode::Once,
+        None => LoopMode::Loop,
+    })
+}
+
+/// Decode `animation`, with `placeholder` standing in for any image that can't be decoded.
+fn decode_animation(
+    mut animation: AnimationXml,
+    resample: Resample,
+    placeholder: &Frame,
+) -> anyhow::Result<AnimationData> {
+    animation.frames.sort_by_key(|f| f.number);
+    let (frames, delays) = decode_frames(&animation, resample, placeholder)?;
+    Ok(assemble_animation(
+        animation,
+        frames,
+        delays,
+        resample.scale,
+    ))
+}
+
+/// The pack's [`FrameStore`] for `animations` decoded with `resample`, if it can have one.
+fn frame_store(animations: &[AnimationXml], resample: Resample) -> Option<FrameStore> {
+    FrameStore::for_animations(
+        animations,
+        (
+            resample.scale.to_bits(),
+            resample.filter,
+            resample.premultiplied,
+        ),
+    )
+}
+
+/// The frames of all `animations` of a pack from its `store`, if they were saved there before.
+fn stored_frames(store: Option<&FrameStore>, animations: usize) -> Option<Vec<DecodedFrames>> {
+    let store = store?;
+    match store.load() {
+        Ok(Some(decoded)) if decoded.len() == animations => {
+            log::debug!("Took frames from {}", store.path().display());
+            Some(decoded)
+        }
+        Ok(_) => None,
+        Err(why) => {
+            log::warn!("Decoding frames again: {why:#}");
+            None
+        }
+    }
+}
+
+/// Decode the frames of every one of `animations`, saving them to the pack's `store` if it
+/// has one.
+fn decode_animations(
+    pack: &str,
+    animations: &[AnimationXml],
+    resample: Resample,
+    placeholder: &Frame,
+    store: Option<&FrameStore>,
+) -> anyhow::Result<Vec<DecodedFrames>> {
+    let started = Instant::now();
+    let progress = Progress::new(pack, animations.len());
+    let decoded = in_parallel(animations, |animation| {
+        let decoded = decode_frames(animation, resample, placeholder);
+        progress.advance();
+        decoded
+    })
+    .into_iter()
+    .collect::<anyhow::Result<Vec<_>>>()?;
+    log::debug!(
+        "Decoded {} animations of {pack} in {:.2?}",
+        animations.len(),
+        started.elapsed()
+    );
+    if let Some(store) = store {
+        if let Err(why) = store.save(&decoded) {
+            log::warn!("{why:#}");
+        }
+    }
+    Ok(decoded)
+}
+
+/// `work` done on every one of `items`, in order, spread over as many threads as there are
+/// cores. A panic in `work` is passed on once every thread is done.
+pub(crate) fn in_parallel<T: Sync, R: Send>(items: &[T], work: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = thread::available_parallelism().map_or(1, NonZero::get);
+    if items.len() < 2 || threads < 2 {
+        return items.iter().map(work).collect();
+    }
+    let work = &work;
+    thread::scope(|scope| {
+        items
+            .chunks(items.len().div_ceil(threads))
+            .map(|chunk| scope.spawn(move || chunk.iter().map(work).collect::<Vec<_>>()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|why| std::panic::resume_unwind(why))
+            })
+            .collect()
+    })
+}
+
+/// How many of a pack's animations have been decoded, logged every tenth of the way
+/// for packs big enough to take a while.
+struct Progress<'p> {
+    pack: &'p str,
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl<'p> Progress<'p> {
+    /// Packs with fewer animations than this load too fast to be worth reporting on.
+    const WORTH_REPORTING: usize = 20;
+
+    fn new(pack: &'p str, total: usize) -> Self {
+        Self {
+            pack,
+            total,
+            done: AtomicUsize::new(0),
+        }
+    }
+    fn advance(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.total >= Self::WORTH_REPORTING
+            && done * 10 / self.total > (done - 1) * 10 / self.total
+        {
+            log::info!("Decoding {}: {done}/{} animations", self.pack, self.total);
+        }
+    }
+}
+
+/// Decode the frames of `animation`, whose frames are in order, with `placeholder`
+/// standing in for any image that can't be decoded.
+fn decode_frames(
+    animation: &AnimationXml,
+    resample: Resample,
+    placeholder: &Frame,
+) -> anyhow::Result<DecodedFrames> {
+    if let Some(src) = &animation.src {
+        return Ok(or_placeholder(decode_apng(src, resample), src, || {
+            (vec![placeholder.clone()], None)
+        }));
+    }
+    let mut frame_buf: Vec<Frame> = Vec::with_capacity(animation.frames.len());
+    // sprite sheets are decoded once, then cut up
+    let mut sheets: HashMap<&str, Frame> = HashMap::new();
+    for frame in animation.frames.iter() {
+        let Some(region) = frame.region else {
+            let decoded = decode_png(&frame.file_path, resample);
+            frame_buf.push(or_placeholder(decoded, &frame.file_path, || {
+                placeholder.clone()
+            }));
+            continue;
+        };
+        if !sheets.contains_key(frame.file_path.as_str()) {
+            let decoded = decode_png(&frame.file_path, Resample::NONE).map(Some);
+            let Some(sheet) = or_placeholder(decoded, &frame.file_path, || None) else {
+                frame_buf.push(placeholder.clone());
+                continue;
+            };
+            sheets.insert(&frame.file_path, sheet);
+        }
+        let Some(cropped) = sheets[frame.file_path.as_str()].cropped(region) else {
+            bail!(
+                "frame {} of {} is outside of {}",
+                frame.number,
+                animation.name,
+                frame.file_path
+            );
+        };
+        frame_buf.push(resample.apply(cropped));
+    }
+    Ok((frame_buf, None))
+}
+
+/// Make sure every one of `animation`'s decoded `frames` has as many pixels as a `width` by
+/// `height` shimeji, so none are cut short or run over when they're drawn.
+fn check_frame_sizes(
+    animation: &AnimationXml,
+    frames: &[Frame],
+    width: u32,
+    height: u32,
+) -> anyhow::Result<()> {
+    for (index, frame) in frames.iter().enumerate() {
+        if let Some(problem) = frame_size_problem(animation, index, frame, width, height) {
+            bail!("{problem}");
+        }
+    }
+    Ok(())
+}
+
+/// What's wrong with `frame`, the `index`th of `animation`, if it doesn't have
+/// as many pixels as a `width` by `height` shimeji.
+fn frame_size_problem(
+    animation: &AnimationXml,
+    index: usize,
+    frame: &Frame,
+    width: u32,
+    height: u32,
+) -> Option<String> {
+    let expected = width as usize * height as usize;
+    let pixels = frame.pixels_row_major.len();
+    if pixels == expected && pixels == frame.width as usize * frame.height as usize {
+        return None;
+    }
+    let file = match (&animation.src, animation.frames.get(index)) {
+        (Some(src), _) => src.as_str(),
+        (None, Some(frame)) => frame.file_path.as_str(),
+        (None, None) => "an unknown file",
+    };
+    Some(format!(
+        "frame {} of {} from {file} is {}x{} ({pixels} pixels), \
+         but the shimeji is {width}x{height} ({expected} pixels)",
+        index + 1,
+        animation.name,
+        frame.width,
+        frame.height,
+    ))
+}
+
+/// Why each of `animation`'s images, whose frames are in order, can't be decoded
+/// or doesn't fit a `width` by `height` shimeji.
+fn animation_problems(animation: &AnimationXml, width: u32, height: u32) -> Vec<String> {
+    let undecodable = |file: &str, why: anyhow::Error| {
+        format!("could not decode {file} for {}: {why:#}", animation.name)
+    };
+    let frames: Vec<Result<Frame, String>> = match &animation.src {
+        Some(src) => match decode_apng(src, Resample::NONE) {
+            Ok((frames, _)) => frames.into_iter().map(Ok).collect(),
+            Err(why) => return vec![undecodable(src, why)],
+        },
+        None => {
+            let mut sheets: HashMap<&str, Result<Frame, String>> = HashMap::new();
+            let mut frames = Vec::with_capacity(animation.frames.len());
+            for frame in animation.frames.iter() {
+                let file = frame.file_path.as_str();
+                let decode =
+                    || decode_png(file, Resample::NONE).map_err(|why| undecodable(file, why));
+                let Some(region) = frame.region else {
+                    frames.push(decode());
+                    continue;
+                };
+                let sheet = sheets.entry(file).or_insert_with(decode);
+                frames.push(sheet.as_ref().map_err(Clone::clone).and_then(|sheet| {
+                    sheet.cropped(region).ok_or_else(|| {
+                        format!(
+                            "frame {} of {} is outside of {file}",
+                            frame.number, animation.name
+                        )
+                    })
+                }));
+            }
+            frames
+        }
+    };
+    let mut problems = vec![];
+    for (index, frame) in frames.into_iter().enumerate() {
+        match frame {
+            Ok(frame) => {
+                problems.extend(frame_size_problem(animation, index, &frame, width, height))
+            }
+            Err(why) => problems.push(why),
+        }
+    }
+    // every frame cut from a sheet that can't be decoded says so
+    problems.dedup();
+    problems
+}
+
+/// Put `animation` together with its decoded `frames`, and the `delays` of its animated png.
+/// Anchors are scaled `scale` times, like the frames were as they were decoded.
+fn assemble_animation(
+    animation: AnimationXml,
+    frames: Vec<Frame>,
+    delays: Option<Vec<Duration>>,
+    scale: f64,
+) -> AnimationData {
+    let loop_mode = loop_mode_of(&animation);
+    if animation.src.is_some() {
+        // an fps set in the pack wins over the file's own delays
+        let durations = match (animation.fps, delays) {
+            (None, Some(delays)) => delays,
+            (fps, _) => {
+                let duration = Duration::from_secs_f64(1.0 / fps.unwrap_or(DEFAULT_FPS));
+                vec![duration; frames.len()]
+            }
+        };
+        return AnimationData {
+            durations,
+            weight: animation.weight,
+            loop_mode,
+            next: animation.next,
+            frames,
+            sounds: vec![],
+            anchors: vec![],
+            hitboxes: vec![],
+            steps: vec![],
+        };
+    }
+    let fps = animation.fps.unwrap_or(DEFAULT_FPS);
+
+    let durations = animation
+        .frames
+        .iter()
+        .map(|frame| frame.duration.unwrap_or(Duration::from_secs_f64(1.0 / fps)))
+        .collect();
+    let sounds = animation
+        .frames
+        .iter()
+        .map(|frame| frame.sound.clone())
+        .collect();
+    let anchors = animation
+        .frames
+        .iter()
+        .map(|frame| Anchor {
+            x: frame.anchor_x.map(|x| x * scale),
+            y: frame.anchor_y.map(|y| y * scale),
+        })
+        .collect();
+    let scaled = |value: u32| (value as f64 * scale).round() as u32;
+    let hitboxes = animation
+        .frames
+        .iter()
+        .map(|frame| {
+            frame
+                .hitboxes
+                .iter()
+                .map(|hitbox| FrameRegion {
+                    x: scaled(hitbox.x),
+                    y: scaled(hitbox.y),
+                    width: scaled(hitbox.width).max(1),
+                    height: scaled(hitbox.height).max(1),
+                })
+                .collect()
+        })
+        .collect();
+    AnimationData {
+        durations,
+        weight: animation.weight,
+        loop_mode,
+        next: animation.next,
+        frames,
+        sounds,
+        anchors,
+        hitboxes,
+        steps: vec![],
+    }
+}
+
+/// `sequence` as an animation that plays its steps, which are among `animations`.
+fn sequence_animation(
+    sequence: SequenceXml,
+    animations: &HashMap<String, AnimationData>,
+) -> anyhow::Result<(String, AnimationData)> {
+    let name = sequence.name;
+    if animations.contains_key(&name) {
+        bail!("sequence {name} has the same name as an animation");
+    }
+    if sequence.steps.is_empty() {
+        bail!("sequence {name} has no steps");
+    }
+    for step in sequence.steps.iter() {
+        match animations.get(&step.animation) {
+            None => bail!(
+                "sequence {name} plays {}, which does not exist",
+                step.animation
+            ),
+            // sequences don't nest
+            Some(animation) if !animation.steps.is_empty() => bail!(
+                "sequence {name} plays {}, which is another sequence",
+                step.animation
+            ),
+            Some(_) => (),
+        }
+    }
+    let animation = AnimationData {
+        durations: vec![],
+        weight: sequence.weight,
+        next: sequence.next,
+        loop_mode: LoopMode::Once,
+        frames: vec![],
+        sounds: vec![],
+        anchors: vec![],
+        hitboxes: vec![],
+        steps: sequence.steps,
+    };
+    Ok((name, animation))
+}
+
+/// Where other installed packs are found when a pack borrows their animations
+/// with `<Use pack="..."/>`.
+///
+/// A pack named `base-cat` is the file `base-cat.xml` in the library's directory.
+///
+/// Also carries the [`FrameLimits`] every pack is decoded with, how much to scale them,
+/// whether to refuse packs with warnings, and whether to decode their frames lazily.
+#[derive(Debug, Clone)]
+pub struct PackLibrary {
+    directory: PathBuf,
+    limits: FrameLimits,
+    scale: f64,
+    strict: bool,
+    lazy_frames: bool,
+}
+
+impl PackLibrary {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            limits: FrameLimits::default(),
+            scale: 1.0,
+            strict: false,
+            lazy_frames: false,
+        }
+    }
+    pub fn with_frame_limits(mut self, limits: FrameLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    /// Draw every pack `scale` times as big as it asks for, still within the frame limits.
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+    /// Refuse packs with unknown elements, attributes or keys, which are only logged
+    /// otherwise, so typos like `<Animaton>` don't go unnoticed.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+    /// Have [`ShimejiLibrary::load`] decode only the animations of each pack's initial
+    /// behavior, showing placeholders for the rest until [`Undecoded::decode`] gets to them,
+    /// so big packs show up sooner. Packs whose frames are in their [`FrameStore`] are
+    /// taken from there whole.
+    pub fn with_lazy_frames(mut self, lazy: bool) -> Self {
+        self.lazy_frames = lazy;
+        self
+    }
+    /// `data` parsed from `file` with its warnings logged, or the first of them as an error
+    /// if the library is strict.
+    fn heed_warnings(
+        &self,
+        mut data: XmlReturnData,
+        file: &Path,
+    ) -> Result<XmlReturnData, PackError> {
+        let mut warnings = std::mem::take(&mut data.warnings);
+        if self.strict && !warnings.is_empty() {
+            return Err(warnings.swap_remove(0).in_file(file));
+        }
+        for warning in warnings {
+            log::warn!("{}", warning.in_file(file));
+        }
+        Ok(data)
+    }
+    /// The directory in `SHIMEJI_PACK_DIR`, or `./packs`.
+    pub fn from_env() -> Self {
+        Self::new(std::env::var_os("SHIMEJI_PACK_DIR").unwrap_or(OsString::from("./packs")))
+    }
+    pub fn path_of(&self, pack: &str) -> PathBuf {
+        self.directory.join(format!("{pack}.xml"))
+    }
+    /// Decode every animation `uses` asks for, resized like the borrowing pack.
+    ///
+    /// Only animations defined in the other packs themselves can be borrowed,
+    /// not ones they borrowed in turn.
+    ///
+    /// Every file read along the way is added to `sources`.
+    fn resolve(
+        &self,
+        uses: Vec<UseXml>,
+        resample: Resample,
+        placeholder: &Frame,
+        sources: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<Vec<(String, AnimationData)>> {
+        let mut packs: HashMap<String, Vec<AnimationXml>> = HashMap::new();
+        let mut resolved = Vec::with_capacity(uses.len());
+        for used in uses {
+            if !packs.contains_key(&used.pack) {
+                let path = self.path_of(&used.pack);
+                let file = fs::File::open(&path)
+                    .with_context(|| format!("pack {} is not installed", used.pack))?;
+                let data = parse(file).map_err(|why| why.in_file(&path))?;
+                let data = self.heed_warnings(*data, &path)?;
+                sources.push(path);
+                packs.insert(used.pack.clone(), data.animations);
+            }
+            let animations = packs.get_mut(&used.pack).unwrap();
+            let Some(index) = animations
+                .iter()
+                .position(|animation| animation.name == used.animation)
+            else {
+                bail!("pack {} has no animation {}", used.pack, used.animation);
+            };
+            let animation = animations.swap_remove(index);
+            sources.extend(files_of(&animation));
+            let animation =
+                decode_animation(animation, resample, placeholder).with_context(|| {
+                    format!("could not decode {} from {}", used.animation, used.pack)
+                })?;
+            resolved.push((used.name, animation));
+        }
+        Ok(resolved)
+    }
 }
 
 pub fn create_shimeji_data_from_file_name(
     file_name: impl Into<OsString>,
+) -> anyhow::Result<ShimejiData> {
+    create_shimeji_data_with_library(file_name, &PackLibrary::from_env())
+}
+
+/// Like [`create_shimeji_data_from_file_name`],
+/// borrowing animations from the packs in `library`.
+pub fn create_shimeji_data_with_library(
+    file_name: impl Into<OsString>,
+    library: &PackLibrary,
 ) -> anyhow::Result<ShimejiData> {
     let file_name: OsString = file_name.into();
-    let file = fs::File::open(file_name).context("file name passed was invalid")?;
-    let data = parse(file).context("failed to parse XML data")?;
+    if is_shimeji_ee_pack(Path::new(&file_name)) {
+        return create_shimeji_data_from_shimeji_ee(file_name, library.limits);
+    }
+    if config::Format::of(Path::new(&file_name)).is_some() {
+        let data = config::parse_file(Path::new(&file_name)).context("failed to parse pack")?;
+        let data = library.heed_warnings(data, Path::new(&file_name))?;
+        return shimeji_data_from_xml(data, library);
+    }
+    if archive::is_archive(Path::new(&file_name)) {
+        let mut parsed = parse_archive(Path::new(&file_name))?;
+        if parsed.len() > 1 {
+            return Err(PackError::from(XmlParseError::MultipleShimeji)
+                .in_file(&file_name)
+                .into());
+        }
+        let data = library.heed_warnings(parsed.remove(0), Path::new(&file_name))?;
+        return shimeji_data_from_xml(data, library);
+    }
+    let file = fs::File::open(&file_name).context("file name passed was invalid")?;
+    let data = parse(file).map_err(|why| why.in_file(&file_name))?;
+    let data = library.heed_warnings(*data, Path::new(&file_name))?;
+    shimeji_data_from_xml(data, library)
+}
 
-    // we have the data, create animation data in memory for the shimeji
+/// Every shimeji in the pack archive at `path`, see [`archive`].
+pub(crate) fn parse_archive(path: &Path) -> anyhow::Result<Vec<XmlReturnData>> {
+    let xml = archive::unpack(path)?;
+    let file = fs::File::open(&xml).with_context(|| format!("could not open {}", xml.display()))?;
+    let base = xml.parent().unwrap_or(Path::new(""));
+    Ok(parse_many_in(file, base).map_err(|why| why.in_file(path))?)
+}
 
-    let mut decoded_animations = HashMap::with_capacity(data.animations.len());
-    let width = data.shimeji_width;
-    let height = data.shimeji_height;
-    for mut animation in data.animations {
-        let fps = animation.fps.unwrap_or(24.0);
+/// Every image an animation is read from.
+fn files_of(animation: &AnimationXml) -> impl Iterator<Item = PathBuf> + '_ {
+    animation
+        .frames
+        .iter()
+        .map(|frame| PathBuf::from(&frame.file_path))
+        .chain(animation.src.iter().map(PathBuf::from))
+}
 
-        animation.frames.sort_by_key(|f| f.number);
+/// Every file in `directory` and the directories in it.
+fn files_under(directory: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(files_under(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
 
-        let mut frame_buf: Vec<Frame> = Vec::with_capacity(animation.frames.len());
-        for frame in animation.frames {
-            let file = fs::File::open(frame.file_path)
-                .context("File specified in frame data was invalid")?;
-            let decoder = png::Decoder::new(file);
+pub(crate) fn is_shimeji_ee_pack(path: &Path) -> bool {
+    path.join("conf").join("actions.xml").is_file()
+}
 
-            let mut reader = decoder.read_info()?;
+fn shimeji_data_from_xml(
+    data: XmlReturnData,
+    library: &PackLibrary,
+) -> anyhow::Result<ShimejiData> {
+    Ok(load_from_xml(data, library, false)?.0)
+}
 
-            let mut buf = vec![0; reader.output_buffer_size()];
-            let info = reader
-                .next_frame(&mut buf)
-                .context("could not read first png image frame")?;
-            log::debug!("{info:?}");
-            if info.color_type != ColorType::Rgba {
-                bail!("Color type unsupported: {0:?}", info.color_type)
-            }
-            let size = info.buffer_size();
-            if size % 4 != 0 {
-                bail!("size of RGBA data buffer not divisible by 4, malformed size: {size}")
-            }
-            buf.truncate(size);
+/// `data` loaded, with every one of its animations decoded unless it's `lazy`, in which case
+/// it comes with the ones left to decode, see [`PackLibrary::with_lazy_frames`].
+fn load_from_xml(
+    data: XmlReturnData,
+    library: &PackLibrary,
+    lazy: bool,
+) -> anyhow::Result<(ShimejiData, Option<Undecoded>)> {
+    // we have the data, create animation data in memory for the shimeji
 
-            let mut rgba_vec = Vec::with_capacity(size / 4);
-            let mut buf_iter = buf.into_iter();
-            while let Some(byte_1) = buf_iter.next() {
-                let byte_2 = buf_iter.next().unwrap();
-                let byte_3 = buf_iter.next().unwrap();
-                let byte_4 = buf_iter.next().unwrap();
+    let mut decoded_animations = HashMap::with_capacity(data.animations.len() + data.uses.len());
+    let mut sources: Vec<PathBuf> = data
+        .animations
+        .iter()
+        .flat_map(files_of)
+        .chain(data.props.iter().map(|prop| PathBuf::from(&prop.file_path)))
+        .collect();
+    // borrowed animations aren't counted, their packs haven't been read yet
+    let counts = data
+        .animations
+        .iter()
+        .map(|animation| match &animation.src {
+            Some(src) => apng_frame_count(src),
+            None => Ok(animation.frames.len()),
+        })
+        .collect::<anyhow::Result<Vec<usize>>>()?;
+    let frames = counts.iter().sum();
+    // the limits are for the shimeji as big as the pack asks for
+    let asked = data.scale * library.scale;
+    let (asked_width, asked_height) =
+        Frame::scaled_size(data.shimeji_width, data.shimeji_height, asked);
+    let limited = library.limits.scale_for(asked_width, asked_height, frames);
+    if limited < 1.0 {
+        log::info!(
+            "Shrinking {} to {limited:.2}x to fit the frame limits",
+            data.name
+        );
+    }
+    let scale = asked * limited;
+    let resample = Resample {
+        scale,
+        filter: match data.scaling {
+            ScalingMode::Linear => Filter::Linear,
+            ScalingMode::Integer | ScalingMode::Nearest => Filter::Nearest,
+        },
+        premultiplied: data.premultiplied,
+    };
+    let (width, height) = Frame::scaled_size(data.shimeji_width, data.shimeji_height, scale);
+    let placeholder = Frame::placeholder(width, height);
+    let mut animations = data.animations;
+    for animation in animations.iter_mut() {
+        animation.frames.sort_by_key(|f| f.number);
+    }
+    let store = frame_store(&animations, resample);
+    let stored = stored_frames(store.as_ref(), animations.len());
+    // kept to decode once the pack is loaded with placeholders
+    let undecoded = (lazy && stored.is_none()).then(|| animations.clone());
+    let decoded = match stored {
+        Some(decoded) => decoded,
+        None if lazy => {
+            let placeholder = placeholder.clone().shared();
+            counts
+                .iter()
+                .map(|&count| (vec![placeholder.clone(); count], None))
+                .collect()
+        }
+        None => decode_animations(
+            &data.name,
+            &animations,
+            resample,
+            &placeholder,
+            store.as_ref(),
+        )?,
+    };
+    for (animation, (frames, delays)) in animations.into_iter().zip(decoded) {
+        check_frame_sizes(&animation, &frames, width, height)?;
+        let mut assembled = assemble_animation(animation.clone(), frames, delays, resample.scale);
+        if undecoded.is_some() {
+            // silent until its frames are decoded
+            assembled.sounds.clear();
+        }
+        decoded_animations.insert(animation.name, assembled);
+    }
+    for (name, animation) in library.resolve(data.uses, resample, &placeholder, &mut sources)? {
+        if decoded_animations.contains_key(&name) {
+            bail!("borrowed animation {name} has the same name as one of the pack's own");
+        }
+        decoded_animations.insert(name, animation);
+    }
+    for sequence in data.sequences {
+        let (name, animation) = sequence_animation(sequence, &decoded_animations)?;
+        decoded_animations.insert(name, animation);
+    }
 
-                rgba_vec.push(Rgba::new(byte_1, byte_2, byte_3, byte_4))
+    for (name, animation) in decoded_animations.iter() {
+        if let Some(next) = &animation.next {
+            if !decoded_animations.contains_key(next) {
+                bail!("animation {name} is followed by {next}, which does not exist");
             }
-            let bytes: Box<[Rgba]> = rgba_vec.into_boxed_slice();
-            frame_buf.push(Frame {
-                pixels_row_major: bytes,
-            })
         }
-        decoded_animations.insert(
-            animation.name,
-            AnimationData {
-                fps,
-                frames: frame_buf,
-            },
-        );
     }
 
+    let props = data
+        .props
+        .into_iter()
+        .map(|prop| {
+            let frame = decode_png(&prop.file_path, resample)
+                .with_context(|| format!("could not decode prop {}", prop.name))?;
+            let (width, height) = Frame::scaled_size(prop.width, prop.height, scale);
+            Ok(Arc::new(PropData {
+                name: Arc::from(prop.name),
+                width,
+                height,
+                count: prop.count,
+                frame,
+            }))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let behaviors = match data.behaviors {
+        Some(behaviors) => {
+            BehaviorTable::new(behaviors.behaviors, behaviors.initial, &decoded_animations)
+                .context("invalid behaviors")?
+        }
+        None => BehaviorTable::from_animations(
+            &decoded_animations,
+            data.walk_speed.unwrap_or(DEFAULT_WALK_SPEED),
+        ),
+    };
+
     let ret = ShimejiData {
         name: data.name,
         animations: decoded_animations,
+        behaviors,
+        props,
+        gravity: data.gravity,
+        shadow: data.shadow,
+        sources,
+        scaling: data.scaling,
+        letterbox: data.letterbox,
+        frame_cache: FrameCache::default(),
+        variants: VariantCache::default(),
         height,
         width,
     };
@@ -87,5 +1313,661 @@ pub fn create_shimeji_data_from_file_name(
     //     "{:#?}",
     //     ret.animations.get("idle").unwrap().frames.first().unwrap()
     // );
-    Ok(ret)
+    match undecoded {
+        Some(animations) => Undecoded::start(ret, animations, resample, placeholder, store),
+        None => Ok((ret, None)),
+    }
+}
+
+/// The animations `behavior` plays: its own and the ones its script plays by name,
+/// then the ones they chain into and the steps of sequences among them.
+fn animations_of<'a>(
+    behavior: &'a Behavior,
+    animations: &'a HashMap<String, AnimationData>,
+) -> Vec<&'a str> {
+    let mut played: Vec<&str> = vec![];
+    let scripted = behavior
+        .script
+        .iter()
+        .flat_map(|script| script.animations());
+    for name in std::iter::once(behavior.animation.as_str()).chain(scripted) {
+        if !played.contains(&name) {
+            played.push(name);
+        }
+    }
+    let mut at = 0;
+    while let Some(&name) = played.get(at) {
+        at += 1;
+        let Some(animation) = animations.get(name) else {
+            continue;
+        };
+        let steps = animation.steps.iter().map(|step| step.animation.as_str());
+        for follows in steps.chain(animation.next.as_deref()) {
+            if !played.contains(&follows) {
+                played.push(follows);
+            }
+        }
+    }
+    played
+}
+
+/// Every animation `behaviors` play, in the order a shimeji is likeliest to play them,
+/// see [`BehaviorTable::likeliest_first`].
+pub(crate) fn likeliest_animations<'a>(
+    behaviors: &'a BehaviorTable,
+    animations: &'a HashMap<String, AnimationData>,
+) -> Vec<&'a str> {
+    let mut ordered: Vec<&str> = vec![];
+    // no shimeji to roll for yet, any roll makes as good a guess
+    let mut rng = Rng::new();
+    for behavior in behaviors.likeliest_first(&WorldSnapshot::default(), &mut rng) {
+        for animation in animations_of(behavior, animations) {
+            if !ordered.contains(&animation) {
+                ordered.push(animation);
+            }
+        }
+    }
+    ordered
+}
+
+/// A pack loaded with [`PackLibrary::with_lazy_frames`], whose animations show placeholders
+/// until [`Undecoded::decode`] gets to them.
+#[derive(derive_more::Debug, Clone)]
+pub struct Undecoded {
+    /// The pack, with its frames as far as they've been decoded.
+    #[debug("{}", self.data.name)]
+    data: ShimejiData,
+    /// Every one of the pack's own animations, in the order its [`FrameStore`] keeps them.
+    #[debug(skip)]
+    animations: Vec<AnimationXml>,
+    /// The frames of each of `animations`, once they're decoded.
+    #[debug(skip)]
+    decoded: Vec<Option<DecodedFrames>>,
+    /// Where in `animations` the ones left to decode are, likeliest to play first.
+    left: VecDeque<usize>,
+    resample: Resample,
+    #[debug(skip)]
+    placeholder: Frame,
+    store: Option<FrameStore>,
+}
+
+impl Undecoded {
+    /// The least time between two handovers of a pack in [`Undecoded::decode`],
+    /// so its shimejis aren't swapped after every animation.
+    pub const DELIVER_EVERY: Duration = Duration::from_secs(1);
+
+    /// `data`, loaded with placeholders for its own `animations`, with the ones its initial
+    /// behavior plays decoded, and the rest of them left to decode if there are any.
+    fn start(
+        mut data: ShimejiData,
+        animations: Vec<AnimationXml>,
+        resample: Resample,
+        placeholder: Frame,
+        store: Option<FrameStore>,
+    ) -> anyhow::Result<(ShimejiData, Option<Self>)> {
+        let index_of = |name: &str| {
+            animations
+                .iter()
+                .position(|animation| animation.name == name)
+        };
+        let first: Vec<usize> = animations_of(data.behaviors.initial(), &data.animations)
+            .into_iter()
+            .filter_map(index_of)
+            .collect();
+        let mut queued: HashSet<usize> = first.iter().copied().collect();
+        let left = likeliest_animations(&data.behaviors, &data.animations)
+            .into_iter()
+            .filter_map(index_of)
+            // and any no behavior plays
+            .chain(0..animations.len())
+            .filter(|&index| queued.insert(index))
+            .collect();
+        // handing the pack over as it's decoded only copies pointers to frames
+        for animation in data.animations.values_mut() {
+            let frames = std::mem::take(&mut animation.frames);
+            animation.frames = frames.into_iter().map(Frame::shared).collect();
+        }
+        let mut undecoded = Self {
+            data,
+            decoded: animations.iter().map(|_| None).collect(),
+            animations,
+            left,
+            resample,
+            placeholder,
+            store,
+        };
+        for index in first {
+            undecoded.decode_one(index)?;
+        }
+        if undecoded.left.is_empty() {
+            undecoded.save();
+            return Ok((undecoded.data, None));
+        }
+        Ok((undecoded.data.clone(), Some(undecoded)))
+    }
+    /// Decode the rest of the pack's animations one after another, likeliest to play first,
+    /// handing the pack with as many of them decoded as there are to `deliver` at most every
+    /// [`Undecoded::DELIVER_EVERY`] and once they all are. Stops once `deliver` returns `false`.
+    ///
+    /// An animation that can't be decoded is logged, and shows placeholders.
+    /// Once every one is decoded they're saved to the pack's [`FrameStore`],
+    /// so the next launch doesn't have to.
+    pub fn decode(mut self, mut deliver: impl FnMut(ShimejiData) -> bool) {
+        let started = Instant::now();
+        let mut delivered = started;
+        while let Some(index) = self.left.pop_front() {
+            if let Err(why) = self.decode_one(index) {
+                log::error!(
+                    "Could not decode {} of {}, showing placeholders instead: {why:#}",
+                    self.animations[index].name,
+                    self.data.name
+                );
+            }
+            if self.left.is_empty() || delivered.elapsed() >= Self::DELIVER_EVERY {
+                if !deliver(self.data.clone()) {
+                    return;
+                }
+                delivered = Instant::now();
+            }
+        }
+        log::debug!(
+            "Decoded the rest of {} in {:.2?}",
+            self.data.name,
+            started.elapsed()
+        );
+        self.save();
+    }
+    fn decode_one(&mut self, index: usize) -> anyhow::Result<()> {
+        let animation = &self.animations[index];
+        let (frames, delays) = decode_frames(animation, self.resample, &self.placeholder)?;
+        check_frame_sizes(animation, &frames, self.data.width, self.data.height)?;
+        let frames: Vec<Frame> = frames.into_iter().map(Frame::shared).collect();
+        let assembled = assemble_animation(
+            animation.clone(),
+            frames.clone(),
+            delays.clone(),
+            self.resample.scale,
+        );
+        self.data
+            .animations
+            .insert(animation.name.clone(), assembled);
+        self.decoded[index] = Some((frames, delays));
+        Ok(())
+    }
+    /// Save the pack's frames to its store, if it has one and they were all decoded.
+    fn save(&mut self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let decoded = std::mem::take(&mut self.decoded);
+        let Some(decoded) = decoded.into_iter().collect::<Option<Vec<_>>>() else {
+            return;
+        };
+        if let Err(why) = store.save(&decoded) {
+            log::warn!("{why:#}");
+        }
+    }
+}
+
+/// Everything wrong with `data` that loading it would stop at the first of, or cover up
+/// with placeholders: images that can't be decoded or don't fit, animations followed by
+/// or borrowed from ones that don't exist, and props that can't be decoded.
+/// Images are checked at the size the pack asks for, before any scaling.
+///
+/// If there's nothing like that, whatever loading it runs into instead.
+pub(crate) fn problems_of(mut data: XmlReturnData, library: &PackLibrary) -> Vec<String> {
+    let mut problems = vec![];
+    let (width, height) = (data.shimeji_width, data.shimeji_height);
+    for animation in data.animations.iter_mut() {
+        animation.frames.sort_by_key(|f| f.number);
+        problems.extend(animation_problems(animation, width, height));
+    }
+    let names: HashSet<&str> = data
+        .animations
+        .iter()
+        .map(|animation| animation.name.as_str())
+        .chain(data.uses.iter().map(|used| used.name.as_str()))
+        .chain(data.sequences.iter().map(|sequence| sequence.name.as_str()))
+        .collect();
+    for animation in data.animations.iter() {
+        if let Some(next) = &animation.next {
+            if !names.contains(next.as_str()) {
+                problems.push(format!(
+                    "animation {} is followed by {next}, which does not exist",
+                    animation.name
+                ));
+            }
+        }
+    }
+    // the names of the animations in each borrowed from pack, or why it couldn't be read
+    let mut packs: HashMap<&str, Result<Vec<String>, String>> = HashMap::new();
+    for used in data.uses.iter() {
+        let animations = packs.entry(&used.pack).or_insert_with(|| {
+            let path = library.path_of(&used.pack);
+            let file = fs::File::open(&path)
+                .map_err(|why| format!("pack {} is not installed: {why}", used.pack))?;
+            let data = parse(file).map_err(|why| why.in_file(&path).to_string())?;
+            Ok(data.animations.into_iter().map(|a| a.name).collect())
+        });
+        match animations {
+            Ok(animations) if !animations.contains(&used.animation) => problems.push(format!(
+                "pack {} has no animation {}",
+                used.pack, used.animation
+            )),
+            Ok(_) => (),
+            Err(why) => problems.push(why.clone()),
+        }
+    }
+    for prop in data.props.iter() {
+        if let Err(why) = decode_png(&prop.file_path, Resample::NONE) {
+            problems.push(format!("could not decode prop {}: {why:#}", prop.name));
+        }
+    }
+    problems.dedup();
+    if problems.is_empty() {
+        if let Err(why) = shimeji_data_from_xml(data, library) {
+            problems.push(format!("{why:#}"));
+        }
+    }
+    problems
+}
+
+/// Every shimeji defined in a directory or a multi-`<Shimeji>` file, by name.
+#[derive(derive_more::Debug, Clone, Default)]
+pub struct ShimejiLibrary {
+    #[debug("{:?}", self.names())]
+    shimejis: HashMap<Arc<str>, Arc<ShimejiData>>,
+    /// Where the library was loaded from, and with which packs, to load it again.
+    origin: Option<(PathBuf, PackLibrary)>,
+    /// The library's path and every file it has definitions in.
+    #[debug(skip)]
+    files: Vec<PathBuf>,
+    /// Files loaded on top of the library with [`ShimejiLibrary::load_also`].
+    #[debug(skip)]
+    also: Vec<PathBuf>,
+    /// Packs loaded with [`PackLibrary::with_lazy_frames`] that have animations left to decode.
+    #[debug(skip)]
+    undecoded: Vec<Undecoded>,
+}
+
+impl ShimejiLibrary {
+    /// Load `path`, which can be a single- or multi-`<Shimeji>` file,
+    /// a Shimeji-ee pack, or a directory of any of those.
+    ///
+    /// # Errors
+    /// Errors if anything fails to load, or two shimejis share a name.
+    pub fn load(path: impl AsRef<Path>, packs: &PackLibrary) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut library = Self {
+            origin: Some((path.to_owned(), packs.clone())),
+            files: vec![path.to_owned()],
+            ..Self::default()
+        };
+        if path.is_dir() && !is_shimeji_ee_pack(path) {
+            let mut entries = fs::read_dir(path)
+                .with_context(|| format!("could not read {}", path.display()))?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            entries.sort();
+            for entry in entries {
+                let is_xml = entry
+                    .extension()
+                    .is_some_and(|extension| extension == "xml");
+                if is_xml || is_shimeji_ee_pack(&entry) || archive::is_archive(&entry) {
+                    library.load_one(&entry, packs)?;
+                }
+            }
+        } else {
+            library.load_one(path, packs)?;
+        }
+        Ok(library)
+    }
+    fn load_one(&mut self, path: &Path, packs: &PackLibrary) -> anyhow::Result<()> {
+        self.files.push(path.to_owned());
+        let loaded = if is_shimeji_ee_pack(path) {
+            vec![(
+                create_shimeji_data_from_shimeji_ee(path, packs.limits)?,
+                None,
+            )]
+        } else if archive::is_archive(path) {
+            parse_archive(path)?
+                .into_iter()
+                .map(|data| {
+                    load_from_xml(packs.heed_warnings(data, path)?, packs, packs.lazy_frames)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            let file = fs::File::open(path)
+                .with_context(|| format!("could not open {}", path.display()))?;
+            parse_many(file)
+                .map_err(|why| why.in_file(path))?
+                .into_iter()
+                .map(|data| {
+                    load_from_xml(packs.heed_warnings(data, path)?, packs, packs.lazy_frames)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+        for (data, undecoded) in loaded {
+            self.undecoded.extend(undecoded);
+            let name = Arc::clone(&data.name);
+            if self
+                .shimejis
+                .insert(Arc::clone(&name), Arc::new(data))
+                .is_some()
+            {
+                bail!(
+                    "more than one shimeji is called {name}, the last in {}",
+                    path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+    /// Load `path` into the library too, and again whenever it's reloaded.
+    ///
+    /// # Errors
+    /// See [`ShimejiLibrary::load`].
+    pub fn load_also(&mut self, path: &Path, packs: &PackLibrary) -> anyhow::Result<()> {
+        self.load_one(path, packs)?;
+        self.also.push(path.to_owned());
+        Ok(())
+    }
+    pub fn get(&self, name: &str) -> Option<Arc<ShimejiData>> {
+        self.shimejis.get(name).cloned()
+    }
+    /// Swap in `data` for the shimeji of the same name,
+    /// like one with more of its frames decoded by [`Undecoded::decode`].
+    pub fn replace(&mut self, data: Arc<ShimejiData>) {
+        self.shimejis.insert(Arc::clone(&data.name), data);
+    }
+    /// The library's packs that have animations left to decode,
+    /// see [`PackLibrary::with_lazy_frames`]. They're only handed out once.
+    pub fn take_undecoded(&mut self) -> Vec<Undecoded> {
+        std::mem::take(&mut self.undecoded)
+    }
+    /// Load the library again from where it was first loaded,
+    /// or `None` if it wasn't loaded from anywhere.
+    ///
+    /// # Errors
+    /// See [`ShimejiLibrary::load`].
+    pub fn reload(&self) -> Option<anyhow::Result<Self>> {
+        let (path, packs) = self.origin.as_ref()?;
+        Some(Self::load(path, packs).and_then(|mut library| {
+            for also in self.also.iter() {
+                library.load_also(also, packs)?;
+            }
+            Ok(library)
+        }))
+    }
+    /// Every file the library was loaded from, images included, sorted.
+    pub fn sources(&self) -> Vec<PathBuf> {
+        let mut sources: Vec<PathBuf> = self
+            .shimejis
+            .values()
+            .flat_map(|data| data.sources.iter().cloned())
+            .chain(self.files.iter().cloned())
+            .collect();
+        sources.sort();
+        sources.dedup();
+        sources
+    }
+    /// Every name in the library, sorted.
+    pub fn names(&self) -> Vec<Arc<str>> {
+        let mut names: Vec<_> = self.shimejis.keys().cloned().collect();
+        names.sort();
+        names
+    }
+    pub fn len(&self) -> usize {
+        self.shimejis.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.shimejis.is_empty()
+    }
+}
+
+/// How many times a second Shimeji-ee ticks.
+const EE_TICKS_PER_SECOND: f64 = 25.0;
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Load a pack in the classic Shimeji-ee layout:
+/// `conf/actions.xml`, `conf/behaviors.xml` and the images in `img/`.
+///
+/// Every action with an animation becomes an animation of the same name,
+/// and every behavior whose action (or the first action of its sequence) has one
+/// becomes a behavior, weighted by its frequency. Conditions and scripted
+/// actions aren't supported, but `Dragged`, `Thrown` and `Fall` are wired up
+/// to being held, let go and falling.
+///
+/// Frames are shrunk to fit `limits`, sized by the first image any action uses.
+pub fn create_shimeji_data_from_shimeji_ee(
+    directory: impl Into<OsString>,
+    limits: FrameLimits,
+) -> anyhow::Result<ShimejiData> {
+    let directory = PathBuf::from(directory.into());
+    let conf = directory.join("conf");
+    let img = directory.join("img");
+    let actions = parse_ee_actions(
+        fs::File::open(conf.join("actions.xml")).context("could not open actions.xml")?,
+    )
+    .context("failed to parse actions.xml")?;
+    let ee_behaviors = parse_ee_behaviors(
+        fs::File::open(conf.join("behaviors.xml")).context("could not open behaviors.xml")?,
+    )
+    .context("failed to parse behaviors.xml")?;
+    let actions: HashMap<&str, &EeActionXml> = actions
+        .iter()
+        .map(|action| (action.name.as_str(), action))
+        .collect();
+
+    let image_path = |image: &str| img.join(image.trim_start_matches('/'));
+    // Shimeji-ee gives every pose its own duration, we only have one fps per animation,
+    // so repeat poses to make up the difference
+    let tick_of = |action: &EeActionXml| {
+        action
+            .poses
+            .iter()
+            .fold(0, |tick, pose| gcd(tick, pose.duration))
+            .max(1)
+    };
+    let animated = || actions.values().filter(|action| !action.poses.is_empty());
+    let scale = match animated().next() {
+        Some(action) => {
+            let path = image_path(&action.poses[0].image);
+            let (width, height) = png_size(&path.to_string_lossy())
+                .with_context(|| format!("could not read the size of {}", path.display()))?;
+            let frames = animated()
+                .map(|action| {
+                    let tick = tick_of(action);
+                    action
+                        .poses
+                        .iter()
+                        .map(|pose| (pose.duration / tick) as usize)
+                        .sum::<usize>()
+                })
+                .sum();
+            limits.scale_for(width, height, frames)
+        }
+        None => 1.0,
+    };
+
+    let mut images: HashMap<&str, Frame> = HashMap::new();
+    let mut animations = HashMap::new();
+    for action in animated() {
+        let tick = tick_of(action);
+        let mut frames = vec![];
+        for pose in action.poses.iter() {
+            if !images.contains_key(pose.image.as_str()) {
+                let path = image_path(&pose.image);
+                let frame = decode_png(
+                    &path.to_string_lossy(),
+                    Resample {
+                        scale,
+                        ..Resample::NONE
+                    },
+                )
+                .with_context(|| format!("could not decode {}", path.display()))?;
+                images.insert(&pose.image, frame);
+            }
+            let frame = &images[pose.image.as_str()];
+            frames.extend(std::iter::repeat_n(frame, (pose.duration / tick) as usize).cloned());
+        }
+        animations.insert(
+            action.name.clone(),
+            AnimationData {
+                durations: vec![
+                    Duration::from_secs_f64(tick as f64 / EE_TICKS_PER_SECOND);
+                    frames.len()
+                ],
+                weight: None,
+                next: None,
+                loop_mode: LoopMode::Loop,
+                frames,
+                sounds: vec![],
+                anchors: vec![],
+                hitboxes: vec![],
+                steps: vec![],
+            },
+        );
+    }
+    let Some(first_frame) = images.values().next() else {
+        bail!("no action in actions.xml has an animation");
+    };
+    let (width, height) = (first_frame.width, first_frame.height);
+
+    // the action with an animation that a behavior's action boils down to
+    let resolve = |name: &str| {
+        let mut action = *actions.get(name)?;
+        for _ in 0..actions.len() {
+            if !action.poses.is_empty() {
+                return Some(action);
+            }
+            action = *actions.get(action.references.first()?.as_str())?;
+        }
+        None
+    };
+    let to_action = |name: &str, animated: &EeActionXml| {
+        let velocity = animated.poses[0].velocity;
+        let class = actions
+            .get(name)
+            .and_then(|action| action.class.as_deref())
+            .unwrap_or_default();
+        if class.ends_with("Fall") {
+            Action::Fall
+        } else if class.ends_with("Jump") {
+            Action::Jump
+        } else if animated.border.as_deref() == Some("Wall") {
+            Action::Climb(velocity.1 * EE_TICKS_PER_SECOND)
+        } else if animated.border.as_deref() == Some("Ceiling") && animated.kind != "Move" {
+            Action::Hang
+        } else if animated.kind == "Move" {
+            Action::Walk(velocity.0 * EE_TICKS_PER_SECOND)
+        } else {
+            Action::Stand
+        }
+    };
+
+    let mut behaviors: Vec<Behavior> = ee_behaviors
+        .iter()
+        .filter_map(|behavior| {
+            let Some(animated) = resolve(&behavior.action) else {
+                log::warn!("Skipping behavior {}, it has no animation", behavior.name);
+                return None;
+            };
+            Some(Behavior {
+                name: behavior.name.clone(),
+                animation: animated.name.clone(),
+                action: to_action(&behavior.action, animated),
+                weight: (behavior.frequency > 0)
+                    .then_some(Formula::Number(behavior.frequency as f64)),
+                transitions: vec![],
+                next: vec![],
+                script: None,
+                condition: None,
+            })
+        })
+        .collect();
+    let names: HashSet<String> = behaviors
+        .iter()
+        .map(|behavior| behavior.name.clone())
+        .collect();
+    let (dragged, thrown, fall) = (
+        names.contains("Dragged"),
+        names.contains("Thrown"),
+        names.contains("Fall"),
+    );
+    let mut next_of: HashMap<&str, &str> = HashMap::new();
+    for behavior in ee_behaviors.iter() {
+        if let (false, Some(next)) = (behavior.add_next, behavior.next.first()) {
+            if names.contains(next) {
+                next_of.insert(&behavior.name, next);
+            }
+        }
+    }
+    // where a shimeji ends up once it's back on the ground
+    let landing = behaviors
+        .iter()
+        .find(|behavior| behavior.weight.is_some())
+        .or(behaviors.first())
+        .map(|behavior| behavior.name.clone())
+        .context("no behavior in behaviors.xml has an animation")?;
+    let initial = if fall {
+        String::from("Fall")
+    } else {
+        landing.clone()
+    };
+    let to = |to: &str, when| Transition {
+        to: String::from(to),
+        when,
+        after: None,
+        apps: vec![],
+    };
+    for behavior in behaviors.iter_mut() {
+        let transitions = &mut behavior.transitions;
+        match behavior.name.as_str() {
+            "Dragged" if thrown => transitions.push(to("Thrown", Condition::Released)),
+            "Dragged" if fall => transitions.push(to("Fall", Condition::Released)),
+            "Dragged" => transitions.push(to(&landing, Condition::Released)),
+            "Thrown" | "Fall" => transitions.push(to(&landing, Condition::Grounded)),
+            _ => {}
+        }
+        if dragged && behavior.name != "Dragged" {
+            transitions.push(to("Dragged", Condition::Held));
+        }
+        if fall && !matches!(behavior.name.as_str(), "Dragged" | "Thrown" | "Fall") {
+            transitions.push(to("Fall", Condition::Airborne));
+        }
+        if let Some(next) = next_of.get(behavior.name.as_str()) {
+            transitions.push(to(next, Condition::Finished));
+        }
+    }
+    let behaviors = BehaviorTable::new(behaviors, initial, &animations)
+        .context("behaviors.xml does not fit actions.xml")?;
+
+    let name = directory
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or(String::from("shimeji-ee"));
+    Ok(ShimejiData {
+        name: Arc::from(name),
+        animations,
+        behaviors,
+        props: vec![],
+        gravity: true,
+        shadow: None,
+        sources: files_under(&directory)
+            .with_context(|| format!("could not list the files of {}", directory.display()))?,
+        scaling: ScalingMode::default(),
+        letterbox: None,
+        frame_cache: FrameCache::default(),
+        variants: VariantCache::default(),
+        height,
+        width,
+    })
 }
@@ -0,0 +1,96 @@
+//! Keeps messages logged every frame from drowning out everything else.
+
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often a [`throttled!`] message is logged, unless told otherwise.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Every key [`once!`] has let a message through for.
+static LOGGED_ONCE: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Whether nothing has been logged for `key` with [`once!`] yet, remembering it if so.
+pub fn first_time(key: &str) -> bool {
+    let mut logged = LOGGED_ONCE.lock().unwrap_or_else(|e| e.into_inner());
+    !logged.contains(key) && logged.insert(String::from(key))
+}
+
+/// Lets a message through at most once per interval, counting the ones it held back.
+#[derive(Debug)]
+pub struct Throttle {
+    last_logged: Mutex<Option<Instant>>,
+    suppressed: AtomicUsize,
+}
+
+impl Throttle {
+    pub const fn new() -> Self {
+        Self {
+            last_logged: Mutex::new(None),
+            suppressed: AtomicUsize::new(0),
+        }
+    }
+    /// Returns how many messages were held back since the last one,
+    /// or `None` if this one should be held back too.
+    pub fn check(&self, interval: Duration) -> Option<usize> {
+        let now = Instant::now();
+        let mut last_logged = self.last_logged.lock().unwrap_or_else(|e| e.into_inner());
+        if last_logged.is_some_and(|last| now - last < interval) {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        *last_logged = Some(now);
+        Some(self.suppressed.swap(0, Ordering::Relaxed))
+    }
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Log like [`log::log!`], but at most once every [`DEFAULT_INTERVAL`] from this call site,
+/// across every thread. Messages held back in between are counted in the next one.
+///
+/// ```ignore
+/// throttled!(log::Level::Error, "{name} has no frame to show");
+/// ```
+macro_rules! throttled {
+    ($level:expr, $($arg:tt)+) => {{
+        static THROTTLE: $crate::log_throttle::Throttle = $crate::log_throttle::Throttle::new();
+        let level = $level;
+        if ::log::log_enabled!(level) {
+            match THROTTLE.check($crate::log_throttle::DEFAULT_INTERVAL) {
+                Some(0) => ::log::log!(level, $($arg)+),
+                Some(suppressed) => ::log::log!(
+                    level,
+                    "{} ({suppressed} more like this since last time)",
+                    format_args!($($arg)+)
+                ),
+                None => (),
+            }
+        }
+    }};
+}
+pub(crate) use throttled;
+
+/// Log like [`log::log!`], but only the first time for `key`, across every thread.
+/// For problems with one asset that would otherwise be logged every time it's used.
+///
+/// ```ignore
+/// once!(file_path, log::Level::Error, "{file_path} could not be decoded");
+/// ```
+macro_rules! once {
+    ($key:expr, $level:expr, $($arg:tt)+) => {{
+        if $crate::log_throttle::first_time(::std::convert::AsRef::<str>::as_ref(&$key)) {
+            ::log::log!($level, $($arg)+);
+        }
+    }};
+}
+pub(crate) use once;
@@ -1,294 +1,200 @@
-#![deny(unused_must_use)]
-#![allow(dead_code)]
+use std::{ffi::OsString, path::Path, thread};
 
 use anyhow::Context as _;
 use cfg_if::cfg_if;
-use itertools::Itertools;
-use std::{
-    cell::RefCell,
-    collections::HashMap,
-    ffi::OsString,
-    ops::Deref,
-    rc::Rc,
-    sync::{atomic::AtomicBool, Arc, LazyLock},
-    thread,
+#[cfg(target_os = "linux")]
+use new_shimeji::backend;
+use new_shimeji::{
+    capabilities, download, headless,
+    loader::{FrameLimits, PackLibrary, ShimejiLibrary},
+    notify, picker,
+    population::SavedPopulation,
+    preview, recording, renderer,
+    session::Session,
+    settings::Settings,
+    soak, supervisor, validate, BucketManager, SpawnConfig,
 };
-use winit::{
-    application::ApplicationHandler,
-    dpi::PhysicalSize,
-    error::EventLoopError,
-    event::WindowEvent,
-    event_loop::{ActiveEventLoop, EventLoop},
-    raw_window_handle::HasWindowHandle,
-    window::{WindowAttributes, WindowId, WindowLevel},
-};
-
-mod bucket;
-mod loader;
-mod rgba;
-#[path = "./off_thread/shimeji.rs"]
-mod shimeji;
-mod xml_parser;
-
-use bucket::{BucketError, ShimejiBucket};
-use shimeji::ShimejiData;
-
-use derive_more::{derive::From, Display, Error};
-
-#[derive(Debug)]
-enum Status {
-    Ok,
-    Exiting,
-}
-
-impl Status {
-    /// Returns `true` if the status is [`Ok`].
-    ///
-    /// [`Ok`]: Status::Ok
-    #[must_use]
-    fn is_ok(&self) -> bool {
-        matches!(self, Self::Ok)
-    }
-}
-#[derive(Display, Debug, Error, From)]
-enum ManagerError {
-    /// Should never happen.
-    NoBucketsAvailable,
-    BucketError(BucketError),
-    EventLoopError(EventLoopError),
-}
-
-#[derive(Debug)]
-struct BucketManager {
-    should_exit: Arc<AtomicBool>,
-    /// Shimejis that are waiting
-    /// for a context / window to be sent to a bucket.
-    pending_shimejis: Vec<Arc<ShimejiData>>,
-    buckets: Vec<Rc<RefCell<ShimejiBucket>>>,
-    buckets_windows_map: HashMap<WindowId, Rc<RefCell<ShimejiBucket>>>,
-}
-cfg_if! {
-    if #[cfg(target_os = "linux")] {
-        use winit::platform::x11::{EventLoopBuilderExtX11, WindowAttributesExtX11, WindowType};
-        static WINDOW_ATTRIBS: LazyLock<WindowAttributes> = std::sync::LazyLock::new(|| {
-            WindowAttributes::default()
-                .with_visible(true)
-                .with_transparent(true)
-                .with_decorations(false)
-                .with_x11_window_type(vec![WindowType::Dock])
-                .with_window_level(WindowLevel::AlwaysOnTop)
-                .with_inner_size(PhysicalSize::new(10, 10))
-        });
-    } else {
-        static WINDOW_ATTRIBS: LazyLock<WindowAttributes> = std::sync::LazyLock::new(|| {
-            WindowAttributes::default()
-                .with_visible(true)
-                .with_transparent(true)
-                .with_decorations(false)
-                .with_window_level(WindowLevel::AlwaysOnTop)
-                .with_inner_size(PhysicalSize::new(10, 10))
-        });
-    }
-
-}
-
-impl ApplicationHandler for BucketManager {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        log::debug!("Resumed");
-
-        self.address_pending_shimejis(event_loop);
-    }
-    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
-        log::debug!("Exiting");
-    }
-    fn window_event(
-        &mut self,
-        event_loop: &ActiveEventLoop,
-        window_id: WindowId,
-        event: WindowEvent,
-    ) {
-        use WindowEvent::*;
-        if self.should_exit.load(std::sync::atomic::Ordering::Acquire) {
-            event_loop.exit()
-        }
-        log::trace!("WindowEvent: {event:?}");
-        match event {
-            RedrawRequested => {
-                log::trace!("WindowEvent: RedrawRequested")
-            }
-            Resized(size) => {
-                log::trace!("WindowEvent: Resized");
-                let bucket: &RefCell<ShimejiBucket> =
-                    Rc::deref(self.buckets_windows_map.get(&window_id).unwrap());
-                bucket
-                    .borrow_mut()
-                    .was_resized(window_id, size)
-                    .context("could not resize window on resize event received")
-                    .unwrap();
-            }
-            MouseInput {
-                device_id: _,
-                state: _,
-                button: _,
-            } => {}
-            _ => (),
-        }
-    }
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, _event: ()) {}
-}
 
-impl BucketManager {
-    /// # Panics
-    /// Panics if `amount == 0`.
-    pub fn new(amount: usize) -> Self {
-        assert!(amount != 0);
-        let mut buckets = Vec::with_capacity(amount);
-        let should_exit = Arc::new(AtomicBool::new(false));
-        for i in 0..amount {
-            let mut bucket = ShimejiBucket::new(i, should_exit.clone());
-            bucket.init().expect("should be able to init bucket");
-            buckets.push(Rc::new(RefCell::new(bucket)));
-        }
-        Self {
-            pending_shimejis: vec![],
-            should_exit,
-            buckets,
-            buckets_windows_map: HashMap::new(),
-        }
-    }
-    pub fn add_shimeji(&mut self, pending: Arc<ShimejiData>) {
-        self.pending_shimejis.push(pending)
-    }
-    #[cfg(not(target_os = "windows"))]
-    pub fn run_with_tray_handle(
-        self,
-        tray_handle: Option<tray_item::TrayItem>,
-    ) -> Result<(), ManagerError> {
-        let copy = Arc::clone(&self.should_exit);
-        if let Some(mut handle) = tray_handle {
-            handle
-                .add_menu_item("Kill", move || {
-                    copy.store(true, std::sync::atomic::Ordering::SeqCst);
-                })
-                .unwrap();
-        }
-        self.run()
+fn main() -> anyhow::Result<()> {
+    simple_logger::SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .env()
+        .init()
+        .expect("Should be able to set up logger");
+    log::debug!("Starting");
+    if supervisor::wants_auto_restart(std::env::args().skip(1)) {
+        return supervisor::supervise();
     }
-    pub fn run(mut self) -> Result<(), ManagerError> {
+    if capabilities::wants_report(std::env::args().skip(1)) {
         cfg_if! {
             if #[cfg(target_os = "linux")] {
-                let event_loop = EventLoop::builder().with_x11().build().unwrap();
+                let backend = backend::Backend::from_args(std::env::args().skip(1))?
+                    .unwrap_or_else(backend::Backend::detect);
+                print!("{}", capabilities::for_backend(backend));
             } else {
-                let event_loop = EventLoop::new().unwrap();
+                print!("{}", capabilities::capabilities());
             }
         }
-        event_loop.run_app(&mut self)?;
-        log::debug!("Manager returned");
-        Ok(())
+        return Ok(());
     }
-
-    // pub fn run(mut self, tray_handle: Option<tray_item::TrayItem>) -> Result<(), ManagerError> {
-    //     let copy = Arc::clone(&self.should_exit);
-    //     if let Some(mut handle) = tray_handle {
-    //         handle
-    //             .add_menu_item("Kill", move || {
-    //                 copy.store(true, std::sync::atomic::Ordering::SeqCst);
-    //             })
-    //             .unwrap();
-    //     }
-
-    //     cfg_if! {
-    //         if #[cfg(target_os = "linux")] {
-    //             let event_loop = EventLoop::builder().with_x11().build().unwrap();
-    //         } else {
-    //             let event_loop = EventLoop::new().unwrap();
-    //         }
-    //     }
-    //     event_loop.run_app(&mut self)?;
-    //     log::debug!("Manager returned");
-    //     Ok(())
-    // }
-
-    fn address_pending_shimejis(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        // If we don't collect here, the compiler
-        // believes a reference is still in use
-        let mut buckets_by_count = self
-            .buckets
-            .iter()
-            .sorted_by_key(|x| Rc::deref(x).borrow_mut().contained_shimejis())
-            .enumerate()
-            .map(|(idx, _)| idx)
-            .collect::<Vec<_>>()
-            .into_iter()
-            .cycle();
-
-        let buckets: Vec<_> = self
-            .buckets
-            .iter_mut()
-            .sorted_by_key(|x| Rc::deref(x).borrow_mut().contained_shimejis())
-            .collect();
-
-        // while we still have pending shimejis...
-        while let Some(pending_shimeji) = self.pending_shimejis.pop() {
-            let index = buckets_by_count.next().unwrap();
-            let window = event_loop
-                .create_window(WINDOW_ATTRIBS.clone())
-                .expect("should be able to create window for shimeji");
-
-            window
-                .window_handle()
-                .expect("window handloe should be able to be grabbed");
-
-            let id = window.id();
-
-            let bucket_rc = &buckets[index];
-            let bucket_to_add_to: &RefCell<ShimejiBucket> = Rc::deref(bucket_rc);
-            bucket_to_add_to
-                .borrow_mut()
-                .add(pending_shimeji, window)
-                .expect("should be able to add shimeji to bucket");
-            let clone = Rc::clone(bucket_rc);
-            self.buckets_windows_map.insert(id, clone);
+    if let Some(path) = validate::from_args(std::env::args().skip(1)) {
+        let packs = PackLibrary::from_env().with_frame_limits(FrameLimits::from_env()?);
+        let problems = validate::validate(&path, &packs);
+        for problem in problems.iter() {
+            println!("{problem}");
         }
+        let errors = problems
+            .iter()
+            .filter(|problem| problem.severity == validate::Severity::Error)
+            .count();
+        println!(
+            "{}: {errors} errors, {} warnings",
+            path.display(),
+            problems.len() - errors
+        );
+        anyhow::ensure!(errors == 0, "{} is not a valid pack", path.display());
+        return Ok(());
     }
-}
-fn main() -> anyhow::Result<()> {
-    simple_logger::SimpleLogger::new()
-        .with_level(log::LevelFilter::Info)
-        .env()
-        .init()
-        .expect("Should be able to set up logger");
-    log::debug!("Starting");
 
     let parallelism = thread::available_parallelism()
         .context("Failed to get available parallelism for this system")?
         .get();
     log::debug!("Available parallelism: {}", parallelism);
 
-    cfg_if! {
-        if #[cfg(not(target_os = "windows"))] {
-            let icon_red = tray_item::IconSource::Resource("/home/lucy/tray_icon-red.png");
-            let tray_handle = tray_item::TrayItem::new("Example", icon_red).ok();
+    let soak = soak::SoakConfig::from_args(std::env::args().skip(1))?;
+    let headless = headless::HeadlessConfig::from_args(std::env::args().skip(1))?;
+    let recording = recording::Mode::from_args(std::env::args().skip(1));
+    let preview = preview::from_args(std::env::args().skip(1));
 
-        } else {
-            // let tray_handle: Option<()> = None;
-        }
+    if let Some(kind) = renderer::RendererKind::from_args(std::env::args().skip(1))? {
+        renderer::choose(kind);
     }
 
     log::debug!("Running manager");
     let mut manager = BucketManager::new(parallelism);
+    #[cfg(target_os = "linux")]
+    if let Some(backend) = backend::Backend::from_args(std::env::args().skip(1))? {
+        manager.set_backend(backend);
+    }
     let file_name =
         std::env::var_os("SHIMEJI_CONFIG_FILE").unwrap_or(OsString::from("./default.xml"));
-    let config = loader::create_shimeji_data_from_file_name(file_name)?;
-    let config = Arc::new(config);
-
-    for _ in 0..1 {
-        manager.add_shimeji(config.clone());
+    // nothing to load shimejis from yet, so the user picks from the packs directory
+    let first_run = std::env::var_os("SHIMEJI_CONFIG_FILE").is_none()
+        && !Path::new(&file_name).exists()
+        && picker::packs_dir().is_dir();
+    let file_name = match first_run {
+        true => picker::packs_dir().into_os_string(),
+        false => file_name,
+    };
+    let settings = Settings::from_env()?;
+    let packs = PackLibrary::from_env()
+        .with_frame_limits(FrameLimits::from_env()?)
+        .with_scale(settings.scale)
+        .with_strict(std::env::var_os("SHIMEJI_STRICT_PACKS").is_some())
+        // only the manager decodes the rest, a headless run or a preview would never see them
+        .with_lazy_frames(
+            headless.is_none()
+                && preview.is_none()
+                && std::env::var_os("SHIMEJI_LAZY_FRAMES").is_some(),
+        );
+    let mut library = ShimejiLibrary::load(file_name, &packs).inspect_err(|why| {
+        notify::problem("Could not load the shimejis", &format!("{why:#}"));
+    })?;
+    let pack_urls = download::from_args(std::env::args().skip(1))?;
+    let allow_unpinned = std::env::var_os(download::ALLOW_UNPINNED).is_some();
+    for pack in pack_urls.iter().chain(settings.pack_urls.iter()) {
+        let loaded =
+            download::fetch(pack, allow_unpinned).and_then(|path| library.load_also(&path, &packs));
+        if let Err(why) = loaded {
+            notify::problem("Could not download a pack", &format!("{why:#}"));
+        }
     }
-    manager.add_shimeji(config);
+    if let Some(headless) = headless {
+        let report = headless::run(&headless, &library)?;
+        println!("{report}");
+        return Ok(());
+    }
+    if let Some(animation) = preview {
+        return preview::run(&library, &animation);
+    }
+    let names = library.names();
+    manager.set_library(library);
+    manager.set_anonymous_windows(std::env::var_os("SHIMEJI_ANONYMOUS_WINDOWS").is_some());
+    manager.set_compositor_effects(std::env::var_os("SHIMEJI_COMPOSITOR_EFFECTS").is_some());
+    manager.set_shaped_windows(std::env::var_os("SHIMEJI_SHAPED_WINDOWS").is_some());
+    manager.set_overlay(std::env::var_os("SHIMEJI_OVERLAY").is_some());
+    manager.set_settings(settings);
+    manager.set_debug_stepping(std::env::var_os("SHIMEJI_DEBUG_STEPPING").is_some());
+    manager.set_hot_reload(std::env::var_os("SHIMEJI_HOT_RELOAD").is_some());
+    let population = SavedPopulation::from_env();
+    let restored = match std::env::var_os(supervisor::RESTORE_VAR) {
+        Some(_) => population.load().unwrap_or_else(|why| {
+            log::error!("Could not restore the population: {why:#}");
+            vec![]
+        }),
+        None => vec![],
+    };
+    if std::env::var_os(supervisor::SUPERVISED_VAR).is_some() {
+        manager.set_saved_population(Some(population));
+    }
+    // soak tests and replays start from nothing and aren't worth coming back to
+    let replaying = matches!(recording, Some(recording::Mode::Replay(_)));
+    let session = (soak.is_none() && !replaying).then(Session::from_env);
+    let saved = match (&session, restored.is_empty()) {
+        (Some(session), true) => session.load().unwrap_or_else(|why| {
+            log::error!("Could not load the last session: {why:#}");
+            vec![]
+        }),
+        _ => vec![],
+    };
+
+    if let Some(soak) = soak {
+        log::info!(
+            "Soaking {} shimejis for {:?}",
+            soak.population,
+            soak.duration
+        );
+        for name in names.iter().cycle().take(soak.population) {
+            manager.add_shimeji_by_name(name);
+        }
+        manager.set_soak(soak);
+    } else if let Some(recording::Mode::Replay(path)) = &recording {
+        let entries = recording::load(path)?;
+        log::info!("Replaying {} things from {}", entries.len(), path.display());
+        manager.set_replay(entries);
+    } else if !restored.is_empty() {
+        log::info!(
+            "Bringing back {} shimejis from before the crash",
+            restored.len()
+        );
+        for name in restored {
+            manager.add_shimeji_by_name(&name);
+        }
+    } else if !saved.is_empty() {
+        log::info!("Bringing back {} shimejis from last time", saved.len());
+        for shimeji in saved {
+            manager.spawn(&SpawnConfig {
+                position: shimeji.position,
+                ..SpawnConfig::new(shimeji.name)
+            });
+        }
+    } else if first_run {
+        manager.set_picker(true);
+    } else {
+        for name in names.iter() {
+            for _ in 0..2 {
+                manager.add_shimeji_by_name(name);
+            }
+        }
+    }
+    if let Some(recording::Mode::Record(path)) = &recording {
+        log::info!("Recording to {}", path.display());
+        manager.set_recorder(recording::Recorder::create(path)?);
+    }
+    manager.set_session(session);
     cfg_if! {
         if #[cfg(not(target_os = "windows"))] {
-            manager.run_with_tray_handle(tray_handle)?;
+            manager.run_with_tray()?;
         } else {
             manager.run()?;
         }
@@ -296,64 +202,3 @@ fn main() -> anyhow::Result<()> {
     log::debug!("At the end");
     Ok(())
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    fn init_logger() {
-        // It is non-essential if the logger fails, which can often happen
-        // since Rust by default runs threads in parallel.
-        // SimpleLogger errors if it was already initialized.
-        simple_logger::SimpleLogger::new()
-            .with_level(log::LevelFilter::Trace)
-            .env()
-            .init()
-            .ok();
-    }
-
-    #[test]
-    fn buckets_are_created_successfully() {
-        init_logger();
-        let manager = BucketManager::new(1);
-
-        assert!(manager.buckets.first().is_some());
-    }
-
-    mod fuzz {
-        use std::fs::File;
-
-        use xml_parser::XmlParseError;
-
-        use super::*;
-
-        #[test]
-        fn bad_filename() {
-            init_logger();
-            let err =
-                xml_parser::parse(File::open("./fuzz/bad-filename.xml").unwrap()).unwrap_err();
-            dbg!(&err);
-            assert!(matches!(err, XmlParseError::MissingImageFile { .. }))
-        }
-
-        #[test]
-        fn missing_shimeji() {
-            init_logger();
-            let err =
-                xml_parser::parse(File::open("./fuzz/missing-shimeji.xml").unwrap()).unwrap_err();
-            dbg!(&err);
-            assert!(matches!(err, XmlParseError::NoShimeji))
-        }
-    }
-    // #[test]
-    // fn buckets_receive_shimeji_sequentially() -> anyhow::Result<()> {
-    //     init_logger();
-    //     let mut manager = BucketManager::new(1);
-
-    //     manager.add_shimeji(ShimejiConfig {
-    //         name: String::from("example"),
-    //     });
-
-    //     assert_eq!(manager.buckets.first().unwrap().contained_shimejis(), 1);
-    //     Ok(())
-    // }
-}
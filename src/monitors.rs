@@ -0,0 +1,303 @@
+//! Where every monitor is on the desktop, so shimejis can walk from one monitor onto the
+//! next and land on whichever one they are over.
+//!
+//! Shimejis land on top of taskbars, panels and docks rather than behind them, going by
+//! the work area the platform reports: `_NET_WORKAREA` on X11, `SPI_GETWORKAREA` on Windows.
+//! Wayland has neither, so the floor offsets from calibrating cover the difference there.
+
+use cfg_if::cfg_if;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event_loop::ActiveEventLoop,
+    monitor::MonitorHandle,
+};
+
+/// A rectangle of the desktop, in physical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkArea {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl WorkArea {
+    /// The part of this area that is also in `other`, if they overlap at all.
+    fn intersection(&self, other: &WorkArea) -> Option<WorkArea> {
+        let area = WorkArea {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        };
+        (area.left < area.right && area.top < area.bottom).then_some(area)
+    }
+}
+
+/// One monitor's place on the desktop, in physical pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorArea {
+    pub name: Option<String>,
+    pub position: PhysicalPosition<i32>,
+    pub size: PhysicalSize<u32>,
+    /// The part of the monitor not taken up by taskbars and panels.
+    pub work_area: WorkArea,
+}
+
+impl MonitorArea {
+    pub fn new(
+        name: Option<String>,
+        position: PhysicalPosition<i32>,
+        size: PhysicalSize<u32>,
+    ) -> Self {
+        Self {
+            name,
+            position,
+            size,
+            work_area: WorkArea {
+                left: position.x,
+                top: position.y,
+                right: position.x + size.width as i32,
+                bottom: position.y + size.height as i32,
+            },
+        }
+    }
+    pub fn of(monitor: &MonitorHandle) -> Self {
+        Self::new(monitor.name(), monitor.position(), monitor.size())
+    }
+    /// Leave out whatever of the monitor is outside the desktop's `work_area`.
+    /// Work areas on other monitors are ignored.
+    pub fn clipped_to(mut self, work_area: &WorkArea) -> Self {
+        if let Some(area) = self.work_area.intersection(work_area) {
+            self.work_area = area;
+        }
+        self
+    }
+    pub fn left(&self) -> i32 {
+        self.position.x
+    }
+    pub fn right(&self) -> i32 {
+        self.position.x + self.size.width as i32
+    }
+    /// The furthest left and right the left of a `width` pixel wide window can go
+    /// without leaving this monitor.
+    pub fn walls(&self, width: u32) -> (f64, f64) {
+        let right = self.right() - width as i32;
+        (self.left() as f64, right.max(self.left()) as f64)
+    }
+    pub fn top(&self) -> i32 {
+        self.position.y
+    }
+    pub fn bottom(&self) -> i32 {
+        self.position.y + self.size.height as i32
+    }
+    /// The lowest the top of a `height` pixel tall window can go while standing on
+    /// this monitor's floor, `floor_offset` pixels above the bottom of its work area.
+    pub fn floor(&self, height: u32, floor_offset: i32) -> f64 {
+        (self.work_area.bottom - floor_offset - height as i32) as f64
+    }
+    /// The highest the top of a window can go, just under any panels along the top.
+    pub fn ceiling(&self) -> f64 {
+        self.work_area.top as f64
+    }
+    fn contains(&self, x: f64, y: f64) -> bool {
+        (self.left() as f64..self.right() as f64).contains(&x)
+            && (self.top() as f64..self.bottom() as f64).contains(&y)
+    }
+    /// Whether `other` is right next to this monitor, close enough to walk over to.
+    fn borders(&self, other: &MonitorArea) -> bool {
+        let touching = self.right() == other.left() || other.right() == self.left();
+        let overlapping = self.top() < other.bottom() && other.top() < self.bottom();
+        touching && overlapping
+    }
+}
+
+/// Every monitor, as of when they were last looked up.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Monitors {
+    areas: Vec<MonitorArea>,
+}
+
+impl Monitors {
+    pub fn new(areas: Vec<MonitorArea>) -> Self {
+        Self { areas }
+    }
+    pub fn from_event_loop(event_loop: &ActiveEventLoop) -> Self {
+        let work_area = desktop_work_area(event_loop).unwrap_or_else(|why| {
+            log::warn!("Could not find the work area, shimejis may land behind taskbars: {why}");
+            None
+        });
+        Self::new(
+            event_loop
+                .available_monitors()
+                .map(|monitor| MonitorArea::of(&monitor))
+                .map(|area| match work_area {
+                    Some(work_area) => area.clipped_to(&work_area),
+                    None => area,
+                })
+                .collect(),
+        )
+    }
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+    /// The smallest rectangle around every monitor, if there are any.
+    pub fn bounds(&self) -> Option<(PhysicalPosition<i32>, PhysicalSize<u32>)> {
+        let left = self.areas.iter().map(MonitorArea::left).min()?;
+        let top = self.areas.iter().map(MonitorArea::top).min()?;
+        let right = self.areas.iter().map(MonitorArea::right).max()?;
+        let bottom = self.areas.iter().map(MonitorArea::bottom).max()?;
+        Some((
+            PhysicalPosition::new(left, top),
+            PhysicalSize::new((right - left) as u32, (bottom - top) as u32),
+        ))
+    }
+    /// The monitor a window at `position` of `size` belongs to: the one its middle is on,
+    /// or if it's between monitors, the closest one below or above its middle,
+    /// or failing that the closest one to either side.
+    pub fn under(
+        &self,
+        position: PhysicalPosition<f64>,
+        size: PhysicalSize<u32>,
+    ) -> Option<&MonitorArea> {
+        let x = position.x + size.width as f64 / 2.0;
+        let y = position.y + size.height as f64 / 2.0;
+        if let Some(area) = self.areas.iter().find(|area| area.contains(x, y)) {
+            return Some(area);
+        }
+        let vertical_distance = |area: &&MonitorArea| {
+            (area.top() as f64 - y)
+                .max(y - area.bottom() as f64)
+                .max(0.0)
+        };
+        let horizontal_distance = |area: &&MonitorArea| {
+            (area.left() as f64 - x)
+                .max(x - area.right() as f64)
+                .max(0.0)
+        };
+        self.areas
+            .iter()
+            .filter(|area| horizontal_distance(area) == 0.0)
+            .min_by(|a, b| vertical_distance(a).total_cmp(&vertical_distance(b)))
+            .or_else(|| {
+                self.areas
+                    .iter()
+                    .min_by(|a, b| horizontal_distance(a).total_cmp(&horizontal_distance(b)))
+            })
+    }
+    /// The furthest left and right the left of a `width` pixel wide window can walk
+    /// starting on `monitor`, across every monitor next to it, and every monitor next to those.
+    pub fn roaming_walls(&self, monitor: &MonitorArea, width: u32) -> (f64, f64) {
+        let mut reachable = vec![monitor];
+        let mut index = 0;
+        while let Some(area) = reachable.get(index).copied() {
+            for other in self.areas.iter() {
+                if area.borders(other) && !reachable.contains(&other) {
+                    reachable.push(other);
+                }
+            }
+            index += 1;
+        }
+        let left = reachable.iter().map(|area| area.left()).min();
+        let right = reachable.iter().map(|area| area.right()).max();
+        let left = left.unwrap_or(monitor.left());
+        let right = right.unwrap_or(monitor.right()) - width as i32;
+        (left as f64, right.max(left) as f64)
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use winit::platform::x11::ActiveEventLoopExtX11;
+        use x11rb::{
+            connection::Connection,
+            protocol::xproto::{AtomEnum, ConnectionExt as _},
+        };
+
+        /// The current virtual desktop's `_NET_WORKAREA`, set by the window manager.
+        ///
+        /// It's one rectangle across every monitor, so a panel along the bottom of
+        /// one monitor only shows up on the others if they reach down as far.
+        fn desktop_work_area(event_loop: &ActiveEventLoop) -> anyhow::Result<Option<WorkArea>> {
+            if !event_loop.is_x11() {
+                return Ok(None);
+            }
+            let (connection, screen) = x11rb::connect(None)?;
+            let root = connection.setup().roots[screen].root;
+            let property = |name: &str| -> anyhow::Result<Vec<u32>> {
+                let atom = connection.intern_atom(false, name.as_bytes())?.reply()?.atom;
+                let reply = connection
+                    .get_property(false, root, atom, AtomEnum::CARDINAL, 0, u32::MAX)?
+                    .reply()?;
+                Ok(reply.value32().map(Iterator::collect).unwrap_or_default())
+            };
+            let desktop = property("_NET_CURRENT_DESKTOP")?.first().copied().unwrap_or_default();
+            // x, y, width, height for every desktop
+            let areas = property("_NET_WORKAREA")?;
+            Ok(areas.chunks_exact(4).nth(desktop as usize).map(|area| WorkArea {
+                left: area[0] as i32,
+                top: area[1] as i32,
+                right: (area[0] + area[2]) as i32,
+                bottom: (area[1] + area[3]) as i32,
+            }))
+        }
+
+        /// Where the mouse cursor is on the desktop, or `None` on Wayland, which won't say.
+        pub fn cursor_position(
+            event_loop: &ActiveEventLoop,
+        ) -> anyhow::Result<Option<PhysicalPosition<i32>>> {
+            if !event_loop.is_x11() {
+                return Ok(None);
+            }
+            let (connection, screen) = x11rb::connect(None)?;
+            let root = connection.setup().roots[screen].root;
+            let pointer = connection.query_pointer(root)?.reply()?;
+            Ok(Some(PhysicalPosition::new(
+                pointer.root_x.into(),
+                pointer.root_y.into(),
+            )))
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use windows_sys::Win32::{
+            Foundation::{POINT, RECT},
+            UI::WindowsAndMessaging::{GetCursorPos, SystemParametersInfoW, SPI_GETWORKAREA},
+        };
+
+        /// The primary monitor's work area, everything but the taskbar.
+        fn desktop_work_area(_event_loop: &ActiveEventLoop) -> anyhow::Result<Option<WorkArea>> {
+            let mut rect = RECT { left: 0, top: 0, right: 0, bottom: 0 };
+            // SAFETY: SPI_GETWORKAREA writes a RECT to the pointer, which is to a local.
+            let ok = unsafe { SystemParametersInfoW(SPI_GETWORKAREA, 0, &mut rect as *mut RECT as *mut _, 0) };
+            if ok == 0 {
+                anyhow::bail!("SystemParametersInfoW failed: {}", std::io::Error::last_os_error());
+            }
+            Ok(Some(WorkArea {
+                left: rect.left,
+                top: rect.top,
+                right: rect.right,
+                bottom: rect.bottom,
+            }))
+        }
+
+        /// Where the mouse cursor is on the desktop.
+        pub fn cursor_position(
+            _event_loop: &ActiveEventLoop,
+        ) -> anyhow::Result<Option<PhysicalPosition<i32>>> {
+            let mut point = POINT { x: 0, y: 0 };
+            // SAFETY: GetCursorPos writes a POINT to the pointer, which is to a local.
+            if unsafe { GetCursorPos(&mut point) } == 0 {
+                anyhow::bail!("GetCursorPos failed: {}", std::io::Error::last_os_error());
+            }
+            Ok(Some(PhysicalPosition::new(point.x, point.y)))
+        }
+    } else {
+        fn desktop_work_area(_event_loop: &ActiveEventLoop) -> anyhow::Result<Option<WorkArea>> {
+            Ok(None)
+        }
+        pub fn cursor_position(
+            _event_loop: &ActiveEventLoop,
+        ) -> anyhow::Result<Option<PhysicalPosition<i32>>> {
+            Ok(None)
+        }
+    }
+}
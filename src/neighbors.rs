@@ -0,0 +1,80 @@
+//! Where every shimeji is, across all buckets, so shimejis can react to each other:
+//! walk up to one another, greet, and stand on each other's heads.
+//!
+//! Each bucket publishes where its own shimejis are once per update, and reads everyone's.
+//! The lock is only held to swap in or clone out [`Arc`]s, so buckets never wait on
+//! each other for long.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::WindowId,
+};
+
+/// How close, in pixels between their middles, two shimejis are to count as near each other.
+pub const NEAR_DISTANCE: f64 = 80.0;
+
+/// A shimeji, as the others see it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor {
+    pub id: WindowId,
+    /// The top left of its window.
+    pub position: PhysicalPosition<f64>,
+    pub size: PhysicalSize<u32>,
+}
+
+impl Neighbor {
+    pub fn center(&self) -> PhysicalPosition<f64> {
+        PhysicalPosition::new(
+            self.position.x + self.size.width as f64 / 2.0,
+            self.position.y + self.size.height as f64 / 2.0,
+        )
+    }
+    pub fn distance_to(&self, other: &Neighbor) -> f64 {
+        let (a, b) = (self.center(), other.center());
+        (a.x - b.x).hypot(a.y - b.y)
+    }
+    /// The closest of `others` that isn't this shimeji, and how far away it is.
+    pub fn nearest<'n>(&self, others: &'n [Neighbor]) -> Option<(&'n Neighbor, f64)> {
+        others
+            .iter()
+            .filter(|other| other.id != self.id)
+            .map(|other| (other, self.distance_to(other)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+/// Every bucket's shimejis, shared between all bucket threads.
+#[derive(Debug, Clone, Default)]
+pub struct Neighborhood {
+    buckets: Arc<RwLock<BTreeMap<usize, Arc<[Neighbor]>>>>,
+}
+
+impl Neighborhood {
+    /// Replace where the shimejis of `bucket` are.
+    pub fn publish(&self, bucket: usize, shimejis: Vec<Neighbor>) {
+        let shimejis = Arc::from(shimejis);
+        let mut buckets = self.buckets.write().unwrap_or_else(|e| e.into_inner());
+        buckets.insert(bucket, shimejis);
+    }
+    /// Forget the shimejis of `bucket`, e.g. once its thread stops.
+    pub fn leave(&self, bucket: usize) {
+        let mut buckets = self.buckets.write().unwrap_or_else(|e| e.into_inner());
+        buckets.remove(&bucket);
+    }
+    /// Every shimeji of every bucket, as last published.
+    pub fn everyone(&self) -> Arc<[Neighbor]> {
+        let buckets: Vec<_> = {
+            let buckets = self.buckets.read().unwrap_or_else(|e| e.into_inner());
+            buckets.values().cloned().collect()
+        };
+        buckets
+            .iter()
+            .flat_map(|shimejis| shimejis.iter().copied())
+            .collect()
+    }
+}
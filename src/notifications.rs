@@ -0,0 +1,148 @@
+//! Hearing about other applications' desktop notifications, so shimejis can react to them,
+//! looking or jumping toward the corner they pop up in,
+//! see [`Settings::notification_behavior`](crate::settings::Settings::notification_behavior).
+//!
+//! On Linux this monitors `org.freedesktop.Notifications` calls on the session bus,
+//! other platforms don't let applications see each other's notifications.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use derive_more::derive::{Display, Error};
+use winit::event_loop::EventLoopProxy;
+
+use crate::ManagerEvent;
+
+/// The least time between two reactions, so a burst of notifications is only one.
+pub const COOLDOWN: Duration = Duration::from_secs(5);
+
+/// Where on a monitor notifications pop up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Corner {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    pub fn is_top(self) -> bool {
+        matches!(self, Self::TopLeft | Self::TopRight)
+    }
+    pub fn is_left(self) -> bool {
+        matches!(self, Self::TopLeft | Self::BottomLeft)
+    }
+}
+
+#[derive(Debug, Display, Error)]
+#[display("unknown corner {corner:?}, expected top-left, top-right, bottom-left or bottom-right")]
+pub struct InvalidCorner {
+    corner: String,
+}
+
+impl FromStr for Corner {
+    type Err = InvalidCorner;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "top-left" => Self::TopLeft,
+            "top-right" => Self::TopRight,
+            "bottom-left" => Self::BottomLeft,
+            "bottom-right" => Self::BottomRight,
+            _ => {
+                return Err(InvalidCorner {
+                    corner: s.to_owned(),
+                })
+            }
+        })
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::TopLeft => "top-left",
+            Self::TopRight => "top-right",
+            Self::BottomLeft => "bottom-left",
+            Self::BottomRight => "bottom-right",
+        })
+    }
+}
+
+/// Listen for notifications on their own thread, sending [`ManagerEvent::Notification`]
+/// for each one that isn't ours, until `should_exit` is set.
+pub fn spawn(
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Notification listener thread"))
+        .spawn(move || {
+            if let Err(why) = listen(proxy, should_exit) {
+                log::warn!("Shimejis won't react to notifications: {why:#}");
+            }
+        })
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use std::sync::atomic::Ordering;
+
+        use dbus::{blocking::Connection, channel::MatchingReceiver, message::MatchRule};
+
+        const PROCESS_TIMEOUT: Duration = Duration::from_secs(1);
+
+        fn listen(
+            proxy: EventLoopProxy<ManagerEvent>,
+            should_exit: Arc<AtomicBool>,
+        ) -> anyhow::Result<()> {
+            let connection = Connection::new_session()?;
+            let rule = MatchRule::new_method_call()
+                .with_interface("org.freedesktop.Notifications")
+                .with_member("Notify");
+            // a monitor can't send anything afterwards, which is fine as it only listens
+            let _: () = connection
+                .with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", Duration::from_secs(2))
+                .method_call(
+                    "org.freedesktop.DBus.Monitoring",
+                    "BecomeMonitor",
+                    (vec![rule.match_str()], 0u32),
+                )?;
+            let manager_gone = Arc::new(AtomicBool::new(false));
+            let gone = Arc::clone(&manager_gone);
+            connection.start_receive(
+                rule,
+                Box::new(move |message, _| {
+                    // our own, e.g. about a broken pack
+                    if message
+                        .read1::<&str>()
+                        .is_ok_and(|app| app == crate::backend::APP_ID)
+                    {
+                        return true;
+                    }
+                    let sent = proxy.send_event(ManagerEvent::Notification).is_ok();
+                    gone.store(!sent, Ordering::Relaxed);
+                    sent
+                }),
+            );
+            while !should_exit.load(Ordering::Relaxed) && !manager_gone.load(Ordering::Relaxed) {
+                connection.process(PROCESS_TIMEOUT)?;
+            }
+            Ok(())
+        }
+    } else {
+        fn listen(
+            proxy: EventLoopProxy<ManagerEvent>,
+            should_exit: Arc<AtomicBool>,
+        ) -> anyhow::Result<()> {
+            let _ = (proxy, should_exit);
+            anyhow::bail!("other applications' notifications can't be seen on this platform")
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Telling the user about problems with their packs where they'll see them:
+//! a desktop notification on Linux and macOS, a message box on Windows.
+//! Most people starting shimejis never look at a terminal, let alone a log.
+
+use cfg_if::cfg_if;
+
+/// Show `summary`, with `body` saying what went wrong. Failing to is only logged,
+/// the problem itself should already have been.
+pub fn problem(summary: &str, body: &str) {
+    if let Err(why) = show(summary, body) {
+        log::warn!("Could not show {summary:?} to the user: {why:#}");
+    }
+}
+
+fn show(summary: &str, body: &str) -> anyhow::Result<()> {
+    cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            use std::time::Duration;
+
+            use dbus::{arg::PropMap, blocking::Connection};
+
+            let connection = Connection::new_session()?;
+            let notifications = connection.with_proxy(
+                "org.freedesktop.Notifications",
+                "/org/freedesktop/Notifications",
+                Duration::from_secs(2),
+            );
+            // notification servers may read the body as markup
+            let body = body
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            let _: (u32,) = notifications.method_call(
+                "org.freedesktop.Notifications",
+                "Notify",
+                (
+                    "new-shimeji",
+                    0u32,
+                    "dialog-warning",
+                    summary,
+                    body,
+                    Vec::<String>::new(),
+                    PropMap::new(),
+                    -1i32,
+                ),
+            )?;
+            Ok(())
+        } else if #[cfg(target_os = "windows")] {
+            use windows_sys::Win32::UI::WindowsAndMessaging::{
+                MessageBoxW, MB_ICONWARNING, MB_OK,
+            };
+
+            let wide = |text: &str| text.encode_utf16().chain([0]).collect::<Vec<u16>>();
+            let (text, caption) = (wide(body), wide(summary));
+            // SAFETY: both strings are nul-terminated and outlive the call
+            let pressed = unsafe {
+                MessageBoxW(
+                    std::ptr::null_mut(),
+                    text.as_ptr(),
+                    caption.as_ptr(),
+                    MB_OK | MB_ICONWARNING,
+                )
+            };
+            if pressed == 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            Ok(())
+        } else if #[cfg(target_os = "macos")] {
+            // AppleScript strings escape quotes and backslashes the same way
+            let script = format!("display notification {body:?} with title {summary:?}");
+            let status = std::process::Command::new("osascript")
+                .args(["-e", &script])
+                .status()?;
+            anyhow::ensure!(status.success(), "osascript exited with {status}");
+            Ok(())
+        } else {
+            let _ = (summary, body);
+            anyhow::bail!("there's no way to show it on this platform")
+        }
+    }
+}
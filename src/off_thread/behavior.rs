@@ -0,0 +1,533 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+
+use crate::{
+    formula::{Formula, Predicate},
+    loader::AnimationData,
+    movement::MovementCommand,
+    rng::Rng,
+    script::{Locals, Script, ScriptEffect, ScriptState},
+    world::WorldSnapshot,
+};
+
+/// How fast the `walk` animation of a pack without behaviors walks, in pixels per second.
+pub const DEFAULT_WALK_SPEED: f64 = 80.0;
+/// How many times likelier a partying shimeji is to pick a behavior that
+/// walks, jumps or multiplies, see [`BehaviorState::start_partying`].
+pub const PARTY_BOOST: f64 = 5.0;
+
+/// When a [`Transition`] is allowed to fire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// The behavior's animation played all the way through.
+    Finished,
+    /// The shimeji is being held by the user.
+    Held,
+    /// The shimeji was let go since the behavior started.
+    Released,
+    /// The shimeji was petted since the behavior started.
+    Petted,
+    /// The shimeji is in the air.
+    Airborne,
+    /// The shimeji is standing on something.
+    Grounded,
+    /// The shimeji is up against the left or right of its monitor.
+    Wall,
+    /// The shimeji is up against the top of its monitor.
+    Ceiling,
+    /// Another shimeji is within [`NEAR_DISTANCE`](crate::neighbors::NEAR_DISTANCE).
+    Near,
+    /// The user hasn't touched the keyboard or mouse for a while, see
+    /// [`crate::settings::Settings::idle_after`].
+    Idle,
+    /// The user is at the keyboard or mouse, or came back to it.
+    Active,
+    /// One of the transition's [`Transition::apps`] is focused, see [`crate::focus`].
+    Focused,
+    /// None of the transition's [`Transition::apps`] is focused.
+    Unfocused,
+    /// Fires as soon as the transition's `after` delay has passed.
+    Always,
+}
+
+impl FromStr for Condition {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "finished" => Self::Finished,
+            "held" => Self::Held,
+            "released" => Self::Released,
+            "petted" => Self::Petted,
+            "airborne" => Self::Airborne,
+            "grounded" => Self::Grounded,
+            "wall" => Self::Wall,
+            "ceiling" => Self::Ceiling,
+            "near" => Self::Near,
+            "idle" => Self::Idle,
+            "active" => Self::Active,
+            "focused" => Self::Focused,
+            "unfocused" => Self::Unfocused,
+            "always" => Self::Always,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// How a behavior moves the shimeji when it starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Stand,
+    /// Walk at this many pixels per second. Negative walks left.
+    Walk(f64),
+    Jump,
+    /// Climb at this many pixels per second. Negative climbs up.
+    Climb(f64),
+    /// Hold on to the ceiling.
+    Hang,
+    /// Let whatever is pulling the shimeji down do its thing.
+    Fall,
+    /// Walk toward the nearest other shimeji at this many pixels per second,
+    /// stopping once near it.
+    Approach(f64),
+    /// Walk toward the nearest prop within [`PROP_REACH`](crate::prop::PROP_REACH)
+    /// at this many pixels per second, kicking it along once there.
+    ApproachProp(f64),
+    /// Stand, and ask for another shimeji like this one where it stands,
+    /// unless there are already [`crate::settings::Settings::max_population`] of them.
+    Multiply,
+}
+
+impl Action {
+    /// Parse an `action` attribute, using `speed` for actions that move.
+    pub fn parse(action: &str, speed: f64) -> Option<Self> {
+        Some(match action {
+            "stand" => Self::Stand,
+            "walk" => Self::Walk(speed),
+            "jump" => Self::Jump,
+            "climb" => Self::Climb(speed),
+            "hang" => Self::Hang,
+            "fall" => Self::Fall,
+            "approach" => Self::Approach(speed),
+            "approach-prop" => Self::ApproachProp(speed),
+            "multiply" => Self::Multiply,
+            _ => return None,
+        })
+    }
+    pub fn movement(self) -> MovementCommand {
+        match self {
+            // steered toward its neighbor or the prop by the shimeji as it goes
+            Self::Stand
+            | Self::Fall
+            | Self::Approach(_)
+            | Self::ApproachProp(_)
+            | Self::Multiply => MovementCommand::Stop,
+            Self::Walk(speed) => MovementCommand::Walk(speed),
+            Self::Jump => MovementCommand::Jump,
+            Self::Climb(speed) => MovementCommand::Climb(speed),
+            Self::Hang => MovementCommand::Hang,
+        }
+    }
+    /// How much a partying shimeji's weight for a behavior with this action is scaled by.
+    fn party_boost(self) -> f64 {
+        match self {
+            Self::Walk(_) | Self::Jump | Self::Multiply => PARTY_BOOST,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub to: String,
+    pub when: Condition,
+    /// The least time to spend in the behavior before this can fire.
+    pub after: Option<Duration>,
+    /// Parts of the application names or window titles [`Condition::Focused`] looks for,
+    /// e.g. `app="spotify|rhythmbox"`.
+    pub apps: Vec<String>,
+}
+
+impl Transition {
+    /// Whether one of [`Transition::apps`] is focused in `world`.
+    fn app_focused(&self, world: &WorldSnapshot) -> bool {
+        world
+            .focused
+            .as_ref()
+            .is_some_and(|focused| self.apps.iter().any(|app| focused.matches(app)))
+    }
+}
+
+/// A behavior that may follow another, and how likely it is to.
+#[derive(Debug, Clone)]
+pub struct NextBehavior {
+    pub to: String,
+    pub weight: Formula,
+}
+
+#[derive(Debug, Clone)]
+pub struct Behavior {
+    pub name: String,
+    pub animation: String,
+    pub action: Action,
+    /// How likely this behavior is to be picked when the current one
+    /// finishes without any of its transitions firing.
+    pub weight: Option<Formula>,
+    /// Checked in order, the first one that can fire wins.
+    pub transitions: Vec<Transition>,
+    /// Picked from by weight when the behavior finishes without any of its transitions firing.
+    /// Any behavior with a `weight` can be picked if this is empty.
+    pub next: Vec<NextBehavior>,
+    /// Run every update while in the behavior, see [`crate::script`].
+    pub script: Option<Script>,
+    /// What has to hold for the behavior to be picked or transitioned to,
+    /// e.g. `month == 12`. It can always be if there's none.
+    pub condition: Option<Predicate>,
+}
+
+impl Behavior {
+    /// Whether the behavior's [`Behavior::condition`] holds in `world`.
+    pub fn allowed(&self, world: &WorldSnapshot, rng: &mut Rng) -> bool {
+        self.condition
+            .as_ref()
+            .is_none_or(|condition| condition.holds(world, rng))
+    }
+}
+
+/// Every behavior of a pack.
+#[derive(Debug, Clone)]
+pub struct BehaviorTable {
+    behaviors: HashMap<String, Behavior>,
+    initial: String,
+}
+
+impl BehaviorTable {
+    /// # Errors
+    /// Errors if a behavior or its script uses an animation that doesn't exist,
+    /// a transition, next behavior or script goes to a behavior that doesn't exist,
+    /// or `initial` doesn't exist.
+    pub fn new(
+        behaviors: Vec<Behavior>,
+        initial: String,
+        animations: &HashMap<String, AnimationData>,
+    ) -> anyhow::Result<Self> {
+        let behaviors: HashMap<_, _> = behaviors
+            .into_iter()
+            .map(|behavior| (behavior.name.clone(), behavior))
+            .collect();
+        for behavior in behaviors.values() {
+            if !animations.contains_key(&behavior.animation) {
+                bail!(
+                    "behavior {} uses animation {}, which does not exist",
+                    behavior.name,
+                    behavior.animation
+                );
+            }
+            for transition in behavior.transitions.iter() {
+                if !behaviors.contains_key(&transition.to) {
+                    bail!(
+                        "behavior {} transitions to {}, which does not exist",
+                        behavior.name,
+                        transition.to
+                    );
+                }
+            }
+            if let Some(next) = behavior
+                .next
+                .iter()
+                .find(|next| !behaviors.contains_key(&next.to))
+            {
+                bail!(
+                    "behavior {} can be followed by {}, which does not exist",
+                    behavior.name,
+                    next.to
+                );
+            }
+            let Some(script) = &behavior.script else {
+                continue;
+            };
+            if let Some(animation) = script
+                .animations()
+                .into_iter()
+                .find(|animation| !animations.contains_key(*animation))
+            {
+                bail!(
+                    "the script of behavior {} plays animation {animation}, which does not exist",
+                    behavior.name
+                );
+            }
+            if let Some(to) = script
+                .behaviors()
+                .into_iter()
+                .find(|to| !behaviors.contains_key(*to))
+            {
+                bail!(
+                    "the script of behavior {} switches to {to}, which does not exist",
+                    behavior.name
+                );
+            }
+        }
+        if !behaviors.contains_key(&initial) {
+            bail!("initial behavior {initial} does not exist");
+        }
+        Ok(Self { behaviors, initial })
+    }
+    /// One behavior per animation, weighted like the animation,
+    /// for packs that don't define any behaviors of their own.
+    ///
+    /// Every animation stands still, except:
+    /// - `walk` walks left at `walk_speed`, turning around at the edges of the screen,
+    ///   or climbing them if there is a `climb` animation.
+    /// - `climb` climbs up at `walk_speed`, hanging from the top of the screen
+    ///   if there is a `hang` animation.
+    /// - `hang` holds on to the top of the screen. Walking from there walks along it.
+    pub fn from_animations(animations: &HashMap<String, AnimationData>, walk_speed: f64) -> Self {
+        let to_if_exists = |to: &str, when| {
+            animations.contains_key(to).then(|| Transition {
+                to: String::from(to),
+                when,
+                after: None,
+                apps: vec![],
+            })
+        };
+        let behaviors = animations
+            .iter()
+            .map(|(name, animation)| {
+                let (action, transitions) = match name.as_str() {
+                    "walk" => (
+                        Action::Walk(-walk_speed),
+                        to_if_exists("climb", Condition::Wall),
+                    ),
+                    "climb" => (
+                        Action::Climb(-walk_speed),
+                        to_if_exists("hang", Condition::Ceiling),
+                    ),
+                    "hang" => (Action::Hang, None),
+                    _ => (Action::Stand, None),
+                };
+                let behavior = Behavior {
+                    name: name.clone(),
+                    animation: name.clone(),
+                    action,
+                    weight: animation.weight.clone(),
+                    transitions: transitions.into_iter().collect(),
+                    next: vec![],
+                    script: None,
+                    condition: None,
+                };
+                (name.clone(), behavior)
+            })
+            .collect();
+        let initial = if animations.contains_key("idle") {
+            String::from("idle")
+        } else {
+            animations.keys().min().cloned().unwrap_or_default()
+        };
+        Self { behaviors, initial }
+    }
+    pub fn get(&self, name: &str) -> Option<&Behavior> {
+        self.behaviors.get(name)
+    }
+    pub fn initial(&self) -> &Behavior {
+        &self.behaviors[&self.initial]
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Behavior> {
+        self.behaviors.values()
+    }
+    /// Every behavior, in the order a shimeji is likeliest to play them in `world`:
+    /// the initial one, then the ones its transitions go to, the ones its `next` list
+    /// picks from and then the rest, each most heavily weighted first.
+    /// Weights are rolled with `rng`.
+    pub fn likeliest_first(&self, world: &WorldSnapshot, rng: &mut Rng) -> Vec<&Behavior> {
+        fn by_weight(mut weighted: Vec<(&Behavior, f64)>) -> impl Iterator<Item = &Behavior> {
+            weighted.sort_by(|(a, a_weight), (b, b_weight)| {
+                b_weight
+                    .total_cmp(a_weight)
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+            weighted.into_iter().map(|(behavior, _)| behavior)
+        }
+        let initial = self.initial();
+        let transitions = initial
+            .transitions
+            .iter()
+            .filter_map(|transition| self.get(&transition.to));
+        let next = initial
+            .next
+            .iter()
+            .filter_map(|next| Some((self.get(&next.to)?, next.weight.evaluate(world, rng))))
+            .collect();
+        let rest = self
+            .iter()
+            .map(|behavior| {
+                let weight = behavior.weight.as_ref();
+                (
+                    behavior,
+                    weight.map_or(0.0, |weight| weight.evaluate(world, rng)),
+                )
+            })
+            .collect();
+        let mut ordered = vec![initial];
+        for behavior in transitions.chain(by_weight(next)).chain(by_weight(rest)) {
+            if !ordered.iter().any(|seen| seen.name == behavior.name) {
+                ordered.push(behavior);
+            }
+        }
+        ordered
+    }
+}
+
+/// What a shimeji is going through, as far as its behaviors care.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Situation {
+    pub held: bool,
+    pub airborne: bool,
+    pub at_wall: bool,
+    pub at_ceiling: bool,
+    pub animation_finished: bool,
+    pub near_another: bool,
+}
+
+/// Which behavior a shimeji is in, and what has happened to it since.
+#[derive(Debug, Clone)]
+pub struct BehaviorState {
+    current: String,
+    entered_at: Instant,
+    petted: bool,
+    released: bool,
+    /// Whether the shimeji is at a party, which lasts as long as it does.
+    partying: bool,
+    /// The Lua state of the behavior's script.
+    script: ScriptState,
+}
+
+impl BehaviorState {
+    pub fn new(table: &BehaviorTable) -> Self {
+        Self {
+            current: table.initial().name.clone(),
+            entered_at: Instant::now(),
+            petted: false,
+            released: false,
+            partying: false,
+            script: ScriptState::default(),
+        }
+    }
+    pub fn current<'t>(&self, table: &'t BehaviorTable) -> Option<&'t Behavior> {
+        table.get(&self.current)
+    }
+    pub fn time_in_behavior(&self) -> Duration {
+        self.entered_at.elapsed()
+    }
+    pub fn petted(&mut self) {
+        self.petted = true;
+    }
+    pub fn released(&mut self) {
+        self.released = true;
+    }
+    /// Scale up the weights of behaviors that walk, jump or multiply by [`PARTY_BOOST`]
+    /// from now on, however they're picked.
+    pub fn start_partying(&mut self) {
+        self.partying = true;
+    }
+    fn boost(&self, behavior: &Behavior) -> f64 {
+        match self.partying {
+            true => behavior.action.party_boost(),
+            false => 1.0,
+        }
+    }
+    /// Run the current behavior's script, if it has one, for what it wants done
+    /// to the shimeji `locals` describes.
+    pub fn run_script(
+        &mut self,
+        table: &BehaviorTable,
+        world: &WorldSnapshot,
+        locals: Locals,
+        rng: &mut Rng,
+    ) -> Vec<ScriptEffect> {
+        let Some(script) = table
+            .get(&self.current)
+            .and_then(|behavior| behavior.script.as_ref())
+        else {
+            return vec![];
+        };
+        let locals = Locals {
+            time: self.time_in_behavior().as_secs_f64(),
+            ..locals
+        };
+        script.run(world, &locals, &mut self.script, rng)
+    }
+    /// Work out which behavior comes next, if the current one should end.
+    pub fn next<'t>(
+        &self,
+        table: &'t BehaviorTable,
+        situation: Situation,
+        world: &WorldSnapshot,
+        rng: &mut Rng,
+    ) -> Option<&'t Behavior> {
+        let current = self.current(table)?;
+        let elapsed = self.time_in_behavior();
+        for transition in current.transitions.iter() {
+            if transition.after.is_some_and(|after| elapsed < after) {
+                continue;
+            }
+            let fires = match transition.when {
+                Condition::Finished => situation.animation_finished,
+                Condition::Held => situation.held,
+                Condition::Released => self.released,
+                Condition::Petted => self.petted,
+                Condition::Airborne => situation.airborne,
+                Condition::Grounded => !situation.airborne,
+                Condition::Wall => situation.at_wall,
+                Condition::Ceiling => situation.at_ceiling,
+                Condition::Near => situation.near_another,
+                Condition::Idle => world.user_idle,
+                Condition::Active => !world.user_idle,
+                Condition::Focused => transition.app_focused(world),
+                Condition::Unfocused => !transition.app_focused(world),
+                Condition::Always => true,
+            };
+            if let Some(to) = table.get(&transition.to).filter(|_| fires) {
+                if to.allowed(world, rng) {
+                    return Some(to);
+                }
+            }
+        }
+        if !situation.animation_finished {
+            return None;
+        }
+        if !current.next.is_empty() {
+            let weighted: Vec<_> = current
+                .next
+                .iter()
+                .filter_map(|next| {
+                    let behavior = table.get(&next.to)?;
+                    let weight = next.weight.evaluate(world, rng);
+                    behavior.allowed(world, rng).then_some((behavior, weight))
+                })
+                .map(|(behavior, weight)| (behavior, weight * self.boost(behavior)))
+                .collect();
+            return rng.choose_weighted(&weighted).copied();
+        }
+        let weighted: Vec<_> = table
+            .iter()
+            .filter_map(|behavior| {
+                let weight = behavior.weight.as_ref()?.evaluate(world, rng);
+                behavior.allowed(world, rng).then_some((behavior, weight))
+            })
+            .map(|(behavior, weight)| (behavior, weight * self.boost(behavior)))
+            .collect();
+        rng.choose_weighted(&weighted).copied()
+    }
+    pub fn enter(&mut self, behavior: &Behavior) {
+        self.current.clone_from(&behavior.name);
+        self.entered_at = Instant::now();
+        self.petted = false;
+        self.released = false;
+        self.script = ScriptState::default();
+    }
+}
@@ -0,0 +1,279 @@
+use std::str::FromStr;
+
+use crate::{
+    ambient::Tint,
+    loader::Frame,
+    rgba::{PixelFormat, Rgba},
+};
+
+/// What to do when a frame and the buffer it's drawn into aren't the same size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlitPolicy {
+    /// Keep the frame's size and center it, cutting off whatever doesn't fit.
+    #[default]
+    Center,
+    /// Keep the frame's size and pin it to the top left, cutting off whatever doesn't fit.
+    Clip,
+    /// Stretch the frame to fill the buffer, nearest neighbour.
+    Scale,
+    /// Scale the frame as big as fits without changing its shape, centered.
+    Fit(Filter),
+}
+
+/// How a frame is sampled when it's drawn at a different size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Filter {
+    /// Blocky, which keeps pixel art crisp.
+    #[default]
+    Nearest,
+    /// Blends the four closest pixels, smooth but blurry.
+    Linear,
+}
+
+/// How a pack's sprites fill a window the window manager made bigger or smaller than them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScalingMode {
+    /// Leave it to the renderer, which only scales by whole numbers.
+    #[default]
+    Integer,
+    Nearest,
+    Linear,
+}
+
+impl ScalingMode {
+    /// What to draw frames into a buffer as big as the window with,
+    /// or `None` if the buffer should stay the size of the frames.
+    pub fn policy(self) -> Option<BlitPolicy> {
+        match self {
+            Self::Integer => None,
+            Self::Nearest => Some(BlitPolicy::Fit(Filter::Nearest)),
+            Self::Linear => Some(BlitPolicy::Fit(Filter::Linear)),
+        }
+    }
+}
+
+impl FromStr for ScalingMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "integer" => Self::Integer,
+            "nearest" => Self::Nearest,
+            "linear" => Self::Linear,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Which way a frame is drawn. Sprites face left as drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Facing {
+    #[default]
+    Left,
+    /// Mirrored horizontally.
+    Right,
+}
+
+impl Facing {
+    /// The way something moving at `speed` pixels per second faces,
+    /// or `None` if it isn't moving.
+    pub fn of_speed(speed: f64) -> Option<Self> {
+        if speed > 0.0 {
+            Some(Self::Right)
+        } else if speed < 0.0 {
+            Some(Self::Left)
+        } else {
+            None
+        }
+    }
+}
+
+/// A soft ellipse under a sprite's feet, drawn into the same buffer as it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    /// How dark the middle of the shadow is, from 0 to 1.
+    pub opacity: f64,
+    /// How wide the shadow is, as a fraction of the buffer's width.
+    pub size: f64,
+}
+
+impl Shadow {
+    pub const DEFAULT_OPACITY: f64 = 0.35;
+    pub const DEFAULT_SIZE: f64 = 0.6;
+
+    /// This shadow under something `height` pixels off the ground. It shrinks and fades
+    /// the higher that is, and is gone at `fade_height`.
+    pub fn lifted(self, height: f64, fade_height: f64) -> Option<Self> {
+        let left = 1.0 - height / fade_height;
+        (left > 0.0).then_some(Self {
+            opacity: self.opacity * left,
+            size: self.size * (0.5 + 0.5 * left),
+        })
+    }
+    /// How opaque the shadow is at pixel `x`, `y` of a buffer of `size`,
+    /// darkest in the middle and fading out towards the edge.
+    fn alpha_at(self, x: i64, y: i64, size: BufferSize) -> u8 {
+        let radius_x = self.size * size.width as f64 / 2.0;
+        // flat, like it's seen from the side
+        let radius_y = (radius_x / 4.0).max(1.0);
+        let (center_x, center_y) = (size.width as f64 / 2.0, size.height as f64 - radius_y);
+        let distance = ((x as f64 + 0.5 - center_x) / radius_x).powi(2)
+            + ((y as f64 + 0.5 - center_y) / radius_y).powi(2);
+        if distance >= 1.0 {
+            return 0;
+        }
+        (self.opacity.clamp(0.0, 1.0) * (1.0 - distance) * 255.0).round() as u8
+    }
+}
+
+/// Everything drawn on top of, or under, a frame as it is copied.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Effects {
+    pub shadow: Option<Shadow>,
+    /// Tints the frame, but not its shadow.
+    pub tint: Option<Tint>,
+    /// What the buffer is filled with where the frame doesn't reach, transparent if `None`.
+    pub letterbox: Option<Rgba>,
+}
+
+/// The size of a buffer of pixels, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Copy `frame` into `buffer` row by row, honouring each one's own width.
+///
+/// Pixels of the buffer that the frame doesn't cover are made transparent,
+/// apart from the shadow under it, which is left out for formats without alpha.
+pub fn blit(
+    frame: &Frame,
+    buffer: &mut [u8],
+    size: BufferSize,
+    format: PixelFormat,
+    policy: BlitPolicy,
+    facing: Facing,
+    effects: Effects,
+) {
+    let shadow = effects.shadow.filter(|_| format.has_alpha());
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let stride = size.width as usize * bytes_per_pixel;
+    if stride == 0 || frame.width == 0 || frame.height == 0 {
+        return;
+    }
+    let (frame_width, frame_height) = (frame.width as i64, frame.height as i64);
+    let (width, height) = (size.width as i64, size.height as i64);
+    // where the buffer's top left corner lands on the frame, for the unscaled policies
+    let (offset_x, offset_y) = match policy {
+        BlitPolicy::Center => ((frame_width - width) / 2, (frame_height - height) / 2),
+        BlitPolicy::Clip | BlitPolicy::Scale | BlitPolicy::Fit(_) => (0, 0),
+    };
+    // how much bigger the frame is drawn, and where its top left lands on the buffer, to fit
+    let fit_scale = (width as f64 / frame_width as f64).min(height as f64 / frame_height as f64);
+    let fit_left = (width as f64 - frame_width as f64 * fit_scale) / 2.0;
+    let fit_top = (height as f64 - frame_height as f64 * fit_scale) / 2.0;
+    for (y, row) in buffer
+        .chunks_exact_mut(stride)
+        .take(size.height as usize)
+        .enumerate()
+    {
+        for (x, pixel) in row.chunks_exact_mut(bytes_per_pixel).enumerate() {
+            let (x, y) = (x as i64, y as i64);
+            let color = match policy {
+                BlitPolicy::Fit(filter) => {
+                    let source_x = (x as f64 + 0.5 - fit_left) / fit_scale;
+                    let source_y = (y as f64 + 0.5 - fit_top) / fit_scale;
+                    let source_x = match facing {
+                        Facing::Left => source_x,
+                        Facing::Right => frame_width as f64 - source_x,
+                    };
+                    sample(frame, source_x, source_y, filter)
+                }
+                BlitPolicy::Scale | BlitPolicy::Center | BlitPolicy::Clip => {
+                    let (source_x, source_y) = match policy {
+                        BlitPolicy::Scale => (x * frame_width / width, y * frame_height / height),
+                        _ => (x + offset_x, y + offset_y),
+                    };
+                    let source_x = match facing {
+                        Facing::Left => source_x,
+                        Facing::Right => frame_width - 1 - source_x,
+                    };
+                    pixel_at(frame, source_x, source_y)
+                }
+            };
+            let mut color = color
+                .map(|color| match effects.tint {
+                    Some(tint) => tint.apply(color),
+                    None => color,
+                })
+                .or(effects.letterbox);
+            if let Some(shadow) = shadow {
+                let alpha = shadow.alpha_at(x, y, size);
+                if alpha > 0 {
+                    let under = Rgba::new(0, 0, 0, alpha);
+                    color = Some(color.map_or(under, |color| color.over(under)));
+                }
+            }
+            match color {
+                Some(color) => color.write_as(format, pixel),
+                None => pixel.fill(0),
+            }
+        }
+    }
+}
+
+/// The pixel of `frame` at `x`, `y`, or `None` if that's outside of it.
+fn pixel_at(frame: &Frame, x: i64, y: i64) -> Option<Rgba> {
+    let inside = (0..frame.width as i64).contains(&x) && (0..frame.height as i64).contains(&y);
+    inside
+        .then(|| {
+            frame
+                .pixels_row_major
+                .get((y * frame.width as i64 + x) as usize)
+        })
+        .flatten()
+        .copied()
+}
+
+/// The color of `frame` at the point `x`, `y`, where pixel 0 spans from 0 up to 1,
+/// or `None` if that's outside of it.
+pub(crate) fn sample(frame: &Frame, x: f64, y: f64, filter: Filter) -> Option<Rgba> {
+    if x < 0.0 || y < 0.0 || x >= frame.width as f64 || y >= frame.height as f64 {
+        return None;
+    }
+    if filter == Filter::Nearest {
+        return pixel_at(frame, x as i64, y as i64);
+    }
+    // between the centers of the four closest pixels, clamped at the edges
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (left, top) = (x.floor(), y.floor());
+    let (right_weight, bottom_weight) = (x - left, y - top);
+    let clamp_x = |x: f64| (x as i64).clamp(0, frame.width as i64 - 1);
+    let clamp_y = |y: f64| (y as i64).clamp(0, frame.height as i64 - 1);
+    let corners = [
+        (left, top, (1.0 - right_weight) * (1.0 - bottom_weight)),
+        (left + 1.0, top, right_weight * (1.0 - bottom_weight)),
+        (left, top + 1.0, (1.0 - right_weight) * bottom_weight),
+        (left + 1.0, top + 1.0, right_weight * bottom_weight),
+    ];
+    let (mut red, mut green, mut blue, mut alpha) = (0.0, 0.0, 0.0, 0.0);
+    for (x, y, weight) in corners {
+        let pixel = pixel_at(frame, clamp_x(x), clamp_y(y))?;
+        // weighed by alpha too, so transparent pixels don't darken the edges
+        let opacity = weight * pixel.alpha as f64;
+        red += pixel.red as f64 * opacity;
+        green += pixel.green as f64 * opacity;
+        blue += pixel.blue as f64 * opacity;
+        alpha += opacity;
+    }
+    if alpha == 0.0 {
+        return Some(Rgba::new(0, 0, 0, 0));
+    }
+    Some(Rgba::new(
+        (red / alpha).round() as u8,
+        (green / alpha).round() as u8,
+        (blue / alpha).round() as u8,
+        alpha.round() as u8,
+    ))
+}
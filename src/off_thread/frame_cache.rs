@@ -0,0 +1,89 @@
+//! Frames already drawn into a window's pixel buffer, shared by every shimeji of a pack
+//! so each one only copies them over instead of drawing them again.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    ambient::Tint,
+    blit::{BufferSize, Facing},
+    rgba::PixelFormat,
+};
+
+/// How many bytes of drawn frames a pack keeps before starting over.
+const MAX_CACHED_BYTES: usize = 64 * 1024 * 1024;
+
+/// Everything that changes how a frame ends up in a buffer,
+/// other than what is the same for the whole pack.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FrameKey {
+    pub animation: String,
+    pub frame: usize,
+    pub size: BufferSize,
+    pub format: PixelFormat,
+    pub facing: Facing,
+    /// The bits of each channel of the tint, which can't be hashed as floats.
+    pub tint: Option<[u64; 3]>,
+    /// The shimeji's [`Variant::bits`](crate::variant::Variant::bits), if it's recolored.
+    pub variant: Option<[u64; 4]>,
+}
+
+impl FrameKey {
+    pub fn tint_bits(tint: Option<Tint>) -> Option<[u64; 3]> {
+        tint.map(|tint| {
+            [
+                tint.red.to_bits(),
+                tint.green.to_bits(),
+                tint.blue.to_bits(),
+            ]
+        })
+    }
+}
+
+#[derive(derive_more::Debug, Default)]
+pub struct FrameCache {
+    #[debug("{} frames", self.frames.lock().map_or(0, |frames| frames.len()))]
+    frames: Mutex<HashMap<FrameKey, Arc<[u8]>>>,
+}
+
+impl Clone for FrameCache {
+    /// A clone starts out empty, it is only a cache.
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl FrameCache {
+    /// The frame drawn for `key`, drawing it into a buffer of `length` bytes with `draw`
+    /// if no shimeji has yet.
+    pub fn get_or_draw(
+        &self,
+        key: FrameKey,
+        length: usize,
+        draw: impl FnOnce(&mut [u8]),
+    ) -> Arc<[u8]> {
+        if let Some(frame) = self.lock().get(&key) {
+            return Arc::clone(frame);
+        }
+        // drawn without holding the lock, another shimeji drawing the same frame
+        // at the same time only costs the time to draw it
+        let mut buffer = vec![0; length];
+        draw(&mut buffer);
+        let frame: Arc<[u8]> = Arc::from(buffer);
+        let mut frames = self.lock();
+        let cached: usize = frames.values().map(|frame| frame.len()).sum();
+        if cached + length > MAX_CACHED_BYTES {
+            frames.clear();
+        }
+        frames.insert(key, Arc::clone(&frame));
+        frame
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<FrameKey, Arc<[u8]>>> {
+        // a panic mid-insert leaves nothing half written worth throwing away
+        self.frames
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
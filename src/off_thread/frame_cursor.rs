@@ -0,0 +1,104 @@
+/// What happens when an animation runs out of frames, from an animation's `loop` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopMode {
+    /// `loop`, start again from the first frame.
+    #[default]
+    Loop,
+    /// `once`, stay on the last frame and let the behavior move on.
+    Once,
+    /// `ping-pong`, play backwards to the first frame, then forwards again.
+    PingPong,
+    /// `hold-last`, stay on the last frame without ever finishing,
+    /// for poses that last until something else ends the behavior.
+    HoldLast,
+}
+
+impl std::str::FromStr for LoopMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "loop" => Self::Loop,
+            "once" => Self::Once,
+            "ping-pong" => Self::PingPong,
+            "hold-last" => Self::HoldLast,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Which frame of an animation is showing, and which one comes next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameCursor {
+    index: usize,
+    len: usize,
+    mode: LoopMode,
+    finished: bool,
+    /// Whether a [`LoopMode::PingPong`] animation is on its way back to the first frame.
+    backwards: bool,
+}
+
+impl FrameCursor {
+    /// A cursor at the first of `len` frames.
+    pub fn new(len: usize, mode: LoopMode) -> Self {
+        Self {
+            index: 0,
+            len,
+            mode,
+            finished: len == 0,
+            backwards: false,
+        }
+    }
+    /// The zero-indexed frame to show, or `None` if there are no frames.
+    pub fn index(&self) -> Option<usize> {
+        (self.index < self.len).then_some(self.index)
+    }
+    pub fn mode(&self) -> LoopMode {
+        self.mode
+    }
+    /// Whether a [`LoopMode::Once`] or [`LoopMode::HoldLast`] animation
+    /// has reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+    /// Move to the next frame.
+    ///
+    /// Returns `true` if this completed a full pass over the animation, either by
+    /// wrapping around, by coming back to the first frame of a ping-pong animation,
+    /// or by reaching the end of a one-shot animation. Held animations never complete one.
+    pub fn advance(&mut self) -> bool {
+        if self.finished {
+            return false;
+        }
+        if self.backwards {
+            // a ping-pong pass only ends back on the first frame
+            self.index -= 1;
+            self.backwards = self.index > 0;
+            return self.index == 0;
+        }
+        if self.index + 1 < self.len {
+            self.index += 1;
+            return false;
+        }
+        match self.mode {
+            LoopMode::Loop => self.index = 0,
+            LoopMode::PingPong if self.len > 1 => {
+                self.index -= 1;
+                self.backwards = self.index > 0;
+                return self.index == 0;
+            }
+            LoopMode::PingPong => (),
+            LoopMode::Once => self.finished = true,
+            LoopMode::HoldLast => {
+                self.finished = true;
+                return false;
+            }
+        }
+        true
+    }
+    pub fn reset(&mut self) {
+        self.index = 0;
+        self.finished = self.len == 0;
+        self.backwards = false;
+    }
+}
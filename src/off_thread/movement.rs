@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+use winit::dpi::PhysicalPosition;
+
+/// Initial upward speed of a jump, in pixels per second.
+const JUMP_SPEED: f64 = 600.0;
+/// Downward acceleration pulling a shimeji back down, in pixels per second squared.
+const GRAVITY: f64 = 1800.0;
+/// The fastest a shimeji can be thrown, in pixels per second.
+const MAX_THROW_SPEED: f64 = 2500.0;
+/// How much of its speed a thrown shimeji keeps when it bounces off the ground or the ceiling.
+const BOUNCINESS: f64 = 0.45;
+/// A thrown shimeji landing slower than this, in pixels per second, stays down.
+const MIN_BOUNCE_SPEED: f64 = 150.0;
+
+/// A request to change how a shimeji is moving.
+///
+/// Behaviors and direct control (e.g. a gamepad) both move shimejis
+/// exclusively through these commands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MovementCommand {
+    /// Walk horizontally at `speed` pixels per second.
+    /// Negative speeds walk left, positive speeds walk right.
+    Walk(f64),
+    /// Hop into the air, landing back where the jump started,
+    /// or on the floor if the shimeji falls.
+    Jump,
+    /// Climb vertically at `speed` pixels per second, if touching a wall.
+    /// Negative speeds climb up, positive speeds climb down.
+    ///
+    /// Climbing up into the ceiling grabs onto it, see [`MovementCommand::Hang`].
+    Climb(f64),
+    /// Stop moving while holding on to the ceiling, or just stop if not touching it.
+    Hang,
+    /// Stop walking or climbing, letting go of whatever the shimeji holds on to.
+    Stop,
+}
+
+/// Something a shimeji can hold on to instead of falling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Surface {
+    Wall,
+    /// Walking while holding on to the ceiling walks along it.
+    Ceiling,
+}
+
+/// An edge of the monitor a shimeji is touching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    LeftWall,
+    RightWall,
+    Ceiling,
+}
+
+/// The position and velocity of a shimeji's window.
+#[derive(Debug, Clone)]
+pub struct Movement {
+    position: PhysicalPosition<f64>,
+    /// In pixels per second.
+    velocity: (f64, f64),
+    /// The height a jump started from, if the shimeji is currently jumping.
+    jumped_from: Option<f64>,
+    clinging: Option<Surface>,
+    /// Whether the shimeji is flying after being thrown, bouncing until it lands for good.
+    thrown: bool,
+    /// Whether gravity pulls the shimeji down to the floor when it isn't jumping.
+    falls: bool,
+    /// The lowest the top of the window can go, if known.
+    floor: Option<f64>,
+    /// The furthest left and right the left of the window can go, if known.
+    walls: Option<(f64, f64)>,
+    /// Whether going past one wall comes back in at the other, instead of stopping at it.
+    wraps: bool,
+    /// The highest the top of the window can go, if known.
+    ceiling: Option<f64>,
+}
+
+impl Movement {
+    pub fn new(position: PhysicalPosition<f64>) -> Self {
+        Self {
+            position,
+            velocity: (0.0, 0.0),
+            jumped_from: None,
+            clinging: None,
+            thrown: false,
+            falls: false,
+            floor: None,
+            walls: None,
+            wraps: false,
+            ceiling: None,
+        }
+    }
+    /// Let gravity pull the shimeji down to the floor, see [`Movement::set_floor`].
+    pub fn with_gravity(mut self, falls: bool) -> Self {
+        self.falls = falls;
+        self
+    }
+    pub fn set_floor(&mut self, floor: Option<f64>) {
+        self.floor = floor;
+    }
+    /// Turn around when walking into `walls`, see [`Movement::step`].
+    pub fn set_walls(&mut self, walls: Option<(f64, f64)>) {
+        self.walls = walls;
+    }
+    /// Come back in at the other wall when going past one, with no walls to climb.
+    pub fn set_wrapping(&mut self, wraps: bool) {
+        self.wraps = wraps;
+    }
+    pub fn set_ceiling(&mut self, ceiling: Option<f64>) {
+        self.ceiling = ceiling;
+    }
+    /// The edge of the monitor the shimeji is up against, if any.
+    pub fn touching(&self) -> Option<Edge> {
+        if let Some((left, right)) = self.walls.filter(|_| !self.wraps) {
+            if self.position.x <= left {
+                return Some(Edge::LeftWall);
+            }
+            if self.position.x >= right {
+                return Some(Edge::RightWall);
+            }
+        }
+        self.ceiling
+            .filter(|ceiling| self.position.y <= *ceiling)
+            .map(|_| Edge::Ceiling)
+    }
+    /// Whether the shimeji is up against the left or right of the monitor.
+    ///
+    /// Always true if the walls aren't known, so shimejis on unknown monitors can still climb.
+    fn at_wall(&self) -> bool {
+        self.walls.is_none() || matches!(self.touching(), Some(Edge::LeftWall | Edge::RightWall))
+    }
+    pub fn clinging_to(&self) -> Option<Surface> {
+        self.clinging
+    }
+    /// Let go of whatever the shimeji holds on to, e.g. when it is picked up.
+    pub fn let_go(&mut self) {
+        self.clinging = None;
+    }
+    /// Where the current fall or jump ends, if the shimeji is in one.
+    fn ground(&self) -> Option<f64> {
+        if self.clinging.is_some() {
+            return None;
+        }
+        if self.falls {
+            self.floor.or(self.jumped_from)
+        } else {
+            self.jumped_from
+        }
+    }
+    pub fn position(&self) -> PhysicalPosition<f64> {
+        self.position
+    }
+    /// Teleport to `position`, keeping the current velocity.
+    pub fn set_position(&mut self, position: PhysicalPosition<f64>) {
+        self.position = position;
+    }
+    pub fn velocity(&self) -> (f64, f64) {
+        self.velocity
+    }
+    /// How far above where it will land the shimeji is,
+    /// or `None` while it holds on to a wall or the ceiling.
+    pub fn height_above_ground(&self) -> Option<f64> {
+        if self.clinging.is_some() {
+            return None;
+        }
+        Some(
+            self.ground()
+                .map_or(0.0, |ground| (ground - self.position.y).max(0.0)),
+        )
+    }
+    pub fn is_jumping(&self) -> bool {
+        self.jumped_from.is_some()
+    }
+    /// Whether stepping would leave it where it is.
+    pub fn is_still(&self) -> bool {
+        self.velocity == (0.0, 0.0) && !self.is_airborne()
+    }
+    /// Whether the shimeji is jumping or falling.
+    pub fn is_airborne(&self) -> bool {
+        self.is_jumping() || self.ground().is_some_and(|ground| self.position.y < ground)
+    }
+    /// Set the velocity outright, e.g. when something is kicked.
+    /// An upwards push arcs back down to the current height, like a jump.
+    pub fn push(&mut self, velocity: (f64, f64)) {
+        self.velocity = velocity;
+        if velocity.1 < 0.0 && !self.is_airborne() {
+            self.jumped_from = Some(self.position.y);
+        }
+    }
+    /// Fly off at `velocity` after being let go of, bouncing off the ground, the ceiling and
+    /// the walls until it lands. Without gravity, it lands back where it was let go.
+    pub fn throw(&mut self, velocity: (f64, f64)) {
+        let speed = velocity.0.hypot(velocity.1);
+        let velocity = match speed > MAX_THROW_SPEED {
+            true => (
+                velocity.0 / speed * MAX_THROW_SPEED,
+                velocity.1 / speed * MAX_THROW_SPEED,
+            ),
+            false => velocity,
+        };
+        self.clinging = None;
+        self.thrown = true;
+        self.velocity = velocity;
+        // in the air even when let go of on the floor
+        self.jumped_from = Some(self.position.y);
+    }
+    /// Slow down horizontally while not in the air,
+    /// keeping `keep_per_second` of the speed after each second.
+    pub fn apply_friction(&mut self, delta: Duration, keep_per_second: f64) {
+        if self.is_airborne() {
+            return;
+        }
+        self.velocity.0 *= keep_per_second.powf(delta.as_secs_f64());
+        if self.velocity.0.abs() < 1.0 {
+            self.velocity.0 = 0.0;
+        }
+    }
+    pub fn command(&mut self, command: MovementCommand) {
+        match command {
+            // a thrown shimeji can't walk until it has landed
+            MovementCommand::Walk(_) if self.thrown => (),
+            MovementCommand::Walk(speed) => self.velocity.0 = speed,
+            MovementCommand::Climb(speed) => {
+                if !self.is_jumping() && self.at_wall() {
+                    self.clinging = Some(Surface::Wall);
+                    self.velocity = (0.0, speed);
+                }
+            }
+            MovementCommand::Hang if self.touching() == Some(Edge::Ceiling) => {
+                self.clinging = Some(Surface::Ceiling);
+                self.velocity = (0.0, 0.0);
+            }
+            MovementCommand::Stop | MovementCommand::Hang => {
+                self.velocity.0 = 0.0;
+                if self.clinging.take().is_some() {
+                    self.velocity.1 = 0.0;
+                }
+            }
+            MovementCommand::Jump => {
+                if !self.is_airborne() {
+                    self.clinging = None;
+                    self.jumped_from = Some(self.position.y);
+                    self.velocity.1 = -JUMP_SPEED;
+                }
+            }
+        }
+    }
+    /// Advance by `delta`, returning the new window position if it moved.
+    ///
+    /// Walking into a wall turns the shimeji around, or comes back in at the other one
+    /// if it [wraps](Movement::set_wrapping).
+    pub fn step(&mut self, delta: Duration) -> Option<PhysicalPosition<i32>> {
+        let secs = delta.as_secs_f64();
+        let before = self.position.cast::<i32>();
+
+        if let Some(ground) = self.ground().filter(|_| self.is_airborne()) {
+            self.velocity.1 += GRAVITY * secs;
+            self.position.y += self.velocity.1 * secs;
+            if let Some(ceiling) = self
+                .ceiling
+                .filter(|ceiling| self.thrown && self.position.y < *ceiling)
+            {
+                self.position.y = ceiling;
+                self.velocity.1 = self.velocity.1.abs() * BOUNCINESS;
+            }
+            if self.position.y >= ground {
+                self.position.y = ground;
+                if self.thrown && self.velocity.1 > MIN_BOUNCE_SPEED {
+                    // off the ground again, and still in the air until it comes back to it
+                    self.velocity = (self.velocity.0 * BOUNCINESS, -self.velocity.1 * BOUNCINESS);
+                    self.jumped_from = Some(ground);
+                } else {
+                    self.velocity.1 = 0.0;
+                    self.jumped_from = None;
+                    if self.thrown {
+                        self.thrown = false;
+                        self.velocity.0 = 0.0;
+                    }
+                }
+            }
+        } else if let Some(floor) = self
+            .floor
+            .filter(|floor| self.falls && self.clinging.is_none() && self.position.y > *floor)
+        {
+            // walked onto a monitor whose floor is higher up, step up onto it
+            self.position.y = floor;
+        } else if self.clinging == Some(Surface::Wall) {
+            self.position.y += self.velocity.1 * secs;
+            if let Some(ceiling) = self.ceiling.filter(|ceiling| self.position.y <= *ceiling) {
+                // climbed all the way up, hang on
+                self.position.y = ceiling;
+                self.velocity.1 = 0.0;
+                self.clinging = Some(Surface::Ceiling);
+            } else if let Some(floor) = self.floor.filter(|floor| self.position.y >= *floor) {
+                // climbed all the way down
+                self.position.y = floor;
+                self.velocity.1 = 0.0;
+                self.clinging = None;
+            }
+        } else if let Some(ceiling) = self.ceiling.filter(|_| self.clinging.is_some()) {
+            self.position.y = ceiling;
+        }
+        self.position.x += self.velocity.0 * secs;
+        if let Some((left, right)) = self.walls.filter(|_| self.clinging != Some(Surface::Wall)) {
+            if self.wraps {
+                if self.position.x > right {
+                    self.position.x = left;
+                } else if self.position.x < left {
+                    self.position.x = right;
+                }
+            } else if self.position.x < left {
+                self.position.x = left;
+                self.velocity.0 = self.velocity.0.abs();
+            } else if self.position.x > right {
+                self.position.x = right;
+                self.velocity.0 = -self.velocity.0.abs();
+            }
+        }
+
+        let after = self.position.cast::<i32>();
+        (before != after).then_some(after)
+    }
+}
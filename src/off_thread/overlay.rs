@@ -0,0 +1,201 @@
+//! Drawing every shimeji of a bucket into one window covering the whole desktop,
+//! instead of giving each its own window and GPU surface, see `SHIMEJI_OVERLAY`.
+//!
+//! The overlay lets the mouse through to whatever is under it, so shimejis drawn on it
+//! can't be clicked, dragged or thrown. Their own windows are kept around but hidden,
+//! to move them about and tell them apart.
+
+use std::sync::Arc;
+
+use winit::{dpi::PhysicalPosition, window::Window};
+
+use crate::{
+    blit::{blit, BlitPolicy, BufferSize, Effects, Facing},
+    loader::Frame,
+    log_throttle::throttled,
+    renderer::{self, RenderError, Renderer},
+    rgba::{PixelFormat, Rgba},
+};
+
+/// One shimeji to draw on the overlay, with its top left at `position` on the desktop.
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite<'f> {
+    pub frame: &'f Frame,
+    pub position: PhysicalPosition<i32>,
+    pub size: BufferSize,
+    pub policy: BlitPolicy,
+    pub facing: Facing,
+    pub effects: Effects,
+}
+
+/// A bucket's window over the whole desktop, which all of its shimejis are drawn into.
+pub struct Overlay<'pix> {
+    window: Arc<Window>,
+    pixels: Box<dyn Renderer + 'pix>,
+    /// Where the window's top left is on the desktop.
+    origin: PhysicalPosition<i32>,
+    size: BufferSize,
+    format: PixelFormat,
+    /// Where sprites were drawn last time, to clear before drawing them again.
+    drawn: Vec<(PhysicalPosition<i32>, BufferSize)>,
+    /// What each sprite is drawn into before it's laid over the others.
+    scratch: Vec<u8>,
+}
+
+impl<'pix> Overlay<'pix> {
+    /// Its window, dropping the surface that drew to it.
+    pub fn into_window(self) -> Arc<Window> {
+        self.window
+    }
+    ///
+    /// # Errors
+    /// Errors if no renderer can draw to `window`.
+    pub fn new(window: Arc<Window>) -> Result<Self, RenderError> {
+        let size = window.inner_size();
+        let mut pixels = renderer::create(&window, size.width, size.height)?;
+        pixels.set_clear_color(None);
+        let format = pixels
+            .layout()
+            .map(|(_, format)| format)
+            .unwrap_or_default();
+        let origin = window.outer_position().unwrap_or_default();
+        Ok(Self {
+            window,
+            pixels,
+            origin,
+            size: BufferSize {
+                width: size.width,
+                height: size.height,
+            },
+            format,
+            drawn: vec![],
+            scratch: vec![],
+        })
+    }
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+    /// Draw `sprites` over each other in order, in place of the ones drawn last time.
+    pub fn draw<'f>(&mut self, sprites: impl IntoIterator<Item = Sprite<'f>>) {
+        let (size, format) = (self.size, self.format);
+        let on_window = |position: PhysicalPosition<i32>| {
+            PhysicalPosition::new(position.x - self.origin.x, position.y - self.origin.y)
+        };
+        for (position, sprite_size) in std::mem::take(&mut self.drawn) {
+            clear(
+                self.pixels.frame_mut(),
+                size,
+                format,
+                on_window(position),
+                sprite_size,
+            );
+        }
+        for sprite in sprites {
+            let bytes =
+                sprite.size.width as usize * sprite.size.height as usize * format.bytes_per_pixel();
+            self.scratch.resize(bytes, 0);
+            blit(
+                sprite.frame,
+                &mut self.scratch,
+                sprite.size,
+                format,
+                sprite.policy,
+                sprite.facing,
+                sprite.effects,
+            );
+            lay_over(
+                self.pixels.frame_mut(),
+                size,
+                format,
+                &self.scratch,
+                on_window(sprite.position),
+                sprite.size,
+            );
+            self.drawn.push((sprite.position, sprite.size));
+        }
+        if let Err(why) = self.pixels.render() {
+            throttled!(log::Level::Error, "Could not draw the overlay: {why}");
+        }
+    }
+}
+
+/// The rows and columns of a `buffer_size` buffer that a `size` rectangle
+/// at `offset` covers, as `(first column, first row, columns, rows)`.
+fn covered(
+    buffer_size: BufferSize,
+    offset: PhysicalPosition<i32>,
+    size: BufferSize,
+) -> Option<(usize, usize, usize, usize)> {
+    let left = offset.x.max(0);
+    let top = offset.y.max(0);
+    let right = (offset.x + size.width as i32).min(buffer_size.width as i32);
+    let bottom = (offset.y + size.height as i32).min(buffer_size.height as i32);
+    (left < right && top < bottom).then(|| {
+        (
+            left as usize,
+            top as usize,
+            (right - left) as usize,
+            (bottom - top) as usize,
+        )
+    })
+}
+
+/// Make the `size` rectangle at `offset` of `buffer` transparent again.
+fn clear(
+    buffer: &mut [u8],
+    buffer_size: BufferSize,
+    format: PixelFormat,
+    offset: PhysicalPosition<i32>,
+    size: BufferSize,
+) {
+    let Some((left, top, columns, rows)) = covered(buffer_size, offset, size) else {
+        return;
+    };
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let stride = buffer_size.width as usize * bytes_per_pixel;
+    for row in buffer.chunks_exact_mut(stride).skip(top).take(rows) {
+        row[left * bytes_per_pixel..(left + columns) * bytes_per_pixel].fill(0);
+    }
+}
+
+/// Lay the `size` pixels of `sprite` over `buffer` with their top left at `offset`,
+/// cutting off whatever is outside of it.
+pub fn lay_over(
+    buffer: &mut [u8],
+    buffer_size: BufferSize,
+    format: PixelFormat,
+    sprite: &[u8],
+    offset: PhysicalPosition<i32>,
+    size: BufferSize,
+) {
+    let Some((left, top, columns, rows)) = covered(buffer_size, offset, size) else {
+        return;
+    };
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let stride = buffer_size.width as usize * bytes_per_pixel;
+    let sprite_stride = size.width as usize * bytes_per_pixel;
+    // where the covered part starts on the sprite, which is cut off above and to the left
+    let (sprite_left, sprite_top) = (
+        (left as i32 - offset.x) as usize,
+        (top as i32 - offset.y) as usize,
+    );
+    for row in 0..rows {
+        let below = &mut buffer[(top + row) * stride..][left * bytes_per_pixel..]
+            [..columns * bytes_per_pixel];
+        let above = &sprite[(sprite_top + row) * sprite_stride..][sprite_left * bytes_per_pixel..]
+            [..columns * bytes_per_pixel];
+        for (below, above) in below
+            .chunks_exact_mut(bytes_per_pixel)
+            .zip(above.chunks_exact(bytes_per_pixel))
+        {
+            let color = Rgba::read_as(format, above);
+            match color.alpha {
+                0 => (),
+                255 => below.copy_from_slice(above),
+                _ => color
+                    .over(Rgba::read_as(format, below))
+                    .write_as(format, below),
+            }
+        }
+    }
+}
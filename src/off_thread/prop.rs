@@ -0,0 +1,151 @@
+use std::{sync::Arc, time::Instant};
+
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    window::{Window, WindowId},
+};
+
+use crate::{
+    blit::{BlitPolicy, Effects, Facing},
+    loader::PropData,
+    log_throttle::throttled,
+    movement::Movement,
+    neighbors::Neighbor,
+    renderer::{RenderError, Renderer},
+    shimeji::{draw_frame, PHYSICS_TICK},
+};
+
+/// Fraction of a prop's horizontal speed left after sliding for a second.
+const FRICTION: f64 = 0.2;
+/// How much faster than the shimeji kicking it a prop flies off.
+const KICK_SPEED_MULTIPLIER: f64 = 1.5;
+/// Upwards speed given to a kicked prop, in pixels per second.
+const KICK_POP: f64 = 250.0;
+/// How close, in pixels between their middles, a prop has to be for a shimeji to go after it.
+pub const PROP_REACH: f64 = 400.0;
+
+/// An inert object living in its own window, see [`PropData`].
+///
+/// Like [`ShimejiWindow`](crate::shimeji), only used on the bucket thread.
+pub struct PropWindow<'pix> {
+    window: Arc<Window>,
+    pixels: Box<dyn Renderer + 'pix>,
+    data: Arc<PropData>,
+    movement: Movement,
+    last_moved: Instant,
+}
+
+impl<'pix> PropWindow<'pix> {
+    pub fn new(
+        window: Arc<Window>,
+        mut pixels: Box<dyn Renderer + 'pix>,
+        data: Arc<PropData>,
+    ) -> Self {
+        let _ = window.request_inner_size(LogicalSize::new(data.width, data.height));
+        pixels.set_clear_color(None);
+        draw_frame(
+            pixels.as_mut(),
+            &data.frame,
+            BlitPolicy::Center,
+            Facing::Left,
+            Effects::default(),
+        );
+        if let Err(why) = pixels.render() {
+            throttled!(
+                log::Level::Error,
+                "Could not draw prop {}: {why}",
+                data.name
+            );
+        }
+        window.set_visible(true);
+
+        let position = window
+            .outer_position()
+            .map(|position| position.cast())
+            .unwrap_or_default();
+        Self {
+            window,
+            pixels,
+            data,
+            movement: Movement::new(position),
+            last_moved: Instant::now(),
+        }
+    }
+}
+
+impl PropWindow<'_> {
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+    /// Its window and data, dropping the surface that drew to it.
+    pub fn into_parts(self) -> (Arc<Window>, Arc<PropData>) {
+        (self.window, self.data)
+    }
+    pub fn data(&self) -> &PropData {
+        &self.data
+    }
+    pub fn position(&self) -> PhysicalPosition<f64> {
+        self.movement.position()
+    }
+    /// Where the prop is, as the shimejis see it.
+    pub fn as_neighbor(&self) -> Neighbor {
+        Neighbor {
+            id: self.id(),
+            position: self.position(),
+            size: PhysicalSize::new(self.data.width, self.data.height),
+        }
+    }
+    pub fn resize_surface(&mut self, size: PhysicalSize<u32>) -> Result<(), RenderError> {
+        self.pixels.resize_surface(size.width, size.height)?;
+        draw_frame(
+            self.pixels.as_mut(),
+            &self.data.frame,
+            BlitPolicy::Center,
+            Facing::Left,
+            Effects::default(),
+        );
+        self.pixels.render()
+    }
+    /// Send the prop flying if a shimeji at `position` moving at `velocity` walks into it.
+    pub fn kick_if_touched(
+        &mut self,
+        position: PhysicalPosition<f64>,
+        size: PhysicalSize<u32>,
+        velocity: (f64, f64),
+    ) {
+        if velocity.0 == 0.0 || self.movement.is_jumping() {
+            return;
+        }
+        let own = self.movement.position();
+        let overlaps = position.x < own.x + f64::from(self.data.width)
+            && own.x < position.x + f64::from(size.width)
+            && position.y < own.y + f64::from(self.data.height)
+            && own.y < position.y + f64::from(size.height);
+        if overlaps {
+            self.movement
+                .push((velocity.0 * KICK_SPEED_MULTIPLIER, -KICK_POP));
+        }
+    }
+    /// Whether it's sliding or flying, and has to be stepped every [`PHYSICS_TICK`].
+    pub fn is_moving(&self) -> bool {
+        !self.movement.is_still()
+    }
+    /// Keep the prop where it is for now, without the time held counting once it moves again.
+    pub fn hold_still(&mut self) {
+        self.last_moved = Instant::now();
+    }
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let mut delta = now - self.last_moved;
+        self.last_moved = now;
+        // time spent lying still isn't time spent moving once it's kicked
+        if self.movement.is_still() {
+            delta = delta.min(PHYSICS_TICK);
+        }
+
+        self.movement.apply_friction(delta, FRICTION);
+        if let Some(position) = self.movement.step(delta) {
+            self.window.set_outer_position(position);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+/// One of the animations a `<Sequence>` plays, from a `<Step>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceStep {
+    pub animation: String,
+    /// How many passes of the animation to play before moving on, at least 1.
+    pub repeat: u32,
+}
+
+/// What to do once a step of a sequence finishes a pass, see [`Sequencer::finished_pass`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pass {
+    /// Play the step's animation again.
+    Again,
+    /// Move on to this step's animation.
+    Next(String),
+    /// The last step is over, and so is the sequence.
+    /// The last step carries on however its animation loops.
+    Done,
+}
+
+/// Where a shimeji is in a sequence: which of its steps is playing,
+/// and how many passes of it have been played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequencer {
+    name: String,
+    steps: Vec<SequenceStep>,
+    step: usize,
+    passes: u32,
+}
+
+impl Sequencer {
+    /// At the first of `steps` of the sequence called `name`,
+    /// or `None` if there are no steps, and so no sequence.
+    pub fn new(name: &str, steps: &[SequenceStep]) -> Option<Self> {
+        if steps.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: name.to_owned(),
+            steps: steps.to_vec(),
+            step: 0,
+            passes: 0,
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The animation of the step playing.
+    pub fn animation(&self) -> &str {
+        &self.steps[self.step].animation
+    }
+    /// Count a pass of the step playing, moving on to the next step once it's played
+    /// as many as it repeats.
+    pub fn finished_pass(&mut self) -> Pass {
+        self.passes = self.passes.saturating_add(1);
+        if self.passes < self.steps[self.step].repeat {
+            return Pass::Again;
+        }
+        if self.step + 1 == self.steps.len() {
+            return Pass::Done;
+        }
+        self.step += 1;
+        self.passes = 0;
+        Pass::Next(self.animation().to_owned())
+    }
+}
@@ -1,106 +1,1253 @@
-use pixels::Pixels;
 use std::{
     collections::HashMap,
-    num::NonZeroU32,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::{self, Receiver},
+        mpsc::{Receiver, RecvTimeoutError},
         Arc,
     },
-    thread::{self},
     time::{Duration, Instant},
 };
 use winit::{
-    dpi::{LogicalSize, PhysicalPosition},
-    window::Window,
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    event_loop::EventLoopProxy,
+    window::{Window, WindowId},
 };
 
-use crate::{bucket::BucketThreadMessage, loader::AnimationData};
+use crate::{
+    audio,
+    behavior::{Action, Behavior, BehaviorState, BehaviorTable, Situation},
+    blit::{blit, BlitPolicy, BufferSize, Effects, Facing, ScalingMode, Shadow},
+    bucket::{BucketThreadMessage, Orphans, ShimejiInputEvent},
+    frame_cache::{FrameCache, FrameKey},
+    frame_cursor::{FrameCursor, LoopMode},
+    handle,
+    interaction::{FilterChain, FilterContext, InteractionKind},
+    loader::{AnimationData, Frame, PropData},
+    log_throttle::{once, throttled},
+    movement::{Edge, Movement, MovementCommand},
+    neighbors::{Neighbor, Neighborhood, NEAR_DISTANCE},
+    notifications::Corner,
+    overlay::{self, Sprite},
+    prop::{PropWindow, PROP_REACH},
+    renderer::{self, RenderError, Renderer},
+    rgba::Rgba,
+    rng::Rng,
+    script::{Locals, ScriptEffect},
+    sequencer::{Pass, Sequencer},
+    variant::{Variant, VariantCache},
+    window_shape,
+    window_surfaces::WindowSurface,
+    world::WorldSnapshot,
+    xml_parser::FrameRegion,
+    ManagerEvent,
+};
 use BucketThreadMessage::*;
+
+/// How far a single physics step moves a paused shimeji, see [`StepCommand::Tick`].
+const DEBUG_TICK: Duration = Duration::from_micros(16_667);
+/// How often moving shimejis and props are stepped.
+pub const PHYSICS_TICK: Duration = Duration::from_micros(16_667);
+/// The longest a bucket thread sleeps with nothing due, so it notices it should exit.
+const IDLE_WAKE: Duration = Duration::from_secs(1);
+
+/// Debugging controls that freeze a shimeji and move it along one step at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepCommand {
+    /// Freeze the shimeji's clock, or let it run again.
+    TogglePause,
+    /// Show the next frame of its animation, while paused.
+    Frame,
+    /// Move it by one [`DEBUG_TICK`] of physics, while paused.
+    Tick,
+}
+
+/// Whether a shimeji is frozen for debugging, and the steps it has been told to take.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stepper {
+    paused: bool,
+    frames: usize,
+    ticks: usize,
+}
+
+impl Stepper {
+    pub fn is_paused(self) -> bool {
+        self.paused
+    }
+    /// Carry out `command`, returning `false` if it's a step taken without pausing first.
+    pub fn apply(&mut self, command: StepCommand) -> bool {
+        match command {
+            StepCommand::TogglePause => {
+                *self = Self {
+                    paused: !self.paused,
+                    ..Self::default()
+                };
+            }
+            StepCommand::Frame if self.paused => self.frames += 1,
+            StepCommand::Tick if self.paused => self.ticks += 1,
+            StepCommand::Frame | StepCommand::Tick => return false,
+        }
+        true
+    }
+    /// Whether the animation can move on, taking a frame step if paused.
+    pub fn take_frame(&mut self) -> bool {
+        Self::take(self.paused, &mut self.frames)
+    }
+    /// Whether the physics can move on, taking a tick step if paused.
+    pub fn take_tick(&mut self) -> bool {
+        Self::take(self.paused, &mut self.ticks)
+    }
+    fn take(paused: bool, steps: &mut usize) -> bool {
+        match (paused, *steps) {
+            (false, _) => true,
+            (true, 0) => false,
+            (true, _) => {
+                *steps -= 1;
+                true
+            }
+        }
+    }
+}
+
+/// How far above a shimeji's feet another window's top can be while it still stands on it.
+const LEDGE_TOLERANCE: f64 = 8.0;
+/// How long a shimeji has to hold the same pose before its GPU surface is dropped.
+/// Its window keeps showing the last frame drawn until it moves again.
+const SHED_SURFACE_AFTER: Duration = Duration::from_secs(3 * 60);
+
+/// Everything that decides what a shimeji's window shows, to tell when it holds still.
+#[derive(Debug, Clone, PartialEq)]
+struct Pose {
+    animation: String,
+    frame: Option<usize>,
+    position: PhysicalPosition<i32>,
+    facing: Facing,
+    effects: Effects,
+}
+
 /// All associated functions run on the inner thread.
 ///
 /// ShimejiWindow is only used in the worker function passed to the spawned thread.
 struct ShimejiWindow<'pix> {
     window: Arc<Window>,
-    pixels: Box<Pixels<'pix>>,
+    /// `None` once the shimeji has held still for [`SHED_SURFACE_AFTER`],
+    /// see [`ShimejiWindow::pixels`].
+    pixels: Option<Box<dyn Renderer + 'pix>>,
     data: Arc<ShimejiData>,
-    last_rendered_frame: Instant,
-    current_frame: Option<NonZeroU32>,
+    /// When the frame showing is over and the next one is due.
+    next_frame_at: Instant,
+    /// The soonest the next frame can be drawn, however soon it's due,
+    /// see [`WorldSnapshot::min_frame_interval`].
+    frame_not_before: Instant,
+    /// What was drawn last, and since when.
+    pose: Option<(Pose, Instant)>,
+    cursor: FrameCursor,
+    /// The animation showing, which can differ from the behavior's
+    /// once the behavior's animation chains into its `next`, or is a sequence.
+    animation: String,
+    /// Where the shimeji is in the sequence it's playing, if it is.
+    sequencer: Option<Sequencer>,
+    /// Where the shimeji is being held, relative to its window, if it is being dragged.
+    held_at: Option<PhysicalPosition<f64>>,
+    /// When the shimeji was last clicked, for telling double clicks apart.
+    last_click: Option<Instant>,
+    /// Whether the mouse cursor is over the window.
+    hovered: bool,
+    /// Whether the window can't be seen, so there's no point drawing it.
+    occluded: bool,
+    movement: Movement,
+    last_moved: Instant,
+    behavior: BehaviorState,
+    /// Set when the animation completes a pass, cleared when the behavior changes.
+    animation_finished: bool,
+    /// Which way the shimeji last walked.
+    facing: Facing,
+    /// How far the window is from where the shimeji is, so the frame showing lands
+    /// on its anchor, see [`Anchor`](crate::loader::Anchor).
+    anchor_offset: PhysicalPosition<i32>,
+    /// The frame and window size the window was last cut to the shape of,
+    /// see [`WorldSnapshot::shaped_windows`].
+    shaped_as: Option<(String, usize, Facing, PhysicalSize<u32>)>,
+    /// The frame and window size the window last took clicks on the parts of,
+    /// see [`window_shape::hit_rects`].
+    hit_as: Option<(String, usize, Facing, PhysicalSize<u32>)>,
+    hit_rects: Vec<window_shape::Rect>,
+    /// Whether clicks go through the window, where that follows the cursor,
+    /// see [`window_shape::cursor_hits`].
+    passing_clicks: bool,
+    /// The lowest the window can go on its monitor, see [`ShimejiWindow::floor_under`].
+    monitor_floor: Option<f64>,
+    /// Frozen for debugging, see [`StepCommand`].
+    stepper: Stepper,
+    /// Hidden through its handle, see [`handle::Control::SetVisible`].
+    hidden: bool,
+    /// Hidden while another application is fullscreen on its monitor, see [`crate::fullscreen`].
+    covered: bool,
+    /// How the shimeji is recolored, see [`handle::Control::SetVariant`].
+    variant: Variant,
+    /// The frames of the animation playing, by name, recolored as `variant` if it isn't plain.
+    recolored: Option<(String, Arc<[Frame]>)>,
+    /// The behavior told to play through its handle, until it is over.
+    playing: Option<String>,
+    /// What to tell the shimeji's handle, see [`ShimejiWindow::take_reports`].
+    reports: Vec<handle::ShimejiEvent>,
+    /// Set when a behavior multiplies, see [`ShimejiWindow::take_multiply`].
+    multiplying: bool,
+    /// The behaviors gone into since last time, see [`ShimejiWindow::take_entered`].
+    entered: Vec<String>,
+    rng: Rng,
+    /// Every shimeji, this one included, as of the bucket's last update.
+    neighbors: Arc<[Neighbor]>,
+    /// Its bucket's props, as of the bucket's last update.
+    props: Arc<[Neighbor]>,
+    /// Drawn on its bucket's [`overlay::Overlay`] rather than its own window, which stays hidden.
+    overlaid: bool,
 }
 
 impl<'pix> ShimejiWindow<'pix> {
-    pub fn new(arc_window: Arc<Window>, mut pixels: Pixels<'pix>, data: Arc<ShimejiData>) -> Self {
+    pub fn new(
+        arc_window: Arc<Window>,
+        mut pixels: Option<Box<dyn Renderer + 'pix>>,
+        data: Arc<ShimejiData>,
+        world: &WorldSnapshot,
+    ) -> Self {
         let shimeji_width = data.width;
         let shimeji_height = data.height;
         let _ = arc_window.request_inner_size(LogicalSize::new(shimeji_width, shimeji_height));
-        arc_window.set_visible(true);
-        pixels.clear_color(pixels::wgpu::Color::TRANSPARENT);
+        // without a surface of its own, it's drawn on the bucket's overlay
+        let overlaid = pixels.is_none();
+        arc_window.set_visible(!overlaid);
+        if let Some(pixels) = pixels.as_mut() {
+            pixels.set_clear_color(data.letterbox);
+        }
+        let initial = data.behaviors.initial();
+        let mut movement = Movement::new(
+            arc_window
+                .outer_position()
+                .map(|position| position.cast())
+                .unwrap_or_default(),
+        )
+        .with_gravity(data.gravity);
+        let PhysicalSize {
+            width: physical_width,
+            height: physical_height,
+        } = data.size_at(arc_window.scale_factor());
+        let monitor_floor = floor_of(&arc_window, physical_height, world);
+        movement.set_floor(monitor_floor);
+        movement.set_walls(walls_of(&arc_window, physical_width));
+        movement.set_ceiling(ceiling_of(&arc_window));
+        movement.command(initial.action.movement());
+        let behavior = BehaviorState::new(&data.behaviors);
+        let initial_animation = initial.animation.clone();
 
-        Self {
+        let mut shimeji = Self {
             window: arc_window,
-            last_rendered_frame: Instant::now(),
+            next_frame_at: Instant::now(),
+            frame_not_before: Instant::now(),
+            pose: None,
             data,
-            pixels: Box::new(pixels),
-            current_frame: None,
+            pixels,
+            cursor: FrameCursor::new(0, LoopMode::Loop),
+            animation: String::new(),
+            sequencer: None,
+            held_at: None,
+            last_click: None,
+            hovered: false,
+            occluded: false,
+            movement,
+            last_moved: Instant::now(),
+            behavior,
+            animation_finished: false,
+            facing: Facing::default(),
+            anchor_offset: PhysicalPosition::new(0, 0),
+            shaped_as: None,
+            hit_as: None,
+            hit_rects: vec![],
+            passing_clicks: false,
+            hidden: false,
+            covered: false,
+            variant: Variant::default(),
+            recolored: None,
+            stepper: Stepper::default(),
+            neighbors: Arc::from([]),
+            props: Arc::from([]),
+            playing: None,
+            reports: vec![],
+            multiplying: false,
+            entered: Vec::new(),
+            overlaid,
+            monitor_floor,
+            rng: Rng::new(),
+        };
+        shimeji.start_animation(&initial_animation);
+        shimeji
+    }
+    /// The window's surface, made again if it was shed while the shimeji held still.
+    ///
+    /// # Errors
+    /// Errors if no renderer can draw to the window.
+    fn pixels(&mut self) -> Result<&mut (dyn Renderer + 'pix), RenderError> {
+        if self.pixels.is_none() {
+            log::debug!("{} is moving again, making its surface", self.data.name);
+            let size = match self.data.scaling.policy() {
+                Some(_) => self.window.inner_size(),
+                None => PhysicalSize::new(self.data.width, self.data.height),
+            };
+            let mut pixels = renderer::create(&self.window, size.width, size.height)?;
+            pixels.set_clear_color(self.data.letterbox);
+            self.pixels = Some(pixels);
         }
+        Ok(self
+            .pixels
+            .as_deref_mut()
+            .expect("its surface was just made"))
     }
 }
 
 impl ShimejiWindow<'_> {
-    pub fn update(&mut self) {
-        let idle_animation = self.data.animations.get("idle").unwrap();
-        let time_between_frames = Duration::from_secs_f64(1.0 / idle_animation.fps);
-
-        let delta_time = self.last_rendered_frame.elapsed();
-        log::trace!("delta_time: {delta_time:?}, time_between_frames: {time_between_frames:?}");
-        if delta_time < time_between_frames {
-            return;
-        } // passed frame cap, time to render
-        log::debug!("delta_time check passed");
-
-        let frame_index: usize = self
-            .current_frame
-            .unwrap_or(unsafe { NonZeroU32::new_unchecked(1) })
-            .get()
-            .try_into()
-            .unwrap();
-
-        let mut frame_index = frame_index + 1;
-        self.current_frame = Some(NonZeroU32::new(frame_index.try_into().unwrap()).unwrap());
-
-        let zero_indexed_frame_index = frame_index - 1;
-        if idle_animation
-            .frames
-            .get(zero_indexed_frame_index)
-            .is_none()
-        {
-            self.current_frame = Some(unsafe { NonZeroU32::new_unchecked(1) });
-            frame_index = 1;
+    /// Follow the window to its new `size`. Unless the pack leaves scaling to the renderer,
+    /// the buffer grows or shrinks with it, and frames are scaled as they're drawn.
+    fn resize(&mut self, size: PhysicalSize<u32>) -> Result<(), RenderError> {
+        // a shed surface is made at the window's size when it's needed again
+        let Some(pixels) = self.pixels.as_mut() else {
+            return Ok(());
+        };
+        pixels.resize_surface(size.width, size.height)?;
+        if self.data.scaling.policy().is_some() {
+            pixels.resize_buffer(size.width, size.height)?;
         }
-        log::debug!("frame_index: {frame_index}");
-
-        let zero_indexed_frame_index = frame_index - 1;
-        let frame = &idle_animation.frames[zero_indexed_frame_index];
-        {
-            let buffer = self.pixels.frame_mut();
-            for (color, pixel) in frame
-                .pixels_row_major
-                .iter()
-                .zip(buffer.chunks_exact_mut(4))
+        Ok(())
+    }
+    pub fn grab(&mut self, offset: PhysicalPosition<f64>) {
+        self.held_at = Some(offset);
+    }
+    pub fn drag_to(&mut self, position: PhysicalPosition<f64>) {
+        let Some(offset) = self.held_at else {
+            return;
+        };
+        let Ok(outer) = self.window.outer_position() else {
+            return;
+        };
+        let new_position = PhysicalPosition::new(
+            outer.x + (position.x - offset.x).round() as i32,
+            outer.y + (position.y - offset.y).round() as i32,
+        );
+        self.movement.set_position(PhysicalPosition::new(
+            f64::from(new_position.x - self.anchor_offset.x),
+            f64::from(new_position.y - self.anchor_offset.y),
+        ));
+        self.window.set_outer_position(new_position);
+    }
+    /// Where the window goes for the shimeji to be at `position`.
+    fn anchored(&self, position: PhysicalPosition<i32>) -> PhysicalPosition<i32> {
+        PhysicalPosition::new(
+            position.x + self.anchor_offset.x,
+            position.y + self.anchor_offset.y,
+        )
+    }
+    /// Let go of the shimeji, throwing it at `velocity` in pixels per second.
+    pub fn release(&mut self, velocity: (f64, f64), world: &WorldSnapshot) {
+        // a grab held back by a filter has nothing to let go of
+        if self.held_at.take().is_none() {
+            return;
+        }
+        self.movement.throw(velocity);
+        // it may have been dragged onto another monitor
+        self.update_surroundings(world);
+        self.behavior.released();
+    }
+    pub fn pet(&mut self) {
+        self.behavior.petted();
+    }
+    /// Whether `filters` let an interaction of `kind` through to this shimeji.
+    pub fn allows(&self, kind: InteractionKind, filters: &FilterChain) -> bool {
+        let current = self.behavior.current(&self.data.behaviors);
+        let context = FilterContext {
+            behavior: current.map(|behavior| behavior.name.as_str()),
+            last_click: self.last_click,
+        };
+        filters.allows(kind, context)
+    }
+    /// Keep the shimeji as big to the eye on a monitor with another DPI, drawing it
+    /// again straight away at the size the manager asked for its window.
+    fn rescale(&mut self, scale_factor: f64) {
+        if self.overlaid {
+            return;
+        }
+        let size = self.data.size_at(scale_factor);
+        log::debug!(
+            "{} moved to a {scale_factor}x monitor, resizing to {size:?}",
+            self.data.name
+        );
+        if let Err(why) = self.resize(size) {
+            log::error!("Could not rescale {}: {why}", self.data.name);
+        }
+        // the anchor offset and shape were worked out at the old scale
+        self.shaped_as = None;
+        self.hit_as = None;
+        self.pose = None;
+        self.next_frame_at = Instant::now();
+        self.frame_not_before = Instant::now();
+    }
+    /// React to something happening to the window.
+    pub fn input(&mut self, event: ShimejiInputEvent) {
+        match event {
+            ShimejiInputEvent::CursorEntered => self.hovered = true,
+            ShimejiInputEvent::CursorLeft => self.hovered = false,
+            // look at the cursor while standing around
+            ShimejiInputEvent::CursorMoved(position)
+                if self.hovered && !self.is_held() && self.movement.is_still() =>
             {
-                let slice = [color.red, color.green, color.blue, color.alpha];
-                pixel.copy_from_slice(&slice);
-                //     buffer[index] = value.to_softbuf_u32();
+                let middle = self.window.outer_size().width as f64 / 2.0;
+                self.facing = match position.x < middle {
+                    true => Facing::Left,
+                    false => Facing::Right,
+                };
+            }
+            ShimejiInputEvent::Occluded(occluded) => self.occluded = occluded,
+            ShimejiInputEvent::ScaleFactorChanged(scale_factor) => self.rescale(scale_factor),
+            _ => (),
+        }
+    }
+    /// Remember that the shimeji was clicked, even if the click goes no further.
+    pub fn clicked(&mut self) {
+        self.last_click = Some(Instant::now());
+    }
+    pub fn is_held(&self) -> bool {
+        self.held_at.is_some()
+    }
+    /// The window's position and size.
+    pub fn bounds(&self) -> (PhysicalPosition<f64>, PhysicalSize<u32>) {
+        (
+            self.movement.position(),
+            PhysicalSize::new(self.data.width, self.data.height),
+        )
+    }
+    pub fn steer(&mut self, command: MovementCommand) {
+        self.movement.command(command);
+    }
+    pub fn control(&mut self, control: handle::Control) {
+        match control {
+            handle::Control::SetPosition(position) => {
+                self.movement.set_position(position.cast());
+                self.window.set_outer_position(self.anchored(position));
+            }
+            handle::Control::SetBehavior(name) => {
+                let data = Arc::clone(&self.data);
+                match data.behaviors.get(&name) {
+                    Some(behavior) => self.enter_behavior(behavior),
+                    None => log::warn!("{} has no behavior {name}", data.name),
+                }
+            }
+            handle::Control::Play(name) => {
+                let data = Arc::clone(&self.data);
+                let Some(behavior) = data.behaviors.get(&name) else {
+                    log::warn!("{} has no behavior {name} to play", data.name);
+                    self.reports.push(handle::ShimejiEvent::Interrupted(name));
+                    return;
+                };
+                self.enter_behavior(behavior);
+                self.playing = Some(name);
+            }
+            handle::Control::SetVisible(visible) => {
+                self.hidden = !visible;
+                if !self.overlaid {
+                    self.window.set_visible(visible && !self.covered);
+                }
+            }
+            handle::Control::SetVariant(variant) => {
+                self.variant = variant;
+                self.recolored = None;
+                // drawn again even if it's holding still
+                self.pose = None;
+            }
+            handle::Control::SetSeed(seed) => self.rng = Rng::with_seed(seed),
+            handle::Control::Despawn => {
+                log::error!("Shimejis are despawned by the manager, not its bucket")
+            }
+        }
+    }
+    /// Swap in `data` loaded again from disk, starting over from its initial behavior
+    /// where the shimeji is now.
+    pub fn reload(&mut self, data: Arc<ShimejiData>) {
+        if (data.width, data.height) != (self.data.width, self.data.height) {
+            let _ = self
+                .window
+                .request_inner_size(LogicalSize::new(data.width, data.height));
+            // scaled buffers follow the window once it has resized
+            if let Some(pixels) = self
+                .pixels
+                .as_mut()
+                .filter(|_| data.scaling.policy().is_none())
+            {
+                if let Err(why) = pixels.resize_buffer(data.width, data.height) {
+                    log::error!("Could not resize {} to its new size: {why}", data.name);
+                }
+            }
+        }
+        if let Some(pixels) = self.pixels.as_mut() {
+            pixels.set_clear_color(data.letterbox);
+        }
+        self.movement = self.movement.clone().with_gravity(data.gravity);
+        self.behavior = BehaviorState::new(&data.behaviors);
+        self.data = data;
+        self.recolored = None;
+        // frames of the same name can have another shape in the reloaded pack
+        self.shaped_as = None;
+        self.hit_as = None;
+        let data = Arc::clone(&self.data);
+        self.enter_behavior(data.behaviors.initial());
+    }
+    /// Swap in `data`, the same pack with more of its frames decoded,
+    /// carrying on with whatever the shimeji was doing.
+    pub fn swap_frames(&mut self, data: Arc<ShimejiData>) {
+        let frames = |data: &ShimejiData| {
+            data.animations
+                .get(&self.animation)
+                .map(|animation| animation.frames.len())
+        };
+        // an animated png can hold more or fewer frames than it claims
+        if frames(&data) != frames(&self.data) {
+            self.cursor = cursor_for(&data, &self.animation);
+        }
+        self.data = data;
+        // drawn and cut to shape again even if it holds still, it was showing a placeholder
+        self.pose = None;
+        self.shaped_as = None;
+        self.hit_as = None;
+        // recolored from placeholders, maybe
+        self.recolored = None;
+    }
+    pub fn debug_step(&mut self, command: StepCommand) {
+        if !self.stepper.apply(command) {
+            log::warn!("Pause {} before stepping through it", self.data.name);
+        } else if command == StepCommand::TogglePause {
+            log::info!(
+                "{} {}",
+                self.data.name,
+                if self.stepper.is_paused() {
+                    "paused"
+                } else {
+                    "resumed"
+                }
+            );
+        }
+    }
+    pub fn start_partying(&mut self) {
+        self.behavior.start_partying();
+    }
+    /// React to a notification in the `corner` of the shimeji's monitor: switch to `behavior`
+    /// if its pack has it, then face the corner, walking that way if it walks,
+    /// and jump toward it if it's at the top.
+    pub fn alert(&mut self, behavior: &str, corner: Corner, world: &WorldSnapshot) {
+        if self.is_held() {
+            return;
+        }
+        let data = Arc::clone(&self.data);
+        if let Some(behavior) = data.behaviors.get(behavior) {
+            self.enter_behavior(behavior);
+        }
+        let (position, size) = self.bounds();
+        let Some(monitor) = world.monitors.under(position, size) else {
+            return;
+        };
+        let corner_x = match corner.is_left() {
+            true => monitor.work_area.left,
+            false => monitor.work_area.right,
+        };
+        let direction = (corner_x as f64 - (position.x + size.width as f64 / 2.0)).signum();
+        self.facing = match direction < 0.0 {
+            true => Facing::Left,
+            false => Facing::Right,
+        };
+        let speed = self.movement.velocity().0;
+        if speed != 0.0 && !self.movement.is_airborne() {
+            self.movement
+                .command(MovementCommand::Walk(speed.abs() * direction));
+        }
+        if corner.is_top() {
+            self.movement.command(MovementCommand::Jump);
+        }
+    }
+    /// Find the floor, walls and ceiling of the monitor the shimeji is on.
+    pub fn update_surroundings(&mut self, world: &WorldSnapshot) {
+        let (position, size) = self.bounds();
+        if let Some(monitor) = world.monitors.under(position, size) {
+            self.set_covered(world.fullscreen_on(monitor));
+            let offset = world.floor_offset(monitor.name.as_deref());
+            self.monitor_floor = Some(monitor.floor(size.height, offset));
+            // wrapping around only makes sense on one monitor, roaming to the next is the default
+            let walls = match world.wrap_around {
+                true => monitor.walls(size.width),
+                false => world.monitors.roaming_walls(monitor, size.width),
+            };
+            self.movement.set_walls(Some(walls));
+            self.movement.set_ceiling(Some(monitor.ceiling()));
+        } else {
+            // the manager hasn't looked up the monitors, ask the window
+            let size = self.window.outer_size();
+            self.monitor_floor = floor_of(&self.window, size.height, world);
+            self.movement.set_walls(walls_of(&self.window, size.width));
+            self.movement.set_ceiling(ceiling_of(&self.window));
+        }
+        self.movement.set_wrapping(world.wrap_around);
+        self.movement.set_floor(self.floor_under(world));
+    }
+    /// Hide the shimeji while `covered` by a fullscreen application, showing it again after.
+    fn set_covered(&mut self, covered: bool) {
+        if self.covered == covered {
+            return;
+        }
+        self.covered = covered;
+        if !self.overlaid && !self.hidden {
+            self.window.set_visible(!covered);
+        }
+    }
+    /// Where the top of the window would be standing on the highest thing below it,
+    /// another application's window, another shimeji's head or the floor of the monitor.
+    fn floor_under(&self, world: &WorldSnapshot) -> Option<f64> {
+        let (position, size) = self.bounds();
+        let heads = self
+            .neighbors
+            .iter()
+            .filter(|other| other.id != self.window.id())
+            .map(|other| WindowSurface {
+                left: other.position.x as i32,
+                right: other.position.x as i32 + other.size.width as i32,
+                top: other.position.y as i32,
+            });
+        let surfaces = world.window_surfaces.iter().copied().chain(heads);
+        floor_among(surfaces, position, size, self.monitor_floor)
+    }
+    /// Carry on after the bucket was paused, as if no time had passed while it was.
+    fn resume(&mut self) {
+        self.last_moved = Instant::now();
+    }
+    /// Move the window by its velocity, pulling it down if it falls.
+    pub fn step_physics(&mut self, world: &WorldSnapshot) {
+        let now = Instant::now();
+        // held in place, without the time held counting once it moves again
+        if world.saving_power {
+            self.last_moved = now;
+            return;
+        }
+        let mut delta = now - self.last_moved;
+        self.last_moved = now;
+        // the bucket may have slept a long time while the shimeji stood still
+        if self.movement.is_still() {
+            delta = delta.min(PHYSICS_TICK);
+        }
+        if !self.stepper.take_tick() {
+            return;
+        }
+        if self.stepper.is_paused() {
+            delta = DEBUG_TICK;
+        }
+        if self.is_held() {
+            return;
+        }
+        if world.monitors.is_empty() {
+            self.movement.set_floor(self.floor_under(world));
+        } else {
+            // it may have walked onto another monitor since the last step
+            self.update_surroundings(world);
+        }
+        if let Some(position) = self.movement.step(delta) {
+            self.window.set_outer_position(self.anchored(position));
+        }
+        // face the wall while climbing it, otherwise wherever it's walking
+        let facing = match self.movement.touching() {
+            Some(Edge::LeftWall) => Some(Facing::Left),
+            Some(Edge::RightWall) => Some(Facing::Right),
+            _ => Facing::of_speed(self.movement.velocity().0),
+        };
+        if let Some(facing) = facing {
+            self.facing = facing;
+        }
+        if self.stepper.is_paused() {
+            log::info!(
+                "{} ticked to {:?} at {:?} px/s",
+                self.data.name,
+                self.movement.position(),
+                self.movement.velocity()
+            );
+        }
+    }
+}
+
+impl ShimejiWindow<'_> {
+    fn behaviors(&self) -> &BehaviorTable {
+        &self.data.behaviors
+    }
+    /// Where this shimeji is, as the others see it.
+    fn as_neighbor(&self) -> Neighbor {
+        let (position, size) = self.bounds();
+        Neighbor {
+            id: self.window.id(),
+            position,
+            size,
+        }
+    }
+    /// The prop closest to this shimeji, if any is within [`PROP_REACH`].
+    fn nearest_prop(&self) -> Option<&Neighbor> {
+        self.as_neighbor()
+            .nearest(&self.props)
+            .filter(|(_, distance)| *distance <= PROP_REACH)
+            .map(|(prop, _)| prop)
+    }
+    /// Walk up to the nearest other shimeji while in an `approach` behavior,
+    /// stopping once near enough to greet it, or to the nearest prop while in an
+    /// `approach-prop` one, walking into it.
+    fn approach(&mut self) {
+        let action = self
+            .behavior
+            .current(&self.data.behaviors)
+            .map(|behavior| behavior.action);
+        if !matches!(action, Some(Action::Approach(_) | Action::ApproachProp(_))) {
+            return;
+        }
+        if self.is_held() || self.movement.is_airborne() {
+            return;
+        }
+        let me = self.as_neighbor();
+        let command = match action {
+            Some(Action::Approach(speed)) => match me.nearest(&self.neighbors) {
+                Some((other, distance)) if distance > NEAR_DISTANCE => {
+                    let direction = (other.center().x - me.center().x).signum();
+                    MovementCommand::Walk(speed.abs() * direction)
+                }
+                _ => MovementCommand::Stop,
+            },
+            Some(Action::ApproachProp(speed)) => walk_toward(&me, self.nearest_prop(), speed),
+            _ => return,
+        };
+        self.movement.command(command);
+    }
+    /// Switch to `behavior`, restarting its animation and starting its action.
+    fn enter_behavior(&mut self, behavior: &Behavior) {
+        if let Some(playing) = self.playing.take() {
+            self.reports
+                .push(handle::ShimejiEvent::Interrupted(playing));
+        }
+        self.behavior.enter(behavior);
+        self.entered.push(behavior.name.clone());
+        self.multiplying |= behavior.action == Action::Multiply;
+        self.animation_finished = false;
+        self.start_animation(&behavior.animation);
+        self.movement.command(behavior.action.movement());
+    }
+    /// Start playing `name` from the beginning, from its first step if it's a sequence.
+    fn start_animation(&mut self, name: &str) {
+        self.sequencer = self
+            .data
+            .animations
+            .get(name)
+            .and_then(|animation| Sequencer::new(name, &animation.steps));
+        let showing = match &self.sequencer {
+            Some(sequencer) => sequencer.animation().to_owned(),
+            None => String::from(name),
+        };
+        self.show_animation(showing);
+    }
+    /// Show `name` from its first frame, without leaving the sequence playing.
+    fn show_animation(&mut self, name: String) {
+        self.cursor = cursor_for(&self.data, &name);
+        self.animation = name;
+    }
+    /// The animation playing: the sequence if it's one, see [`ShimejiWindow::animation`].
+    fn playing_animation(&self) -> &str {
+        match &self.sequencer {
+            Some(sequencer) => sequencer.name(),
+            None => &self.animation,
+        }
+    }
+    /// Move on to the next behavior if any of the current one's transitions fire.
+    fn think(&mut self, world: &WorldSnapshot) {
+        let situation = Situation {
+            held: self.is_held(),
+            airborne: self.movement.is_airborne(),
+            at_wall: matches!(
+                self.movement.touching(),
+                Some(Edge::LeftWall | Edge::RightWall)
+            ),
+            at_ceiling: self.movement.touching() == Some(Edge::Ceiling),
+            animation_finished: self.animation_finished,
+            near_another: self
+                .as_neighbor()
+                .nearest(&self.neighbors)
+                .is_some_and(|(_, distance)| distance <= NEAR_DISTANCE),
+        };
+        let data = Arc::clone(&self.data);
+        if !situation.held && self.run_script(world) {
+            return;
+        }
+        let Some(next) = self
+            .behavior
+            .next(&data.behaviors, situation, world, &mut self.rng)
+        else {
+            // a finished pass only counts once, the next one has to finish again
+            self.animation_finished = false;
+            return;
+        };
+        log::debug!("{} is now {}", data.name, next.name);
+        self.enter_behavior(next);
+    }
+    /// Do what the current behavior's script says, returning whether it switched behaviors.
+    fn run_script(&mut self, world: &WorldSnapshot) -> bool {
+        let position = self.movement.position();
+        let velocity = self.movement.velocity();
+        let locals = Locals {
+            x: position.x,
+            y: position.y,
+            velocity_x: velocity.0,
+            velocity_y: velocity.1,
+            time: 0.0,
+        };
+        let data = Arc::clone(&self.data);
+        let effects = self
+            .behavior
+            .run_script(&data.behaviors, world, locals, &mut self.rng);
+        for effect in effects {
+            match effect {
+                ScriptEffect::Velocity(x, y) => self.movement.push((x, y)),
+                ScriptEffect::Animation(name) => {
+                    if self.playing_animation() != name {
+                        self.start_animation(&name);
+                    }
+                }
+                ScriptEffect::Behavior(name) => {
+                    if let Some(next) = data.behaviors.get(&name) {
+                        log::debug!("{} is now {} by script", data.name, next.name);
+                        self.enter_behavior(next);
+                        return true;
+                    }
+                }
             }
         }
+        false
+    }
+    pub fn update(&mut self, world: &WorldSnapshot) {
+        self.follow_cursor();
+        if !self.stepper.take_frame() {
+            return;
+        }
+        self.think(world);
+        self.approach();
 
-        let _ = self.pixels.render();
-        if !self.window.is_visible().unwrap() {
-            self.window.set_visible(true);
+        let data = Arc::clone(&self.data);
+        let Some(animation) = data.animations.get(&self.animation) else {
+            once!(
+                format!("{}/{}", data.name, self.animation),
+                log::Level::Error,
+                "{} has no animation {}, showing a placeholder",
+                data.name,
+                self.animation
+            );
+            self.draw_placeholder();
+            return;
+        };
+        let now = Instant::now();
+        if now < self.next_frame_at.max(self.frame_not_before) && !self.stepper.is_paused() {
+            return;
+        } // the next frame is due, time to render
+        log::trace!("{:?} late for the next frame", now - self.next_frame_at);
+
+        let recolored = self.recolored_frames(animation);
+        let frames = recolored.as_deref().unwrap_or(&animation.frames);
+        let Some((index, frame)) = self
+            .cursor
+            .index()
+            .and_then(|index| Some((index, frames.get(index)?)))
+        else {
+            once!(
+                format!("{}/{}/{:?}", data.name, self.animation, self.cursor.index()),
+                log::Level::Error,
+                "{} has no frame {:?} to show in {}, showing a placeholder",
+                data.name,
+                self.cursor.index(),
+                self.animation
+            );
+            self.draw_placeholder();
+            return;
+        };
+        log::trace!("frame_index: {:?}", self.cursor.index());
+        if self.stepper.is_paused() {
+            log::info!(
+                "{} showing frame {:?} of {}",
+                data.name,
+                self.cursor.index(),
+                self.animation
+            );
+        }
+        // gone by the time the shimeji is twice its own height off the ground
+        let shadow = data.shadow.and_then(|shadow| {
+            let height = self.movement.height_above_ground()?;
+            shadow.lifted(height, data.height as f64 * 2.0)
+        });
+        let effects = Effects {
+            shadow,
+            tint: world.tint,
+            letterbox: data.letterbox,
+        };
+        let (x, y) =
+            animation
+                .anchor_of(index)
+                .offset(frame.width as f64, frame.height as f64, self.facing);
+        // frames are drawn as big as the shimeji, which its window is in logical pixels
+        let mut scale = data.width as f64 / frame.width.max(1) as f64;
+        if !self.overlaid {
+            scale *= self.window.scale_factor();
+        }
+        let anchor_offset =
+            PhysicalPosition::new((x * scale).round() as i32, (y * scale).round() as i32);
+        if anchor_offset != self.anchor_offset {
+            self.anchor_offset = anchor_offset;
+            let position = self.anchored(self.movement.position().cast());
+            self.window.set_outer_position(position);
         }
-        self.last_rendered_frame = Instant::now();
+        let pose = Pose {
+            animation: self.animation.clone(),
+            frame: self.cursor.index(),
+            position: self.movement.position().cast(),
+            facing: self.facing,
+            effects,
+        };
+        let still_since = match self.pose.take() {
+            Some((last, since)) if last == pose => since,
+            _ => Instant::now(),
+        };
+        self.pose = Some((pose, still_since));
+        if let Some(sound) = animation.sound_of(index).filter(|_| !world.muted) {
+            audio::play(sound);
+        }
+        if !self.overlaid {
+            self.hit_test_to(frame, index, animation.hitboxes_of(index));
+        }
+        if self.overlaid {
+            // drawn with the rest of the bucket once everyone is updated, see [`Self::sprite`]
+        } else if self.occluded {
+            // nobody would see it, the next frame after it shows again is drawn
+        } else if still_since.elapsed() < SHED_SURFACE_AFTER {
+            let policy = data.scaling.policy().unwrap_or_default();
+            let facing = self.facing;
+            let animation_name = self.animation.clone();
+            let variant = (!self.variant.is_plain()).then(|| self.variant.bits());
+            match self.pixels() {
+                Ok(pixels) => {
+                    // a shadow partway through fading out is rarely drawn twice the same
+                    match pixels.layout().filter(|_| effects.shadow == data.shadow) {
+                        Some((size, format)) => {
+                            let key = FrameKey {
+                                animation: animation_name,
+                                frame: index,
+                                size,
+                                format,
+                                facing,
+                                tint: FrameKey::tint_bits(effects.tint),
+                                variant,
+                            };
+                            draw_cached_frame(
+                                pixels,
+                                &data.frame_cache,
+                                key,
+                                frame,
+                                policy,
+                                effects,
+                            );
+                        }
+                        None => draw_frame(pixels, frame, policy, facing, effects),
+                    }
+                    if let Err(why) = pixels.render() {
+                        throttled!(log::Level::Error, "Could not draw {}: {why}", data.name);
+                    }
+                }
+                Err(why) => throttled!(
+                    log::Level::Error,
+                    "Could not make a surface for {}: {why}",
+                    data.name
+                ),
+            }
+            if world.shaped_windows {
+                self.shape_to(frame, index);
+            }
+        } else if self.pixels.take().is_some() {
+            // the window keeps showing the last frame, which is the same as this one
+            log::debug!(
+                "{} has held still for a while, dropping its surface",
+                data.name
+            );
+        }
+        self.show();
         // buffer.present().unwrap();
+
+        // frames are due one after another however late each was drawn, so a late frame
+        // shows for less instead of pushing every frame after it back
+        self.next_frame_at += animation.duration_of(index);
+        let mut chained = self.advance_frame(animation);
+        // skip frames that were over before they could be drawn, but only once around
+        let mut skipped = 0;
+        while self.next_frame_at <= now && !chained && skipped < animation.frames.len() {
+            let Some(index) = self.cursor.index().filter(|_| !self.cursor.is_finished()) else {
+                break;
+            };
+            self.next_frame_at += animation.duration_of(index);
+            chained = self.advance_frame(animation);
+            skipped += 1;
+        }
+        // too far behind to catch up, e.g. after being paused
+        if self.next_frame_at <= now {
+            self.next_frame_at = now;
+        }
+        // frames due before then are skipped like late ones
+        self.frame_not_before = now + world.min_frame_interval();
+    }
+    /// Show a [`Frame::placeholder`] for a frame that isn't there,
+    /// looking for it again once [`IDLE_WAKE`] has passed.
+    fn draw_placeholder(&mut self) {
+        let now = Instant::now();
+        if now < self.next_frame_at {
+            return;
+        }
+        self.next_frame_at = now + IDLE_WAKE;
+        // it isn't a pose worth keeping still for
+        self.pose = None;
+        if self.overlaid {
+            return;
+        }
+        let placeholder = Frame::placeholder(self.data.width, self.data.height);
+        let policy = self.data.scaling.policy().unwrap_or_default();
+        let facing = self.facing;
+        let drawn = self.pixels().and_then(|pixels| {
+            draw_frame(pixels, &placeholder, policy, facing, Effects::default());
+            pixels.render()
+        });
+        if let Err(why) = drawn {
+            throttled!(
+                log::Level::Error,
+                "Could not draw {}: {why}",
+                self.data.name
+            );
+        }
+        self.show();
+    }
+    /// Make the window visible, unless it's meant to be hidden.
+    fn show(&self) {
+        // Wayland can't tell, and doesn't let windows be hidden anyway
+        if !self.hidden
+            && !self.covered
+            && !self.overlaid
+            && self.window.is_visible() == Some(false)
+        {
+            self.window.set_visible(true);
+        }
+    }
+    /// Move on to the next frame of `animation`, which is showing,
+    /// returning whether that chained into the animation after it.
+    fn advance_frame(&mut self, animation: &AnimationData) -> bool {
+        if !self.cursor.advance() {
+            return false;
+        }
+        let data = Arc::clone(&self.data);
+        // a sequence only finishes once its last step does, and then follows its own `next`
+        let animation = match self.sequencer.as_mut().map(Sequencer::finished_pass) {
+            None => animation,
+            Some(Pass::Again) => {
+                self.cursor.reset();
+                return false;
+            }
+            Some(Pass::Next(step)) => {
+                self.show_animation(step);
+                return true;
+            }
+            Some(Pass::Done) => match data.animations.get(self.playing_animation()) {
+                Some(sequence) => sequence,
+                None => animation,
+            },
+        };
+        match &animation.next {
+            Some(next) => {
+                self.start_animation(next);
+                true
+            }
+            None => {
+                self.animation_finished = true;
+                if let Some(playing) = self.playing.take() {
+                    self.reports.push(handle::ShimejiEvent::Finished(playing));
+                }
+                false
+            }
+        }
+    }
+    /// When the shimeji next has to be updated: at its next frame, or its next physics tick
+    /// while it moves. `None` while paused, since only [`StepCommand`]s move it on then.
+    fn next_due(&self, now: Instant, world: &WorldSnapshot) -> Option<Instant> {
+        if self.stepper.is_paused() {
+            return None;
+        }
+        let moving = !self.is_held() && !self.movement.is_still() && !world.saving_power;
+        [
+            Some(self.next_frame_at.max(self.frame_not_before)),
+            moving.then(|| now + PHYSICS_TICK),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+    /// What happened to the behavior told to play since last time, for its handle.
+    pub fn take_reports(&mut self) -> Vec<handle::ShimejiEvent> {
+        std::mem::take(&mut self.reports)
+    }
+    /// Cut the window to the shape of `frame`, frame `index` of the animation showing,
+    /// unless it already is.
+    fn shape_to(&mut self, frame: &Frame, index: usize) {
+        let size = self.window.inner_size();
+        let shape = (self.animation.clone(), index, self.facing, size);
+        if self.shaped_as.as_ref() == Some(&shape) {
+            return;
+        }
+        let rects = window_shape::opaque_rects(frame, self.facing, size.width, size.height);
+        window_shape::apply(&self.window, &rects);
+        self.shaped_as = Some(shape);
+    }
+    /// Take clicks only on frame `index` of the animation playing, `frame`, see
+    /// [`window_shape::hit_rects`].
+    fn hit_test_to(&mut self, frame: &Frame, index: usize, hitboxes: &[FrameRegion]) {
+        let size = self.window.inner_size();
+        let hit = (self.animation.clone(), index, self.facing, size);
+        if self.hit_as.as_ref() == Some(&hit) {
+            return;
+        }
+        self.hit_rects =
+            window_shape::hit_rects(frame, hitboxes, self.facing, size.width, size.height);
+        window_shape::apply_input(&self.window, &self.hit_rects);
+        self.hit_as = Some(hit);
+    }
+    /// Let clicks through while the cursor is off the hit rects, where input regions
+    /// can't do that, but never while held.
+    fn follow_cursor(&mut self) {
+        if self.overlaid || self.hit_as.is_none() {
+            return;
+        }
+        let passing = match window_shape::cursor_hits(&self.window, &self.hit_rects) {
+            Some(hit) => !hit && !self.is_held(),
+            None => return,
+        };
+        if passing != self.passing_clicks {
+            if let Err(why) = self.window.set_cursor_hittest(!passing) {
+                throttled!(log::Level::Warn, "Could not let clicks through: {why}");
+            }
+            self.passing_clicks = passing;
+        }
+    }
+    /// The frames of the animation playing, `animation`, recolored as the shimeji's
+    /// [`Variant`], or `None` if it keeps the pack's own colors.
+    fn recolored_frames(&mut self, animation: &AnimationData) -> Option<Arc<[Frame]>> {
+        if self.variant.is_plain() {
+            return None;
+        }
+        if let Some((name, frames)) = &self.recolored {
+            if *name == self.animation {
+                return Some(Arc::clone(frames));
+            }
+        }
+        let frames =
+            self.data
+                .variants
+                .get_or_recolor(&self.variant, &self.animation, &animation.frames);
+        self.recolored = Some((self.animation.clone(), Arc::clone(&frames)));
+        Some(frames)
+    }
+    /// What to draw of the shimeji on its bucket's [`overlay::Overlay`], if it's overlaid and showing.
+    fn sprite(&self) -> Option<Sprite<'_>> {
+        if !self.overlaid || self.hidden || self.covered {
+            return None;
+        }
+        let (pose, _) = self.pose.as_ref()?;
+        let frames = match &self.recolored {
+            Some((animation, frames)) if *animation == pose.animation => &**frames,
+            _ => &self.data.animations.get(&pose.animation)?.frames,
+        };
+        let frame = frames.get(pose.frame?)?;
+        Some(Sprite {
+            frame,
+            position: self.anchored(self.movement.position().cast()),
+            size: BufferSize {
+                width: self.data.width,
+                height: self.data.height,
+            },
+            policy: self.data.scaling.policy().unwrap_or_default(),
+            facing: pose.facing,
+            effects: pose.effects,
+        })
+    }
+    /// The behaviors gone into since last time, in order.
+    pub fn take_entered(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.entered)
     }
+    /// Where to put another shimeji like this one, if it multiplied since last time.
+    pub fn take_multiply(&mut self) -> Option<PhysicalPosition<i32>> {
+        std::mem::take(&mut self.multiplying).then(|| self.movement.position().cast())
+    }
+}
+
+/// A cursor at the start of `animation`, looping like it says.
+fn cursor_for(data: &ShimejiData, animation: &str) -> FrameCursor {
+    match data.animations.get(animation) {
+        Some(animation) => FrameCursor::new(animation.frames.len(), animation.loop_mode),
+        None => FrameCursor::new(0, LoopMode::Loop),
+    }
+}
+
+/// Copy `frame` into the pixel buffer, row by row,
+/// in whatever byte order the buffer's texture wants.
+///
+/// A frame that doesn't match the buffer's size is centered on it.
+pub fn draw_frame(
+    pixels: &mut dyn Renderer,
+    frame: &Frame,
+    policy: BlitPolicy,
+    facing: Facing,
+    effects: Effects,
+) {
+    let Some((size, format)) = pixels.layout() else {
+        return;
+    };
+    if (size.width, size.height) != (frame.width, frame.height) {
+        throttled!(
+            log::Level::Debug,
+            "Drawing a {}x{} frame into a {size:?} buffer",
+            frame.width,
+            frame.height
+        );
+    }
+    blit(
+        frame,
+        pixels.frame_mut(),
+        size,
+        format,
+        policy,
+        facing,
+        effects,
+    );
+}
+
+/// [`draw_frame`], but copied from `cache` if another shimeji has drawn the frame
+/// for the same `key`, and left there for the next one if not.
+pub fn draw_cached_frame(
+    pixels: &mut dyn Renderer,
+    cache: &FrameCache,
+    key: FrameKey,
+    frame: &Frame,
+    policy: BlitPolicy,
+    effects: Effects,
+) {
+    let (size, format, facing) = (key.size, key.format, key.facing);
+    let buffer = pixels.frame_mut();
+    let drawn = cache.get_or_draw(key, buffer.len(), |buffer| {
+        blit(frame, buffer, size, format, policy, facing, effects)
+    });
+    buffer.copy_from_slice(&drawn);
 }
 
 /// Signify that an error has happened on thread `num`.
@@ -116,110 +1263,482 @@ macro_rules! thread_debug {
     };
 }
 
-/// The thread is started, we are executing.
-#[inline]
-pub fn loop_for_shimeji_execution(
-    receiver: Receiver<BucketThreadMessage>,
-    should_exit: Arc<AtomicBool>,
+/// Everything owned by one bucket thread.
+struct BucketState<'pix> {
     thread_id: usize,
-) -> () {
-    'running: while !should_exit.load(Ordering::Relaxed) {
-        let mut inner_vec = vec![];
-        let recv = receiver.recv();
-        let recv = match recv {
-            Ok(val) => val,
-            Err(_) => {
-                thread_debug!(thread_id, "Sender hung up without sending any shimeji");
-                break 'running;
-            }
-        };
-        match recv {
+    world: WorldSnapshot,
+    neighborhood: Neighborhood,
+    shimejis: Vec<ShimejiWindow<'pix>>,
+    props: Vec<PropWindow<'pix>>,
+    /// Where overlaid shimejis are drawn, see [`crate::overlay`].
+    overlay: Option<overlay::Overlay<'pix>>,
+    manager: Option<EventLoopProxy<ManagerEvent>>,
+    /// Whether everything is frozen, see [`BucketThreadMessage::Pause`].
+    paused: bool,
+}
+
+impl<'pix> BucketState<'pix> {
+    fn new(thread_id: usize, neighborhood: Neighborhood) -> Self {
+        Self {
+            thread_id,
+            world: WorldSnapshot::default(),
+            neighborhood,
+            shimejis: vec![],
+            props: vec![],
+            overlay: None,
+            manager: None,
+            paused: false,
+        }
+    }
+    /// Give every window back to the manager after a panic, for a new thread to carry on with,
+    /// see [`ShimejiBucket::restart`](crate::bucket::ShimejiBucket::restart).
+    fn hand_back(&mut self) {
+        let Some(manager) = self.manager.take() else {
+            thread_error!(self.thread_id, "No manager to hand the windows back to");
+            return;
+        };
+        let orphans = Orphans {
+            shimejis: self
+                .shimejis
+                .drain(..)
+                .map(|shimeji| (shimeji.window, shimeji.data))
+                .collect(),
+            props: self.props.drain(..).map(PropWindow::into_parts).collect(),
+            overlay: self.overlay.take().map(overlay::Overlay::into_window),
+        };
+        manager
+            .send_event(ManagerEvent::BucketCrashed(self.thread_id, orphans))
+            .ok();
+    }
+    fn find_shimeji(&mut self, id: WindowId) -> Option<&mut ShimejiWindow<'pix>> {
+        let res = self
+            .shimejis
+            .iter_mut()
+            .find(|shimeji| shimeji.window.id() == id);
+        if res.is_none() {
+            thread_error!(
+                self.thread_id,
+                "Could not find a shimeji that corresponds to id {id:?}"
+            );
+        }
+        res
+    }
+    fn handle(&mut self, message: BucketThreadMessage<'pix>) {
+        let thread_id = self.thread_id;
+        match message {
             Add(window, pixels, data) => {
-                thread_debug!(thread_id, "Received initial window: {0:?}", &window);
-                let monitor = window.current_monitor();
-                match monitor {
-                    Some(monitor) => {
-                        // log::debug!("monitor: {monitor:?}");
-                        let size = monitor.size();
-                        let position = window.outer_position().unwrap();
-                        thread_debug!(thread_id, "monitor size: {size:?}");
-                        thread_debug!(thread_id, "window position: {position:?}");
-                        window.set_outer_position(PhysicalPosition::new(
-                            0, // size.height - window.inner_size().height,
-                            500,
-                        ));
+                thread_debug!(thread_id, "Received window: {0:?}", &window);
+                let first = self
+                    .shimejis
+                    .is_empty()
+                    .then(|| place_first_window(&window, &self.world, thread_id));
+                let mut shimeji = ShimejiWindow::new(window, pixels, data, &self.world);
+                if let Some(position) = first {
+                    // the window manager may not have moved the window yet
+                    shimeji.movement.set_position(position.cast());
+                }
+                self.shimejis.push(shimeji)
+            }
+            AddProp(window, pixels, data) => {
+                thread_debug!(thread_id, "Received prop {}: {:?}", data.name, &window);
+                self.props.push(PropWindow::new(window, pixels, data))
+            }
+            Remove(id) => {
+                match self
+                    .shimejis
+                    .iter()
+                    .position(|shimeji| shimeji.window.id() == id)
+                {
+                    Some(index) => {
+                        close(self.shimejis.remove(index));
+                        thread_debug!(thread_id, "Removed shimeji {id:?}");
                     }
-                    None => {
-                        log::warn!("Current monitor could not be detected");
-                        window.set_outer_position(PhysicalPosition::new(0, 0));
+                    None => thread_error!(thread_id, "Could not find shimeji {id:?} to remove"),
+                }
+                // even if it wasn't here, the manager shouldn't keep routing to us
+                if let Some(manager) = self.manager.as_ref() {
+                    manager.send_event(ManagerEvent::Removed(id)).ok();
+                }
+            }
+            Manager(proxy) => self.manager = Some(proxy),
+            Overlay(window) => {
+                thread_debug!(thread_id, "Drawing on overlay {:?}", window.id());
+                match overlay::Overlay::new(window) {
+                    Ok(overlay) => self.overlay = Some(overlay),
+                    Err(why) => thread_error!(thread_id, "Could not draw on the overlay: {why}"),
+                }
+            }
+            World(world) => {
+                // the floor may have been calibrated
+                for shimeji in self.shimejis.iter_mut() {
+                    shimeji.update_surroundings(&world);
+                }
+                self.world = *world;
+            }
+            Party(id) => {
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.start_partying()
+                }
+            }
+            Alert { behavior, corner } => {
+                for shimeji in self.shimejis.iter_mut() {
+                    shimeji.alert(&behavior, corner, &self.world);
+                }
+            }
+            Grab { id, offset } => {
+                let filters = self.world.filters.clone();
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    if shimeji.allows(InteractionKind::Grab, &filters) {
+                        shimeji.grab(offset)
                     }
                 }
-                inner_vec.push(ShimejiWindow::new(window, pixels, data))
             }
-            _ => unimplemented!(),
-        };
-        'has_window: loop {
-            log::trace!("Looping 'has_window");
-            if should_exit.load(Ordering::Relaxed) {
-                log::debug!("Should exit, breaking loop");
-                break 'running;
+            Drag { id, position } => {
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.drag_to(position)
+                }
             }
-            // add a new shimeji, if we're waiting to receive one
-            let val = match receiver.try_recv() {
-                Err(mpsc::TryRecvError::Empty) => None,
-                Err(what) => {
-                    thread_error!(thread_id, "Unrecognized try_recv error: {what:?}");
-                    break;
+            Release { id, velocity } => {
+                let world = self.world.clone();
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.release(velocity, &world)
                 }
-                Ok(val) => Some(val),
-            };
-
-            if let Some(val) = val {
-                match val {
-                    Add(window, pixels, data) => {
-                        thread_debug!(thread_id, "Received window: {0:?}", &window);
-                        inner_vec.push(ShimejiWindow::new(window, pixels, data))
+            }
+            Steer { id, command } => {
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.steer(command)
+                }
+            }
+            Step { id, command } => {
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.debug_step(command)
+                }
+            }
+            Control { id, control } => {
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.control(control)
+                }
+            }
+            Reload { id, data } => {
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.reload(data)
+                }
+            }
+            SwapFrames { id, data } => {
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.swap_frames(data)
+                }
+            }
+            Pause => {
+                thread_debug!(thread_id, "Pausing");
+                self.paused = true;
+            }
+            Resume => {
+                thread_debug!(thread_id, "Resuming");
+                self.paused = false;
+                for shimeji in self.shimejis.iter_mut() {
+                    shimeji.resume();
+                }
+                for prop in self.props.iter_mut() {
+                    prop.hold_still();
+                }
+            }
+            Pet(id) => {
+                let filters = self.world.filters.clone();
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    let allowed = shimeji.allows(InteractionKind::Click, &filters);
+                    shimeji.clicked();
+                    if allowed {
+                        thread_debug!(thread_id, "Shimeji {id:?} was petted");
+                        shimeji.pet();
                     }
-                    Remove(..) => todo!(),
-                    Resized { id, size } => {
-                        let res = inner_vec
-                            .iter_mut()
-                            .find(|shimeji| (**shimeji).window.id() == id);
-                        if let Some(shimeji) = res {
-                            match shimeji.pixels.resize_surface(size.width, size.height) {
-                                Ok(_) => (),
-                                Err(why) => {
-                                    thread_error!(
-                                        thread_id,
-                                        "Error resizing inner window id {id:?}: {why}"
-                                    );
-                                }
-                            };
-                        } else {
-                            thread_error!(
-                                thread_id,
-                                "Could not find a shimeji that corresponds to id {id:?}"
-                            );
-                        }
+                }
+            }
+            WindowEvent(id, event) => {
+                // props only react to being dragged, which is an interaction
+                if self.props.iter().any(|prop| prop.id() == id) {
+                    return;
+                }
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    shimeji.input(event)
+                }
+            }
+            Resized { id, size } => {
+                if let Some(prop) = self.props.iter_mut().find(|prop| prop.id() == id) {
+                    if let Err(why) = prop.resize_surface(size) {
+                        thread_error!(thread_id, "Error resizing prop window id {id:?}: {why}");
                     }
+                    return;
+                }
+                let world = self.world.clone();
+                if let Some(shimeji) = self.find_shimeji(id) {
+                    if let Err(why) = shimeji.resize(size) {
+                        thread_error!(thread_id, "Error resizing inner window id {id:?}: {why}");
+                    }
+                    shimeji.update_surroundings(&world);
                 }
             }
-            if inner_vec.is_empty() {
-                log::debug!("No windows in inner_vec! Stopping 'has_window");
-                break 'has_window;
+        }
+    }
+    /// When the soonest shimeji or prop has to be updated, if any do before something changes.
+    fn next_due(&self) -> Option<Instant> {
+        if self.paused {
+            return None;
+        }
+        let now = Instant::now();
+        let shimejis = self
+            .shimejis
+            .iter()
+            .filter_map(|shimeji| shimeji.next_due(now, &self.world));
+        let props = self
+            .props
+            .iter()
+            .filter(|prop| prop.is_moving() && !self.world.saving_power)
+            .map(|_| now + PHYSICS_TICK);
+        shimejis.chain(props).min()
+    }
+    fn update(&mut self) {
+        if self.paused {
+            return;
+        }
+        let ours = self
+            .shimejis
+            .iter()
+            .map(ShimejiWindow::as_neighbor)
+            .collect();
+        self.neighborhood.publish(self.thread_id, ours);
+        let everyone = self.neighborhood.everyone();
+        let props: Arc<[Neighbor]> = self.props.iter().map(PropWindow::as_neighbor).collect();
+        for shimeji in self.shimejis.iter_mut() {
+            shimeji.neighbors = Arc::clone(&everyone);
+            shimeji.props = Arc::clone(&props);
+            shimeji.step_physics(&self.world);
+            shimeji.update(&self.world);
+            for report in shimeji.take_reports() {
+                if let Some(manager) = self.manager.as_ref() {
+                    let id = shimeji.window.id();
+                    manager.send_event(ManagerEvent::Report(id, report)).ok();
+                }
+            }
+            // only worth the messages while recording
+            let entered = shimeji.take_entered();
+            if let Some(manager) = self.manager.as_ref().filter(|_| self.world.recording) {
+                let position = shimeji.movement.position().cast();
+                for behavior in entered {
+                    let id = shimeji.window.id();
+                    manager
+                        .send_event(ManagerEvent::Entered(id, behavior, position))
+                        .ok();
+                }
+            }
+            if let Some(position) = shimeji.take_multiply() {
+                if let Some(manager) = self.manager.as_ref() {
+                    let id = shimeji.window.id();
+                    manager
+                        .send_event(ManagerEvent::Multiply(id, position))
+                        .ok();
+                }
             }
-            for shimeji in inner_vec.iter_mut() {
-                shimeji.update();
-                thread::yield_now();
+        }
+        if let Some(overlay) = self.overlay.as_mut() {
+            overlay.draw(self.shimejis.iter().filter_map(ShimejiWindow::sprite));
+        }
+        for prop in self.props.iter_mut() {
+            if self.world.saving_power {
+                prop.hold_still();
+                continue;
+            }
+            for shimeji in self.shimejis.iter() {
+                let (position, size) = shimeji.bounds();
+                prop.kick_if_touched(position, size, shimeji.movement.velocity());
             }
+            prop.update();
         }
     }
 }
 
+/// Where the top of a `size` window at `position` would be standing on the highest of
+/// `surfaces` under it, or on `monitor_floor` if that's higher.
+pub(crate) fn floor_among(
+    surfaces: impl IntoIterator<Item = WindowSurface>,
+    position: PhysicalPosition<f64>,
+    size: PhysicalSize<u32>,
+    monitor_floor: Option<f64>,
+) -> Option<f64> {
+    let (left, right) = (position.x, position.x + size.width as f64);
+    let feet = position.y + size.height as f64;
+    let window_floor = surfaces
+        .into_iter()
+        .filter(|surface| surface.spans(left, right))
+        .map(|surface| surface.top as f64)
+        // a window that slid up a little is still underfoot
+        .filter(|top| *top >= feet - LEDGE_TOLERANCE)
+        .map(|top| top - size.height as f64)
+        .min_by(f64::total_cmp);
+    match (window_floor, monitor_floor) {
+        (Some(window), Some(monitor)) => Some(window.min(monitor)),
+        (window, monitor) => window.or(monitor),
+    }
+}
+
+/// Walk at `speed` toward the middle of `prop`, so as to walk into and kick it,
+/// or stop if there's none or it's already right there.
+pub(crate) fn walk_toward(me: &Neighbor, prop: Option<&Neighbor>, speed: f64) -> MovementCommand {
+    let Some(prop) = prop else {
+        return MovementCommand::Stop;
+    };
+    let gap = prop.center().x - me.center().x;
+    match gap.abs() < 1.0 {
+        true => MovementCommand::Stop,
+        false => MovementCommand::Walk(speed.abs() * gap.signum()),
+    }
+}
+
+/// Drop the first window in from the top left of its monitor's work area.
+fn place_first_window(
+    window: &Window,
+    world: &WorldSnapshot,
+    thread_id: usize,
+) -> PhysicalPosition<i32> {
+    let position = match window.current_monitor() {
+        Some(monitor) => {
+            thread_debug!(thread_id, "monitor size: {:?}", monitor.size());
+            let position = monitor.position();
+            match world
+                .monitors
+                .under(position.cast(), PhysicalSize::new(1, 1))
+            {
+                Some(area) => PhysicalPosition::new(area.work_area.left, area.work_area.top),
+                None => position,
+            }
+        }
+        None => {
+            log::warn!("Current monitor could not be detected");
+            PhysicalPosition::new(0, 0)
+        }
+    };
+    window.set_outer_position(position);
+    position
+}
+
+/// The furthest left and right the left of a `width` pixel wide `window`
+/// can go while staying on its monitor.
+fn walls_of(window: &Window, width: u32) -> Option<(f64, f64)> {
+    let monitor = window.current_monitor()?;
+    let left = monitor.position().x;
+    let right = left + monitor.size().width as i32 - width as i32;
+    Some((left as f64, right.max(left) as f64))
+}
+
+/// The highest the top of `window` can go while staying on its monitor.
+fn ceiling_of(window: &Window) -> Option<f64> {
+    Some(window.current_monitor()?.position().y as f64)
+}
+
+/// The lowest the top of a `height` pixel tall `window` can go
+/// while standing on its monitor's calibrated floor.
+fn floor_of(window: &Window, height: u32, world: &WorldSnapshot) -> Option<f64> {
+    let monitor = window.current_monitor()?;
+    let bottom = monitor.position().y + monitor.size().height as i32
+        - world.floor_offset(monitor.name().as_deref());
+    Some((bottom - height as i32) as f64)
+}
+
+/// The thread is started, we are executing.
+#[inline]
+pub fn loop_for_shimeji_execution(
+    receiver: Receiver<BucketThreadMessage>,
+    should_exit: Arc<AtomicBool>,
+    thread_id: usize,
+    neighborhood: Neighborhood,
+) {
+    let mut state = BucketState::new(thread_id, neighborhood.clone());
+    // one bad surface shouldn't take every shimeji of the bucket down with it
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        run_bucket(&mut state, &receiver, &should_exit)
+    }));
+    if let Err(why) = run {
+        let why = why
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| why.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("no message");
+        thread_error!(thread_id, "Crashed: {why}");
+        if !should_exit.load(Ordering::Relaxed) {
+            state.hand_back();
+        }
+    }
+    // close whatever the manager didn't get around to removing
+    for shimeji in state.shimejis.drain(..) {
+        close(shimeji);
+    }
+    neighborhood.leave(thread_id);
+}
+
+fn run_bucket<'pix>(
+    state: &mut BucketState<'pix>,
+    receiver: &Receiver<BucketThreadMessage<'pix>>,
+    should_exit: &AtomicBool,
+) {
+    let thread_id = state.thread_id;
+    while !should_exit.load(Ordering::Relaxed) {
+        // sleep until a message comes in or something is due, whichever is first
+        let due = state
+            .next_due()
+            .unwrap_or_else(|| Instant::now() + IDLE_WAKE);
+        match receiver.recv_timeout(due.saturating_duration_since(Instant::now())) {
+            Ok(message) => {
+                state.handle(message);
+                while let Ok(message) = receiver.try_recv() {
+                    state.handle(message);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
+                thread_debug!(thread_id, "Sender hung up, stopping");
+                break;
+            }
+        }
+        state.update();
+    }
+}
+
+/// Hide a shimeji's window, then destroy it.
+fn close(shimeji: ShimejiWindow) {
+    let ShimejiWindow { window, pixels, .. } = shimeji;
+    window.set_visible(false);
+    // the surface has to go before the window it draws to
+    drop(pixels);
+    drop(window);
+}
+
 #[derive(Debug, Clone)]
 pub struct ShimejiData {
     pub name: Arc<str>,
     pub height: u32,
     pub width: u32,
     pub animations: HashMap<String, AnimationData>,
+    pub behaviors: BehaviorTable,
+    pub props: Vec<Arc<PropData>>,
+    /// Whether the shimejis fall to the bottom of their monitor.
+    pub gravity: bool,
+    pub shadow: Option<Shadow>,
+    /// Every file the shimeji was loaded from, images included.
+    pub sources: Vec<PathBuf>,
+    /// How frames fill a window resized by the window manager.
+    pub scaling: ScalingMode,
+    /// What fills the rest of a window that frames don't, transparent if `None`.
+    pub letterbox: Option<Rgba>,
+    /// Frames as drawn by any of this pack's shimejis.
+    pub frame_cache: FrameCache,
+    /// Animations as recolored by any of this pack's shimejis, see [`Variant`].
+    pub variants: VariantCache,
+}
+
+impl ShimejiData {
+    /// How big a window showing the shimeji is on a monitor scaled by `scale_factor`,
+    /// so it looks as big on every monitor.
+    pub fn size_at(&self, scale_factor: f64) -> PhysicalSize<u32> {
+        LogicalSize::new(self.width, self.height).to_physical(scale_factor)
+    }
 }
@@ -0,0 +1,200 @@
+//! Recoloring single shimejis, so copies of the same pack can come in different colors
+//! without art of their own: a hue shift, then a tint, e.g. `hue:120 tint:#ffe0c0`.
+//!
+//! Frames are recolored an animation at a time, the first time a shimeji of that color
+//! plays it, and kept for every other shimeji of the pack in the same color.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use derive_more::derive::{Display, Error};
+
+use crate::{ambient::Tint, loader::Frame, rgba::Rgba};
+
+#[derive(Debug, Display, Error)]
+pub enum InvalidVariant {
+    #[display("hue {value:?} is not a number of degrees")]
+    Hue { value: String },
+    #[display("tint {value:?} is not #rrggbb")]
+    Tint { value: String },
+    #[display("unknown color change {part:?}, expected hue:<degrees> or tint:#rrggbb")]
+    Unknown { part: String },
+}
+
+/// How a shimeji's frames are recolored. The default leaves them be.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Variant {
+    /// How many degrees around the color wheel every color is turned.
+    pub hue_shift: f64,
+    /// Applied after the hue shift.
+    pub tint: Option<Tint>,
+}
+
+impl Variant {
+    /// Whether frames come out the same as they went in.
+    pub fn is_plain(&self) -> bool {
+        self.hue_shift.rem_euclid(360.0) == 0.0 && self.tint.is_none()
+    }
+    /// What tells this variant apart from others, as floats can't be hashed.
+    pub fn bits(&self) -> [u64; 4] {
+        let tint = self
+            .tint
+            .map_or([0.0; 3], |tint| [tint.red, tint.green, tint.blue]);
+        [
+            self.hue_shift.rem_euclid(360.0).to_bits(),
+            tint[0].to_bits(),
+            tint[1].to_bits(),
+            tint[2].to_bits(),
+        ]
+    }
+    /// `color` recolored, as see-through as it was.
+    pub fn apply(&self, color: Rgba) -> Rgba {
+        let color = hue_shifted(color, self.hue_shift);
+        match self.tint {
+            Some(tint) => tint.apply(color),
+            None => color,
+        }
+    }
+    /// Every pixel of `frame` recolored.
+    pub fn recolor(&self, frame: &Frame) -> Frame {
+        Frame {
+            width: frame.width,
+            height: frame.height,
+            pixels_row_major: frame
+                .pixels_row_major
+                .iter()
+                .map(|pixel| self.apply(*pixel))
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+}
+
+impl FromStr for Variant {
+    type Err = InvalidVariant;
+
+    /// Any of `hue:<degrees>` and `tint:#rrggbb`, separated by spaces.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut variant = Self::default();
+        for part in s.split_whitespace() {
+            if let Some(value) = part.strip_prefix("hue:") {
+                variant.hue_shift = value
+                    .parse()
+                    .ok()
+                    .filter(|hue: &f64| hue.is_finite())
+                    .ok_or_else(|| InvalidVariant::Hue {
+                        value: value.to_owned(),
+                    })?;
+            } else if let Some(value) = part.strip_prefix("tint:") {
+                let color = Rgba::from_hex(value).ok_or_else(|| InvalidVariant::Tint {
+                    value: value.to_owned(),
+                })?;
+                let keep = |channel: u8| channel as f64 / 255.0;
+                variant.tint = Some(Tint {
+                    red: keep(color.red),
+                    green: keep(color.green),
+                    blue: keep(color.blue),
+                });
+            } else {
+                return Err(InvalidVariant::Unknown {
+                    part: part.to_owned(),
+                });
+            }
+        }
+        Ok(variant)
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "hue:{}", self.hue_shift)?;
+        if let Some(tint) = self.tint {
+            let channel = |keep: f64| (keep * 255.0).round() as u8;
+            write!(
+                f,
+                " tint:#{:02x}{:02x}{:02x}",
+                channel(tint.red),
+                channel(tint.green),
+                channel(tint.blue)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `color` turned `degrees` around the color wheel, keeping its saturation and value.
+fn hue_shifted(color: Rgba, degrees: f64) -> Rgba {
+    let [red, green, blue] = [color.red, color.green, color.blue].map(|c| c as f64 / 255.0);
+    let max = red.max(green).max(blue);
+    let chroma = max - red.min(green).min(blue);
+    if chroma == 0.0 {
+        // grays have no hue to turn
+        return color;
+    }
+    // which sixth of the wheel the color is in, and how far through it
+    let hue = if max == red {
+        ((green - blue) / chroma).rem_euclid(6.0)
+    } else if max == green {
+        (blue - red) / chroma + 2.0
+    } else {
+        (red - green) / chroma + 4.0
+    };
+    let hue = (hue + degrees / 60.0).rem_euclid(6.0);
+    let middle = chroma * (1.0 - (hue.rem_euclid(2.0) - 1.0).abs());
+    let (red, green, blue) = match hue as u32 {
+        0 => (chroma, middle, 0.0),
+        1 => (middle, chroma, 0.0),
+        2 => (0.0, chroma, middle),
+        3 => (0.0, middle, chroma),
+        4 => (middle, 0.0, chroma),
+        _ => (chroma, 0.0, middle),
+    };
+    let channel = |value: f64| ((value + max - chroma) * 255.0).round() as u8;
+    Rgba::new(channel(red), channel(green), channel(blue), color.alpha)
+}
+
+/// A variant's [`Variant::bits`], and the name of an animation.
+type AnimationKey = ([u64; 4], String);
+
+/// Animations recolored by any of a pack's shimejis, by variant and animation name.
+#[derive(derive_more::Debug, Default)]
+pub struct VariantCache {
+    #[debug("{} animations", self.frames.lock().map_or(0, |frames| frames.len()))]
+    frames: Mutex<HashMap<AnimationKey, Arc<[Frame]>>>,
+}
+
+impl Clone for VariantCache {
+    /// A clone starts out empty, it is only a cache.
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl VariantCache {
+    /// The `frames` of `animation` recolored as `variant`,
+    /// recoloring them if no shimeji has yet.
+    pub fn get_or_recolor(
+        &self,
+        variant: &Variant,
+        animation: &str,
+        frames: &[Frame],
+    ) -> Arc<[Frame]> {
+        let key = (variant.bits(), animation.to_owned());
+        if let Some(frames) = self.lock().get(&key) {
+            return Arc::clone(frames);
+        }
+        let recolored: Arc<[Frame]> = frames.iter().map(|frame| variant.recolor(frame)).collect();
+        self.lock().insert(key, Arc::clone(&recolored));
+        recolored
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<AnimationKey, Arc<[Frame]>>> {
+        // a panic elsewhere can't leave half a recolored animation behind
+        self.frames
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -0,0 +1,265 @@
+//! Cutting shimeji windows down to the shape of their sprite, for when there's no
+//! compositor to blend them with what's behind: an X11 SHAPE bounding region on Linux
+//! and a window region on Windows. Without one, see-through pixels show as black.
+//!
+//! Clicks on the cut away parts go through to whatever is behind, like they should anyway.
+//!
+//! Shimejis that aren't cut to shape only take clicks on their [`hit_rects`] too, through an
+//! X11 SHAPE input region on Linux. Windows can't have windows take clicks on only part
+//! of them, so there the window lets clicks through whenever the cursor is off its hit
+//! rects, see [`cursor_hits`].
+
+use cfg_if::cfg_if;
+use winit::window::Window;
+
+use crate::{blit::Facing, loader::Frame, xml_parser::FrameRegion};
+
+/// How opaque a pixel has to be to keep its part of the window.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// A rectangle of a window, in its pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The parts of a `width` by `height` window that `frame` covers when stretched over it
+/// facing `facing`, as rows of rectangles. Rows alike are merged into one taller row.
+pub fn opaque_rects(frame: &Frame, facing: Facing, width: u32, height: u32) -> Vec<Rect> {
+    let mut rects = vec![];
+    if frame.width == 0 || frame.height == 0 {
+        return rects;
+    }
+    let opaque = |x: u32, y: u32| {
+        let source_x = (x as u64 * frame.width as u64 / width as u64) as u32;
+        let source_x = match facing {
+            Facing::Left => source_x,
+            Facing::Right => frame.width - 1 - source_x,
+        };
+        let source_y = (y as u64 * frame.height as u64 / height as u64) as u32;
+        frame.pixels_row_major[(source_y * frame.width + source_x) as usize].alpha
+            >= ALPHA_THRESHOLD
+    };
+    // the rectangles of the rows above that are still growing down
+    let mut open: Vec<Rect> = vec![];
+    for y in 0..height {
+        let mut row = vec![];
+        let mut start = None;
+        for x in 0..=width {
+            match (start, x < width && opaque(x, y)) {
+                (None, true) => start = Some(x),
+                (Some(left), false) => {
+                    row.push(Rect {
+                        x: left,
+                        y,
+                        width: x - left,
+                        height: 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        let same_runs = row.len() == open.len()
+            && row
+                .iter()
+                .zip(&open)
+                .all(|(run, above)| (run.x, run.width) == (above.x, above.width));
+        if same_runs {
+            open.iter_mut().for_each(|rect| rect.height += 1);
+        } else {
+            rects.append(&mut open);
+            open = row;
+        }
+    }
+    rects.append(&mut open);
+    rects
+}
+
+/// The parts of a `width` by `height` window that take clicks when `frame` is stretched
+/// over it facing `facing`: its `hitboxes`, or its opaque parts if it has none.
+pub fn hit_rects(
+    frame: &Frame,
+    hitboxes: &[FrameRegion],
+    facing: Facing,
+    width: u32,
+    height: u32,
+) -> Vec<Rect> {
+    if hitboxes.is_empty() {
+        return opaque_rects(frame, facing, width, height);
+    }
+    if frame.width == 0 || frame.height == 0 {
+        return vec![];
+    }
+    let across = |value: u32| (value as u64 * width as u64 / frame.width as u64) as u32;
+    let down = |value: u32| (value as u64 * height as u64 / frame.height as u64) as u32;
+    hitboxes
+        .iter()
+        .filter_map(|hitbox| {
+            let right = across((hitbox.x + hitbox.width).min(frame.width));
+            let left = across(hitbox.x.min(frame.width));
+            let bottom = down((hitbox.y + hitbox.height).min(frame.height));
+            let top = down(hitbox.y.min(frame.height));
+            let x = match facing {
+                Facing::Left => left,
+                // mirrored, so it's as far from the right as it was from the left
+                Facing::Right => width - right,
+            };
+            (right > left && bottom > top).then_some(Rect {
+                x,
+                y: top,
+                width: right - left,
+                height: bottom - top,
+            })
+        })
+        .collect()
+}
+
+impl Rect {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use std::sync::OnceLock;
+
+        use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+        use x11rb::{
+            connection::{Connection, RequestConnection},
+            protocol::{
+                shape::{self, ConnectionExt as _, SK, SO},
+                xproto::{ClipOrdering, Rectangle},
+            },
+            rust_connection::RustConnection,
+        };
+
+        /// Shared by every bucket, connected the first time a window is shaped.
+        static CONNECTION: OnceLock<Option<RustConnection>> = OnceLock::new();
+
+        fn connect() -> anyhow::Result<RustConnection> {
+            let (connection, _) = x11rb::connect(None)?;
+            if connection
+                .extension_information(shape::X11_EXTENSION_NAME)?
+                .is_none()
+            {
+                anyhow::bail!("the X server has no SHAPE extension");
+            }
+            Ok(connection)
+        }
+
+        /// Show only `rects` of `window`. Does nothing for non-X11 windows.
+        pub fn apply(window: &Window, rects: &[Rect]) {
+            shape(window, SK::BOUNDING, rects);
+        }
+        /// Let clicks off `rects` through to whatever is behind `window`, still showing
+        /// all of it. Does nothing for non-X11 windows.
+        pub fn apply_input(window: &Window, rects: &[Rect]) {
+            shape(window, SK::INPUT, rects);
+        }
+        /// The input region does it all here.
+        pub fn cursor_hits(_window: &Window, _rects: &[Rect]) -> Option<bool> {
+            None
+        }
+        fn shape(window: &Window, kind: SK, rects: &[Rect]) {
+            let Ok(RawWindowHandle::Xlib(handle)) =
+                window.window_handle().map(|handle| handle.as_raw())
+            else {
+                return;
+            };
+            let connection = CONNECTION.get_or_init(|| {
+                connect()
+                    .inspect_err(|why| log::warn!("Could not shape windows: {why}"))
+                    .ok()
+            });
+            let Some(connection) = connection else {
+                return;
+            };
+            let rectangles: Vec<Rectangle> = rects
+                .iter()
+                .map(|rect| Rectangle {
+                    x: rect.x as i16,
+                    y: rect.y as i16,
+                    width: rect.width as u16,
+                    height: rect.height as u16,
+                })
+                .collect();
+            let shaped = connection
+                .shape_rectangles(
+                    SO::SET,
+                    kind,
+                    ClipOrdering::UNSORTED,
+                    handle.window as u32,
+                    0,
+                    0,
+                    &rectangles,
+                )
+                .and_then(|_| connection.flush());
+            if let Err(why) = shaped {
+                log::warn!("Could not shape a window: {why}");
+            }
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use windows_sys::Win32::{
+            Foundation::POINT,
+            Graphics::Gdi::{CombineRgn, CreateRectRgn, DeleteObject, SetWindowRgn, RGN_OR},
+            UI::WindowsAndMessaging::GetCursorPos,
+        };
+        use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+        /// Show only `rects` of `window`.
+        pub fn apply(window: &Window, rects: &[Rect]) {
+            let Ok(RawWindowHandle::Win32(handle)) =
+                window.window_handle().map(|handle| handle.as_raw())
+            else {
+                return;
+            };
+            let hwnd = handle.hwnd.get() as _;
+            // SAFETY: every region made is either deleted or handed over to the window,
+            // which is alive for the whole call.
+            unsafe {
+                let region = CreateRectRgn(0, 0, 0, 0);
+                for rect in rects {
+                    let (left, top) = (rect.x as i32, rect.y as i32);
+                    let part =
+                        CreateRectRgn(left, top, left + rect.width as i32, top + rect.height as i32);
+                    CombineRgn(region, region, part, RGN_OR);
+                    DeleteObject(part);
+                }
+                // the window owns the region from here on
+                if SetWindowRgn(hwnd, region, 1) == 0 {
+                    DeleteObject(region);
+                    log::warn!("Could not shape a window: {}", std::io::Error::last_os_error());
+                }
+            }
+        }
+        /// Does nothing, see [`cursor_hits`].
+        pub fn apply_input(_window: &Window, _rects: &[Rect]) {}
+        /// Whether the cursor is on one of `rects` of `window`, or `None` if it's off the
+        /// window altogether, for the window to let clicks through while it isn't.
+        pub fn cursor_hits(window: &Window, rects: &[Rect]) -> Option<bool> {
+            let mut cursor = POINT { x: 0, y: 0 };
+            // SAFETY: `cursor` is a valid POINT to write to.
+            if unsafe { GetCursorPos(&mut cursor) } == 0 {
+                return None;
+            }
+            let origin = window.inner_position().ok()?;
+            let size = window.inner_size();
+            let x = u32::try_from(cursor.x - origin.x).ok().filter(|x| *x < size.width)?;
+            let y = u32::try_from(cursor.y - origin.y).ok().filter(|y| *y < size.height)?;
+            Some(rects.iter().any(|rect| rect.contains(x, y)))
+        }
+    } else {
+        /// Does nothing, since windows can't be shaped on this platform.
+        pub fn apply(_window: &Window, _rects: &[Rect]) {}
+        /// Does nothing, since windows can't be shaped on this platform.
+        pub fn apply_input(_window: &Window, _rects: &[Rect]) {}
+        pub fn cursor_hits(_window: &Window, _rects: &[Rect]) -> Option<bool> {
+            None
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! The window shown on the first run, when there's no config to load shimejis from yet:
+//! every pack in the [`packs_dir`] as a tile showing its first idle frame. Clicking a tile
+//! picks it, and Enter or closing the window spawns one shimeji of each picked pack.
+//!
+//! There's no font to draw names with, so the title names the pack under the cursor.
+
+use std::{path::PathBuf, sync::Arc};
+
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
+    event_loop::ActiveEventLoop,
+    keyboard::{KeyCode, PhysicalKey},
+    window::{Window, WindowAttributes, WindowId},
+};
+
+use crate::{
+    blit::{blit, BlitPolicy, BufferSize, Effects, Facing, Filter},
+    loader::{Frame, ShimejiLibrary},
+    overlay::lay_over,
+    renderer::{self, Renderer},
+    rgba::{PixelFormat, Rgba},
+    settings::config_path,
+    shimeji::ShimejiData,
+};
+
+pub const PACKS_DIR_VAR: &str = "SHIMEJI_PACKS_DIR";
+const TITLE: &str = "Pick shimejis, then press Enter";
+/// How big each tile is, in pixels.
+const TILE: u32 = 128;
+/// The space around a frame inside its tile, in pixels.
+const PADDING: u32 = 8;
+/// In [`PixelFormat::Rgba8`].
+const BACKGROUND: [u8; 4] = [32, 32, 40, 255];
+const HOVERED: [u8; 4] = [56, 56, 72, 255];
+const PICKED: [u8; 4] = [64, 112, 192, 255];
+
+/// The directory of packs to pick from: `$SHIMEJI_PACKS_DIR`, or `packs` in the
+/// [`config_dir`](crate::settings::config_dir).
+pub fn packs_dir() -> PathBuf {
+    config_path(PACKS_DIR_VAR, "packs")
+}
+
+/// How many columns `count` tiles are laid out in, about as many as there are rows.
+pub fn columns(count: usize) -> usize {
+    (count as f64).sqrt().ceil().max(1.0) as usize
+}
+
+/// The tile under `position` when `count` tiles are laid out `columns` wide, if any.
+pub fn tile_at(position: PhysicalPosition<f64>, columns: usize, count: usize) -> Option<usize> {
+    if position.x < 0.0 || position.y < 0.0 {
+        return None;
+    }
+    let column = (position.x / TILE as f64) as usize;
+    let row = (position.y / TILE as f64) as usize;
+    let index = row * columns + column;
+    (column < columns && index < count).then_some(index)
+}
+
+/// The frame a shimeji of `data` starts out showing.
+fn idle_frame(data: &ShimejiData) -> Option<&Frame> {
+    data.animations
+        .get(&data.behaviors.initial().animation)
+        .and_then(|animation| animation.frames.first())
+}
+
+#[derive(Debug)]
+struct Tile {
+    data: Arc<ShimejiData>,
+    picked: bool,
+}
+
+#[derive(derive_more::Debug)]
+pub struct Picker {
+    window: Arc<Window>,
+    #[debug(skip)]
+    pixels: Box<dyn Renderer>,
+    tiles: Vec<Tile>,
+    columns: usize,
+    hovered: Option<usize>,
+    /// What each frame is drawn into before it's laid over its tile.
+    #[debug(skip)]
+    scratch: Vec<u8>,
+}
+
+impl Picker {
+    /// Show every pack in `library`.
+    ///
+    /// # Errors
+    /// Errors if the library is empty or the window can't be made.
+    pub fn open(event_loop: &ActiveEventLoop, library: &ShimejiLibrary) -> anyhow::Result<Self> {
+        let tiles: Vec<_> = library
+            .names()
+            .iter()
+            .filter_map(|name| library.get(name))
+            .map(|data| Tile {
+                data,
+                picked: false,
+            })
+            .collect();
+        anyhow::ensure!(!tiles.is_empty(), "there are no packs to pick from");
+        let columns = columns(tiles.len());
+        let rows = tiles.len().div_ceil(columns);
+        let size = PhysicalSize::new(columns as u32 * TILE, rows as u32 * TILE);
+        let attributes = WindowAttributes::default()
+            .with_title(TITLE)
+            .with_inner_size(size)
+            .with_resizable(false);
+        let window = Arc::new(event_loop.create_window(attributes)?);
+        let pixels = renderer::create(&window, size.width, size.height)?;
+        let mut picker = Self {
+            window,
+            pixels,
+            tiles,
+            columns,
+            hovered: None,
+            scratch: vec![],
+        };
+        picker.draw();
+        Ok(picker)
+    }
+    pub fn owns(&self, id: WindowId) -> bool {
+        self.window.id() == id
+    }
+    /// Pick packs with the left mouse button.
+    ///
+    /// Returns the names of the picked packs once the user is done.
+    pub fn handle(&mut self, event: &WindowEvent) -> Option<Vec<Arc<str>>> {
+        match *event {
+            WindowEvent::RedrawRequested => self.draw(),
+            WindowEvent::Resized(size) => {
+                if let Err(why) = self.pixels.resize_surface(size.width, size.height) {
+                    log::error!("Could not resize the pack picker: {why}");
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.hover(tile_at(position, self.columns, self.tiles.len()))
+            }
+            WindowEvent::CursorLeft { .. } => self.hover(None),
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                if let Some(tile) = self.hovered.and_then(|index| self.tiles.get_mut(index)) {
+                    tile.picked = !tile.picked;
+                    self.window.request_redraw();
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Enter | KeyCode::NumpadEnter),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            }
+            | WindowEvent::CloseRequested => {
+                return Some(
+                    self.tiles
+                        .iter()
+                        .filter(|tile| tile.picked)
+                        .map(|tile| Arc::clone(&tile.data.name))
+                        .collect(),
+                );
+            }
+            _ => (),
+        }
+        None
+    }
+    fn hover(&mut self, hovered: Option<usize>) {
+        if self.hovered == hovered {
+            return;
+        }
+        self.hovered = hovered;
+        match hovered.and_then(|index| self.tiles.get(index)) {
+            Some(tile) => self
+                .window
+                .set_title(&format!("{} - {TITLE}", tile.data.name)),
+            None => self.window.set_title(TITLE),
+        }
+        self.window.request_redraw();
+    }
+    fn draw(&mut self) {
+        let Some((size, format)) = self.pixels.layout() else {
+            return;
+        };
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let stride = size.width as usize * bytes_per_pixel;
+        let color = |rgba: &[u8; 4]| Rgba::read_as(PixelFormat::Rgba8, rgba);
+        let background = color(&BACKGROUND);
+        let buffer = self.pixels.frame_mut();
+        for pixel in buffer.chunks_exact_mut(bytes_per_pixel) {
+            background.write_as(format, pixel);
+        }
+        let inner = BufferSize {
+            width: TILE - 2 * PADDING,
+            height: TILE - 2 * PADDING,
+        };
+        self.scratch.resize(
+            inner.width as usize * inner.height as usize * bytes_per_pixel,
+            0,
+        );
+        for (index, tile) in self.tiles.iter().enumerate() {
+            let left = (index % self.columns) as u32 * TILE;
+            let top = (index / self.columns) as u32 * TILE;
+            let fill = match (tile.picked, self.hovered == Some(index)) {
+                (true, _) => Some(color(&PICKED)),
+                (false, true) => Some(color(&HOVERED)),
+                (false, false) => None,
+            };
+            if let Some(fill) = fill {
+                for row in buffer
+                    .chunks_exact_mut(stride)
+                    .skip(top as usize)
+                    .take(TILE as usize)
+                {
+                    let right = ((left + TILE).min(size.width) as usize) * bytes_per_pixel;
+                    let start = (left as usize * bytes_per_pixel).min(right);
+                    for pixel in row[start..right].chunks_exact_mut(bytes_per_pixel) {
+                        fill.write_as(format, pixel);
+                    }
+                }
+            }
+            let Some(frame) = idle_frame(&tile.data) else {
+                continue;
+            };
+            blit(
+                frame,
+                &mut self.scratch,
+                inner,
+                format,
+                BlitPolicy::Fit(Filter::Nearest),
+                Facing::Left,
+                Effects::default(),
+            );
+            lay_over(
+                buffer,
+                size,
+                format,
+                &self.scratch,
+                PhysicalPosition::new((left + PADDING) as i32, (top + PADDING) as i32),
+                inner,
+            );
+        }
+        if let Err(why) = self.pixels.render() {
+            log::error!("Could not draw the pack picker: {why}");
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! The shimejis that were alive, saved so a restarted manager can bring them back.
+//!
+//! Stored as one shimeji name per line, oldest first.
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedPopulation {
+    path: PathBuf,
+}
+
+impl SavedPopulation {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+    /// The population saved at `SHIMEJI_POPULATION_FILE`, or `./population.conf` if it isn't set.
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var_os("SHIMEJI_POPULATION_FILE")
+                .unwrap_or(OsString::from("./population.conf")),
+        )
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Replace the saved population with `names`.
+    ///
+    /// The file is written next to the old one and moved over it,
+    /// so a crash halfway through leaves the old population intact.
+    ///
+    /// # Errors
+    /// Errors if the file can't be written.
+    pub fn save<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> anyhow::Result<()> {
+        let contents: String = names.into_iter().map(|name| format!("{name}\n")).collect();
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, contents)
+            .and_then(|()| fs::rename(&temporary, &self.path))
+            .with_context(|| format!("could not save population to {}", self.path.display()))
+    }
+    /// The names of the saved shimejis, oldest first. Nothing is saved if the file doesn't exist.
+    ///
+    /// # Errors
+    /// Errors if the file can't be read.
+    pub fn load(&self) -> anyhow::Result<Vec<String>> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(contents
+                .lines()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect()),
+            Err(why) if why.kind() == ErrorKind::NotFound => Ok(vec![]),
+            Err(why) => Err(why).with_context(|| format!("could not read {}", self.path.display())),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Noticing when the computer runs on battery, so shimejis can save power while it does
+//! if [`Settings::low_power`](crate::settings::Settings::low_power) is on.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use winit::event_loop::EventLoopProxy;
+
+use crate::ManagerEvent;
+
+/// Batteries don't come and go quickly, so there's no need to look often.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// How often animations are drawn while saving power.
+pub const LOW_POWER_FPS: f64 = 5.0;
+
+/// Check whether the computer runs on battery every [`POLL_INTERVAL`] on its own thread,
+/// sending [`ManagerEvent::OnBattery`] whenever it's unplugged or plugged back in,
+/// until `should_exit` is set.
+pub fn spawn(
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Battery thread"))
+        .spawn(move || poll_battery(proxy, should_exit))
+}
+
+fn poll_battery(proxy: EventLoopProxy<ManagerEvent>, should_exit: Arc<AtomicBool>) {
+    let mut last = false;
+    while !should_exit.load(Ordering::Relaxed) {
+        let on_battery = match on_battery() {
+            Ok(on_battery) => on_battery,
+            Err(why) => {
+                log::warn!("Could not tell whether this runs on battery: {why:#}");
+                return;
+            }
+        };
+        if on_battery != last {
+            if proxy
+                .send_event(ManagerEvent::OnBattery(on_battery))
+                .is_err()
+            {
+                return;
+            }
+            last = on_battery;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        /// Whether any battery the kernel knows about is discharging.
+        /// Desktops without one are never on battery.
+        fn on_battery() -> anyhow::Result<bool> {
+            let read = |path: std::path::PathBuf| {
+                std::fs::read_to_string(path).map(|contents| contents.trim().to_owned())
+            };
+            for supply in std::fs::read_dir("/sys/class/power_supply")? {
+                let supply = supply?.path();
+                if read(supply.join("type")).is_ok_and(|kind| kind == "Battery")
+                    && read(supply.join("status")).is_ok_and(|status| status == "Discharging")
+                {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+        /// Whether the AC line is offline, as `GetSystemPowerStatus` sees it.
+        fn on_battery() -> anyhow::Result<bool> {
+            // SAFETY: `SYSTEM_POWER_STATUS` is plain integers, for which zeroes are valid.
+            let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+            // SAFETY: `status` is a local the call only writes into.
+            if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+                anyhow::bail!("GetSystemPowerStatus failed: {}", std::io::Error::last_os_error());
+            }
+            Ok(status.ACLineStatus == 0)
+        }
+    } else if #[cfg(target_os = "macos")] {
+        /// Whether `pmset` says power is drawn from the battery.
+        fn on_battery() -> anyhow::Result<bool> {
+            let output = std::process::Command::new("pmset").args(["-g", "batt"]).output()?;
+            anyhow::ensure!(output.status.success(), "pmset exited with {}", output.status);
+            Ok(String::from_utf8_lossy(&output.stdout).contains("'Battery Power'"))
+        }
+    } else {
+        fn on_battery() -> anyhow::Result<bool> {
+            anyhow::bail!("battery state isn't known on this platform")
+        }
+    }
+}
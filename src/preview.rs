@@ -0,0 +1,323 @@
+//! `--preview <animation>` or `--preview <pack>/<animation>`: play one animation over and
+//! over in an ordinary window, for pack authors to check their art without the shimejis
+//! walking off with it. Nothing else starts, not even the tray.
+//!
+//! Under the frame, a strip shows each frame's share of the whole animation, with the one
+//! showing picked out. There's no font to draw with, so the title has the frame number and
+//! how long it shows for. Space pauses, the arrow keys step through frames while paused,
+//! and Escape closes the window.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use winit::{
+    application::ApplicationHandler,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{Window, WindowAttributes, WindowId},
+};
+
+use crate::{
+    blit::{blit, BlitPolicy, BufferSize, Effects, Facing},
+    loader::{AnimationData, ShimejiLibrary},
+    overlay::lay_over,
+    renderer::{self, Renderer},
+    rgba::{PixelFormat, Rgba},
+    shimeji::ShimejiData,
+};
+
+pub const FLAG: &str = "--preview";
+/// How tall the strip of frames under the animation is, in pixels.
+const STRIP: u32 = 12;
+/// So the strip stays readable under small frames.
+const MIN_WIDTH: u32 = 160;
+/// In [`PixelFormat::Rgba8`].
+const BACKGROUND: [u8; 4] = [32, 32, 40, 255];
+/// Every other frame in the strip is drawn in the second shade, to tell them apart.
+const FRAME_SHADES: [[u8; 4]; 2] = [[72, 72, 88, 255], [96, 96, 116, 255]];
+const SHOWING: [u8; 4] = [64, 112, 192, 255];
+
+/// The animation passed with `--preview <name>` or `--preview=<name>`, if any.
+pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if let Some(name) = arg.strip_prefix("--preview=") {
+            return Some(name.to_owned());
+        }
+        if arg == FLAG {
+            return args.next();
+        }
+    }
+    None
+}
+
+/// The pack and animation `name` means: `<pack>/<animation>`, or the first pack
+/// by name with an animation called `name`.
+///
+/// # Errors
+/// Errors if there's no such animation, or it's a sequence of other animations.
+pub fn find(library: &ShimejiLibrary, name: &str) -> anyhow::Result<(Arc<ShimejiData>, String)> {
+    let (data, animation) = match name.split_once('/') {
+        Some((pack, animation)) => {
+            let data = library
+                .get(pack)
+                .with_context(|| format!("there's no pack called {pack}"))?;
+            (data, animation)
+        }
+        None => {
+            let data = library
+                .names()
+                .iter()
+                .filter_map(|pack| library.get(pack))
+                .find(|data| data.animations.contains_key(name))
+                .with_context(|| format!("no pack has an animation called {name}"))?;
+            (data, name)
+        }
+    };
+    let found = data
+        .animations
+        .get(animation)
+        .with_context(|| format!("{} has no animation called {animation}", data.name))?;
+    anyhow::ensure!(
+        found.steps.is_empty(),
+        "{animation} is a sequence of other animations, preview those instead"
+    );
+    anyhow::ensure!(!found.frames.is_empty(), "{animation} has no frames");
+    Ok((Arc::clone(&data), animation.to_owned()))
+}
+
+/// The frame showing `at` into `animation`, the last one if it's over by then.
+pub fn frame_at(animation: &AnimationData, at: Duration) -> usize {
+    let mut start = Duration::ZERO;
+    for index in 0..animation.frames.len() {
+        start += animation.duration_of(index);
+        if at < start {
+            return index;
+        }
+    }
+    animation.frames.len().saturating_sub(1)
+}
+
+/// Open the preview window for the animation `name` means, see [`find`],
+/// and return once it's closed.
+///
+/// # Errors
+/// Errors if there's no such animation, or the window can't be made.
+pub fn run(library: &ShimejiLibrary, name: &str) -> anyhow::Result<()> {
+    let (data, animation) = find(library, name)?;
+    log::info!("Previewing {}/{animation}", data.name);
+    let event_loop = EventLoop::new()?;
+    let mut preview = Preview {
+        data,
+        animation,
+        surface: None,
+        index: 0,
+        shown_at: Instant::now(),
+        paused: false,
+        failed: None,
+    };
+    event_loop.run_app(&mut preview)?;
+    match preview.failed {
+        Some(why) => Err(why),
+        None => Ok(()),
+    }
+}
+
+#[derive(derive_more::Debug)]
+struct Surface {
+    window: Arc<Window>,
+    #[debug(skip)]
+    pixels: Box<dyn Renderer>,
+    /// What the frame is drawn into before it's laid over the background.
+    #[debug(skip)]
+    scratch: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Preview {
+    data: Arc<ShimejiData>,
+    animation: String,
+    surface: Option<Surface>,
+    /// The frame showing.
+    index: usize,
+    /// When it started showing.
+    shown_at: Instant,
+    paused: bool,
+    /// Why the window couldn't be made, if it couldn't.
+    failed: Option<anyhow::Error>,
+}
+
+impl Preview {
+    fn animation(&self) -> &AnimationData {
+        &self.data.animations[&self.animation]
+    }
+    /// Big enough for every frame, with the strip under them.
+    fn size(&self) -> PhysicalSize<u32> {
+        let frames = &self.animation().frames;
+        let width = frames.iter().map(|frame| frame.width).max().unwrap_or(0);
+        let height = frames.iter().map(|frame| frame.height).max().unwrap_or(0);
+        PhysicalSize::new(width.max(MIN_WIDTH), height + STRIP)
+    }
+    fn open(&mut self, event_loop: &ActiveEventLoop) -> anyhow::Result<()> {
+        let size = self.size();
+        let attributes = WindowAttributes::default()
+            .with_inner_size(size)
+            .with_min_inner_size(size);
+        let window = Arc::new(event_loop.create_window(attributes)?);
+        let pixels = renderer::create(&window, size.width, size.height)?;
+        self.surface = Some(Surface {
+            window,
+            pixels,
+            scratch: vec![],
+        });
+        self.show(0);
+        Ok(())
+    }
+    /// Show frame `index` from now on.
+    fn show(&mut self, index: usize) {
+        let count = self.animation().frames.len();
+        self.index = index % count;
+        self.shown_at = Instant::now();
+        let duration = self.animation().duration_of(self.index);
+        let Some(surface) = self.surface.as_ref() else {
+            return;
+        };
+        surface.window.set_title(&format!(
+            "{}/{}: frame {} of {count}, {} ms{}",
+            self.data.name,
+            self.animation,
+            self.index + 1,
+            duration.as_millis(),
+            if self.paused { " (paused)" } else { "" }
+        ));
+        surface.window.request_redraw();
+    }
+    fn draw(&mut self) {
+        let animation = &self.data.animations[&self.animation];
+        let Some(surface) = self.surface.as_mut() else {
+            return;
+        };
+        let Some((size, format)) = surface.pixels.layout() else {
+            return;
+        };
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let stride = size.width as usize * bytes_per_pixel;
+        let color = |rgba: &[u8; 4]| Rgba::read_as(PixelFormat::Rgba8, rgba);
+        let background = color(&BACKGROUND);
+        let buffer = surface.pixels.frame_mut();
+        for pixel in buffer.chunks_exact_mut(bytes_per_pixel) {
+            background.write_as(format, pixel);
+        }
+
+        let area = BufferSize {
+            width: size.width,
+            height: size.height.saturating_sub(STRIP),
+        };
+        surface.scratch.resize(
+            area.width as usize * area.height as usize * bytes_per_pixel,
+            0,
+        );
+        blit(
+            &animation.frames[self.index],
+            &mut surface.scratch,
+            area,
+            format,
+            BlitPolicy::Center,
+            Facing::Left,
+            Effects::default(),
+        );
+        lay_over(
+            buffer,
+            size,
+            format,
+            &surface.scratch,
+            PhysicalPosition::new(0, 0),
+            area,
+        );
+
+        // each column of the strip is the frame showing that far into the animation
+        let total: Duration = (0..animation.frames.len())
+            .map(|index| animation.duration_of(index))
+            .sum();
+        let columns: Vec<_> = (0..size.width)
+            .map(|x| {
+                let index = frame_at(animation, total.mul_f64(x as f64 / size.width as f64));
+                match index == self.index {
+                    true => color(&SHOWING),
+                    false => color(&FRAME_SHADES[index % FRAME_SHADES.len()]),
+                }
+            })
+            .collect();
+        for row in buffer.chunks_exact_mut(stride).skip(area.height as usize) {
+            for (pixel, column) in row.chunks_exact_mut(bytes_per_pixel).zip(columns.iter()) {
+                column.write_as(format, pixel);
+            }
+        }
+        if let Err(why) = surface.pixels.render() {
+            log::error!("Could not draw the preview: {why}");
+        }
+    }
+}
+
+impl ApplicationHandler for Preview {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.surface.is_some() {
+            return;
+        }
+        if let Err(why) = self.open(event_loop) {
+            self.failed = Some(why.context("could not open the preview window"));
+            event_loop.exit();
+        }
+    }
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::RedrawRequested => self.draw(),
+            WindowEvent::Resized(size) => {
+                if let Some(surface) = self.surface.as_mut() {
+                    if let Err(why) = surface.pixels.resize_surface(size.width, size.height) {
+                        log::error!("Could not resize the preview: {why}");
+                    }
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => match key {
+                KeyCode::Space => {
+                    self.paused = !self.paused;
+                    self.show(self.index);
+                }
+                KeyCode::ArrowRight if self.paused => self.show(self.index + 1),
+                KeyCode::ArrowLeft if self.paused => {
+                    let count = self.animation().frames.len();
+                    self.show(self.index + count - 1)
+                }
+                KeyCode::Escape => event_loop.exit(),
+                _ => (),
+            },
+            WindowEvent::CloseRequested => event_loop.exit(),
+            _ => (),
+        }
+    }
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.paused || self.surface.is_none() {
+            event_loop.set_control_flow(ControlFlow::Wait);
+            return;
+        }
+        let due = self.shown_at + self.animation().duration_of(self.index);
+        if Instant::now() >= due {
+            self.show(self.index + 1);
+        }
+        let due = self.shown_at + self.animation().duration_of(self.index);
+        event_loop.set_control_flow(ControlFlow::WaitUntil(due));
+    }
+}
@@ -0,0 +1,285 @@
+//! `--record <file>` writes down every shimeji's spawn, with the seed its behaviors choose
+//! by, and every behavior it goes into, with where it was. `--replay <file>` spawns the
+//! same shimejis with the same seeds, and puts them through the same behaviors at the same
+//! places and times, so a report like "it got stuck off-screen" can be watched again.
+//!
+//! A recording is one line per thing that happened, seconds since the start first:
+//! `1.250 3 spawn 12345 100,900 Shimeji`, `4.000 3 enter 140,900 Walk` or `9.5 3 despawn`,
+//! where `3` numbers the shimeji within the recording.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{LineWriter, Write as _},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use derive_more::derive::{Display, Error};
+use winit::{dpi::PhysicalPosition, event_loop::EventLoopProxy, window::WindowId};
+
+use crate::{rng::Rng, settings::create_parent, ManagerEvent};
+
+pub const RECORD_FLAG: &str = "--record";
+pub const REPLAY_FLAG: &str = "--replay";
+/// The longest the replay thread sleeps at once, so it notices it should stop.
+const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Display, Error)]
+pub enum InvalidEntry {
+    #[display("{line:?} should be <seconds> <shimeji> spawn|enter|despawn ...")]
+    Malformed { line: String },
+    #[display("{value:?} is not <x>,<y>")]
+    Position { value: String },
+}
+
+/// Whether to record this run or replay an earlier one, and the file to do it with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+impl Mode {
+    /// `--record <file>` or `--replay <file>`, or either with `=<file>`, if given.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        while let Some(arg) = args.next() {
+            for (flag, mode) in [
+                (RECORD_FLAG, Self::Record as fn(PathBuf) -> Self),
+                (REPLAY_FLAG, Self::Replay),
+            ] {
+                match arg.strip_prefix(flag) {
+                    Some("") => return args.next().map(|path| mode(path.into())),
+                    Some(path) if path.starts_with('=') => return Some(mode(path[1..].into())),
+                    _ => (),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// What happened to a shimeji.
+#[derive(Debug, Clone, PartialEq)]
+pub enum What {
+    Spawn {
+        /// What its behaviors choose by.
+        seed: u64,
+        position: PhysicalPosition<i32>,
+        pack: Arc<str>,
+    },
+    Enter {
+        position: PhysicalPosition<i32>,
+        behavior: String,
+    },
+    Despawn,
+}
+
+/// One line of a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Since the recording started.
+    pub at: Duration,
+    /// Which shimeji of the recording, counting from 0 in the order they spawned.
+    pub shimeji: usize,
+    pub what: What,
+}
+
+fn parse_position(value: &str) -> Result<PhysicalPosition<i32>, InvalidEntry> {
+    value
+        .split_once(',')
+        .and_then(|(x, y)| Some(PhysicalPosition::new(x.parse().ok()?, y.parse().ok()?)))
+        .ok_or_else(|| InvalidEntry::Position {
+            value: value.to_owned(),
+        })
+}
+
+impl FromStr for Entry {
+    type Err = InvalidEntry;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let malformed = || InvalidEntry::Malformed {
+            line: line.to_owned(),
+        };
+        // names go last, and can have spaces in them
+        let mut words = line.trim().splitn(4, ' ');
+        let (Some(at), Some(shimeji), Some(kind)) = (words.next(), words.next(), words.next())
+        else {
+            return Err(malformed());
+        };
+        let at = at
+            .parse()
+            .ok()
+            .and_then(|at| Duration::try_from_secs_f64(at).ok())
+            .ok_or_else(malformed)?;
+        let shimeji = shimeji.parse().map_err(|_| malformed())?;
+        let rest = words.next().unwrap_or_default();
+        let what = match kind {
+            "spawn" => {
+                let mut rest = rest.splitn(3, ' ');
+                let (Some(seed), Some(position), Some(pack)) =
+                    (rest.next(), rest.next(), rest.next())
+                else {
+                    return Err(malformed());
+                };
+                What::Spawn {
+                    seed: seed.parse().map_err(|_| malformed())?,
+                    position: parse_position(position)?,
+                    pack: pack.into(),
+                }
+            }
+            "enter" => {
+                let Some((position, behavior)) = rest.split_once(' ') else {
+                    return Err(malformed());
+                };
+                What::Enter {
+                    position: parse_position(position)?,
+                    behavior: behavior.to_owned(),
+                }
+            }
+            "despawn" if rest.is_empty() => What::Despawn,
+            _ => return Err(malformed()),
+        };
+        Ok(Self { at, shimeji, what })
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3} {} ", self.at.as_secs_f64(), self.shimeji)?;
+        match &self.what {
+            What::Spawn {
+                seed,
+                position,
+                pack,
+            } => write!(f, "spawn {seed} {},{} {pack}", position.x, position.y),
+            What::Enter { position, behavior } => {
+                write!(f, "enter {},{} {behavior}", position.x, position.y)
+            }
+            What::Despawn => write!(f, "despawn"),
+        }
+    }
+}
+
+/// Every entry recorded in `path`, in order.
+///
+/// # Errors
+/// Errors if it can't be read, or a line isn't an [`Entry`].
+pub fn load(path: &Path) -> anyhow::Result<Vec<Entry>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read the recording {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            line.parse()
+                .with_context(|| format!("line {} of {}", number + 1, path.display()))
+        })
+        .collect()
+}
+
+/// Writes down what happens to the shimejis as it happens, kept by the manager.
+#[derive(derive_more::Debug)]
+pub struct Recorder {
+    #[debug(skip)]
+    file: LineWriter<File>,
+    started: Instant,
+    /// Each shimeji's number in the recording, by window.
+    numbers: HashMap<WindowId, usize>,
+    rng: Rng,
+}
+
+impl Recorder {
+    /// Start a recording at `path`, replacing any already there.
+    ///
+    /// # Errors
+    /// Errors if the file can't be made.
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        create_parent(path)?;
+        let file = File::create(path)
+            .with_context(|| format!("could not record to {}", path.display()))?;
+        Ok(Self {
+            file: LineWriter::new(file),
+            started: Instant::now(),
+            numbers: HashMap::new(),
+            rng: Rng::new(),
+        })
+    }
+    /// Write down that the shimeji in window `id` spawned, returning the seed it should
+    /// choose its behaviors by.
+    pub fn spawned(
+        &mut self,
+        id: WindowId,
+        pack: &Arc<str>,
+        position: PhysicalPosition<i32>,
+    ) -> u64 {
+        let shimeji = self.numbers.len();
+        self.numbers.insert(id, shimeji);
+        let seed = self.rng.next_u64();
+        self.write(
+            shimeji,
+            What::Spawn {
+                seed,
+                position,
+                pack: Arc::clone(pack),
+            },
+        );
+        seed
+    }
+    pub fn entered(&mut self, id: WindowId, behavior: String, position: PhysicalPosition<i32>) {
+        if let Some(shimeji) = self.numbers.get(&id).copied() {
+            self.write(shimeji, What::Enter { position, behavior });
+        }
+    }
+    pub fn despawned(&mut self, id: WindowId) {
+        if let Some(shimeji) = self.numbers.remove(&id) {
+            self.write(shimeji, What::Despawn);
+        }
+    }
+    fn write(&mut self, shimeji: usize, what: What) {
+        let entry = Entry {
+            at: self.started.elapsed(),
+            shimeji,
+            what,
+        };
+        // written line by line, so a crash loses nothing before it
+        if let Err(why) = writeln!(self.file, "{entry}") {
+            log::error!("Could not record {entry}: {why}");
+        }
+    }
+}
+
+/// Send the manager each of `entries` when it's due, counting from now, until they run out
+/// or `should_exit` is set.
+pub fn spawn_replay(
+    entries: Vec<Entry>,
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Replay thread"))
+        .spawn(move || {
+            let started = Instant::now();
+            for entry in entries {
+                while let Some(left) = entry.at.checked_sub(started.elapsed()) {
+                    if should_exit.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    thread::sleep(left.min(MAX_SLEEP));
+                }
+                if proxy.send_event(ManagerEvent::Replay(entry)).is_err() {
+                    return;
+                }
+            }
+            log::info!("Replayed the whole recording");
+        })
+}
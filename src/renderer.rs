@@ -0,0 +1,541 @@
+//! What draws a window's pixel buffer to the screen, chosen with `--renderer <name>`
+//! or `SHIMEJI_RENDERER`:
+//! - `pixels`, the default, draws through the GPU with wgpu.
+//! - `software` copies the buffer to the window on the CPU, for systems without
+//!   usable GPU drivers such as VMs. It only works on X11, and draws the buffer
+//!   unscaled in the middle of the window.
+//!
+//! If the chosen renderer can't draw to a window, the other one is tried.
+
+use std::{
+    str::FromStr,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
+
+use derive_more::derive::{Display, Error, From};
+use pixels::{wgpu::SurfaceError, Pixels, PixelsBuilder, SurfaceTexture, TextureError};
+use winit::window::Window;
+
+use crate::{
+    blit::BufferSize,
+    log_throttle::{once, throttled},
+    rgba::{PixelFormat, Rgba},
+};
+
+/// Set by [`choose`], see [`RendererKind::from_args`].
+static KIND: OnceLock<RendererKind> = OnceLock::new();
+/// How long a surface that couldn't be brought back is left before trying again,
+/// doubling with every failure in a row up to [`BACKOFF_MAX`].
+const BACKOFF_START: Duration = Duration::from_millis(100);
+const BACKOFF_MAX: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RendererKind {
+    #[default]
+    Pixels,
+    Software,
+}
+
+#[derive(Debug, Display, Error)]
+#[display("unknown renderer {name:?}, expected pixels or software")]
+pub struct UnknownRenderer {
+    name: String,
+}
+
+impl FromStr for RendererKind {
+    type Err = UnknownRenderer;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pixels" | "gpu" => Ok(Self::Pixels),
+            "software" | "cpu" => Ok(Self::Software),
+            _ => Err(UnknownRenderer { name: s.to_owned() }),
+        }
+    }
+}
+
+impl RendererKind {
+    /// The renderer passed with `--renderer <name>` or `--renderer=<name>`,
+    /// or else set in `SHIMEJI_RENDERER`, if any.
+    pub fn from_args(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<Option<Self>, UnknownRenderer> {
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--renderer=") {
+                return name.parse().map(Some);
+            }
+            if arg == "--renderer" {
+                let name = args.next().unwrap_or_default();
+                return name.parse().map(Some);
+            }
+        }
+        match std::env::var("SHIMEJI_RENDERER") {
+            Ok(name) => name.parse().map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+    fn other(self) -> Self {
+        match self {
+            Self::Pixels => Self::Software,
+            Self::Software => Self::Pixels,
+        }
+    }
+}
+
+/// Draw every window created from now on with `kind`. Only the first choice counts.
+pub fn choose(kind: RendererKind) {
+    if KIND.set(kind).is_err() {
+        log::warn!("A renderer was already chosen, not switching to {kind:?}");
+    }
+}
+
+#[derive(Debug, Display, Error, From)]
+pub enum RenderError {
+    #[display("{_0}")]
+    Pixels(pixels::Error),
+    #[display("{_0}")]
+    Texture(TextureError),
+    #[display("{_0}")]
+    Software(#[error(not(source))] String),
+}
+
+/// A window's pixel buffer, and what draws it to the window.
+pub trait Renderer: Send + Sync + std::fmt::Debug {
+    /// The buffer, `size().width * size().height` pixels in `format()`, row by row.
+    fn frame_mut(&mut self) -> &mut [u8];
+    /// The size of the buffer and the byte order to write it in,
+    /// or `None` if that's a format frames can't be drawn in.
+    fn layout(&self) -> Option<(BufferSize, PixelFormat)>;
+    /// Follow the window to its new size.
+    fn resize_surface(&mut self, width: u32, height: u32) -> Result<(), RenderError>;
+    fn resize_buffer(&mut self, width: u32, height: u32) -> Result<(), RenderError>;
+    /// What the window shows around the buffer, transparent if `None`.
+    fn set_clear_color(&mut self, color: Option<Rgba>);
+    /// Show the buffer on the window.
+    fn render(&mut self) -> Result<(), RenderError>;
+}
+
+/// A `width` by `height` buffer for `window`, drawn by the renderer from [`choose`],
+/// or the other one if that can't draw to it.
+///
+/// # Errors
+/// Errors with why the other one couldn't either if neither renderer can draw to the window.
+pub fn create(
+    window: &Arc<Window>,
+    width: u32,
+    height: u32,
+) -> Result<Box<dyn Renderer + 'static>, RenderError> {
+    let kind = KIND.get().copied().unwrap_or_default();
+    match create_with(kind, window, width, height) {
+        Ok(renderer) => Ok(renderer),
+        Err(why) => {
+            let other = kind.other();
+            once!(
+                format!("renderer {kind:?}"),
+                log::Level::Warn,
+                "Could not draw with the {kind:?} renderer, trying {other:?}: {why}"
+            );
+            create_with(other, window, width, height)
+        }
+    }
+}
+
+fn create_with(
+    kind: RendererKind,
+    window: &Arc<Window>,
+    width: u32,
+    height: u32,
+) -> Result<Box<dyn Renderer>, RenderError> {
+    Ok(match kind {
+        RendererKind::Pixels => Box::new(PixelsRenderer::new(window, width, height)?),
+        RendererKind::Software => Box::new(software::SoftwareRenderer::new(window, width, height)?),
+    })
+}
+
+/// Draws through the GPU with [`pixels`].
+///
+/// Frames are drawn with straight alpha, and premultiplied as they're handed to
+/// the GPU, which blends them as premultiplied. Blending straight alpha instead
+/// leaves edges fringed with whatever color the transparent pixels around them have.
+///
+/// A surface lost or outdated, e.g. after resuming from suspend or switching GPUs,
+/// is fitted to the window again, or else built again from scratch.
+#[derive(derive_more::Debug)]
+pub struct PixelsRenderer {
+    pixels: Pixels<'static>,
+    /// What's drawn into, with straight alpha.
+    #[debug(skip)]
+    buffer: Vec<u8>,
+    window: Arc<Window>,
+    clear_color: pixels::wgpu::Color,
+    backoff: Backoff,
+}
+
+impl PixelsRenderer {
+    pub fn new(window: &Arc<Window>, width: u32, height: u32) -> Result<Self, RenderError> {
+        let pixels = Self::build(window, width, height)?;
+        let buffer = pixels.frame().to_vec();
+        Ok(Self {
+            pixels,
+            buffer,
+            window: Arc::clone(window),
+            clear_color: pixels::wgpu::Color::TRANSPARENT,
+            backoff: Backoff::default(),
+        })
+    }
+    fn build(
+        window: &Arc<Window>,
+        width: u32,
+        height: u32,
+    ) -> Result<Pixels<'static>, RenderError> {
+        let window_size = window.inner_size();
+        let surface_texture =
+            SurfaceTexture::new(window_size.width, window_size.height, Arc::clone(window));
+        Ok(PixelsBuilder::new(width, height, surface_texture)
+            .blend_state(pixels::wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+            .build()?)
+    }
+    /// Premultiply the buffer into the surface's, and show it.
+    fn present(&mut self) -> Result<(), pixels::Error> {
+        // both of the formats pixels draws are four bytes with alpha last
+        for (straight, premultiplied) in self
+            .buffer
+            .chunks_exact(4)
+            .zip(self.pixels.frame_mut().chunks_exact_mut(4))
+        {
+            Rgba::read_as(PixelFormat::Rgba8, straight)
+                .premultiplied()
+                .write_as(PixelFormat::Rgba8, premultiplied);
+        }
+        self.pixels.render()
+    }
+    /// Bring a lost or outdated surface back, and show the buffer on it.
+    fn recover(&mut self) -> Result<(), RenderError> {
+        // pixels already configured it again at the size it thought the window was
+        let size = self.window.inner_size();
+        if self.pixels.resize_surface(size.width, size.height).is_ok() && self.present().is_ok() {
+            return Ok(());
+        }
+        let extent = self.pixels.context().texture_extent;
+        self.pixels = Self::build(&self.window, extent.width, extent.height)?;
+        self.pixels.clear_color(self.clear_color);
+        Ok(self.present()?)
+    }
+}
+
+/// When to try again after failing to draw, see [`BACKOFF_START`].
+#[derive(Debug, Default)]
+pub struct Backoff {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    /// Whether it's time to try again.
+    pub fn ready(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| now >= retry_at)
+    }
+    /// Wait longer than last time before trying again, returning how long.
+    pub fn failed(&mut self, now: Instant) -> Duration {
+        let delay = BACKOFF_START
+            .saturating_mul(1 << self.failures.min(16))
+            .min(BACKOFF_MAX);
+        self.failures += 1;
+        self.retry_at = Some(now + delay);
+        delay
+    }
+    pub fn succeeded(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl Renderer for PixelsRenderer {
+    fn frame_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+    fn layout(&self) -> Option<(BufferSize, PixelFormat)> {
+        let context = self.pixels.context();
+        let format = context.texture_format;
+        let Some(format) = PixelFormat::from_texture_format(format) else {
+            throttled!(log::Level::Error, "Unsupported texture format {format:?}");
+            return None;
+        };
+        let size = BufferSize {
+            width: context.texture_extent.width,
+            height: context.texture_extent.height,
+        };
+        Some((size, format))
+    }
+    fn resize_surface(&mut self, width: u32, height: u32) -> Result<(), RenderError> {
+        Ok(self.pixels.resize_surface(width, height)?)
+    }
+    fn resize_buffer(&mut self, width: u32, height: u32) -> Result<(), RenderError> {
+        self.pixels.resize_buffer(width, height)?;
+        self.buffer = self.pixels.frame().to_vec();
+        Ok(())
+    }
+    fn set_clear_color(&mut self, color: Option<Rgba>) {
+        self.clear_color = color.map_or(pixels::wgpu::Color::TRANSPARENT, |color| {
+            color.premultiplied().to_wgpu()
+        });
+        self.pixels.clear_color(self.clear_color);
+    }
+    fn render(&mut self) -> Result<(), RenderError> {
+        let now = Instant::now();
+        // the window keeps its last frame until the surface is back
+        if !self.backoff.ready(now) {
+            return Ok(());
+        }
+        let recovered = match self.present() {
+            Ok(()) => Ok(()),
+            Err(pixels::Error::Surface(SurfaceError::Lost | SurfaceError::Outdated)) => {
+                self.recover()
+            }
+            Err(why) => return Err(why.into()),
+        };
+        match &recovered {
+            Ok(()) => self.backoff.succeeded(),
+            Err(why) => {
+                let delay = self.backoff.failed(now);
+                throttled!(
+                    log::Level::Warn,
+                    "Could not bring a surface back, trying again in {delay:?}: {why}"
+                );
+            }
+        }
+        recovered
+    }
+}
+
+/// Copy the `size` buffer of straight alpha `Bgra8` pixels into the middle of a `surface`
+/// sized image, premultiplying them as compositors expect, with `clear` around it.
+pub fn premultiplied_image(
+    buffer: &[u8],
+    size: BufferSize,
+    surface: BufferSize,
+    clear: Option<Rgba>,
+) -> Vec<u8> {
+    let premultiply = |pixel: &[u8]| {
+        let mut bytes = [0; 4];
+        Rgba::read_as(PixelFormat::Bgra8, pixel)
+            .premultiplied()
+            .write_as(PixelFormat::Bgra8, &mut bytes);
+        bytes
+    };
+    let mut clear_bytes = [0; 4];
+    clear
+        .unwrap_or(Rgba::new(0, 0, 0, 0))
+        .write_as(PixelFormat::Bgra8, &mut clear_bytes);
+    let clear_pixel = premultiply(&clear_bytes);
+    let mut image: Vec<u8> = clear_pixel
+        .iter()
+        .copied()
+        .cycle()
+        .take(surface.width as usize * surface.height as usize * 4)
+        .collect();
+    let left = (surface.width as i64 - size.width as i64) / 2;
+    let top = (surface.height as i64 - size.height as i64) / 2;
+    let stride = (size.width as usize * 4).max(4);
+    for (y, row) in buffer.chunks_exact(stride).enumerate() {
+        let target_y = y as i64 + top;
+        if !(0..surface.height as i64).contains(&target_y) {
+            continue;
+        }
+        for (x, pixel) in row.chunks_exact(4).enumerate() {
+            let target_x = x as i64 + left;
+            if !(0..surface.width as i64).contains(&target_x) {
+                continue;
+            }
+            let at = (target_y as usize * surface.width as usize + target_x as usize) * 4;
+            image[at..at + 4].copy_from_slice(&premultiply(pixel));
+        }
+    }
+    image
+}
+
+#[cfg(target_os = "linux")]
+mod software {
+    use std::sync::{Arc, LazyLock};
+
+    use winit::{
+        raw_window_handle::{HasWindowHandle, RawWindowHandle},
+        window::Window,
+    };
+    use x11rb::{
+        connection::{Connection, RequestConnection},
+        protocol::xproto::{ConnectionExt as _, CreateGCAux, Gcontext, ImageFormat, ImageOrder},
+        rust_connection::RustConnection,
+    };
+
+    use super::{premultiplied_image, RenderError, Renderer};
+    use crate::{
+        blit::BufferSize,
+        rgba::{PixelFormat, Rgba},
+    };
+
+    /// Shared by every window drawn in software, `None` if there's no X server to talk to.
+    static CONNECTION: LazyLock<Option<RustConnection>> = LazyLock::new(|| {
+        x11rb::connect(None)
+            .inspect_err(|why| log::warn!("Could not connect to X for software rendering: {why}"))
+            .ok()
+            .map(|(connection, _)| connection)
+    });
+
+    fn error(why: impl std::fmt::Display) -> RenderError {
+        RenderError::Software(why.to_string())
+    }
+
+    /// Copies the buffer to the window with X11's `PutImage`.
+    #[derive(Debug)]
+    pub struct SoftwareRenderer {
+        window: u32,
+        gc: Gcontext,
+        depth: u8,
+        buffer: Vec<u8>,
+        size: BufferSize,
+        surface: BufferSize,
+        clear: Option<Rgba>,
+    }
+
+    impl SoftwareRenderer {
+        pub fn new(window: &Arc<Window>, width: u32, height: u32) -> Result<Self, RenderError> {
+            let connection = CONNECTION
+                .as_ref()
+                .ok_or_else(|| error("there's no X server to draw to"))?;
+            if connection.setup().image_byte_order != ImageOrder::LSB_FIRST {
+                return Err(error("the X server wants big endian images"));
+            }
+            let handle = window.window_handle().map_err(error)?;
+            let window_id = match handle.as_raw() {
+                RawWindowHandle::Xlib(handle) => handle.window as u32,
+                RawWindowHandle::Xcb(handle) => handle.window.get(),
+                _ => return Err(error("software rendering only works on X11")),
+            };
+            let geometry = connection
+                .get_geometry(window_id)
+                .map_err(error)?
+                .reply()
+                .map_err(error)?;
+            let gc = connection.generate_id().map_err(error)?;
+            connection
+                .create_gc(gc, window_id, &CreateGCAux::new())
+                .map_err(error)?;
+            let surface = window.inner_size();
+            Ok(Self {
+                window: window_id,
+                gc,
+                depth: geometry.depth,
+                buffer: vec![0; width as usize * height as usize * 4],
+                size: BufferSize { width, height },
+                surface: BufferSize {
+                    width: surface.width,
+                    height: surface.height,
+                },
+                clear: None,
+            })
+        }
+    }
+
+    impl Renderer for SoftwareRenderer {
+        fn frame_mut(&mut self) -> &mut [u8] {
+            &mut self.buffer
+        }
+        fn layout(&self) -> Option<(BufferSize, PixelFormat)> {
+            Some((self.size, PixelFormat::Bgra8))
+        }
+        fn resize_surface(&mut self, width: u32, height: u32) -> Result<(), RenderError> {
+            self.surface = BufferSize { width, height };
+            Ok(())
+        }
+        fn resize_buffer(&mut self, width: u32, height: u32) -> Result<(), RenderError> {
+            self.size = BufferSize { width, height };
+            self.buffer = vec![0; width as usize * height as usize * 4];
+            Ok(())
+        }
+        fn set_clear_color(&mut self, color: Option<Rgba>) {
+            self.clear = color;
+        }
+        fn render(&mut self) -> Result<(), RenderError> {
+            let Some(connection) = CONNECTION.as_ref() else {
+                return Err(error("there's no X server to draw to"));
+            };
+            let image = premultiplied_image(&self.buffer, self.size, self.surface, self.clear);
+            let stride = self.surface.width as usize * 4;
+            if stride == 0 {
+                return Ok(());
+            }
+            // big images are sent a band of rows at a time, to fit in a request
+            let rows_per_request = ((connection.maximum_request_bytes() - 64) / stride).max(1);
+            for (band, rows) in image.chunks(rows_per_request * stride).enumerate() {
+                connection
+                    .put_image(
+                        ImageFormat::Z_PIXMAP,
+                        self.window,
+                        self.gc,
+                        self.surface.width as u16,
+                        (rows.len() / stride) as u16,
+                        0,
+                        (band * rows_per_request) as i16,
+                        0,
+                        self.depth,
+                        rows,
+                    )
+                    .map_err(error)?;
+            }
+            connection.flush().map_err(error)?;
+            Ok(())
+        }
+    }
+
+    impl Drop for SoftwareRenderer {
+        fn drop(&mut self) {
+            if let Some(connection) = CONNECTION.as_ref() {
+                let _ = connection.free_gc(self.gc);
+                let _ = connection.flush();
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod software {
+    use std::sync::Arc;
+
+    use winit::window::Window;
+
+    use super::{RenderError, Renderer};
+
+    /// Software rendering isn't supported here yet, so [`super::create`] falls back to pixels.
+    #[derive(Debug)]
+    pub enum SoftwareRenderer {}
+
+    impl SoftwareRenderer {
+        pub fn new(_window: &Arc<Window>, _width: u32, _height: u32) -> Result<Self, RenderError> {
+            Err(RenderError::Software(String::from(
+                "software rendering only works on X11",
+            )))
+        }
+    }
+
+    impl Renderer for SoftwareRenderer {
+        fn frame_mut(&mut self) -> &mut [u8] {
+            match *self {}
+        }
+        fn layout(&self) -> Option<(crate::blit::BufferSize, crate::rgba::PixelFormat)> {
+            match *self {}
+        }
+        fn resize_surface(&mut self, _width: u32, _height: u32) -> Result<(), RenderError> {
+            match *self {}
+        }
+        fn resize_buffer(&mut self, _width: u32, _height: u32) -> Result<(), RenderError> {
+            match *self {}
+        }
+        fn set_clear_color(&mut self, _color: Option<crate::rgba::Rgba>) {
+            match *self {}
+        }
+        fn render(&mut self) -> Result<(), RenderError> {
+            match *self {}
+        }
+    }
+}
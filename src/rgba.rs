@@ -1,6 +1,8 @@
 use std::fmt::Debug;
 
+// four bytes in this order, for the pixels mapped from a frame store
 #[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
 pub struct Rgba {
     pub red: u8,
     pub green: u8,
@@ -53,6 +55,104 @@ impl Rgba {
         }
     }
 
+    /// Parse `#rrggbb` or `#rrggbbaa`.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#')?;
+        if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |index: usize| {
+            hex.get(index * 2..index * 2 + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+        };
+        Some(Self::new(
+            channel(0)?,
+            channel(1)?,
+            channel(2)?,
+            if hex.len() == 8 { channel(3)? } else { 255 },
+        ))
+    }
+    /// This color as `wgpu` wants it, e.g. for a clear color.
+    pub fn to_wgpu(self) -> pixels::wgpu::Color {
+        let channel = |value: u8| value as f64 / 255.0;
+        pixels::wgpu::Color {
+            r: channel(self.red),
+            g: channel(self.green),
+            b: channel(self.blue),
+            a: channel(self.alpha),
+        }
+    }
+    /// This color with red, green and blue scaled by its alpha,
+    /// as compositors and wgpu's premultiplied blending expect.
+    pub fn premultiplied(self) -> Rgba {
+        let alpha = self.alpha as u32;
+        let channel = |value: u8| ((value as u32 * alpha + 127) / 255) as u8;
+        Rgba::new(
+            channel(self.red),
+            channel(self.green),
+            channel(self.blue),
+            self.alpha,
+        )
+    }
+    /// The color this one is [`Rgba::premultiplied`] from, for images saved premultiplied.
+    /// Fully transparent colors come out transparent black.
+    pub fn unpremultiplied(self) -> Rgba {
+        let alpha = self.alpha as u32;
+        if alpha == 0 {
+            return Rgba::new(0, 0, 0, 0);
+        }
+        let channel = |value: u8| ((value as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+        Rgba::new(
+            channel(self.red),
+            channel(self.green),
+            channel(self.blue),
+            self.alpha,
+        )
+    }
+    /// This color drawn over `below`, as APNG's `APNG_BLEND_OP_OVER` does it.
+    pub fn over(self, below: Rgba) -> Rgba {
+        let (alpha_above, alpha_below) = (self.alpha as u32, below.alpha as u32);
+        let alpha = alpha_above * 255 + alpha_below * (255 - alpha_above);
+        if alpha == 0 {
+            return Rgba::new(0, 0, 0, 0);
+        }
+        let mix = |above: u8, below: u8| {
+            ((above as u32 * alpha_above * 255 + below as u32 * alpha_below * (255 - alpha_above))
+                / alpha) as u8
+        };
+        Rgba::new(
+            mix(self.red, below.red),
+            mix(self.green, below.green),
+            mix(self.blue, below.blue),
+            (alpha / 255) as u8,
+        )
+    }
+
+    /// Write this color into `out` in the byte order of `format`.
+    ///
+    /// `out` must be exactly [`PixelFormat::bytes_per_pixel`] long.
+    pub fn write_as(self, format: PixelFormat, out: &mut [u8]) {
+        let bytes = match format {
+            PixelFormat::Rgba8 => [self.red, self.green, self.blue, self.alpha],
+            PixelFormat::Bgra8 => [self.blue, self.green, self.red, self.alpha],
+            PixelFormat::Softbuffer0rgb => self.to_softbuf_u32().to_ne_bytes(),
+        };
+        out.copy_from_slice(&bytes);
+    }
+
+    /// Read a color written with [`Rgba::write_as`] back out of `bytes`.
+    pub fn read_as(format: PixelFormat, bytes: &[u8]) -> Self {
+        match format {
+            PixelFormat::Rgba8 => Rgba::new(bytes[0], bytes[1], bytes[2], bytes[3]),
+            PixelFormat::Bgra8 => Rgba::new(bytes[2], bytes[1], bytes[0], bytes[3]),
+            PixelFormat::Softbuffer0rgb => {
+                let value = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                let alpha = if value == 0 { 0 } else { 255 };
+                Rgba::new((value >> 16) as u8, (value >> 8) as u8, value as u8, alpha)
+            }
+        }
+    }
+
     /// --------
     ///
     /// Pixel format (`u32`):
@@ -63,28 +163,43 @@ impl Rgba {
     /// R: Red channel
     /// G: Green channel
     /// B: Blue channel
+    ///
+    /// softbuffer has no alpha channel, so fully transparent pixels become black.
     pub fn to_softbuf_u32(self) -> u32 {
         if self.alpha == 0 {
             return 0;
         }
-        // // println!("self.alpha as f32: {}", self.alpha as f32);
-        // let alpha = self.alpha as f32 / 255.0;
-        // let red = (self.red as f32 * alpha).floor() as u32;
-        // let blue = (self.blue as f32 * alpha).floor() as u32;
-        // let green = (self.green as f32 * alpha).floor() as u32;
-        // // println!("red: {}", red);
-        // // println!("green: {}", green);
-        // // println!("blue: {}", blue);
-        // // println!("alpha: {}", alpha);
-
-        // let ret = (red << 16) | (blue << 8) | green;
+        (self.red as u32) << 16 | (self.green as u32) << 8 | self.blue as u32
+    }
+}
 
-        // // println!("ret: {:#034b}", ret);
-        // ret
+/// The byte order a surface expects its pixels in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PixelFormat {
+    /// What `pixels` uses unless told otherwise.
+    #[default]
+    Rgba8,
+    Bgra8,
+    /// softbuffer's native-endian `u32`, see [`Rgba::to_softbuf_u32`].
+    Softbuffer0rgb,
+}
 
-        (self.alpha as u32) << 24
-            | (self.red as u32) << 16
-            | (self.green as u32) << 8
-            | self.blue as u32
+impl PixelFormat {
+    /// The format to write into a `pixels` buffer with this texture format,
+    /// if it's one we know how to fill.
+    pub fn from_texture_format(format: pixels::wgpu::TextureFormat) -> Option<Self> {
+        use pixels::wgpu::TextureFormat::*;
+        match format {
+            Rgba8Unorm | Rgba8UnormSrgb => Some(Self::Rgba8),
+            Bgra8Unorm | Bgra8UnormSrgb => Some(Self::Bgra8),
+            _ => None,
+        }
+    }
+    pub fn bytes_per_pixel(self) -> usize {
+        4
+    }
+    /// Whether see-through pixels stay see-through, rather than turning black.
+    pub fn has_alpha(self) -> bool {
+        self != Self::Softbuffer0rgb
     }
 }
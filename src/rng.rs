@@ -0,0 +1,66 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/// A small, fast, non-cryptographic random number generator (xorshift64*).
+///
+/// Every shimeji gets its own, so bucket threads never have to share one.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rng {
+    /// Seeded from the same per-process randomness `HashMap` uses.
+    pub fn new() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0);
+        Self::with_seed(hasher.finish())
+    }
+    pub fn with_seed(seed: u64) -> Self {
+        // xorshift gets stuck on 0
+        Self { state: seed.max(1) }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    /// A number in `0.0..1.0`.
+    pub fn f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+    /// Pick one of `choices` with a probability proportional to its weight.
+    ///
+    /// Returns `None` if there are no choices with a positive weight.
+    pub fn choose_weighted<'a, T>(&mut self, choices: &'a [(T, f64)]) -> Option<&'a T> {
+        let total: f64 = choices.iter().map(|(_, weight)| weight.max(0.0)).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut roll = self.f64() * total;
+        for (choice, weight) in choices {
+            let weight = weight.max(0.0);
+            if roll < weight {
+                return Some(choice);
+            }
+            roll -= weight;
+        }
+        // floating point error can leave us just past the end
+        choices
+            .iter()
+            .rev()
+            .find(|(_, weight)| *weight > 0.0)
+            .map(|(choice, _)| choice)
+    }
+}
@@ -0,0 +1,309 @@
+//! Little Lua scripts behaviors run to decide things for themselves, from a `script` attribute:
+//!
+//! ```lua
+//! if every(2) then set_velocity(random(-120, 120), 0) end
+//! local x, y = position()
+//! if x < 100 then animation("run") elseif time() > 10 then behavior("sit") end
+//! ```
+//!
+//! A script runs from top to bottom every time its shimeji updates, in a Lua 5.4 state of
+//! its own that lasts until the shimeji changes behavior, so globals it sets are still
+//! there the next time. Along with Lua's `math`, `string` and `table` libraries it has:
+//! - `position()` and `velocity()`, the shimeji's x and y and how fast it moves along them,
+//!   in pixels per second.
+//! - `time()`, how many seconds the shimeji has been in the behavior.
+//! - `set_velocity(vx, vy)` sets how fast the shimeji moves. An upwards push while
+//!   standing is a jump.
+//! - `animation(name)` plays another of the pack's animations.
+//! - `behavior(name)` switches to another behavior, and nothing the script asks for
+//!   after it is done.
+//! - `random(a, b)`, a number between `a` and `b`.
+//! - `every(seconds)` is true each time that many more seconds have passed in the
+//!   behavior, keeping a timer for each line it's called on.
+//! - `world`, a table of the `population`, whether the user is `idle`, and the `hour`,
+//!   `day`, `month` and `weekday` if the clock is known.
+//!
+//! Scripts can't reach files or other programs. One that runs for too long or takes
+//! too much memory is stopped, and the error is logged.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use derive_more::derive::{Display, Error};
+use mlua::{Function, HookTriggers, Lua, LuaOptions, RegistryKey, StdLib, Value};
+
+use crate::{ambient::LocalTime, log_throttle::once, rng::Rng, world::WorldSnapshot};
+
+/// How many instructions a script runs between checks that it hasn't run for too long.
+const INSTRUCTIONS_PER_CHECK: u32 = 10_000;
+/// How many checks a script can pass in one run, so it runs at most a million instructions.
+const CHECKS_PER_RUN: u32 = 100;
+/// The most memory one shimeji's script can take.
+const MEMORY_LIMIT: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Display, Error, PartialEq)]
+pub enum ScriptError {
+    #[display("{reason}")]
+    Lua { reason: String },
+}
+
+impl From<mlua::Error> for ScriptError {
+    fn from(why: mlua::Error) -> Self {
+        Self::Lua {
+            reason: why.to_string(),
+        }
+    }
+}
+
+/// What a script knows about the shimeji running it, see `position()`, `velocity()`
+/// and `time()` above.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Locals {
+    pub x: f64,
+    pub y: f64,
+    pub velocity_x: f64,
+    pub velocity_y: f64,
+    pub time: f64,
+}
+
+/// What a script wants done to its shimeji.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptEffect {
+    Velocity(f64, f64),
+    Animation(String),
+    Behavior(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    source: String,
+}
+
+impl Script {
+    /// Check `input` is Lua, without running it.
+    pub fn parse(input: &str) -> Result<Self, ScriptError> {
+        let lua = Lua::new_with(StdLib::NONE, LuaOptions::default())?;
+        lua.load(input).set_name("script").into_function()?;
+        Ok(Self {
+            source: input.to_owned(),
+        })
+    }
+    /// Run the script for the shimeji `locals` describes.
+    ///
+    /// `state` holds its Lua state, and should be reset whenever the shimeji changes behavior.
+    /// A script that fails is logged once, and does nothing that run.
+    pub fn run(
+        &self,
+        world: &WorldSnapshot,
+        locals: &Locals,
+        state: &mut ScriptState,
+        rng: &mut Rng,
+    ) -> Vec<ScriptEffect> {
+        self.try_run(world, locals, state, rng)
+            .unwrap_or_else(|why| {
+                once!(
+                    &self.source,
+                    log::Level::Warn,
+                    "A behavior's script failed: {why}"
+                );
+                vec![]
+            })
+    }
+    fn try_run(
+        &self,
+        world: &WorldSnapshot,
+        locals: &Locals,
+        state: &mut ScriptState,
+        rng: &mut Rng,
+    ) -> Result<Vec<ScriptEffect>, ScriptError> {
+        if state.running.is_none() {
+            state.running = Some(Running::start(&self.source)?);
+        }
+        let Some(Running {
+            lua,
+            main,
+            timers,
+            checks,
+        }) = state.running.as_mut()
+        else {
+            unreachable!("started above");
+        };
+        checks.store(0, Ordering::Relaxed);
+        let effects = RefCell::new(vec![]);
+        let effect = |effect: ScriptEffect| {
+            let mut effects = effects.borrow_mut();
+            // switching behaviors ends the script, as far as its shimeji is concerned
+            if !matches!(effects.last(), Some(ScriptEffect::Behavior(_))) {
+                effects.push(effect);
+            }
+        };
+        let Locals {
+            x,
+            y,
+            velocity_x,
+            velocity_y,
+            time,
+        } = *locals;
+        lua.scope(|scope| {
+            let globals = lua.globals();
+            globals.set("position", lua.create_function(move |_, ()| Ok((x, y)))?)?;
+            globals.set(
+                "velocity",
+                lua.create_function(move |_, ()| Ok((velocity_x, velocity_y)))?,
+            )?;
+            globals.set("time", lua.create_function(move |_, ()| Ok(time))?)?;
+            globals.set(
+                "set_velocity",
+                scope.create_function(|_, (x, y): (f64, f64)| {
+                    effect(ScriptEffect::Velocity(x, y));
+                    Ok(())
+                })?,
+            )?;
+            globals.set(
+                "animation",
+                scope.create_function(|_, name: String| {
+                    effect(ScriptEffect::Animation(name));
+                    Ok(())
+                })?,
+            )?;
+            globals.set(
+                "behavior",
+                scope.create_function(|_, name: String| {
+                    effect(ScriptEffect::Behavior(name));
+                    Ok(())
+                })?,
+            )?;
+            globals.set(
+                "random",
+                scope.create_function_mut(|_, (low, high): (f64, f64)| {
+                    Ok(low + rng.f64() * (high - low))
+                })?,
+            )?;
+            globals.set(
+                "every",
+                scope.create_function_mut(|lua, seconds: f64| {
+                    let line = lua.inspect_stack(1).map_or(0, |caller| caller.curr_line());
+                    let last = timers.entry(line).or_insert(0.0);
+                    let due = time - *last >= seconds;
+                    if due {
+                        *last = time;
+                    }
+                    Ok(due)
+                })?,
+            )?;
+            // nil when the clock isn't known
+            let clock = |part: fn(LocalTime) -> u32| {
+                world
+                    .clock
+                    .map_or(Value::Nil, |clock| Value::Number(part(clock) as f64))
+            };
+            globals.set(
+                "world",
+                lua.create_table_from([
+                    ("population", Value::Number(world.population as f64)),
+                    ("idle", Value::Boolean(world.user_idle)),
+                    ("hour", clock(|clock| clock.hour)),
+                    ("day", clock(|clock| clock.day)),
+                    ("month", clock(|clock| clock.month)),
+                    ("weekday", clock(|clock| clock.weekday)),
+                ])?,
+            )?;
+            lua.registry_value::<Function>(main)?.call::<_, ()>(())
+        })?;
+        Ok(effects.into_inner())
+    }
+    /// Every animation the script plays by a name written out in it, like `animation("run")`.
+    pub fn animations(&self) -> Vec<&str> {
+        names_passed_to(&self.source, "animation")
+    }
+    /// Every behavior the script switches to by a name written out in it.
+    pub fn behaviors(&self) -> Vec<&str> {
+        names_passed_to(&self.source, "behavior")
+    }
+}
+
+/// The string literals `source` calls `function` with, e.g. `run` for `animation("run")`
+/// or `animation 'run'`. Names worked out while it runs can't be found.
+fn names_passed_to<'s>(source: &'s str, function: &str) -> Vec<&'s str> {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut names = vec![];
+    for (at, _) in source.match_indices(function) {
+        if source[..at].ends_with(is_name) {
+            continue;
+        }
+        let rest = source[at + function.len()..].trim_start();
+        let rest = rest.strip_prefix('(').unwrap_or(rest).trim_start();
+        let Some(quote) = rest.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+            continue;
+        };
+        if let Some((name, _)) = rest[1..].split_once(quote) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// A shimeji's Lua state for the script of the behavior it's in, see [`Script::run`].
+/// A copy starts over.
+#[derive(Default)]
+pub struct ScriptState {
+    running: Option<Running>,
+}
+
+struct Running {
+    lua: Lua,
+    /// The script, compiled.
+    main: RegistryKey,
+    /// The `time` each line that calls `every` last had it come true at.
+    timers: HashMap<i32, f64>,
+    /// How many times the script has been checked on this run.
+    checks: Arc<AtomicU32>,
+}
+
+impl Running {
+    fn start(source: &str) -> Result<Self, ScriptError> {
+        let lua = Lua::new_with(
+            StdLib::MATH | StdLib::STRING | StdLib::TABLE,
+            LuaOptions::default(),
+        )?;
+        lua.set_memory_limit(MEMORY_LIMIT)?;
+        let checks = Arc::new(AtomicU32::new(0));
+        let checked = Arc::clone(&checks);
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(INSTRUCTIONS_PER_CHECK),
+            move |_, _| match checked.fetch_add(1, Ordering::Relaxed) < CHECKS_PER_RUN {
+                true => Ok(()),
+                false => Err(mlua::Error::runtime("it ran for too long")),
+            },
+        );
+        let main = lua.load(source).set_name("script").into_function()?;
+        let main = lua.create_registry_value(main)?;
+        Ok(Self {
+            lua,
+            main,
+            timers: HashMap::new(),
+            checks,
+        })
+    }
+}
+
+impl Clone for ScriptState {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for ScriptState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptState")
+            .field("running", &self.running.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,116 @@
+//! The shimejis that were out when the manager last exited, and where they were,
+//! so the next launch brings them back exactly as they were left.
+//!
+//! Stored as one shimeji per line, oldest first, as `name @ x, y`, or just `name`
+//! for shimejis that hadn't been seen moving yet. Blank lines are ignored.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _};
+use winit::dpi::PhysicalPosition;
+
+use crate::settings;
+
+const POSITION_SEPARATOR: &str = " @ ";
+
+/// A shimeji saved in a [`Session`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedShimeji {
+    pub name: String,
+    /// Where its window's top left was, if the manager knew.
+    pub position: Option<PhysicalPosition<i32>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    path: PathBuf,
+}
+
+impl Session {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+    /// The session saved at `SHIMEJI_SESSION_FILE`, or `session.conf`
+    /// in the [config directory](settings::config_dir) if it isn't set.
+    pub fn from_env() -> Self {
+        Self::new(settings::config_path(
+            "SHIMEJI_SESSION_FILE",
+            "session.conf",
+        ))
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Replace the saved session with `shimejis`, making its directory if need be.
+    ///
+    /// # Errors
+    /// Errors if the file can't be written.
+    pub fn save<'a>(
+        &self,
+        shimejis: impl IntoIterator<Item = &'a SavedShimeji>,
+    ) -> anyhow::Result<()> {
+        let contents: String = shimejis
+            .into_iter()
+            .map(|shimeji| match shimeji.position {
+                Some(position) => format!(
+                    "{}{POSITION_SEPARATOR}{}, {}\n",
+                    shimeji.name, position.x, position.y
+                ),
+                None => format!("{}\n", shimeji.name),
+            })
+            .collect();
+        settings::create_parent(&self.path)
+            .and_then(|()| fs::write(&self.path, contents).map_err(Into::into))
+            .with_context(|| format!("could not save session to {}", self.path.display()))
+    }
+    /// The saved shimejis, oldest first. Nothing is saved if the file doesn't exist.
+    ///
+    /// # Errors
+    /// Errors if the file can't be read or a position isn't `x, y`.
+    pub fn load(&self) -> anyhow::Result<Vec<SavedShimeji>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(why) if why.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(why) => {
+                return Err(why).with_context(|| format!("could not read {}", self.path.display()))
+            }
+        };
+        Self::parse(&contents)
+            .with_context(|| format!("could not parse session in {}", self.path.display()))
+    }
+    /// # Errors
+    /// Errors if a position isn't `x, y`.
+    pub fn parse(contents: &str) -> anyhow::Result<Vec<SavedShimeji>> {
+        let mut shimejis = vec![];
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, position)) = line.rsplit_once(POSITION_SEPARATOR) else {
+                shimejis.push(SavedShimeji {
+                    name: line.to_owned(),
+                    position: None,
+                });
+                continue;
+            };
+            let Some((x, y)) = position.split_once(',') else {
+                bail!("position {position} on line {} is not `x, y`", number + 1);
+            };
+            let coordinate = |value: &str| {
+                value.trim().parse::<i32>().with_context(|| {
+                    format!("position {position} on line {} is not `x, y`", number + 1)
+                })
+            };
+            shimejis.push(SavedShimeji {
+                name: name.trim().to_owned(),
+                position: Some(PhysicalPosition::new(coordinate(x)?, coordinate(y)?)),
+            });
+        }
+        Ok(shimejis)
+    }
+}
@@ -0,0 +1,449 @@
+//! Settings changed from inside the app, saved between runs.
+//!
+//! Stored as `key = value` lines, e.g. `floor_offset.DP-1 = 40`, `ambient_tint = false`
+//! `muted = true`, `max_population = 20`, `scale = 1.5`, `idle_after = 300`,
+//! `max_fps = 30`, `low_power = true`, `wrap_around = true` or `hide_in_fullscreen = false`.
+//! `notification_behavior = Alert` has shimejis react to notifications popping up in the
+//! `notification_corner = top-right` of their monitor.
+//! Caps and pins are `monitor_cap.DP-1 = 5` and `pin.Gon = DP-1, HDMI-A-1`.
+//! `variants.Gon = hue:0, hue:120, hue:240 tint:#ffe0e0` recolors each new shimeji
+//! of a pack as the next [`Variant`] in turn.
+//! Each `filter = ...` line adds an [`EventFilter`] to the end of the chain, and each
+//! `pack_url = ...` line a [`PackUrl`] to download at startup.
+//! `hotkey.ctrl+alt+s = spawn Gon` binds a [`Hotkey`] to a [`HotkeyAction`].
+//! Blank lines and lines starting with `#` are ignored.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context as _};
+use itertools::Itertools as _;
+
+use crate::{
+    download::PackUrl,
+    hotkeys::{Hotkey, HotkeyAction},
+    interaction::{EventFilter, FilterChain},
+    notifications::Corner,
+    variant::Variant,
+};
+
+const FLOOR_OFFSET_PREFIX: &str = "floor_offset.";
+const AMBIENT_TINT: &str = "ambient_tint";
+const MUTED: &str = "muted";
+const MAX_POPULATION: &str = "max_population";
+const MONITOR_CAP_PREFIX: &str = "monitor_cap.";
+const PIN_PREFIX: &str = "pin.";
+const VARIANTS_PREFIX: &str = "variants.";
+const HOTKEY_PREFIX: &str = "hotkey.";
+const FILTER: &str = "filter";
+const SCALE: &str = "scale";
+const IDLE_AFTER: &str = "idle_after";
+const MAX_FPS: &str = "max_fps";
+const LOW_POWER: &str = "low_power";
+const WRAP_AROUND: &str = "wrap_around";
+const HIDE_IN_FULLSCREEN: &str = "hide_in_fullscreen";
+const NOTIFICATION_BEHAVIOR: &str = "notification_behavior";
+const NOTIFICATION_CORNER: &str = "notification_corner";
+const PACK_URL: &str = "pack_url";
+const DEFAULT_MAX_POPULATION: usize = 20;
+const DEFAULT_IDLE_AFTER: Duration = Duration::from_secs(5 * 60);
+/// What the config directory is called inside the platform's own.
+const CONFIG_DIRECTORY: &str = "new-shimeji";
+
+/// Where this app's files go on this platform: `$XDG_CONFIG_HOME/new-shimeji` (or
+/// `~/.config/new-shimeji`) on Linux, `~/Library/Application Support/new-shimeji` on macOS
+/// and `%APPDATA%\new-shimeji` on Windows. `None` if the platform's is unknown.
+pub fn config_dir() -> Option<PathBuf> {
+    let home = || {
+        std::env::var_os("HOME")
+            .filter(|home| !home.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|config| !config.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".config")))
+    };
+    base.map(|base| base.join(CONFIG_DIRECTORY))
+}
+
+/// The file named by `variable`, or `file` in the [`config_dir`],
+/// or in the working directory if there's no config directory.
+pub(crate) fn config_path(variable: &str, file: &str) -> PathBuf {
+    match std::env::var_os(variable) {
+        Some(path) => PathBuf::from(path),
+        None => config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(file),
+    }
+}
+
+/// Make the directory `path` goes in, if it doesn't exist yet.
+pub(crate) fn create_parent(path: &Path) -> anyhow::Result<()> {
+    match path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        Some(parent) => Ok(fs::create_dir_all(parent)?),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    path: PathBuf,
+    /// How many pixels above the bottom of each monitor, by name,
+    /// the shimejis should stand. Negative sinks them below it.
+    pub floor_offsets: BTreeMap<String, i32>,
+    /// Whether shimejis get darker at night and with dark themes, see [`crate::ambient`].
+    pub ambient_tint: bool,
+    /// Whether the sounds packs play are turned off, see [`crate::audio`].
+    pub muted: bool,
+    /// How many shimejis there can be before multiplying ones stop, see
+    /// [`crate::behavior::Action::Multiply`].
+    pub max_population: usize,
+    /// How much bigger to draw every pack than it asks for, see
+    /// [`crate::loader::PackLibrary::with_scale`]. Applies from the next launch.
+    pub scale: f64,
+    /// How long without keyboard or mouse input before the user counts as away,
+    /// see [`crate::behavior::Condition::Idle`]. Applies from the next launch.
+    pub idle_after: Duration,
+    /// The most frames a second any shimeji is drawn at, however fast its animation is.
+    pub max_fps: Option<f64>,
+    /// Whether to save power while running on battery, see [`crate::power`].
+    pub low_power: bool,
+    /// Whether shimejis walking off one side of their monitor come back in at the other,
+    /// see [`crate::movement::Movement::set_wrapping`].
+    pub wrap_around: bool,
+    /// Whether shimejis hide while another application is fullscreen on their monitor,
+    /// see [`crate::fullscreen`]. Applies from the next launch.
+    pub hide_in_fullscreen: bool,
+    /// The behavior shimejis switch to when another application shows a notification,
+    /// if they react at all, see [`crate::notifications`]. Applies from the next launch.
+    pub notification_behavior: Option<String>,
+    /// Where notifications pop up, for shimejis to look or jump toward.
+    pub notification_corner: Corner,
+    /// How many shimejis can live on each monitor, by name. Monitors not in here have no cap.
+    pub monitor_caps: BTreeMap<String, usize>,
+    /// The monitors, by name, each pack's shimejis are kept on. Packs not in here go anywhere.
+    pub pins: BTreeMap<String, Vec<String>>,
+    /// The colors each pack's shimejis take turns at, by pack. Packs not in here keep theirs.
+    pub variants: BTreeMap<String, Vec<Variant>>,
+    /// Which interactions are let through to the shimejis.
+    pub filters: FilterChain,
+    /// Pack archives to download and load at startup, see [`crate::download`].
+    pub pack_urls: Vec<PackUrl>,
+    /// Keys that do something wherever the focus is, see [`crate::hotkeys`].
+    /// Applies from the next launch.
+    pub hotkeys: Vec<(Hotkey, HotkeyAction)>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::default(),
+            floor_offsets: BTreeMap::new(),
+            ambient_tint: true,
+            muted: false,
+            max_population: DEFAULT_MAX_POPULATION,
+            scale: 1.0,
+            idle_after: DEFAULT_IDLE_AFTER,
+            max_fps: None,
+            low_power: false,
+            wrap_around: false,
+            hide_in_fullscreen: true,
+            notification_behavior: None,
+            notification_corner: Corner::default(),
+            monitor_caps: BTreeMap::new(),
+            pins: BTreeMap::new(),
+            variants: BTreeMap::new(),
+            filters: FilterChain::new(),
+            pack_urls: vec![],
+            hotkeys: vec![],
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings from `SHIMEJI_SETTINGS_FILE`, or `settings.conf`
+    /// in the [`config_dir`] if it isn't set.
+    ///
+    /// # Errors
+    /// See [`Settings::load`].
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::load(config_path("SHIMEJI_SETTINGS_FILE", "settings.conf"))
+    }
+    /// Load the settings saved at `path`, which doesn't have to exist yet.
+    ///
+    /// # Errors
+    /// Errors if the file can't be read or a line can't be parsed.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(why) if why.kind() == ErrorKind::NotFound => String::new(),
+            Err(why) => {
+                return Err(why).with_context(|| format!("could not read {}", path.display()))
+            }
+        };
+        let mut settings = Self::parse(&contents)
+            .with_context(|| format!("could not parse settings in {}", path.display()))?;
+        settings.path = path;
+        Ok(settings)
+    }
+    /// # Errors
+    /// Errors if a line isn't `key = value`, the key is unknown or the value is invalid.
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        let mut settings = Self::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // URLs can have `=` in them, anything else can have it in its key
+            let split = match line.split_once('=') {
+                Some((key, value)) if key.trim() == PACK_URL => Some((key, value)),
+                _ => line.rsplit_once('='),
+            };
+            let Some((key, value)) = split else {
+                bail!("line {} is not `key = value`", number + 1);
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key == AMBIENT_TINT {
+                settings.ambient_tint = value.parse().with_context(|| {
+                    format!("{key} {value} on line {} is not true or false", number + 1)
+                })?;
+                continue;
+            }
+            if key == MUTED {
+                settings.muted = value.parse().with_context(|| {
+                    format!("{key} {value} on line {} is not true or false", number + 1)
+                })?;
+                continue;
+            }
+            if key == MAX_POPULATION {
+                settings.max_population = value.parse().with_context(|| {
+                    format!("{key} {value} on line {} is not a whole number", number + 1)
+                })?;
+                continue;
+            }
+            if key == SCALE {
+                settings.scale = value
+                    .parse()
+                    .ok()
+                    .filter(|scale: &f64| *scale > 0.0 && scale.is_finite())
+                    .with_context(|| {
+                        format!(
+                            "{key} {value} on line {} is not a positive number",
+                            number + 1
+                        )
+                    })?;
+                continue;
+            }
+            if key == IDLE_AFTER {
+                settings.idle_after =
+                    value.parse().map(Duration::from_secs).with_context(|| {
+                        format!("{key} {value} on line {} is not whole seconds", number + 1)
+                    })?;
+                continue;
+            }
+            if key == MAX_FPS {
+                settings.max_fps = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|fps: &f64| *fps > 0.0 && fps.is_finite())
+                        .with_context(|| {
+                            format!(
+                                "{key} {value} on line {} is not a positive number",
+                                number + 1
+                            )
+                        })?,
+                );
+                continue;
+            }
+            if key == LOW_POWER {
+                settings.low_power = value.parse().with_context(|| {
+                    format!("{key} {value} on line {} is not true or false", number + 1)
+                })?;
+                continue;
+            }
+            if key == WRAP_AROUND {
+                settings.wrap_around = value.parse().with_context(|| {
+                    format!("{key} {value} on line {} is not true or false", number + 1)
+                })?;
+                continue;
+            }
+            if key == HIDE_IN_FULLSCREEN {
+                settings.hide_in_fullscreen = value.parse().with_context(|| {
+                    format!("{key} {value} on line {} is not true or false", number + 1)
+                })?;
+                continue;
+            }
+            if key == NOTIFICATION_BEHAVIOR {
+                if value.is_empty() {
+                    bail!("{key} on line {} names no behavior", number + 1);
+                }
+                settings.notification_behavior = Some(value.to_owned());
+                continue;
+            }
+            if key == NOTIFICATION_CORNER {
+                settings.notification_corner = value
+                    .parse()
+                    .with_context(|| format!("invalid {key} on line {}", number + 1))?;
+                continue;
+            }
+            if key == PACK_URL {
+                let pack = value
+                    .parse()
+                    .with_context(|| format!("invalid pack URL on line {}", number + 1))?;
+                settings.pack_urls.push(pack);
+                continue;
+            }
+            if key == FILTER {
+                let filter: EventFilter = value
+                    .parse()
+                    .with_context(|| format!("invalid filter on line {}", number + 1))?;
+                settings.filters.push(filter);
+                continue;
+            }
+            if let Some(monitor) = key.strip_prefix(FLOOR_OFFSET_PREFIX) {
+                let offset = value.parse().with_context(|| {
+                    format!(
+                        "floor offset {value} on line {} is not a number",
+                        number + 1
+                    )
+                })?;
+                settings.floor_offsets.insert(monitor.to_owned(), offset);
+            } else if let Some(monitor) = key.strip_prefix(MONITOR_CAP_PREFIX) {
+                let cap = value.parse().with_context(|| {
+                    format!(
+                        "monitor cap {value} on line {} is not a whole number",
+                        number + 1
+                    )
+                })?;
+                settings.monitor_caps.insert(monitor.to_owned(), cap);
+            } else if let Some(pack) = key.strip_prefix(PIN_PREFIX) {
+                let monitors = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|monitor| !monitor.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+                settings.pins.insert(pack.to_owned(), monitors);
+            } else if let Some(pack) = key.strip_prefix(VARIANTS_PREFIX) {
+                let variants = value
+                    .split(',')
+                    .map(|variant| variant.parse())
+                    .collect::<Result<_, _>>()
+                    .with_context(|| format!("invalid variant on line {}", number + 1))?;
+                settings.variants.insert(pack.to_owned(), variants);
+            } else if let Some(hotkey) = key.strip_prefix(HOTKEY_PREFIX) {
+                let hotkey = hotkey
+                    .parse()
+                    .with_context(|| format!("invalid hotkey on line {}", number + 1))?;
+                let action = value
+                    .parse()
+                    .with_context(|| format!("invalid hotkey action on line {}", number + 1))?;
+                settings.hotkeys.push((hotkey, action));
+            } else {
+                bail!("unknown setting {key} on line {}", number + 1);
+            }
+        }
+        Ok(settings)
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Whether shimejis of `pack` may live on `monitor`.
+    pub fn allows(&self, pack: &str, monitor: &str) -> bool {
+        self.pins
+            .get(pack)
+            .is_none_or(|monitors| monitors.iter().any(|pinned| pinned == monitor))
+    }
+    /// Whether `monitor` is over its cap with `crowd` shimejis on it.
+    pub fn is_overcrowded(&self, monitor: &str, crowd: usize) -> bool {
+        self.monitor_caps
+            .get(monitor)
+            .is_some_and(|cap| crowd > *cap)
+    }
+    /// The least crowded monitor a shimeji of `pack` may live on that has room for it,
+    /// from every monitor and how many shimejis already live there.
+    pub fn pick_monitor<'a>(
+        &self,
+        pack: &str,
+        crowds: impl IntoIterator<Item = (&'a str, usize)>,
+    ) -> Option<&'a str> {
+        crowds
+            .into_iter()
+            .filter(|(monitor, crowd)| {
+                self.allows(pack, monitor) && !self.is_overcrowded(monitor, crowd + 1)
+            })
+            .min_by_key(|(_, crowd)| *crowd)
+            .map(|(monitor, _)| monitor)
+    }
+    /// Write the settings back to where they were loaded from, making its directory if need be.
+    ///
+    /// # Errors
+    /// Errors if the file can't be written.
+    pub fn save(&self) -> anyhow::Result<()> {
+        create_parent(&self.path)
+            .and_then(|()| fs::write(&self.path, self.to_string()).map_err(Into::into))
+            .with_context(|| format!("could not save settings to {}", self.path.display()))
+    }
+}
+
+impl std::fmt::Display for Settings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{AMBIENT_TINT} = {}", self.ambient_tint)?;
+        writeln!(f, "{MUTED} = {}", self.muted)?;
+        writeln!(f, "{MAX_POPULATION} = {}", self.max_population)?;
+        writeln!(f, "{SCALE} = {}", self.scale)?;
+        writeln!(f, "{IDLE_AFTER} = {}", self.idle_after.as_secs())?;
+        if let Some(max_fps) = self.max_fps {
+            writeln!(f, "{MAX_FPS} = {max_fps}")?;
+        }
+        writeln!(f, "{LOW_POWER} = {}", self.low_power)?;
+        writeln!(f, "{WRAP_AROUND} = {}", self.wrap_around)?;
+        writeln!(f, "{HIDE_IN_FULLSCREEN} = {}", self.hide_in_fullscreen)?;
+        if let Some(behavior) = &self.notification_behavior {
+            writeln!(f, "{NOTIFICATION_BEHAVIOR} = {behavior}")?;
+        }
+        writeln!(f, "{NOTIFICATION_CORNER} = {}", self.notification_corner)?;
+        for (monitor, offset) in self.floor_offsets.iter() {
+            writeln!(f, "{FLOOR_OFFSET_PREFIX}{monitor} = {offset}")?;
+        }
+        for (monitor, cap) in self.monitor_caps.iter() {
+            writeln!(f, "{MONITOR_CAP_PREFIX}{monitor} = {cap}")?;
+        }
+        for (pack, monitors) in self.pins.iter() {
+            writeln!(f, "{PIN_PREFIX}{pack} = {}", monitors.join(", "))?;
+        }
+        for (pack, variants) in self.variants.iter() {
+            writeln!(
+                f,
+                "{VARIANTS_PREFIX}{pack} = {}",
+                variants.iter().join(", ")
+            )?;
+        }
+        for filter in self.filters.iter() {
+            writeln!(f, "{FILTER} = {filter}")?;
+        }
+        for pack in self.pack_urls.iter() {
+            writeln!(f, "{PACK_URL} = {pack}")?;
+        }
+        for (hotkey, action) in self.hotkeys.iter() {
+            writeln!(f, "{HOTKEY_PREFIX}{hotkey} = {action}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! `--soak <minutes>`: keep spawning, removing, dragging and switching the behaviors of
+//! shimejis through the manager's own APIs, checking it stays consistent throughout,
+//! then exit with a report.
+//!
+//! The population kept around is `SHIMEJI_SOAK_POPULATION` (12 by default), and the
+//! process may use up to `SHIMEJI_SOAK_MAX_RSS_MB` megabytes of memory (1024 by default).
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use derive_more::derive::{Display, Error};
+use winit::event_loop::EventLoopProxy;
+
+use crate::{rng::Rng, ManagerEvent};
+
+pub const FLAG: &str = "--soak";
+const DEFAULT_POPULATION: usize = 12;
+const DEFAULT_MAX_RSS_MB: u64 = 1024;
+/// How often the manager is asked to do something.
+const STEP_INTERVAL: Duration = Duration::from_millis(100);
+/// How long the last shimejis get to close their windows before counting what's left.
+const SETTLE: Duration = Duration::from_secs(2);
+/// Only the first this many problems are kept for the report, the rest are only counted.
+const KEPT_VIOLATIONS: usize = 50;
+
+#[derive(Debug, Display, Error)]
+pub enum InvalidSoak {
+    #[display("{FLAG} needs a number of minutes, not {minutes:?}")]
+    Minutes { minutes: String },
+    #[display("{variable} should be a whole number, not {value:?}")]
+    Variable {
+        variable: &'static str,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoakConfig {
+    pub duration: Duration,
+    /// How many shimejis are kept alive, give or take half.
+    pub population: usize,
+    /// The most memory the process may take up, in bytes.
+    pub max_rss: u64,
+}
+
+impl SoakConfig {
+    /// The soak test asked for with `--soak <minutes>` or `--soak=<minutes>`, if any,
+    /// configured from the environment.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, InvalidSoak> {
+        let minutes = loop {
+            let Some(arg) = args.next() else {
+                return Ok(None);
+            };
+            if let Some(minutes) = arg.strip_prefix("--soak=") {
+                break minutes.to_owned();
+            }
+            if arg == FLAG {
+                break args.next().unwrap_or_default();
+            }
+        };
+        let duration = minutes
+            .parse::<f64>()
+            .ok()
+            .and_then(|minutes| Duration::try_from_secs_f64(minutes * 60.0).ok())
+            .ok_or(InvalidSoak::Minutes { minutes })?;
+        let variable = |variable: &'static str, default: u64| match std::env::var(variable) {
+            Ok(value) => value
+                .parse()
+                .map_err(|_| InvalidSoak::Variable { variable, value }),
+            Err(_) => Ok(default),
+        };
+        Ok(Some(Self {
+            duration,
+            population: variable("SHIMEJI_SOAK_POPULATION", DEFAULT_POPULATION as u64)? as usize,
+            max_rss: variable("SHIMEJI_SOAK_MAX_RSS_MB", DEFAULT_MAX_RSS_MB)? * 1024 * 1024,
+        }))
+    }
+}
+
+/// Sent by the soak thread to move the test along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoakTick {
+    /// Do one more thing to the shimejis, then check on the manager.
+    Step,
+    /// Time is up, remove every shimeji.
+    Drain,
+    /// Check nothing was left behind by [`SoakTick::Drain`], report, and exit.
+    Report,
+}
+
+/// One thing done to the shimejis on a [`SoakTick::Step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SoakOp {
+    Spawn,
+    Remove,
+    /// Pick a shimeji up, carry it around and throw it.
+    Drag,
+    SwitchBehavior,
+}
+
+/// The state of a soak test, kept by the manager.
+#[derive(Debug)]
+pub struct SoakRun {
+    pub config: SoakConfig,
+    rng: Rng,
+    started: Instant,
+    ops: BTreeMap<SoakOp, usize>,
+    violations: Vec<String>,
+    violation_count: usize,
+    peak_rss: Option<u64>,
+}
+
+impl SoakRun {
+    pub fn new(config: SoakConfig) -> Self {
+        Self {
+            config,
+            rng: Rng::new(),
+            started: Instant::now(),
+            ops: BTreeMap::new(),
+            violations: vec![],
+            violation_count: 0,
+            peak_rss: None,
+        }
+    }
+    /// What to do next with `population` shimejis alive,
+    /// keeping it between half and one and a half times the configured population.
+    pub fn next_op(&mut self, population: usize) -> SoakOp {
+        let target = self.config.population;
+        let op = if population < target.div_ceil(2) {
+            SoakOp::Spawn
+        } else if population > target + target / 2 {
+            SoakOp::Remove
+        } else {
+            [
+                SoakOp::Spawn,
+                SoakOp::Remove,
+                SoakOp::Drag,
+                SoakOp::SwitchBehavior,
+            ][(self.rng.next_u64() % 4) as usize]
+        };
+        *self.ops.entry(op).or_default() += 1;
+        op
+    }
+    /// A number in `0..below`, for picking shimejis and behaviors.
+    pub fn pick(&mut self, below: usize) -> usize {
+        (self.rng.next_u64() % below.max(1) as u64) as usize
+    }
+    /// Something that should hold doesn't.
+    pub fn violation(&mut self, what: String) {
+        log::error!("Soak test: {what}");
+        self.violation_count += 1;
+        if self.violations.len() < KEPT_VIOLATIONS {
+            self.violations.push(what);
+        }
+    }
+    /// Check the process isn't taking up more memory than allowed.
+    pub fn sample_memory(&mut self) {
+        let Some(rss) = resident_bytes() else {
+            return;
+        };
+        let peak = self.peak_rss.get_or_insert(rss);
+        *peak = (*peak).max(rss);
+        if rss > self.config.max_rss {
+            self.violation(format!(
+                "using {} MB of memory, more than the {} MB allowed",
+                rss / 1024 / 1024,
+                self.config.max_rss / 1024 / 1024
+            ));
+        }
+    }
+    /// # Errors
+    /// Errors if anything went wrong along the way.
+    pub fn result(&self) -> Result<(), SoakFailed> {
+        match self.violation_count {
+            0 => Ok(()),
+            violations => Err(SoakFailed { violations }),
+        }
+    }
+}
+
+impl fmt::Display for SoakRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Soak test ran for {:.1} minutes",
+            self.started.elapsed().as_secs_f64() / 60.0
+        )?;
+        for (op, count) in self.ops.iter() {
+            writeln!(f, "  {op:?}: {count}")?;
+        }
+        if let Some(peak) = self.peak_rss {
+            writeln!(f, "  peak memory: {} MB", peak / 1024 / 1024)?;
+        }
+        match self.violation_count {
+            0 => writeln!(f, "No problems found"),
+            count => {
+                writeln!(f, "{count} problems found:")?;
+                for violation in self.violations.iter() {
+                    writeln!(f, "  {violation}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Display, Error)]
+#[display("the soak test found {violations} problems")]
+pub struct SoakFailed {
+    violations: usize,
+}
+
+/// Send the manager a [`SoakTick::Step`] every [`STEP_INTERVAL`] for `duration`,
+/// then drain it and ask for the report, unless `should_exit` is set first.
+pub fn spawn(
+    duration: Duration,
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Soak test thread"))
+        .spawn(move || {
+            let end = Instant::now() + duration;
+            let send = |tick| proxy.send_event(ManagerEvent::Soak(tick)).is_ok();
+            while Instant::now() < end {
+                if should_exit.load(Ordering::Relaxed) || !send(SoakTick::Step) {
+                    return;
+                }
+                thread::sleep(STEP_INTERVAL);
+            }
+            if send(SoakTick::Drain) {
+                thread::sleep(SETTLE);
+                send(SoakTick::Report);
+            }
+        })
+}
+
+/// How much memory the process is taking up, if that can be found out.
+fn resident_bytes() -> Option<u64> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            // the second field is the resident set, in pages
+            let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+            let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+            // SAFETY: sysconf only reads configuration.
+            let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+            Some(pages * u64::try_from(page_size).ok()?)
+        } else {
+            None
+        }
+    }
+}
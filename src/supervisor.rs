@@ -0,0 +1,63 @@
+//! `--auto-restart`: run the manager in a child process, and start it again whenever it
+//! crashes, bringing back the shimejis that were alive. For setups left running unattended,
+//! where a rare GPU or driver crash shouldn't mean an empty desktop.
+
+use std::{
+    collections::VecDeque,
+    ffi::OsString,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context as _};
+
+pub const FLAG: &str = "--auto-restart";
+/// Set for the child, so it keeps its population saved as it changes.
+pub const SUPERVISED_VAR: &str = "SHIMEJI_SUPERVISED";
+/// Set for the child after a crash, so it brings back the saved population.
+pub const RESTORE_VAR: &str = "SHIMEJI_RESTORE_POPULATION";
+
+/// Give up after this many crashes within [`CRASH_WINDOW`], it isn't going to get better.
+const MAX_CRASHES: usize = 5;
+const CRASH_WINDOW: Duration = Duration::from_secs(10 * 60);
+const RESTART_DELAY: Duration = Duration::from_secs(2);
+
+pub fn wants_auto_restart(mut args: impl Iterator<Item = String>) -> bool {
+    args.any(|arg| arg == FLAG)
+}
+
+/// Run this executable again with the same arguments, other than [`FLAG`],
+/// until it exits successfully.
+///
+/// # Errors
+/// Errors if the manager can't be started, or keeps crashing.
+pub fn supervise() -> anyhow::Result<()> {
+    let executable = std::env::current_exe().context("could not find the shimeji executable")?;
+    let args: Vec<OsString> = std::env::args_os()
+        .skip(1)
+        .filter(|arg| arg != FLAG)
+        .collect();
+    let mut crashes = VecDeque::with_capacity(MAX_CRASHES);
+    let mut restore = false;
+    loop {
+        let mut command = Command::new(&executable);
+        command.args(&args).env(SUPERVISED_VAR, "1");
+        if restore {
+            command.env(RESTORE_VAR, "1");
+        }
+        let status = command.status().context("could not start the manager")?;
+        if status.success() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        crashes.retain(|crash| now.duration_since(*crash) < CRASH_WINDOW);
+        crashes.push_back(now);
+        if crashes.len() >= MAX_CRASHES {
+            bail!("the manager crashed {MAX_CRASHES} times in {CRASH_WINDOW:?}, giving up");
+        }
+        log::error!("The manager crashed ({status}), restarting it in {RESTART_DELAY:?}");
+        restore = true;
+        thread::sleep(RESTART_DELAY);
+    }
+}
@@ -0,0 +1,35 @@
+//! Making shimeji windows tool windows on Windows, so they stay out of Alt-Tab
+//! and don't take focus away from whatever the user is doing when clicked.
+
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    GetWindowLongPtrW, SetWindowLongPtrW, GWL_EXSTYLE, WS_EX_NOACTIVATE, WS_EX_TOOLWINDOW,
+};
+use winit::{
+    raw_window_handle::{HasWindowHandle, RawWindowHandle},
+    window::Window,
+};
+
+/// Give `window` the tool window and no-activate extended styles.
+/// `WS_EX_LAYERED` is deliberately left off, since DXGI swap chains can't present
+/// to layered windows; transparency comes from winit's blur behind region instead.
+pub fn apply(window: &Window) {
+    let handle = match window.window_handle().map(|handle| handle.as_raw()) {
+        Ok(RawWindowHandle::Win32(handle)) => handle,
+        Ok(_) => return,
+        Err(why) => {
+            log::warn!("Could not make a tool window: {why}");
+            return;
+        }
+    };
+    let hwnd = handle.hwnd.get() as _;
+    // SAFETY: `hwnd` belongs to `window`, which is alive for the whole call.
+    unsafe {
+        let style = GetWindowLongPtrW(hwnd, GWL_EXSTYLE);
+        SetWindowLongPtrW(hwnd, GWL_EXSTYLE, tool_style(style));
+    }
+}
+
+/// The extended `style` of a window, with the tool window and no-activate styles added.
+pub fn tool_style(style: isize) -> isize {
+    style | (WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE) as isize
+}
@@ -0,0 +1,253 @@
+//! The tray icon and its menu: spawning, pausing and the rest of the switches that apply
+//! to every shimeji, then a submenu for each live shimeji to remove it, bring it over
+//! to the cursor, or swap it for another pack.
+//!
+//! The menu is built again from a [`TrayMenu`] whenever shimejis come and go.
+
+use std::{collections::HashMap, sync::Arc};
+
+use cfg_if::cfg_if;
+use winit::{event_loop::EventLoopProxy, window::WindowId};
+
+use crate::ManagerEvent;
+
+const TITLE: &str = "new-shimeji";
+/// The default pack's first idle frame, built in so the icon shows wherever the binary is.
+const ICON: &[u8] = include_bytes!("../img/idle_001.png");
+
+/// What the tray's menu lists.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrayMenu {
+    /// The packs in the library, to spawn or change to.
+    pub packs: Vec<Arc<str>>,
+    /// Every live shimeji, oldest first, and what it's called in the menu.
+    pub shimejis: Vec<(WindowId, String)>,
+}
+
+impl TrayMenu {
+    /// A menu of `packs` and `shimejis`, oldest first, each labelled with its name and
+    /// how many shimejis of the same name came before it, like `Shimeji 2`.
+    pub fn new(
+        packs: Vec<Arc<str>>,
+        shimejis: impl IntoIterator<Item = (WindowId, Arc<str>)>,
+    ) -> Self {
+        let mut counts: HashMap<Arc<str>, usize> = HashMap::new();
+        let shimejis = shimejis
+            .into_iter()
+            .map(|(window, name)| {
+                let count = counts.entry(Arc::clone(&name)).or_default();
+                *count += 1;
+                (window, format!("{name} {count}"))
+            })
+            .collect();
+        Self { packs, shimejis }
+    }
+}
+
+/// [`ICON`] decoded: its width, height, and pixels row by row.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn icon() -> anyhow::Result<(u32, u32, Vec<crate::rgba::Rgba>)> {
+    let mut reader = crate::loader::png_decoder(ICON).read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let pixels = crate::loader::to_rgba(info.color_type, &buf[..info.buffer_size()])?;
+    Ok((info.width, info.height, pixels))
+}
+
+/// An item's label, and the event it sends when clicked.
+type GlobalItem = (&'static str, fn() -> ManagerEvent);
+
+/// The items of the menu that apply to every shimeji, after the spawn submenu.
+const GLOBAL_ITEMS: &[GlobalItem] = &[
+    ("Party!", || ManagerEvent::StartParty),
+    ("Remove one", || ManagerEvent::RemoveOne),
+    ("Pause / resume", || ManagerEvent::TogglePause),
+    ("Calibrate floor", || ManagerEvent::CalibrateFloor),
+    ("Toggle night tint", || ManagerEvent::ToggleAmbientTint),
+    ("Toggle sound", || ManagerEvent::ToggleMute),
+    ("Toggle power saving", || ManagerEvent::ToggleLowPower),
+    ("Reload settings", || ManagerEvent::ReloadSettings),
+];
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use ksni::{
+            menu::{StandardItem, SubMenu},
+            MenuItem,
+        };
+
+        /// The tray icon, shown through the StatusNotifierItem D-Bus protocol.
+        pub struct Tray {
+            handle: ksni::Handle<Model>,
+        }
+
+        impl std::fmt::Debug for Tray {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct("Tray").finish_non_exhaustive()
+            }
+        }
+
+        struct Model {
+            proxy: EventLoopProxy<ManagerEvent>,
+            menu: TrayMenu,
+            icon: ksni::Icon,
+        }
+
+        impl Model {
+            fn send(&self, event: ManagerEvent) {
+                self.proxy.send_event(event).ok();
+            }
+        }
+
+        /// An item that sends the event `event` makes when it's clicked.
+        fn item(label: impl Into<String>, event: impl Fn() -> ManagerEvent + 'static) -> MenuItem<Model> {
+            StandardItem {
+                label: label.into(),
+                activate: Box::new(move |model: &mut Model| model.send(event())),
+                ..Default::default()
+            }
+            .into()
+        }
+
+        fn submenu(label: impl Into<String>, submenu: Vec<MenuItem<Model>>) -> MenuItem<Model> {
+            SubMenu {
+                label: label.into(),
+                enabled: !submenu.is_empty(),
+                submenu,
+                ..Default::default()
+            }
+            .into()
+        }
+
+        impl ksni::Tray for Model {
+            fn id(&self) -> String {
+                String::from(TITLE)
+            }
+            fn title(&self) -> String {
+                String::from(TITLE)
+            }
+            fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+                vec![self.icon.clone()]
+            }
+            fn menu(&self) -> Vec<MenuItem<Self>> {
+                let spawn = self
+                    .menu
+                    .packs
+                    .iter()
+                    .map(|pack| {
+                        let label = pack.to_string();
+                        let pack = Arc::clone(pack);
+                        item(label, move || ManagerEvent::Spawn(Arc::clone(&pack)))
+                    })
+                    .collect();
+                let mut menu = vec![submenu("Spawn", spawn)];
+                menu.extend(GLOBAL_ITEMS.iter().map(|&(label, event)| item(label, event)));
+                if !self.menu.shimejis.is_empty() {
+                    menu.push(MenuItem::Separator);
+                }
+                for (window, label) in self.menu.shimejis.iter() {
+                    let window = *window;
+                    let change = self
+                        .menu
+                        .packs
+                        .iter()
+                        .map(|pack| {
+                            let label = pack.to_string();
+                            let pack = Arc::clone(pack);
+                            item(label, move || ManagerEvent::ChangePack(window, Arc::clone(&pack)))
+                        })
+                        .collect();
+                    menu.push(submenu(
+                        label,
+                        vec![
+                            item("Remove", move || ManagerEvent::Remove(window)),
+                            item("Teleport to cursor", move || ManagerEvent::TeleportToCursor(window)),
+                            submenu("Change pack", change),
+                        ],
+                    ));
+                }
+                menu.push(MenuItem::Separator);
+                menu.push(item("Quit", || ManagerEvent::Exit));
+                menu
+            }
+        }
+
+        impl Tray {
+            /// Show the tray icon with `menu`, whose items are sent to the manager through `proxy`.
+            pub fn new(proxy: EventLoopProxy<ManagerEvent>, menu: TrayMenu) -> anyhow::Result<Self> {
+                let (width, height, pixels) = icon()?;
+                let icon = ksni::Icon {
+                    width: width as i32,
+                    height: height as i32,
+                    data: pixels
+                        .iter()
+                        .flat_map(|pixel| [pixel.alpha, pixel.red, pixel.green, pixel.blue])
+                        .collect(),
+                };
+                let service = ksni::TrayService::new(Model { proxy, menu, icon });
+                let handle = service.handle();
+                service.spawn();
+                Ok(Self { handle })
+            }
+            /// List `menu` from now on.
+            pub fn show(&self, menu: TrayMenu) {
+                self.handle.update(|model| model.menu = menu);
+            }
+        }
+
+        impl Drop for Tray {
+            fn drop(&mut self) {
+                self.handle.shutdown();
+            }
+        }
+    } else if #[cfg(target_os = "macos")] {
+        /// The tray icon. Its menu can't be changed once it's made, so it has
+        /// no submenus for the shimejis, and only spawns the packs it started with.
+        #[derive(derive_more::Debug)]
+        pub struct Tray {
+            #[debug(skip)]
+            _item: tray_item::TrayItem,
+        }
+
+        impl Tray {
+            /// Show the tray icon with `menu`, whose items are sent to the manager through `proxy`.
+            pub fn new(proxy: EventLoopProxy<ManagerEvent>, menu: TrayMenu) -> anyhow::Result<Self> {
+                let (width, height, _) = icon()?;
+                // macOS reads the png itself
+                let icon = tray_item::IconSource::Data {
+                    width: width as i32,
+                    height: height as i32,
+                    data: ICON.to_vec(),
+                };
+                let mut item = tray_item::TrayItem::new(TITLE, icon)?;
+                for pack in menu.packs {
+                    let proxy = proxy.clone();
+                    item.add_menu_item(&format!("Spawn {pack}"), move || {
+                        proxy.send_event(ManagerEvent::Spawn(Arc::clone(&pack))).ok();
+                    })?;
+                }
+                for &(label, event) in GLOBAL_ITEMS {
+                    let proxy = proxy.clone();
+                    item.add_menu_item(label, move || {
+                        proxy.send_event(event()).ok();
+                    })?;
+                }
+                item.add_menu_item("Quit", move || {
+                    proxy.send_event(ManagerEvent::Exit).ok();
+                })?;
+                Ok(Self { _item: item })
+            }
+            pub fn show(&self, _menu: TrayMenu) {}
+        }
+    } else {
+        #[derive(Debug)]
+        pub struct Tray;
+
+        impl Tray {
+            pub fn new(_proxy: EventLoopProxy<ManagerEvent>, _menu: TrayMenu) -> anyhow::Result<Self> {
+                anyhow::bail!("there is no tray icon on this platform yet")
+            }
+            pub fn show(&self, _menu: TrayMenu) {}
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! `new-shimeji validate [<path>]`: everything wrong with a pack, or a directory of them,
+//! without starting any shimejis. Loading stops at the first problem, or quietly stands
+//! placeholders in for images it can't decode, which makes for slow going when writing one.
+
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    archive, config,
+    loader::{self, PackLibrary},
+    xml_parser::{parse_many, PackError, XmlReturnData},
+};
+
+const SUBCOMMAND: &str = "validate";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub enum Severity {
+    /// Ignored when loading, which probably isn't what the pack's author meant.
+    #[display("warning")]
+    Warning,
+    /// Keeps the pack from loading, or from looking right.
+    #[display("error")]
+    Error,
+}
+
+/// Something wrong with a pack, and where, shown like `pack.xml:4:3: warning: ...`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// The line and column, counting from 1, when it's known.
+    pub position: Option<(u64, u64)>,
+    pub message: String,
+}
+
+impl Problem {
+    fn error(file: &Path, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            file: file.to_owned(),
+            position: None,
+            message: message.into(),
+        }
+    }
+    fn from_pack_error(file: &Path, error: &PackError) -> Self {
+        Self {
+            position: error.position,
+            ..Self::error(file, error.error.to_string())
+        }
+    }
+    /// Where `error` is in the pack, if it was in the markup.
+    fn from_load_error(file: &Path, error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<PackError>() {
+            Some(error) => Self::from_pack_error(file, error),
+            None => Self::error(file, format!("{error:#}")),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{line}:{column}")?;
+        }
+        write!(f, ": {}: {}", self.severity, self.message)
+    }
+}
+
+/// The path to validate if `validate` was passed, `$SHIMEJI_CONFIG_FILE` or
+/// `./default.xml` if it wasn't followed by one.
+pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<PathBuf> {
+    if args.next()? != SUBCOMMAND {
+        return None;
+    }
+    Some(args.next().map(PathBuf::from).unwrap_or_else(|| {
+        PathBuf::from(std::env::var_os("SHIMEJI_CONFIG_FILE").unwrap_or("./default.xml".into()))
+    }))
+}
+
+/// Every problem with the pack at `path`, or with every pack in it if it's a directory
+/// like the ones [`ShimejiLibrary::load`](loader::ShimejiLibrary::load) takes,
+/// borrowing animations from the packs in `packs`.
+pub fn validate(path: &Path, packs: &PackLibrary) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut names = HashSet::new();
+    if !path.is_dir() || loader::is_shimeji_ee_pack(path) {
+        validate_one(path, packs, &mut names, &mut problems);
+        return problems;
+    }
+    let mut entries: Vec<PathBuf> = match fs::read_dir(path)
+        .and_then(|entries| entries.map(|entry| Ok(entry?.path())).collect())
+    {
+        Ok(entries) => entries,
+        Err(why) => return vec![Problem::error(path, format!("could not read it: {why}"))],
+    };
+    entries.sort();
+    for entry in entries {
+        let is_xml = entry
+            .extension()
+            .is_some_and(|extension| extension == "xml");
+        if is_xml || loader::is_shimeji_ee_pack(&entry) || archive::is_archive(&entry) {
+            validate_one(&entry, packs, &mut names, &mut problems);
+        }
+    }
+    problems
+}
+
+/// Add the problems with the pack at `path` to `problems`, and the names of its shimejis
+/// to `names`, the shimejis found so far.
+fn validate_one(
+    path: &Path,
+    packs: &PackLibrary,
+    names: &mut HashSet<Arc<str>>,
+    problems: &mut Vec<Problem>,
+) {
+    let mut name = |name: Arc<str>, problems: &mut Vec<Problem>| {
+        if !names.insert(Arc::clone(&name)) {
+            problems.push(Problem::error(
+                path,
+                format!("more than one shimeji is called {name}"),
+            ));
+        }
+    };
+    if loader::is_shimeji_ee_pack(path) {
+        match loader::create_shimeji_data_with_library(path.as_os_str(), packs) {
+            Ok(data) => name(data.name, problems),
+            Err(why) => problems.push(Problem::error(path, format!("{why:#}"))),
+        }
+        return;
+    }
+    let parsed: Vec<XmlReturnData> = if config::Format::of(path).is_some() {
+        match config::parse_file(path) {
+            Ok(data) => vec![data],
+            Err(why) => {
+                problems.push(Problem::from_load_error(path, &why));
+                return;
+            }
+        }
+    } else if archive::is_archive(path) {
+        match loader::parse_archive(path) {
+            Ok(parsed) => parsed,
+            Err(why) => {
+                problems.push(Problem::from_load_error(path, &why));
+                return;
+            }
+        }
+    } else {
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(why) => {
+                problems.push(Problem::error(path, format!("could not read it: {why}")));
+                return;
+            }
+        };
+        match parse_many(file) {
+            Ok(parsed) => parsed,
+            Err(why) => {
+                problems.push(Problem::from_pack_error(path, &why));
+                return;
+            }
+        }
+    };
+    for mut data in parsed {
+        name(Arc::clone(&data.name), problems);
+        problems.extend(data.warnings.drain(..).map(|warning| Problem {
+            severity: Severity::Warning,
+            ..Problem::from_pack_error(path, &warning)
+        }));
+        let shimeji = Arc::clone(&data.name);
+        problems.extend(
+            loader::problems_of(data, packs)
+                .into_iter()
+                .map(|problem| Problem::error(path, format!("{shimeji}: {problem}"))),
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Finding the top edges of other applications' windows, so shimejis can stand on them.
+//!
+//! On Linux this only works on X11, Wayland doesn't let clients see each other's windows.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cfg_if::cfg_if;
+use winit::event_loop::EventLoopProxy;
+
+use crate::ManagerEvent;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The top edge of another application's window, in desktop coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSurface {
+    pub left: i32,
+    pub right: i32,
+    pub top: i32,
+}
+
+impl WindowSurface {
+    /// Whether something between `left` and `right` is over this surface.
+    pub fn spans(&self, left: f64, right: f64) -> bool {
+        left < self.right as f64 && right > self.left as f64
+    }
+}
+
+/// Start looking for window surfaces every [`POLL_INTERVAL`] on its own thread,
+/// sending [`ManagerEvent::WindowSurfaces`] whenever they change, until `should_exit` is set.
+pub fn spawn(
+    proxy: EventLoopProxy<ManagerEvent>,
+    should_exit: Arc<AtomicBool>,
+) -> std::io::Result<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(String::from("Window surfaces thread"))
+        .spawn(move || poll_surfaces(proxy, should_exit))
+}
+
+fn poll_surfaces(proxy: EventLoopProxy<ManagerEvent>, should_exit: Arc<AtomicBool>) {
+    let provider = match Provider::new() {
+        Ok(provider) => provider,
+        Err(why) => {
+            log::warn!("Shimejis can't stand on other windows: {why}");
+            return;
+        }
+    };
+    let mut last = vec![];
+    while !should_exit.load(Ordering::Relaxed) {
+        match provider.surfaces() {
+            Ok(surfaces) if surfaces != last => {
+                if proxy
+                    .send_event(ManagerEvent::WindowSurfaces(surfaces.clone()))
+                    .is_err()
+                {
+                    return;
+                }
+                last = surfaces;
+            }
+            Ok(_) => (),
+            Err(why) => log::warn!("Could not find other windows: {why}"),
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use x11rb::{
+            connection::Connection,
+            properties::WmClass,
+            protocol::xproto::{AtomEnum, ConnectionExt as _, Window},
+            rust_connection::RustConnection,
+        };
+
+        /// Reads window geometry from the X server's window manager hints.
+        struct Provider {
+            connection: RustConnection,
+            root: Window,
+            client_list: u32,
+            frame_extents: u32,
+            state: u32,
+            hidden: u32,
+        }
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                let (connection, screen) = x11rb::connect(None)?;
+                let root = connection.setup().roots[screen].root;
+                let intern = |name: &str| -> anyhow::Result<u32> {
+                    Ok(connection.intern_atom(false, name.as_bytes())?.reply()?.atom)
+                };
+                Ok(Self {
+                    client_list: intern("_NET_CLIENT_LIST")?,
+                    frame_extents: intern("_NET_FRAME_EXTENTS")?,
+                    state: intern("_NET_WM_STATE")?,
+                    hidden: intern("_NET_WM_STATE_HIDDEN")?,
+                    connection,
+                    root,
+                })
+            }
+            fn property(&self, window: Window, property: u32, kind: AtomEnum) -> anyhow::Result<Vec<u32>> {
+                let reply = self
+                    .connection
+                    .get_property(false, window, property, kind, 0, u32::MAX)?
+                    .reply()?;
+                Ok(reply.value32().map(Iterator::collect).unwrap_or_default())
+            }
+            /// The top of `window`'s title bar, unless it is one of ours or minimized.
+            fn surface_of(&self, window: Window) -> anyhow::Result<Option<WindowSurface>> {
+                let class = WmClass::get(&self.connection, window)?.reply()?;
+                if class.is_some_and(|class| class.class() == crate::backend::APP_ID.as_bytes()) {
+                    return Ok(None);
+                }
+                if self.property(window, self.state, AtomEnum::ATOM)?.contains(&self.hidden) {
+                    return Ok(None);
+                }
+                let geometry = self.connection.get_geometry(window)?.reply()?;
+                let position = self
+                    .connection
+                    .translate_coordinates(window, self.root, 0, 0)?
+                    .reply()?;
+                // left, right, top, bottom
+                let extents = self.property(window, self.frame_extents, AtomEnum::CARDINAL)?;
+                let title_bar = extents.get(2).copied().unwrap_or_default() as i32;
+                let left = position.dst_x as i32;
+                Ok(Some(WindowSurface {
+                    left,
+                    right: left + geometry.width as i32,
+                    top: position.dst_y as i32 - title_bar,
+                }))
+            }
+            fn surfaces(&self) -> anyhow::Result<Vec<WindowSurface>> {
+                let mut surfaces = vec![];
+                for window in self.property(self.root, self.client_list, AtomEnum::WINDOW)? {
+                    // windows can close while we look at them
+                    if let Ok(Some(surface)) = self.surface_of(window) {
+                        surfaces.push(surface);
+                    }
+                }
+                Ok(surfaces)
+            }
+        }
+    } else if #[cfg(target_os = "windows")] {
+        use windows_sys::Win32::{
+            Foundation::{BOOL, HWND, LPARAM, RECT, TRUE},
+            UI::WindowsAndMessaging::{
+                EnumWindows, GetWindowRect, GetWindowThreadProcessId, IsIconic, IsWindowVisible,
+            },
+        };
+
+        /// Walks every top level window with `EnumWindows`.
+        struct Provider;
+
+        unsafe extern "system" fn visit(window: HWND, surfaces: LPARAM) -> BOOL {
+            // SAFETY: `surfaces` is the `Vec` passed to `EnumWindows` in `surfaces`,
+            // which outlives the enumeration.
+            let surfaces = unsafe { &mut *(surfaces as *mut Vec<WindowSurface>) };
+            let mut process = 0;
+            let mut rect = RECT { left: 0, top: 0, right: 0, bottom: 0 };
+            // SAFETY: `window` comes straight from `EnumWindows`, the out pointers are to locals.
+            unsafe {
+                if IsWindowVisible(window) == 0 || IsIconic(window) != 0 {
+                    return TRUE;
+                }
+                GetWindowThreadProcessId(window, &mut process);
+                if process == std::process::id() || GetWindowRect(window, &mut rect) == 0 {
+                    return TRUE;
+                }
+            }
+            surfaces.push(WindowSurface {
+                left: rect.left,
+                right: rect.right,
+                top: rect.top,
+            });
+            TRUE
+        }
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                Ok(Self)
+            }
+            fn surfaces(&self) -> anyhow::Result<Vec<WindowSurface>> {
+                let mut surfaces: Vec<WindowSurface> = vec![];
+                // SAFETY: `visit` only uses the pointer during the call.
+                if unsafe { EnumWindows(Some(visit), &mut surfaces as *mut _ as LPARAM) } == 0 {
+                    anyhow::bail!("EnumWindows failed: {}", std::io::Error::last_os_error());
+                }
+                Ok(surfaces)
+            }
+        }
+    } else {
+        struct Provider;
+
+        impl Provider {
+            fn new() -> anyhow::Result<Self> {
+                anyhow::bail!("finding other windows isn't supported on this platform")
+            }
+            fn surfaces(&self) -> anyhow::Result<Vec<WindowSurface>> {
+                Ok(vec![])
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{
+    ambient::{LocalTime, Tint},
+    focus::FocusedWindow,
+    interaction::FilterChain,
+    monitors::{MonitorArea, Monitors, WorkArea},
+    window_surfaces::WindowSurface,
+};
+
+/// What every bucket thread knows about the shimejis outside of it.
+///
+/// Sent to each bucket by the manager whenever it changes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorldSnapshot {
+    /// How many shimejis are alive, across all buckets.
+    pub population: usize,
+    /// How far above the bottom of each monitor, by name, the floor is.
+    pub floor_offsets: BTreeMap<String, i32>,
+    /// The tops of other applications' windows, which shimejis can stand on.
+    pub window_surfaces: Vec<WindowSurface>,
+    /// Where other applications' fullscreen windows are, see [`crate::fullscreen`].
+    /// Shimejis on the same monitor as one hide until it's gone.
+    pub fullscreen: Vec<WorkArea>,
+    /// How sprites are tinted for the time of day, if at all.
+    pub tint: Option<Tint>,
+    /// Which interactions are let through to the shimejis.
+    pub filters: FilterChain,
+    /// Where every monitor is, for roaming between them.
+    pub monitors: Monitors,
+    /// Whether frames play their sounds.
+    pub muted: bool,
+    /// Whether the user has been away from the keyboard and mouse for a while, see [`crate::idle`].
+    pub user_idle: bool,
+    /// The other application's window the user is in, if any, see [`crate::focus`].
+    pub focused: Option<FocusedWindow>,
+    /// The most frames a second any shimeji is drawn at, if there's a cap.
+    pub max_fps: Option<f64>,
+    /// Whether to save power on battery, drawing at [`power::LOW_POWER_FPS`](crate::power::LOW_POWER_FPS)
+    /// and holding everyone still.
+    pub saving_power: bool,
+    /// Whether shimeji windows are cut to the shape of their sprites,
+    /// for when transparency doesn't work, see [`crate::window_shape`].
+    pub shaped_windows: bool,
+    /// Whether a shimeji walking off one side of its monitor comes back in at the other,
+    /// instead of climbing or roaming to the next monitor.
+    pub wrap_around: bool,
+    /// Whether the manager wants every behavior shimejis go into, see [`crate::recording`].
+    pub recording: bool,
+    /// The local time when the manager last checked, if it can tell, see [`crate::ambient`].
+    pub clock: Option<LocalTime>,
+}
+
+impl WorldSnapshot {
+    /// The shortest a frame can show for, from [`WorldSnapshot::max_fps`]
+    /// and whether power is being saved.
+    pub fn min_frame_interval(&self) -> Duration {
+        [
+            self.max_fps,
+            self.saving_power.then_some(crate::power::LOW_POWER_FPS),
+        ]
+        .into_iter()
+        .flatten()
+        .reduce(f64::min)
+        .map_or(Duration::ZERO, |fps| Duration::from_secs_f64(1.0 / fps))
+    }
+    /// Whether an application is fullscreen on `monitor`.
+    pub fn fullscreen_on(&self, monitor: &MonitorArea) -> bool {
+        self.fullscreen.iter().any(|window| {
+            let position = PhysicalPosition::new(window.left as f64, window.top as f64);
+            let size = PhysicalSize::new(
+                (window.right - window.left).max(0) as u32,
+                (window.bottom - window.top).max(0) as u32,
+            );
+            self.monitors.under(position, size) == Some(monitor)
+        })
+    }
+    /// The calibrated floor offset of the monitor called `monitor`, 0 if it wasn't calibrated.
+    pub fn floor_offset(&self, monitor: Option<&str>) -> i32 {
+        monitor
+            .and_then(|monitor| self.floor_offsets.get(monitor))
+            .copied()
+            .unwrap_or_default()
+    }
+}
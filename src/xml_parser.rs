@@ -1,156 +1,779 @@
-use std::{borrow::BorrowMut, collections::HashMap, fs, io::Read, sync::Arc};
+use std::{
+    borrow::BorrowMut,
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
 use derive_more::derive::{Debug, Display, Error};
-use xml::reader::XmlEvent;
+use xml::{common::Position as _, reader::XmlEvent};
 
-static VALID_SHIMEJI_ATTRIBUTES: [&str; 2] = ["name", "gravity"];
+use crate::{
+    behavior::{Action, Behavior, Condition, NextBehavior, Transition},
+    blit::{ScalingMode, Shadow},
+    formula::{Formula, Predicate},
+    frame_cursor::LoopMode,
+    rgba::Rgba,
+    script::Script,
+    sequencer::SequenceStep,
+};
 
-#[derive(Debug)]
+/// Every element of a pack, and the attributes it can have, as in `shimeji.xsd`.
+const KNOWN_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("Shimejis", &[]),
+    (
+        "Shimeji",
+        &[
+            "name",
+            "width",
+            "height",
+            "gravity",
+            "walk_speed",
+            "shadow_opacity",
+            "shadow_size",
+            "scaling",
+            "letterbox",
+            "scale",
+            "premultiplied",
+        ],
+    ),
+    (
+        "Animation",
+        &["name", "src", "fps", "weight", "next", "loop"],
+    ),
+    (
+        "frame",
+        &[
+            "number",
+            "file",
+            "x",
+            "y",
+            "w",
+            "h",
+            "duration_ms",
+            "sound",
+            "anchor_x",
+            "anchor_y",
+            "hitbox",
+        ],
+    ),
+    ("Use", &["pack", "animation", "as"]),
+    ("Sequence", &["name", "next", "weight"]),
+    ("Step", &["animation", "repeat"]),
+    ("Prop", &["name", "file", "width", "height", "count"]),
+    ("Behaviors", &["initial"]),
+    (
+        "Behavior",
+        &[
+            "name",
+            "animation",
+            "action",
+            "speed",
+            "weight",
+            "script",
+            "condition",
+        ],
+    ),
+    ("Transition", &["to", "when", "after", "app"]),
+    ("Next", &["to", "weight"]),
+];
+/// How much smaller or bigger than its frames a pack can ask to be drawn.
+const SCALE_RANGE: std::ops::RangeInclusive<f64> = 0.5..=4.0;
+
+#[derive(Debug, Clone)]
 pub struct AnimationXml {
     pub name: String,
     pub fps: Option<f64>,
+    /// How likely this animation is to be picked next, see [`crate::formula`].
+    pub weight: Option<Formula>,
+    /// The animation to chain into after playing this one once.
+    pub next: Option<String>,
+    /// What happens when it runs out of frames, if the pack says.
+    pub loop_mode: Option<LoopMode>,
+    /// An animated png or GIF to take every frame from, instead of `frames`.
+    pub src: Option<String>,
     pub frames: Vec<FrameXml>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FrameXml {
     pub number: u32,
     pub file_path: String,
+    /// Where the frame is in `file_path`, if it's a sprite sheet.
+    pub region: Option<FrameRegion>,
+    /// How long the frame shows for, if not for as long as its animation's fps says.
+    pub duration: Option<Duration>,
+    /// A sound to play whenever the frame shows, like a footstep.
+    pub sound: Option<String>,
+    /// The point of the frame, in its pixels, that stays put when frames or animations
+    /// change, like where its feet are. The bottom middle of the frame if not given.
+    pub anchor_x: Option<f64>,
+    pub anchor_y: Option<f64>,
+    /// The parts of the frame, in its pixels, that can be clicked, from its `hitbox`:
+    /// `x,y,w,h` rectangles separated by spaces. Empty to go by which pixels are opaque.
+    pub hitboxes: Vec<FrameRegion>,
+}
+/// A rectangle of a sprite sheet, from its `x`, `y`, `w` and `h` attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+/// An inert object, like a ball or a chair, that shimejis of the pack can play with.
+#[derive(Debug)]
+pub struct PropXml {
+    pub name: String,
+    pub file_path: String,
+    pub width: u32,
+    pub height: u32,
+    /// How many of this prop to spawn alongside the pack.
+    pub count: u32,
+}
+
+/// An animation borrowed from another installed pack, e.g.
+/// `<Use pack="base-cat" animation="walk"/>`.
+#[derive(Debug)]
+pub struct UseXml {
+    pub pack: String,
+    pub animation: String,
+    /// What the animation is called in this pack, the same as in the other pack unless `as` is given.
+    pub name: String,
 }
+
+/// `<Sequence name="sit">`, animations played one after another as if they were one,
+/// each for as many passes as its `<Step>` says.
+#[derive(Debug)]
+pub struct SequenceXml {
+    pub name: String,
+    pub weight: Option<Formula>,
+    /// The animation to play once the last step is over.
+    pub next: Option<String>,
+    pub steps: Vec<SequenceStep>,
+}
+
+/// The `<Behaviors>` block of a pack, if it has one.
+#[derive(Debug)]
+pub struct BehaviorsXml {
+    pub initial: String,
+    pub behaviors: Vec<Behavior>,
+}
+
+/// Something wrong with a pack, worded for whoever made it.
 #[derive(Debug, Error, Display)]
 pub enum XmlParseError {
+    #[display("there is more than one <Shimeji> in a file that should only have one")]
     MultipleShimeji,
+    #[display("there is no <Shimeji> in the file")]
     NoShimeji,
+    #[display("the file is not laid out like a pack")]
     MalformedFile,
+    #[display("the file is not valid XML: {message}")]
+    Syntax { message: String },
+    #[display("<{element}> can't go here")]
+    Misplaced { element: &'static str },
+    #[display("animation {animation} needs either <frame>s or a src attribute, not both")]
+    FramesOrSrc { animation: String },
+    #[display("the {attribute} attribute is missing")]
     MissingAttribute { attribute: &'static str },
+    #[display("the image {file_path} does not exist")]
     MissingImageFile { file_path: String },
+    #[display("{formula:?} is not a valid weight formula: {reason}")]
+    InvalidFormula { formula: String, reason: String },
+    #[display("{condition:?} is not a valid condition: {reason}")]
+    InvalidCondition { condition: String, reason: String },
+    #[display("{script:?} is not a valid script: {reason}")]
+    InvalidScript { script: String, reason: String },
+    #[display("{value:?} is not a valid value for {attribute}")]
+    InvalidValue {
+        attribute: &'static str,
+        value: String,
+    },
+    #[display("unknown element <{element}>")]
+    UnknownElement { element: String },
+    #[display("unknown attribute {attribute} of <{element}>")]
+    UnknownAttribute { attribute: String, element: String },
+    #[display("unknown key {key}")]
+    UnknownKey { key: String },
+}
+
+impl XmlParseError {
+    fn misplaced(element: &'static str) -> Self {
+        Self::Misplaced { element }
+    }
+}
+
+/// An [`XmlParseError`] and where it is: the file, once the loader knows it,
+/// and the line and column of the element it's in, counting from 1.
+#[derive(Debug, Error)]
+pub struct PackError {
+    pub error: XmlParseError,
+    pub file: Option<PathBuf>,
+    pub position: Option<(u64, u64)>,
+}
+
+impl PackError {
+    /// The same error, found in `file`.
+    pub fn in_file(self, file: impl Into<PathBuf>) -> Self {
+        Self {
+            file: Some(file.into()),
+            ..self
+        }
+    }
+}
+
+impl From<XmlParseError> for PackError {
+    fn from(error: XmlParseError) -> Self {
+        Self {
+            error,
+            file: None,
+            position: None,
+        }
+    }
+}
+
+impl std::fmt::Display for PackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.file, self.position) {
+            (Some(file), Some((line, column))) => {
+                write!(f, "{}:{line}:{column}: ", file.display())?
+            }
+            (Some(file), None) => write!(f, "{}: ", file.display())?,
+            (None, Some((line, column))) => write!(f, "line {line}, column {column}: ")?,
+            (None, None) => (),
+        }
+        write!(f, "{}", self.error)
+    }
 }
 #[derive(Debug)]
 pub struct XmlReturnData {
     pub shimeji_attributes: HashMap<String, String>,
     pub animations: Vec<AnimationXml>,
+    pub props: Vec<PropXml>,
+    pub uses: Vec<UseXml>,
+    pub sequences: Vec<SequenceXml>,
+    pub behaviors: Option<BehaviorsXml>,
     pub name: Arc<str>,
     pub shimeji_height: u32,
     pub shimeji_width: u32,
+    /// Whether the pack's shimejis fall to the floor, `true` unless `gravity="false"`.
+    pub gravity: bool,
+    /// How fast a `walk` animation walks in packs without behaviors, in pixels per second.
+    pub walk_speed: Option<f64>,
+    /// The shadow under the pack's shimejis, if it sets `shadow_opacity` or `shadow_size`.
+    pub shadow: Option<Shadow>,
+    /// How sprites fill windows resized by the window manager, `integer` unless set.
+    pub scaling: ScalingMode,
+    /// The `#rrggbb` or `#rrggbbaa` around sprites that don't fill their window.
+    pub letterbox: Option<Rgba>,
+    /// How many times bigger than its frames the pack is drawn, 1 unless set.
+    pub scale: f64,
+    /// Whether the pack's images are saved with premultiplied alpha, `false` unless set.
+    pub premultiplied: bool,
+    /// Elements, attributes and keys that were ignored, most likely misspelled.
+    pub warnings: Vec<PackError>,
+}
+/// Parse a file with exactly one `<Shimeji>`.
+pub fn parse(data: impl Read) -> Result<Box<XmlReturnData>, PackError> {
+    let mut parsed = parse_many(data)?;
+    if parsed.len() > 1 {
+        return Err(XmlParseError::MultipleShimeji.into());
+    }
+    Ok(Box::new(parsed.pop().unwrap()))
+}
+
+/// Parse every `<Shimeji>` in a file, e.g. one with several of them in a `<Shimejis>` element.
+///
+/// Each has the warnings about markup inside it, and the last also those after it.
+pub fn parse_many(data: impl Read) -> Result<Vec<XmlReturnData>, PackError> {
+    parse_many_in(data, Path::new(""))
+}
+
+/// [`parse_many`], with the images and sounds the pack names found in `base`
+/// instead of the working directory, like for a pack unpacked from an archive.
+pub fn parse_many_in(data: impl Read, base: &Path) -> Result<Vec<XmlReturnData>, PackError> {
+    let mut xml_reader = xml::EventReader::new(data);
+    let (mut parsed, mut warnings) =
+        parse_events(&mut xml_reader, base).map_err(|error| PackError {
+            error,
+            file: None,
+            position: Some(position_of(&xml_reader)),
+        })?;
+    let Some(last) = parsed.last_mut() else {
+        return Err(XmlParseError::NoShimeji.into());
+    };
+    last.warnings.append(&mut warnings);
+    Ok(parsed)
+}
+
+/// The line and column `xml_reader` is at, counting from 1.
+fn position_of(xml_reader: &xml::EventReader<impl Read>) -> (u64, u64) {
+    let position = xml_reader.position();
+    (position.row + 1, position.column + 1)
+}
+
+/// Whether `element` of a pack can have `attribute`.
+pub(crate) fn is_known_attribute(element: &str, attribute: &str) -> bool {
+    KNOWN_ATTRIBUTES
+        .iter()
+        .any(|(known, attributes)| *known == element && attributes.contains(&attribute))
+}
+
+/// An error for every attribute of `element` that it can't have, or for `element`
+/// itself if it isn't part of a pack.
+fn unknown_markup(
+    element: &str,
+    attributes: &[xml::attribute::OwnedAttribute],
+) -> Vec<XmlParseError> {
+    if !KNOWN_ATTRIBUTES.iter().any(|(known, _)| *known == element) {
+        return vec![XmlParseError::UnknownElement {
+            element: element.to_owned(),
+        }];
+    }
+    attributes
+        .iter()
+        // namespaced ones like xsi:noNamespaceSchemaLocation are for other tools
+        .filter(|attr| attr.name.prefix.is_none())
+        .filter(|attr| !is_known_attribute(element, &attr.name.local_name))
+        .map(|attr| XmlParseError::UnknownAttribute {
+            attribute: attr.name.local_name.clone(),
+            element: element.to_owned(),
+        })
+        .collect()
 }
-pub fn parse(data: impl Read) -> Result<Box<XmlReturnData>, XmlParseError> {
-    let xml_reader = xml::EventReader::new(data);
 
-    let mut shimeji_found = false;
+/// Every `<Shimeji>` read from `xml_reader`, which is left where it went wrong if one can't be,
+/// and the warnings about markup after the last of them.
+fn parse_events(
+    xml_reader: &mut xml::EventReader<impl Read>,
+    base: &Path,
+) -> Result<(Vec<XmlReturnData>, Vec<PackError>), XmlParseError> {
+    let mut parsed = vec![];
+    let mut warnings = vec![];
+    let mut inside_shimeji = false;
     let mut shimeji_attributes = None;
 
     let mut inside_animation = false;
     let mut animation_name: Option<String> = None;
     let mut animation_fps: Option<f64> = None;
+    let mut animation_weight: Option<Formula> = None;
+    let mut animation_next: Option<String> = None;
+    let mut animation_loop: Option<LoopMode> = None;
+    let mut animation_src: Option<String> = None;
     let mut animation_frames: Option<Vec<FrameXml>> = None;
 
     let mut animations: Vec<AnimationXml> = Vec::with_capacity(1);
-    for xml_event in xml_reader {
-        // dbg!(&xml_event);
-        if let Err(x) = xml_event {
-            log::error!("{x}");
-            break;
+    let mut props: Vec<PropXml> = vec![];
+    let mut uses: Vec<UseXml> = vec![];
+
+    let mut behaviors: Option<BehaviorsXml> = None;
+    let mut current_behavior: Option<Behavior> = None;
+    let mut sequences: Vec<SequenceXml> = vec![];
+    let mut current_sequence: Option<SequenceXml> = None;
+    loop {
+        let xml_event = xml_reader.next().map_err(|why| XmlParseError::Syntax {
+            message: why.msg().to_owned(),
+        })?;
+        if let XmlEvent::StartElement {
+            name, attributes, ..
+        } = &xml_event
+        {
+            let position = position_of(xml_reader);
+            warnings.extend(
+                unknown_markup(&name.local_name, attributes)
+                    .into_iter()
+                    .map(|error| PackError {
+                        error,
+                        file: None,
+                        position: Some(position),
+                    }),
+            );
         }
-        match xml_event.unwrap() {
+        match xml_event {
             XmlEvent::Whitespace(_) => (),
             XmlEvent::StartElement {
                 name, attributes, ..
-            } => match name.local_name.as_str() {
-                "Shimeji" => {
-                    if shimeji_found {
-                        return Err(XmlParseError::MultipleShimeji);
-                    }
-                    shimeji_found = true;
-                    shimeji_attributes =
-                        Some(HashMap::with_capacity(VALID_SHIMEJI_ATTRIBUTES.len()));
-                    for attr in attributes {
-                        let name = attr.name.local_name;
-                        shimeji_attributes
-                            .as_mut()
-                            .unwrap()
-                            .insert(name, attr.value);
-                    }
-                    log::debug!("{0:?}", &shimeji_attributes);
-                }
-                "Animation" => {
-                    if inside_animation {
-                        return Err(XmlParseError::MalformedFile);
+            } => {
+                match name.local_name.as_str() {
+                    "Shimeji" => {
+                        if inside_shimeji {
+                            return Err(XmlParseError::misplaced("Shimeji"));
+                        }
+                        inside_shimeji = true;
+                        shimeji_attributes = Some(HashMap::new());
+                        for attr in attributes {
+                            let name = attr.name.local_name;
+                            shimeji_attributes
+                                .as_mut()
+                                .unwrap()
+                                .insert(name, attr.value);
+                        }
+                        log::debug!("{0:?}", &shimeji_attributes);
                     }
-                    inside_animation = true;
-                    animation_frames = Some(vec![]);
+                    "Animation" => {
+                        if inside_animation {
+                            return Err(XmlParseError::misplaced("Animation"));
+                        }
+                        inside_animation = true;
+                        animation_frames = Some(vec![]);
 
-                    // return the fps and parse it if it exists, otherwise send None
-                    animation_fps = Some(
-                        attributes
+                        animation_src = attributes
+                            .iter()
+                            .find(|attr| attr.name.local_name == "src")
+                            .map(|attr| in_base(base, attr.value.clone()));
+                        if let Some(src) = &animation_src {
+                            if !fs::exists(src).unwrap() {
+                                return Err(XmlParseError::MissingImageFile {
+                                    file_path: src.clone(),
+                                });
+                            }
+                        }
+                        // animated pngs have their own frame delays to go by
+                        animation_fps = attributes
                             .iter()
                             .find(|attr| attr.name.local_name == "fps")
-                            .ok_or(XmlParseError::MissingAttribute { attribute: "fps" })?
-                            .value
-                            .parse::<f64>()
-                            .map_err(|_| XmlParseError::MalformedFile)?,
-                    );
-                    animation_name = Some(
-                        attributes
-                            .into_iter()
-                            .find(|attr| &attr.name.local_name == "name")
-                            .ok_or(XmlParseError::MissingAttribute { attribute: "name" })?
-                            .value,
-                    )
-                }
-                "frame" => {
-                    if !inside_animation {
-                        return Err(XmlParseError::MalformedFile);
+                            .map(|attr| parse_value("fps", attr.value.clone()))
+                            .transpose()?;
+                        animation_weight = attributes
+                            .iter()
+                            .find(|attr| attr.name.local_name == "weight")
+                            .map(|attr| parse_weight(attr.value.clone()))
+                            .transpose()?;
+                        animation_next = attributes
+                            .iter()
+                            .find(|attr| attr.name.local_name == "next")
+                            .map(|attr| attr.value.clone());
+                        animation_loop = attributes
+                            .iter()
+                            .find(|attr| attr.name.local_name == "loop")
+                            .map(|attr| parse_loop_mode(&attr.value))
+                            .transpose()?;
+                        animation_name = Some(
+                            attributes
+                                .into_iter()
+                                .find(|attr| &attr.name.local_name == "name")
+                                .ok_or(XmlParseError::MissingAttribute { attribute: "name" })?
+                                .value,
+                        )
                     }
-                    let frames = animation_frames.borrow_mut().as_mut().unwrap();
-                    let mut attr_map = HashMap::new();
-                    for attr in attributes {
-                        attr_map.insert(attr.name.local_name, attr.value);
+                    "frame" => {
+                        if !inside_animation {
+                            return Err(XmlParseError::misplaced("frame"));
+                        }
+                        let frames = animation_frames.borrow_mut().as_mut().unwrap();
+                        frames.push(frame_from_attributes(attributes_of(attributes), base)?);
                     }
+                    "Prop" => {
+                        if !inside_shimeji || inside_animation {
+                            return Err(XmlParseError::misplaced("Prop"));
+                        }
+                        let mut attr_map = HashMap::new();
+                        for attr in attributes {
+                            attr_map.insert(attr.name.local_name, attr.value);
+                        }
+                        let name = attr_map
+                            .remove("name")
+                            .ok_or(XmlParseError::MissingAttribute { attribute: "name" })?;
+                        let file_path = attr_map
+                            .remove("file")
+                            .ok_or(XmlParseError::MissingAttribute { attribute: "file" })?;
+                        let file_path = in_base(base, file_path);
+                        let width = attr_map
+                            .remove("width")
+                            .ok_or(XmlParseError::MissingAttribute { attribute: "width" })?;
+                        let width = parse_value("width", width)?;
+                        let height =
+                            attr_map
+                                .remove("height")
+                                .ok_or(XmlParseError::MissingAttribute {
+                                    attribute: "height",
+                                })?;
+                        let height = parse_value("height", height)?;
+                        let count = match attr_map.remove("count") {
+                            Some(count) => parse_value("count", count)?,
+                            None => 1,
+                        };
 
-                    let file_name = attr_map
-                        .remove("file")
-                        .ok_or(XmlParseError::MissingAttribute { attribute: "file" })?;
-                    let frame_number = attr_map
-                        .remove("number")
-                        .ok_or(XmlParseError::MissingAttribute {
-                            attribute: "number",
-                        })?
-                        .parse::<u32>()
-                        .map_err(|_| XmlParseError::MalformedFile)?;
-
-                    let file_exists = fs::exists(&file_name).unwrap();
-                    if !file_exists {
-                        return Err(XmlParseError::MissingImageFile {
-                            file_path: file_name,
+                        if !fs::exists(&file_path).unwrap() {
+                            return Err(XmlParseError::MissingImageFile { file_path });
+                        }
+                        props.push(PropXml {
+                            name,
+                            file_path,
+                            width,
+                            height,
+                            count,
                         });
                     }
-                    let ret = FrameXml {
-                        file_path: file_name,
-                        number: frame_number,
-                    };
-                    frames.push(ret);
-                }
-                _ => {
-                    log::debug!("Unrecognized local_name: {}", name.local_name);
-                    continue;
+                    "Use" => {
+                        if !inside_shimeji || inside_animation {
+                            return Err(XmlParseError::misplaced("Use"));
+                        }
+                        let mut attr_map = HashMap::new();
+                        for attr in attributes {
+                            attr_map.insert(attr.name.local_name, attr.value);
+                        }
+                        let pack = attr_map
+                            .remove("pack")
+                            .ok_or(XmlParseError::MissingAttribute { attribute: "pack" })?;
+                        let animation = attr_map.remove("animation").ok_or(
+                            XmlParseError::MissingAttribute {
+                                attribute: "animation",
+                            },
+                        )?;
+                        let name = attr_map.remove("as").unwrap_or(animation.clone());
+                        uses.push(UseXml {
+                            pack,
+                            animation,
+                            name,
+                        });
+                    }
+                    "Sequence" => {
+                        if !inside_shimeji || inside_animation || current_sequence.is_some() {
+                            return Err(XmlParseError::misplaced("Sequence"));
+                        }
+                        let mut attr_map = attributes_of(attributes);
+                        let name = attr_map
+                            .remove("name")
+                            .ok_or(XmlParseError::MissingAttribute { attribute: "name" })?;
+                        current_sequence = Some(SequenceXml {
+                            name,
+                            weight: attr_map.remove("weight").map(parse_weight).transpose()?,
+                            next: attr_map.remove("next"),
+                            steps: vec![],
+                        });
+                    }
+                    "Step" => {
+                        let Some(sequence) = current_sequence.as_mut() else {
+                            return Err(XmlParseError::misplaced("Step"));
+                        };
+                        let mut attr_map = attributes_of(attributes);
+                        let animation = attr_map.remove("animation").ok_or(
+                            XmlParseError::MissingAttribute {
+                                attribute: "animation",
+                            },
+                        )?;
+                        let repeat = match attr_map.remove("repeat") {
+                            Some(repeat) => match repeat.parse::<u32>() {
+                                Ok(passes) if passes > 0 => passes,
+                                _ => {
+                                    return Err(XmlParseError::InvalidValue {
+                                        attribute: "repeat",
+                                        value: repeat,
+                                    })
+                                }
+                            },
+                            None => 1,
+                        };
+                        sequence.steps.push(SequenceStep { animation, repeat });
+                    }
+                    "Behaviors" => {
+                        if !inside_shimeji || inside_animation || behaviors.is_some() {
+                            return Err(XmlParseError::misplaced("Behaviors"));
+                        }
+                        let initial = attributes
+                            .into_iter()
+                            .find(|attr| attr.name.local_name == "initial")
+                            .ok_or(XmlParseError::MissingAttribute {
+                                attribute: "initial",
+                            })?
+                            .value;
+                        behaviors = Some(BehaviorsXml {
+                            initial,
+                            behaviors: vec![],
+                        });
+                    }
+                    "Behavior" => {
+                        if behaviors.is_none() || current_behavior.is_some() {
+                            return Err(XmlParseError::misplaced("Behavior"));
+                        }
+                        let mut attr_map = HashMap::new();
+                        for attr in attributes {
+                            attr_map.insert(attr.name.local_name, attr.value);
+                        }
+                        let name = attr_map
+                            .remove("name")
+                            .ok_or(XmlParseError::MissingAttribute { attribute: "name" })?;
+                        let animation = attr_map.remove("animation").unwrap_or(name.clone());
+                        let speed = match attr_map.remove("speed") {
+                            Some(speed) => parse_value("speed", speed)?,
+                            None => 0.0,
+                        };
+                        let action = match attr_map.remove("action") {
+                            Some(action) => Action::parse(&action, speed).ok_or(
+                                XmlParseError::InvalidValue {
+                                    attribute: "action",
+                                    value: action,
+                                },
+                            )?,
+                            None => Action::Stand,
+                        };
+                        let weight = attr_map.remove("weight").map(parse_weight).transpose()?;
+                        let script = attr_map
+                            .remove("script")
+                            .map(|script| {
+                                Script::parse(&script).map_err(|why| XmlParseError::InvalidScript {
+                                    script,
+                                    reason: why.to_string(),
+                                })
+                            })
+                            .transpose()?;
+                        let condition = attr_map
+                            .remove("condition")
+                            .map(|condition| {
+                                Predicate::parse(&condition).map_err(|why| {
+                                    XmlParseError::InvalidCondition {
+                                        condition,
+                                        reason: why.to_string(),
+                                    }
+                                })
+                            })
+                            .transpose()?;
+                        current_behavior = Some(Behavior {
+                            name,
+                            animation,
+                            action,
+                            weight,
+                            transitions: vec![],
+                            next: vec![],
+                            script,
+                            condition,
+                        });
+                    }
+                    "Transition" => {
+                        let Some(behavior) = current_behavior.as_mut() else {
+                            return Err(XmlParseError::misplaced("Transition"));
+                        };
+                        let mut attr_map = HashMap::new();
+                        for attr in attributes {
+                            attr_map.insert(attr.name.local_name, attr.value);
+                        }
+                        let to = attr_map
+                            .remove("to")
+                            .ok_or(XmlParseError::MissingAttribute { attribute: "to" })?;
+                        let when = match attr_map.remove("when") {
+                            Some(when) => parse_value::<Condition>("when", when)?,
+                            None => Condition::Always,
+                        };
+                        let after = match attr_map.remove("after") {
+                            Some(after) => Some(
+                                after
+                                    .parse::<f64>()
+                                    .ok()
+                                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                                    .ok_or(XmlParseError::InvalidValue {
+                                        attribute: "after",
+                                        value: after,
+                                    })?,
+                            ),
+                            None => None,
+                        };
+                        let apps: Vec<_> = attr_map
+                            .remove("app")
+                            .map(|apps| {
+                                apps.split('|')
+                                    .map(str::trim)
+                                    .filter(|app| !app.is_empty())
+                                    .map(String::from)
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        if matches!(when, Condition::Focused | Condition::Unfocused)
+                            && apps.is_empty()
+                        {
+                            return Err(XmlParseError::MissingAttribute { attribute: "app" });
+                        }
+                        behavior.transitions.push(Transition {
+                            to,
+                            when,
+                            after,
+                            apps,
+                        });
+                    }
+                    "Next" => {
+                        let Some(behavior) = current_behavior.as_mut() else {
+                            return Err(XmlParseError::misplaced("Next"));
+                        };
+                        let mut attr_map = HashMap::new();
+                        for attr in attributes {
+                            attr_map.insert(attr.name.local_name, attr.value);
+                        }
+                        let to = attr_map
+                            .remove("to")
+                            .ok_or(XmlParseError::MissingAttribute { attribute: "to" })?;
+                        let weight =
+                            attr_map
+                                .remove("weight")
+                                .ok_or(XmlParseError::MissingAttribute {
+                                    attribute: "weight",
+                                })?;
+                        let weight = parse_weight(weight)?;
+                        behavior.next.push(NextBehavior { to, weight });
+                    }
+                    _ => {
+                        log::debug!("Unrecognized local_name: {}", name.local_name);
+                        continue;
+                    }
                 }
-            },
+            }
             XmlEvent::EndDocument => {
                 break;
             }
             XmlEvent::EndElement { name } => match name.local_name.as_str() {
-                "Shimeji" => {}
+                "Shimeji" => {
+                    inside_shimeji = false;
+                    if inside_animation || current_behavior.is_some() || current_sequence.is_some()
+                    {
+                        return Err(XmlParseError::MalformedFile);
+                    }
+                    let mut data = finish_shimeji(
+                        shimeji_attributes.take().unwrap(),
+                        std::mem::take(&mut animations),
+                        std::mem::take(&mut props),
+                        std::mem::take(&mut uses),
+                        behaviors.take(),
+                    )?;
+                    data.sequences = std::mem::take(&mut sequences);
+                    data.warnings = std::mem::take(&mut warnings);
+                    parsed.push(data);
+                }
                 "Animation" => {
                     inside_animation = false;
                     let name = animation_name.take().unwrap();
                     let frames = animation_frames.take().unwrap();
                     let fps = animation_fps.take();
+                    let weight = animation_weight.take();
+                    let next = animation_next.take();
+                    let loop_mode = animation_loop.take();
+                    let src = animation_src.take();
 
-                    if frames.is_empty() {
-                        return Err(XmlParseError::MalformedFile);
+                    // frames come from either the `<frame>`s or `src`, not both
+                    if frames.is_empty() == src.is_none() {
+                        return Err(XmlParseError::FramesOrSrc { animation: name });
+                    }
+                    if fps.is_none() && src.is_none() && !every_frame_timed(&frames) {
+                        return Err(XmlParseError::MissingAttribute { attribute: "fps" });
                     }
 
-                    animations.push(AnimationXml { name, fps, frames })
+                    animations.push(AnimationXml {
+                        name,
+                        fps,
+                        weight,
+                        next,
+                        loop_mode,
+                        src,
+                        frames,
+                    })
+                }
+                "Sequence" => sequences.extend(current_sequence.take()),
+                "Behavior" => {
+                    let behavior = current_behavior.take().unwrap();
+                    behaviors.as_mut().unwrap().behaviors.push(behavior);
                 }
                 _ => continue,
             },
@@ -160,10 +783,16 @@ pub fn parse(data: impl Read) -> Result<Box<XmlReturnData>, XmlParseError> {
             }
         }
     }
-    if !shimeji_found {
-        return Err(XmlParseError::NoShimeji);
-    }
-    let mut shimeji_attributes = shimeji_attributes.unwrap();
+    Ok((parsed, warnings))
+}
+
+pub(crate) fn finish_shimeji(
+    mut shimeji_attributes: HashMap<String, String>,
+    animations: Vec<AnimationXml>,
+    props: Vec<PropXml>,
+    uses: Vec<UseXml>,
+    behaviors: Option<BehaviorsXml>,
+) -> Result<XmlReturnData, XmlParseError> {
     let name = shimeji_attributes
         .remove("name")
         .ok_or(XmlParseError::MissingAttribute { attribute: "name" })?;
@@ -172,21 +801,452 @@ pub fn parse(data: impl Read) -> Result<Box<XmlReturnData>, XmlParseError> {
         .remove("height")
         .ok_or(XmlParseError::MissingAttribute {
             attribute: "height",
-        })?
-        .parse()
-        .map_err(|_| XmlParseError::MalformedFile)?;
+        })?;
+    let height = parse_value("height", height)?;
     let width = shimeji_attributes
         .remove("width")
-        .ok_or(XmlParseError::MissingAttribute { attribute: "width" })?
-        .parse()
-        .map_err(|_| XmlParseError::MalformedFile)?;
-    let ret = Box::new(XmlReturnData {
+        .ok_or(XmlParseError::MissingAttribute { attribute: "width" })?;
+    let width = parse_value("width", width)?;
+    let gravity = match shimeji_attributes.remove("gravity") {
+        Some(gravity) => parse_value("gravity", gravity)?,
+        None => true,
+    };
+    let walk_speed = shimeji_attributes
+        .remove("walk_speed")
+        .map(|speed| parse_value("walk_speed", speed))
+        .transpose()?;
+    let mut shadow_attribute = |attribute: &'static str| {
+        shimeji_attributes
+            .remove(attribute)
+            .map(|value| match value.parse::<f64>() {
+                Ok(number) if (0.0..=1.0).contains(&number) => Ok(number),
+                _ => Err(XmlParseError::InvalidValue { attribute, value }),
+            })
+            .transpose()
+    };
+    let shadow = match (
+        shadow_attribute("shadow_opacity")?,
+        shadow_attribute("shadow_size")?,
+    ) {
+        (None, None) => None,
+        (opacity, size) => Some(Shadow {
+            opacity: opacity.unwrap_or(Shadow::DEFAULT_OPACITY),
+            size: size.unwrap_or(Shadow::DEFAULT_SIZE),
+        }),
+    };
+    let scaling = match shimeji_attributes.remove("scaling") {
+        Some(scaling) => parse_value("scaling", scaling)?,
+        None => ScalingMode::default(),
+    };
+    let letterbox = shimeji_attributes
+        .remove("letterbox")
+        .map(|color| {
+            Rgba::from_hex(&color).ok_or(XmlParseError::InvalidValue {
+                attribute: "letterbox",
+                value: color,
+            })
+        })
+        .transpose()?;
+    let scale = match shimeji_attributes.remove("scale") {
+        Some(scale) => match scale.parse::<f64>() {
+            Ok(number) if SCALE_RANGE.contains(&number) => number,
+            _ => {
+                return Err(XmlParseError::InvalidValue {
+                    attribute: "scale",
+                    value: scale,
+                })
+            }
+        },
+        None => 1.0,
+    };
+    let premultiplied = match shimeji_attributes.remove("premultiplied") {
+        Some(premultiplied) => parse_value("premultiplied", premultiplied)?,
+        None => false,
+    };
+    let ret = XmlReturnData {
         name: Arc::from(name.as_str()),
         shimeji_height: height,
         shimeji_width: width,
+        gravity,
+        walk_speed,
+        shadow,
+        scaling,
+        letterbox,
+        scale,
+        premultiplied,
         animations,
+        props,
+        uses,
+        behaviors,
         shimeji_attributes,
-    });
+        sequences: vec![],
+        warnings: vec![],
+    };
     log::debug!("Complete return: {ret:#?}");
     Ok(ret)
 }
+
+/// A `<frame>`, from its attributes.
+pub(crate) fn frame_from_attributes(
+    mut attr_map: HashMap<String, String>,
+    base: &Path,
+) -> Result<FrameXml, XmlParseError> {
+    let file_name = attr_map
+        .remove("file")
+        .ok_or(XmlParseError::MissingAttribute { attribute: "file" })?;
+    let file_name = in_base(base, file_name);
+    let frame_number = attr_map
+        .remove("number")
+        .ok_or(XmlParseError::MissingAttribute {
+            attribute: "number",
+        })?;
+    let frame_number = parse_value("number", frame_number)?;
+
+    let region = if ["x", "y", "w", "h"]
+        .iter()
+        .any(|name| attr_map.contains_key(*name))
+    {
+        let mut take = |attribute: &'static str| {
+            let value = attr_map
+                .remove(attribute)
+                .ok_or(XmlParseError::MissingAttribute { attribute })?;
+            parse_value(attribute, value)
+        };
+        Some(FrameRegion {
+            x: take("x")?,
+            y: take("y")?,
+            width: take("w")?,
+            height: take("h")?,
+        })
+    } else {
+        None
+    };
+
+    let duration = attr_map
+        .remove("duration_ms")
+        .map(|duration| match duration.parse::<u64>() {
+            Ok(millis) if millis > 0 => Ok(Duration::from_millis(millis)),
+            _ => Err(XmlParseError::InvalidValue {
+                attribute: "duration_ms",
+                value: duration,
+            }),
+        })
+        .transpose()?;
+    let sound = attr_map.remove("sound").map(|sound| in_base(base, sound));
+    let anchor_x = attr_map
+        .remove("anchor_x")
+        .map(|value| parse_value("anchor_x", value))
+        .transpose()?;
+    let anchor_y = attr_map
+        .remove("anchor_y")
+        .map(|value| parse_value("anchor_y", value))
+        .transpose()?;
+    let hitboxes = attr_map
+        .remove("hitbox")
+        .map(parse_hitboxes)
+        .transpose()?
+        .unwrap_or_default();
+
+    let file_exists = fs::exists(&file_name).unwrap();
+    if !file_exists {
+        return Err(XmlParseError::MissingImageFile {
+            file_path: file_name,
+        });
+    }
+    Ok(FrameXml {
+        file_path: file_name,
+        number: frame_number,
+        region,
+        duration,
+        sound,
+        anchor_x,
+        anchor_y,
+        hitboxes,
+    })
+}
+
+/// Parse a `hitbox` attribute like `4,0,24,32 0,32,32,16`.
+fn parse_hitboxes(value: String) -> Result<Vec<FrameRegion>, XmlParseError> {
+    let invalid = || XmlParseError::InvalidValue {
+        attribute: "hitbox",
+        value: value.clone(),
+    };
+    let hitboxes = value
+        .split_whitespace()
+        .map(|rect| {
+            let numbers: Vec<u32> = rect
+                .split(',')
+                .map(|number| number.trim().parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid())?;
+            match numbers[..] {
+                [x, y, width, height] if width > 0 && height > 0 => Ok(FrameRegion {
+                    x,
+                    y,
+                    width,
+                    height,
+                }),
+                _ => Err(invalid()),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if hitboxes.is_empty() {
+        return Err(invalid());
+    }
+    Ok(hitboxes)
+}
+
+/// `file` in `base`, unless `base` is empty, or `file` is absolute.
+fn in_base(base: &Path, file: String) -> String {
+    if base.as_os_str().is_empty() {
+        return file;
+    }
+    base.join(file).to_string_lossy().into_owned()
+}
+
+/// The `value` of `attribute`, parsed.
+pub(crate) fn parse_value<T: FromStr>(
+    attribute: &'static str,
+    value: String,
+) -> Result<T, XmlParseError> {
+    value
+        .parse()
+        .map_err(|_| XmlParseError::InvalidValue { attribute, value })
+}
+
+/// Parse a `weight` attribute, see [`crate::formula`].
+pub(crate) fn parse_weight(weight: String) -> Result<Formula, XmlParseError> {
+    Formula::parse(&weight).map_err(|why| XmlParseError::InvalidFormula {
+        formula: weight,
+        reason: why.to_string(),
+    })
+}
+
+/// Parse an animation's `loop` attribute, see [`LoopMode`].
+pub(crate) fn parse_loop_mode(mode: &str) -> Result<LoopMode, XmlParseError> {
+    mode.parse().map_err(|_| XmlParseError::InvalidValue {
+        attribute: "loop",
+        value: mode.to_owned(),
+    })
+}
+
+/// Whether every one of `frames` says how long it shows for, so their animation needs no fps.
+pub(crate) fn every_frame_timed(frames: &[FrameXml]) -> bool {
+    frames.iter().all(|frame| frame.duration.is_some())
+}
+
+/// One `<Pose>` of a Shimeji-ee action's animation.
+#[derive(Debug)]
+pub struct EePoseXml {
+    /// Relative to the pack's `img` folder, usually with a leading `/`.
+    pub image: String,
+    /// In pixels per tick, a tick being 40 milliseconds.
+    pub velocity: (f64, f64),
+    /// In ticks.
+    pub duration: u32,
+}
+
+/// An `<Action>` from a Shimeji-ee `actions.xml`.
+#[derive(Debug)]
+pub struct EeActionXml {
+    pub name: String,
+    /// `Stay`, `Move`, `Animate`, `Sequence`, `Select` or `Embedded`.
+    pub kind: String,
+    /// `Floor`, `Wall` or `Ceiling`, if given.
+    pub border: Option<String>,
+    /// The Java class of an `Embedded` action.
+    pub class: Option<String>,
+    /// The poses of the action's first `<Animation>`.
+    pub poses: Vec<EePoseXml>,
+    /// The actions a `Sequence` or `Select` is made of.
+    pub references: Vec<String>,
+}
+
+/// A `<Behavior>` from a Shimeji-ee `behaviors.xml`.
+#[derive(Debug)]
+pub struct EeBehaviorXml {
+    pub name: String,
+    /// The action the behavior runs, the same as its name unless `Action` is given.
+    pub action: String,
+    pub frequency: u32,
+    /// The behaviors listed in `<NextBehavior>`.
+    pub next: Vec<String>,
+    /// Whether `next` is added to the usual candidates rather than replacing them.
+    pub add_next: bool,
+}
+
+fn attributes_of(attributes: Vec<xml::attribute::OwnedAttribute>) -> HashMap<String, String> {
+    attributes
+        .into_iter()
+        .map(|attr| (attr.name.local_name, attr.value))
+        .collect()
+}
+
+/// Parse a Shimeji-ee `actions.xml`.
+///
+/// Conditions on animations are ignored, only the first `<Animation>` of each action is kept.
+pub fn parse_ee_actions(data: impl Read) -> Result<Vec<EeActionXml>, XmlParseError> {
+    let xml_reader = xml::EventReader::new(data);
+
+    let mut actions: Vec<EeActionXml> = vec![];
+    // actions nest inside each other in sequences, the innermost is last
+    let mut open_actions: Vec<EeActionXml> = vec![];
+    let mut animations_seen = 0;
+    for xml_event in xml_reader {
+        let xml_event = xml_event.map_err(|why| {
+            log::error!("{why}");
+            XmlParseError::MalformedFile
+        })?;
+        match xml_event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "Action" => {
+                    let mut attr_map = attributes_of(attributes);
+                    let name = attr_map.remove("Name");
+                    let kind = attr_map.remove("Type").unwrap_or_default();
+                    if let (Some(name), Some(parent)) = (&name, open_actions.last_mut()) {
+                        // an action defined inline in a sequence is also a reference to it
+                        parent.references.push(name.clone());
+                    }
+                    open_actions.push(EeActionXml {
+                        name: name.unwrap_or_default(),
+                        kind,
+                        border: attr_map.remove("BorderType"),
+                        class: attr_map.remove("Class"),
+                        poses: vec![],
+                        references: vec![],
+                    });
+                    animations_seen = 0;
+                }
+                "ActionReference" => {
+                    let Some(action) = open_actions.last_mut() else {
+                        return Err(XmlParseError::MalformedFile);
+                    };
+                    let name = attributes_of(attributes)
+                        .remove("Name")
+                        .ok_or(XmlParseError::MissingAttribute { attribute: "Name" })?;
+                    action.references.push(name);
+                }
+                "Animation" => animations_seen += 1,
+                "Pose" => {
+                    let Some(action) = open_actions.last_mut() else {
+                        return Err(XmlParseError::MalformedFile);
+                    };
+                    if animations_seen > 1 {
+                        continue;
+                    }
+                    let mut attr_map = attributes_of(attributes);
+                    let image = attr_map
+                        .remove("Image")
+                        .ok_or(XmlParseError::MissingAttribute { attribute: "Image" })?;
+                    let velocity = match attr_map.remove("Velocity") {
+                        Some(velocity) => {
+                            let parsed = velocity.split_once(',').and_then(|(x, y)| {
+                                Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+                            });
+                            parsed.ok_or(XmlParseError::InvalidValue {
+                                attribute: "Velocity",
+                                value: velocity,
+                            })?
+                        }
+                        None => (0.0, 0.0),
+                    };
+                    let duration = attr_map
+                        .remove("Duration")
+                        .ok_or(XmlParseError::MissingAttribute {
+                            attribute: "Duration",
+                        })?
+                        .parse::<u32>()
+                        .map_err(|_| XmlParseError::MalformedFile)?;
+                    action.poses.push(EePoseXml {
+                        image,
+                        velocity,
+                        duration,
+                    });
+                }
+                _ => continue,
+            },
+            XmlEvent::EndElement { name } if name.local_name == "Action" => {
+                let action = open_actions.pop().ok_or(XmlParseError::MalformedFile)?;
+                if !action.name.is_empty() {
+                    actions.push(action);
+                }
+            }
+            XmlEvent::EndDocument => break,
+            _ => continue,
+        }
+    }
+    Ok(actions)
+}
+
+/// Parse a Shimeji-ee `behaviors.xml`.
+///
+/// `<Condition>` blocks are flattened, the behaviors inside them are always eligible.
+pub fn parse_ee_behaviors(data: impl Read) -> Result<Vec<EeBehaviorXml>, XmlParseError> {
+    let xml_reader = xml::EventReader::new(data);
+
+    let mut behaviors: Vec<EeBehaviorXml> = vec![];
+    let mut current: Option<EeBehaviorXml> = None;
+    for xml_event in xml_reader {
+        let xml_event = xml_event.map_err(|why| {
+            log::error!("{why}");
+            XmlParseError::MalformedFile
+        })?;
+        match xml_event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "Behavior" => {
+                    if current.is_some() {
+                        return Err(XmlParseError::MalformedFile);
+                    }
+                    let mut attr_map = attributes_of(attributes);
+                    let name = attr_map
+                        .remove("Name")
+                        .ok_or(XmlParseError::MissingAttribute { attribute: "Name" })?;
+                    let frequency = attr_map
+                        .remove("Frequency")
+                        .ok_or(XmlParseError::MissingAttribute {
+                            attribute: "Frequency",
+                        })?
+                        .parse::<u32>()
+                        .map_err(|_| XmlParseError::MalformedFile)?;
+                    current = Some(EeBehaviorXml {
+                        action: attr_map.remove("Action").unwrap_or(name.clone()),
+                        name,
+                        frequency,
+                        next: vec![],
+                        add_next: true,
+                    });
+                }
+                "NextBehavior" => {
+                    let Some(behavior) = current.as_mut() else {
+                        return Err(XmlParseError::MalformedFile);
+                    };
+                    behavior.add_next = attributes_of(attributes)
+                        .remove("Add")
+                        .is_none_or(|add| add != "false");
+                }
+                "BehaviorReference" => {
+                    // references outside a behavior are the initial candidates, which we
+                    // already get from the frequencies
+                    let Some(behavior) = current.as_mut() else {
+                        continue;
+                    };
+                    let name = attributes_of(attributes)
+                        .remove("Name")
+                        .ok_or(XmlParseError::MissingAttribute { attribute: "Name" })?;
+                    behavior.next.push(name);
+                }
+                _ => continue,
+            },
+            XmlEvent::EndElement { name } if name.local_name == "Behavior" => {
+                behaviors.push(current.take().ok_or(XmlParseError::MalformedFile)?);
+            }
+            XmlEvent::EndDocument => break,
+            _ => continue,
+        }
+    }
+    Ok(behaviors)
+}